@@ -8,7 +8,6 @@ use std::{
 use crate::layout::get_next_id;
 use anyhow::Result;
 use eframe::egui;
-use reqwest;
 use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
 
 pub struct AudioPlayer {
@@ -153,19 +152,19 @@ impl AudioPlayer {
             }
 
             // If playback is over, stop
-            if let Some(sink) = sink_lock.as_ref() {
-                if sink.empty() {
-                    if self.should_loop {
-                        drop(sink_lock);
-                        if let Ok(new_sink) = self.create_sink(Duration::ZERO) {
-                            new_sink.play();
-                            *last_play = Some(Instant::now());
-                            *self.sink.lock().unwrap() = Some(new_sink);
-                            *progress = Duration::ZERO;
-                        }
-                    } else {
-                        *is_playing = false;
+            if let Some(sink) = sink_lock.as_ref()
+                && sink.empty()
+            {
+                if self.should_loop {
+                    drop(sink_lock);
+                    if let Ok(new_sink) = self.create_sink(Duration::ZERO) {
+                        new_sink.play();
+                        *last_play = Some(Instant::now());
+                        *self.sink.lock().unwrap() = Some(new_sink);
+                        *progress = Duration::ZERO;
                     }
+                } else {
+                    *is_playing = false;
                 }
             }
 
@@ -1,16 +1,36 @@
 // browser.rs
 use crate::audio_player::AudioPlayer;
-use crate::html_parser;
-use crate::layout::{self, HtmlNode, HtmlTag, NodeType}; // Import layout definitions
+use crate::css_parser::MediaEnvironment;
+use crate::dom::{Document, MutationRecord, NodeId, NodeRef};
+use crate::generated;
+use crate::head::{self, PageInfo};
+use crate::html_parser::{self, ParseUpdate, Resource, StreamingParser};
+use crate::html_serializer::{self, SerializeOptions};
+use crate::image;
+use crate::layout::{self, HtmlTag, NodeType, PseudoElement}; // Import layout definitions
 use crate::network;
+use crate::style::{
+    self, CaptionSide, ComputedStyle, ListStylePosition, TextAlign, VerticalAlign, WhiteSpace,
+};
+use crate::stylesheet::{self, SheetLoads, SheetStatus};
+use crate::svg;
+use crate::table::{self, TableGrid};
 use eframe::egui;
-use egui_extras;
-use std::collections::HashMap;
-use std::sync::mpsc;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Arc, mpsc};
 use std::thread;
+use std::time::{Duration, Instant};
 
 // --- Constants for styling and layout ---
-const BASE_SIZE: f32 = 16.0; // Default font size
+const LIST_INDENT: f32 = 40.0; // Left indentation of list items; markers sit in it
+const BORDER_SPACING: f32 = 2.0; // Gap between table cells unless borders collapse
+const IMAGE_PLACEHOLDER_SIZE: f32 = 24.0; // Side of an image box whose size isn't known yet
+const LAZY_LOAD_MARGIN: f32 = 600.0; // Lazy images load when they are this close to the view
+const MEASURE_WIDTH: f32 = 100_000.0; // Room given to table cells when measuring their content
+const FRAME_SIZE: egui::Vec2 = egui::vec2(300.0, 150.0); // <iframe> without width or height
 
 // --- NEW: Tab State ---
 enum ContentState {
@@ -19,7 +39,8 @@ enum ContentState {
     Error(String),   // Error message
     Loaded {
         url: String,
-        root_node: HtmlNode, // Store the parsed HTML tree
+        document: Arc<Document>, // The parsed HTML tree; shared with the renderer each frame
+        complete: bool,          // false while the rest of the page is still arriving
     },
 }
 
@@ -28,7 +49,80 @@ struct TabState {
     title: String,
     url_input: String, // URL currently in the address bar for this tab
     content_state: ContentState,
-    audio_player: HashMap<String, AudioPlayer>,
+    load_id: usize, // Identifies the current navigation; see `NetworkMessage`
+    audio_player: HashMap<NodeId, AudioPlayer>, // keyed by the <audio> element
+    frames: HashMap<FrameId, FrameState>, // the pages shown in <iframe>s, at any depth
+    info: PageInfo, // from the <meta> and <link>s of the page's <head>
+    refresh: Option<(Instant, String)>, // a <meta> refresh counting down: when and where to
+    stylesheets: SheetLoads, // the linked and imported sheets of the tab's own page
+}
+
+/// An `<iframe>`: the id of the document it is in and its node id there. Node ids are only
+/// unique within one document, and frames can be nested.
+type FrameId = (usize, NodeId);
+
+/// What an `<iframe>` was told to show.
+#[derive(Debug, Clone, PartialEq)]
+enum FrameSource {
+    Url(String),    // `src`, resolved
+    Srcdoc(String), // the markup of `srcdoc`, which wins over `src`
+}
+
+/// A nested browsing context: the page inside an `<iframe>`. It loads, scrolls and follows
+/// links on its own, like a small tab inside the page.
+struct FrameState {
+    parent: Option<FrameId>, // the frame the <iframe> is in; `None` for the tab's own page
+    source: FrameSource,     // reloaded when the <iframe>'s attributes ask for something else
+    content_state: ContentState,
+    load_id: usize,
+    audio_player: HashMap<NodeId, AudioPlayer>,
+    stylesheets: SheetLoads,
+}
+
+impl FrameState {
+    fn show_document(&mut self, url: String, document: Arc<Document>, complete: bool) {
+        let same_document = matches!(&self.content_state,
+            ContentState::Loaded { document: old, .. } if old.id() == document.id());
+        if !same_document {
+            self.audio_player.clear();
+        }
+        self.content_state = ContentState::Loaded {
+            url,
+            document,
+            complete,
+        };
+    }
+
+    /// Add a parser update to the page being loaded, as for `TabState::show_update`.
+    fn show_update(&mut self, url: String, update: ParseUpdate, complete: bool) -> bool {
+        let (document, sheets_changed) = self.content_state.apply_update(update);
+        self.show_document(url, document, complete);
+        sheets_changed
+    }
+}
+
+impl ContentState {
+    /// Replay a load's parser update on the page it is showing, or start the page with it
+    /// if nothing has been shown yet. Returns the document, and whether its rules must be
+    /// collected again because its sheets changed or it is new.
+    fn apply_update(&mut self, update: ParseUpdate) -> (Arc<Document>, bool) {
+        match self {
+            ContentState::Loaded {
+                document,
+                complete: false,
+                ..
+            } => {
+                // Copies the document only if the renderer still holds the old snapshot
+                let sheets_changed = update.apply(Arc::make_mut(document));
+                (Arc::clone(document), sheets_changed)
+            }
+            _ => {
+                let mut document = html_parser::new_document();
+                update.apply(&mut document);
+                (Arc::new(document), true)
+            }
+        }
+    }
 }
 
 impl TabState {
@@ -38,7 +132,73 @@ impl TabState {
             title: "New Tab".to_string(),
             url_input: "".to_string(),
             content_state: ContentState::Idle,
+            load_id: 0,
             audio_player: HashMap::new(),
+            frames: HashMap::new(),
+            info: PageInfo::default(),
+            refresh: None,
+            stylesheets: SheetLoads::default(),
+        }
+    }
+
+    /// The state of the tab's own page (`frame` is `None`) or of one of its frames.
+    fn context_mut(
+        &mut self,
+        frame: Option<FrameId>,
+    ) -> Option<(
+        &mut ContentState,
+        &mut HashMap<NodeId, AudioPlayer>,
+        &mut SheetLoads,
+    )> {
+        match frame {
+            None => Some((
+                &mut self.content_state,
+                &mut self.audio_player,
+                &mut self.stylesheets,
+            )),
+            Some(id) => {
+                let frame = self.frames.get_mut(&id)?;
+                Some((
+                    &mut frame.content_state,
+                    &mut frame.audio_player,
+                    &mut frame.stylesheets,
+                ))
+            }
+        }
+    }
+
+    /// The navigation the tab's own page or one of its frames is showing.
+    fn load_id(&self, frame: Option<FrameId>) -> Option<usize> {
+        match frame {
+            None => Some(self.load_id),
+            Some(id) => self.frames.get(&id).map(|frame| frame.load_id),
+        }
+    }
+
+    /// Forget frames whose `<iframe>` is no longer part of the page that is shown around it,
+    /// because that page navigated away or the element was removed.
+    fn prune_frames(&mut self) {
+        loop {
+            let stale: Vec<FrameId> = self
+                .frames
+                .iter()
+                .filter(|&(&(document_id, node_id), frame)| {
+                    let parent = match frame.parent {
+                        None => Some(&self.content_state),
+                        Some(parent) => self.frames.get(&parent).map(|f| &f.content_state),
+                    };
+                    !matches!(parent, Some(ContentState::Loaded { document, .. })
+                        if document.id() == document_id && document.is_attached(node_id))
+                })
+                .map(|(id, _)| *id)
+                .collect();
+            if stale.is_empty() {
+                break;
+            }
+            // Removing a frame can make the frames inside it stale in turn
+            for id in stale {
+                self.frames.remove(&id);
+            }
         }
     }
 
@@ -46,29 +206,8 @@ impl TabState {
     fn display_title(&self) -> &str {
         match &self.content_state {
             ContentState::Loading(_) => "Loading...",
-            ContentState::Loaded { root_node, url, .. } => {
-                // Try to find title in head
-                if let Some(head) = root_node.children.get(0) {
-                    // Assuming cleanup_tree puts head first
-                    if matches!(head.node_type, NodeType::Element(HtmlTag::Head)) {
-                        for node in &head.children {
-                            if let NodeType::Element(HtmlTag::Title) = node.node_type {
-                                if let Some(text_node) = node.children.get(0) {
-                                    if let NodeType::Text(text) = &text_node.node_type {
-                                        let trimmed = text.trim();
-                                        if !trimmed.is_empty() {
-                                            // TODO: Cache this title?
-                                            // For now, we just return a reference, which is tricky
-                                            // Let's update the TabState title instead when loaded
-                                            // return trimmed; // Cannot return local reference
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                // Fallback title
+            ContentState::Loaded { url, .. } => {
+                // `title` is updated from the <title> tag when the page loads
                 if !self.title.is_empty() && self.title != "New Tab" {
                     &self.title
                 } else if !url.is_empty() {
@@ -81,45 +220,133 @@ impl TabState {
         }
     }
 
+    /// Show a (possibly partial) document. Per-node state such as audio players survives
+    /// when the same document is shown again with more of it loaded.
+    fn show_document(&mut self, url: String, document: Arc<Document>, complete: bool) {
+        let same_document = matches!(&self.content_state,
+            ContentState::Loaded { document: old, .. } if old.id() == document.id());
+        if !same_document {
+            // Players belong to the previous document's <audio> elements
+            self.audio_player.clear();
+            self.refresh = None;
+        }
+        self.info = head::page_info(&document, &url);
+        // The countdown starts once the page has loaded. A delay too far off to schedule
+        // never comes.
+        if complete
+            && let Some(refresh) = &self.info.refresh
+            && let Some(at) = Instant::now().checked_add(refresh.delay)
+        {
+            self.refresh = Some((at, refresh.url.clone()));
+        }
+        self.content_state = ContentState::Loaded {
+            url,
+            document: Arc::clone(&document),
+            complete,
+        };
+        if !same_document {
+            self.prune_frames();
+        }
+        // Update tab title from <title> tag
+        self.update_title_from_node(&document);
+    }
+
+    /// Add a parser update to the page being loaded; the first update of a load starts the
+    /// page. Returns whether the page's rules must be collected again; otherwise only the
+    /// new nodes need styling.
+    fn show_update(&mut self, url: String, update: ParseUpdate, complete: bool) -> bool {
+        let (document, sheets_changed) = self.content_state.apply_update(update);
+        self.show_document(url, document, complete);
+        sheets_changed
+    }
+
     // Update title from HTML <title> tag
-    fn update_title_from_node(&mut self, root_node: &HtmlNode) {
-        if let Some(head) = root_node.children.get(0) {
-            // Assuming cleanup_tree puts head first
-            if matches!(head.node_type, NodeType::Element(HtmlTag::Head)) {
-                for node in &head.children {
-                    if let NodeType::Element(HtmlTag::Title) = node.node_type {
-                        if let Some(text_node) = node.children.get(0) {
-                            if let NodeType::Text(text) = &text_node.node_type {
-                                let trimmed = text.trim();
-                                if !trimmed.is_empty() {
-                                    self.title = trimmed.to_string();
-                                    return; // Found title
-                                }
-                            }
-                        }
-                    }
-                }
+    fn update_title_from_node(&mut self, document: &Document) {
+        if let Some(title) = document
+            .head()
+            .and_then(|head| head.query_selector("title"))
+        {
+            let text = title.text_content();
+            let trimmed = text.trim();
+            if !trimmed.is_empty() {
+                self.title = trimmed.to_string();
+                return; // Found title
             }
         }
         // If no title found, maybe use URL? Or keep existing?
-        if let ContentState::Loaded { url, .. } = &self.content_state {
-            if !url.is_empty() {
-                self.title = url.clone();
-            }
+        if let ContentState::Loaded { url, .. } = &self.content_state
+            && !url.is_empty()
+        {
+            self.title = url.clone();
         }
     }
 }
 
+/// Progress of a page load running on a background thread. `load_id` tells the tab which
+/// navigation a message belongs to, so results of a replaced load are dropped.
+enum NetworkMessage {
+    /// What the parser has added since the previous message, while the rest is still arriving
+    Partial {
+        tab_id: usize,
+        load_id: usize,
+        url: String,
+        update: ParseUpdate,
+    },
+    /// Subresources the parser has just seen, with URLs resolved against the page
+    Resources {
+        tab_id: usize,
+        load_id: usize,
+        resources: Vec<Resource>,
+    },
+    /// The rest of the document
+    Loaded {
+        tab_id: usize,
+        load_id: usize,
+        url: String,
+        update: ParseUpdate,
+    },
+    Failed {
+        tab_id: usize,
+        load_id: usize,
+        url: String,
+        error: String,
+    },
+    /// A linked or imported stylesheet of the page has loaded, or couldn't be
+    Stylesheet {
+        tab_id: usize,
+        load_id: usize,
+        url: String,
+        status: SheetStatus,
+    },
+}
+
+/// The Save Page As window: the file the active tab's page is written to, and how the last
+/// attempt went.
+struct SaveDialog {
+    path: String,
+    result: Option<Result<PathBuf, String>>, // where the page went, or why it didn't
+}
+
+/// Minimum time between two partial updates of a loading page
+const PARTIAL_RENDER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An edit to a tab's document. Rendering only sees an immutable snapshot, so edits made
+/// while drawing a frame are queued and applied once the frame is done.
+type DomChange = Box<dyn FnOnce(&mut Document)>;
+
 pub struct BrowserApp {
     tabs: Vec<TabState>,
     active_tab_index: usize,
     next_tab_id: usize,
-    // Channel now sends Result<(tab_id, url, raw_body, root_node), (tab_id, url, error_msg)>
-    network_receiver:
-        mpsc::Receiver<Result<(usize, String, String, HtmlNode), (usize, String, String)>>,
-    network_sender:
-        mpsc::Sender<Result<(usize, String, String, HtmlNode), (usize, String, String)>>,
-    // network_manager: network::NetworkManager,
+    network_receiver: mpsc::Receiver<NetworkMessage>,
+    network_sender: mpsc::Sender<NetworkMessage>,
+    dom_changes: Vec<(usize, Option<FrameId>, DomChange)>, // (tab_id, frame, change)
+    network_manager: Arc<network::NetworkManager>,
+    show_page_info: bool,            // the Page Info window for the active tab
+    save_dialog: Option<SaveDialog>, // the Save Page As window, while it is open
+    history: HashSet<String>,        // every URL loaded in a tab or frame, for `:visited`
+    // The deepest element under the pointer in each document drawn this frame
+    pointer_targets: HashMap<Option<FrameId>, NodeId>,
 }
 
 impl BrowserApp {
@@ -132,11 +359,11 @@ impl BrowserApp {
         let mut initial_tab = TabState::new(initial_tab_id);
         let next_tab_id = 1; // Start next ID from 1
 
-        if let Some(url) = initial_url {
-            if !url.is_empty() {
-                initial_tab.url_input = url;
-                // Loading will be triggered in the first update if url_input is set
-            }
+        if let Some(url) = initial_url
+            && !url.is_empty()
+        {
+            initial_tab.url_input = url;
+            // Loading will be triggered in the first update if url_input is set
         }
 
         let mut app = Self {
@@ -145,7 +372,12 @@ impl BrowserApp {
             next_tab_id,
             network_receiver: receiver,
             network_sender: sender,
-            // network_manager: network::NetworkManager::new(),
+            dom_changes: Vec::new(),
+            network_manager: Arc::new(network::NetworkManager::new()),
+            show_page_info: false,
+            save_dialog: None,
+            history: HashSet::new(),
+            pointer_targets: HashMap::new(),
         };
         // Trigger initial load if URL was provided
         if !app.tabs[0].url_input.is_empty() {
@@ -157,12 +389,16 @@ impl BrowserApp {
 
     fn start_loading(&mut self, tab_index: usize, url_str: String) {
         if let Some(tab) = self.tabs.get_mut(tab_index) {
+            // Navigating cancels a pending refresh, and the new page brings its own metadata
+            tab.refresh = None;
+            tab.info = PageInfo::default();
+            tab.stylesheets = SheetLoads::default();
             if !url_str.starts_with("http://") && !url_str.starts_with("https://") {
                 // Basic check, URL::new does more validation
                 if !url_str.starts_with("file://") {
                     // Allow file URLs if needed later
                     tab.content_state =
-                        ContentState::Error(format!("URL must start with http:// or https://"));
+                        ContentState::Error("URL must start with http:// or https://".to_string());
                     tab.url_input = url_str; // Update input even on error
                     return;
                 }
@@ -172,31 +408,157 @@ impl BrowserApp {
             tab.url_input = url_str.clone(); // Update input when loading starts
             tab.title = url_str.chars().take(20).collect(); // Temporary title
 
-            let sender = self.network_sender.clone();
-            let url_to_load = url_str;
-            let tab_id = tab.id; // Send tab ID, not index
+            tab.load_id = layout::get_next_id();
+            let (tab_id, load_id) = (tab.id, tab.load_id); // Send tab ID, not index
+            self.history.insert(url_str.clone());
+            self.spawn_load(tab_id, load_id, url_str);
+        } else {
+            eprintln!("Attempted to load URL for invalid tab index: {}", tab_index);
+        }
+    }
 
-            thread::spawn(move || {
-                match network::load_url(&url_to_load) {
-                    // --- MODIFIED: Parse HTML and send root node ---
-                    Ok(body) => {
-                        // Use the robust parser
-                        let root_node = html_parser::parse_html(&body);
-                        // Optionally print the tree for debugging
-                        html_parser::print_tree(&root_node);
-                        sender
-                            .send(Ok((tab_id, url_to_load, body.to_string(), root_node))) // Send tab_id, url, body, node
-                            .unwrap_or_else(|e| eprintln!("Failed to send success result: {}", e));
-                    }
-                    Err(e) => {
-                        sender
-                            .send(Err((tab_id, url_to_load, e.to_string())))
-                            .unwrap_or_else(|e| eprintln!("Failed to send error result: {}", e));
-                    }
+    /// Load a page on a background thread, reporting progress as `NetworkMessage`s for the
+    /// tab's page or frame whose navigation is `load_id`.
+    fn spawn_load(&self, tab_id: usize, load_id: usize, url_to_load: String) {
+        let sender = self.network_sender.clone();
+        thread::spawn(move || {
+            let mut parser = StreamingParser::new();
+            let mut last_partial = Instant::now();
+            let send = |message| {
+                sender
+                    .send(message)
+                    .unwrap_or_else(|e| eprintln!("Failed to send network message: {}", e));
+            };
+            let result = network::load_url_streaming(&url_to_load, |chunk| {
+                if !parser.feed(chunk) {
+                    return;
+                }
+                let resources: Vec<Resource> = parser
+                    .take_resources()
+                    .into_iter()
+                    .filter_map(|resource| match resource {
+                        Resource::Stylesheet(href) => {
+                            network::resolve_url(&url_to_load, &href).map(Resource::Stylesheet)
+                        }
+                        Resource::Image(src) => {
+                            network::resolve_url(&url_to_load, &src).map(Resource::Image)
+                        }
+                    })
+                    .collect();
+                if !resources.is_empty() {
+                    send(NetworkMessage::Resources {
+                        tab_id,
+                        load_id,
+                        resources,
+                    });
+                }
+                if last_partial.elapsed() >= PARTIAL_RENDER_INTERVAL {
+                    last_partial = Instant::now();
+                    send(NetworkMessage::Partial {
+                        tab_id,
+                        load_id,
+                        url: url_to_load.clone(),
+                        update: parser.take_update(),
+                    });
                 }
             });
+            match result {
+                Ok(()) => {
+                    parser.close();
+                    // Optionally print the tree for debugging
+                    html_parser::print_tree(parser.document().root());
+                    send(NetworkMessage::Loaded {
+                        tab_id,
+                        load_id,
+                        url: url_to_load,
+                        update: parser.take_update(),
+                    });
+                }
+                Err(e) => send(NetworkMessage::Failed {
+                    tab_id,
+                    load_id,
+                    url: url_to_load,
+                    error: e.to_string(),
+                }),
+            }
+        });
+    }
+
+    /// Navigate the page inside one of the active tab's `<iframe>`s; the rest of the tab stays.
+    fn navigate_frame(&mut self, id: FrameId, url: String) {
+        let Some(tab) = self.tabs.get_mut(self.active_tab_index) else {
+            return;
+        };
+        let tab_id = tab.id;
+        let Some(frame) = tab.frames.get_mut(&id) else {
+            return;
+        };
+        frame.load_id = layout::get_next_id();
+        frame.stylesheets = SheetLoads::default();
+        let load_id = frame.load_id;
+        // Only what `network::load_url_streaming` can fetch
+        let loadable = url.starts_with("http://") || url.starts_with("https://");
+        if loadable {
+            frame.content_state = ContentState::Loading(url.clone());
+        } else if url == "about:blank" {
+            let document = Arc::new(html_parser::parse_html(""));
+            frame.show_document(url.clone(), document, true);
         } else {
-            eprintln!("Attempted to load URL for invalid tab index: {}", tab_index);
+            frame.content_state = ContentState::Error(format!("Cannot load {} in a frame", url));
+        }
+        // The frames inside the previous page go away with it
+        tab.prune_frames();
+        if loadable {
+            self.history.insert(url.clone());
+            self.spawn_load(tab_id, load_id, url);
+        }
+    }
+
+    /// Make the `<iframe>` `id` of the active tab show `source`. Nothing happens if it already
+    /// does, so this can be called every frame; a changed `src` or `srcdoc` loads anew.
+    fn open_frame(
+        &mut self,
+        ctx: &egui::Context,
+        id: FrameId,
+        parent: Option<FrameId>,
+        source: FrameSource,
+        base_url: &str,
+    ) {
+        let Some(tab) = self.tabs.get_mut(self.active_tab_index) else {
+            return;
+        };
+        if tab
+            .frames
+            .get(&id)
+            .is_some_and(|frame| frame.source == source)
+        {
+            return;
+        }
+        let mut frame = FrameState {
+            parent,
+            source: source.clone(),
+            content_state: ContentState::Idle,
+            load_id: layout::get_next_id(),
+            audio_player: HashMap::new(),
+            stylesheets: SheetLoads::default(),
+        };
+        match source {
+            FrameSource::Url(url) => {
+                tab.frames.insert(id, frame);
+                self.navigate_frame(id, url);
+            }
+            FrameSource::Srcdoc(html) => {
+                // A srcdoc page has no address of its own; its links resolve like the parent's
+                let document = Arc::new(html_parser::parse_html(&html));
+                // The parser's styling is final for pages with only `<style>`s
+                let self_contained = stylesheet::is_self_contained(document.page_sheets());
+                frame.show_document(base_url.to_string(), document, true);
+                tab.frames.insert(id, frame);
+                tab.prune_frames();
+                if !self_contained {
+                    self.apply_stylesheets(ctx, self.active_tab_index, Some(id));
+                }
+            }
         }
     }
 
@@ -207,59 +569,522 @@ impl BrowserApp {
         self.active_tab_index = self.tabs.len() - 1; // Activate the new tab
     }
 
-    // Find tab index by tab ID
-    fn find_tab_index_by_id(&self, tab_id: usize) -> Option<usize> {
-        self.tabs.iter().position(|tab| tab.id == tab_id)
+    /// Open the Save Page As window for the active tab's page, suggesting `<title>.html` in
+    /// the working directory.
+    fn open_save_dialog(&mut self) {
+        let Some(tab) = self.tabs.get(self.active_tab_index) else {
+            return;
+        };
+        if !matches!(tab.content_state, ContentState::Loaded { .. }) {
+            return;
+        }
+        let file_name: String = tab
+            .title
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.save_dialog = Some(SaveDialog {
+            path: format!("{}.html", file_name),
+            result: None,
+        });
     }
-}
 
-impl eframe::App for BrowserApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        ctx.set_debug_on_hover(true);
-        // --- Receive Network Results ---
-        match self.network_receiver.try_recv() {
-            Ok(Ok((tab_id, loaded_url, _, root_node))) => {
-                if let Some(index) = self.find_tab_index_by_id(tab_id) {
-                    if let Some(tab) = self.tabs.get_mut(index) {
-                        tab.content_state = ContentState::Loaded {
-                            url: loaded_url,
-                            root_node: root_node.clone(), // Clone the node into the state
-                        };
-                        // Update tab title from <title> tag
-                        tab.update_title_from_node(&root_node);
+    /// Write the active tab's document to `path`, returning the absolute path written to.
+    fn save_active_page(&self, path: &str) -> Result<PathBuf, String> {
+        let Some(ContentState::Loaded { document, .. }) = self
+            .tabs
+            .get(self.active_tab_index)
+            .map(|tab| &tab.content_state)
+        else {
+            return Err("There is no page to save".to_string());
+        };
+        let html = html_serializer::serialize_html(document.root(), &SerializeOptions::pretty());
+        std::fs::write(path, html).map_err(|e| e.to_string())?;
+        std::path::absolute(path).map_err(|e| e.to_string())
+    }
+
+    /// The Save Page As window, if it is open.
+    fn show_save_dialog(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.save_dialog else {
+            return;
+        };
+        let mut open = true;
+        let mut save = false;
+        let mut cancel = false;
+        egui::Window::new("Save Page As")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("File:");
+                    let input = ui.text_edit_singleline(&mut dialog.path);
+                    save = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                });
+                ui.horizontal(|ui| {
+                    save |= ui.button("Save").clicked();
+                    cancel = ui.button("Close").clicked();
+                });
+                match &dialog.result {
+                    Some(Ok(path)) => {
+                        ui.label(format!("Saved to {}", path.display()));
                     }
-                } else {
-                    eprintln!("Received network result for unknown tab id: {}", tab_id);
+                    Some(Err(error)) => {
+                        ui.colored_label(egui::Color32::RED, format!("Not saved: {}", error));
+                    }
+                    None => {}
                 }
+            });
+        if !open || cancel {
+            self.save_dialog = None;
+        } else if save {
+            let path = dialog.path.trim().to_string();
+            let result = if path.is_empty() {
+                Err("Enter a file name".to_string())
+            } else {
+                self.save_active_page(&path)
+            };
+            if let Some(dialog) = &mut self.save_dialog {
+                dialog.result = Some(result);
             }
-            Ok(Err((tab_id, failed_url, error_msg))) => {
-                if let Some(index) = self.find_tab_index_by_id(tab_id) {
-                    if let Some(tab) = self.tabs.get_mut(index) {
-                        tab.content_state = ContentState::Error(format!(
-                            "Failed to load {}: {}",
-                            failed_url, error_msg
-                        ));
-                        tab.title = "Error".to_string();
-                    }
-                } else {
-                    eprintln!("Received network error for unknown tab id: {}", tab_id);
+        }
+    }
+
+    /// Queue an edit to the document of the active tab, or of one of its frames; it is
+    /// applied after the current frame.
+    fn queue_dom_change(
+        &mut self,
+        frame: Option<FrameId>,
+        change: impl FnOnce(&mut Document) + 'static,
+    ) {
+        if let Some(tab) = self.tabs.get(self.active_tab_index) {
+            self.dom_changes.push((tab.id, frame, Box::new(change)));
+        }
+    }
+
+    /// Handle everything background loads have sent since the last frame.
+    fn receive_network_messages(&mut self, ctx: &egui::Context) {
+        while let Ok(message) = self.network_receiver.try_recv() {
+            let (tab_id, load_id) = match &message {
+                NetworkMessage::Partial {
+                    tab_id, load_id, ..
                 }
+                | NetworkMessage::Resources {
+                    tab_id, load_id, ..
+                }
+                | NetworkMessage::Loaded {
+                    tab_id, load_id, ..
+                }
+                | NetworkMessage::Failed {
+                    tab_id, load_id, ..
+                }
+                | NetworkMessage::Stylesheet {
+                    tab_id, load_id, ..
+                } => (*tab_id, *load_id),
+            };
+            let Some(index) = self.find_tab_index_by_id(tab_id) else {
+                eprintln!("Received network result for unknown tab id: {}", tab_id);
+                continue;
+            };
+            let tab = &mut self.tabs[index];
+            // The load is either the tab's own page or the page of one of its frames
+            let frame = tab
+                .frames
+                .iter()
+                .find(|(_, frame)| frame.load_id == load_id)
+                .map(|(id, _)| *id);
+            if tab.load_id != load_id && frame.is_none() {
+                continue; // The tab or frame has navigated elsewhere since
             }
-            Err(mpsc::TryRecvError::Empty) => {}
-            Err(mpsc::TryRecvError::Disconnected) => {
-                eprintln!("Network channel disconnected!");
-                // Optionally show an error in the active tab?
-                if let Some(tab) = self.tabs.get_mut(self.active_tab_index) {
+
+            let mut restyle = false; // whether the page's rules must be collected again
+            let mut updated = false; // whether the parser added to the page
+            let mut discovered = Vec::new(); // stylesheets the parser has seen
+            let mut loaded = None;
+            match (message, frame.and_then(|id| tab.frames.get_mut(&id))) {
+                (NetworkMessage::Partial { url, update, .. }, Some(frame)) => {
+                    restyle = frame.show_update(url, update, false);
+                    tab.prune_frames();
+                    updated = true;
+                }
+                (NetworkMessage::Loaded { url, update, .. }, Some(frame)) => {
+                    frame.show_update(url, update, true);
+                    tab.prune_frames();
+                    // See below for the tab's own page
+                    restyle = true;
+                }
+                (NetworkMessage::Failed { url, error, .. }, Some(frame)) => {
+                    frame.content_state =
+                        ContentState::Error(format!("Failed to load {}: {}", url, error));
+                    tab.prune_frames();
+                }
+                (NetworkMessage::Partial { url, update, .. }, None) => {
+                    restyle = tab.show_update(url, update, false);
+                    updated = true;
+                }
+                (NetworkMessage::Loaded { url, update, .. }, None) => {
+                    tab.show_update(url, update, true);
+                    // Nodes were styled as they arrived, before their later siblings; once
+                    // the page is complete, selectors like `:last-child` are checked again
+                    restyle = true;
+                }
+                (NetworkMessage::Resources { resources, .. }, _) => {
+                    for resource in resources {
+                        match resource {
+                            Resource::Image(url) => {
+                                // Starts the download in egui's image loaders
+                                let _ = ctx.try_load_image(&url, egui::SizeHint::default());
+                            }
+                            Resource::Stylesheet(url) => discovered.push(url),
+                        }
+                    }
+                }
+                (NetworkMessage::Stylesheet { url, status, .. }, _) => {
+                    loaded = Some((url, status));
+                }
+                (NetworkMessage::Failed { url, error, .. }, None) => {
                     tab.content_state =
-                        ContentState::Error("Internal communication error.".to_string());
+                        ContentState::Error(format!("Failed to load {}: {}", url, error));
+                    tab.title = "Error".to_string();
+                }
+            }
+
+            if let Some((url, status)) = loaded
+                && let Some((_, _, loads)) = self.tabs[index].context_mut(frame)
+            {
+                loads.set(url, status);
+                restyle = true;
+            }
+            self.request_stylesheets(ctx, index, frame, discovered);
+            if restyle {
+                self.apply_stylesheets(ctx, index, frame);
+            } else if updated
+                && let Some((ContentState::Loaded { document, .. }, _, _)) =
+                    self.tabs[index].context_mut(frame)
+            {
+                Arc::make_mut(document).restyle();
+            }
+        }
+    }
+
+    /// Start loading the stylesheets at `urls` for the page of a tab or one of its frames,
+    /// skipping those already requested. Sheets the page may not use are recorded as
+    /// blocked instead.
+    fn request_stylesheets(
+        &mut self,
+        ctx: &egui::Context,
+        tab_index: usize,
+        frame: Option<FrameId>,
+        urls: Vec<String>,
+    ) {
+        let Some(tab) = self.tabs.get_mut(tab_index) else {
+            return;
+        };
+        let tab_id = tab.id;
+        let Some(load_id) = tab.load_id(frame) else {
+            return;
+        };
+        let Some((content_state, _, loads)) = tab.context_mut(frame) else {
+            return;
+        };
+        let page_url = match content_state {
+            ContentState::Loading(url) | ContentState::Loaded { url, .. } => url.clone(),
+            _ => return,
+        };
+        for url in urls {
+            if loads.get(&url).is_some() {
+                continue;
+            }
+            if let Some(reason) = stylesheet::blocked_reason(&page_url, &url) {
+                eprintln!("Blocked stylesheet {}: {}", url, reason);
+                loads.set(url, SheetStatus::Blocked(reason));
+                continue;
+            }
+            loads.set(url.clone(), SheetStatus::Loading);
+            let sender = self.network_sender.clone();
+            let network = Arc::clone(&self.network_manager);
+            let ctx = ctx.clone();
+            thread::spawn(move || {
+                let status = stylesheet::fetch(&network, &url);
+                if let SheetStatus::Failed(error) | SheetStatus::Blocked(error) = &status {
+                    eprintln!("Failed to load stylesheet {}: {}", url, error);
+                }
+                sender
+                    .send(NetworkMessage::Stylesheet {
+                        tab_id,
+                        load_id,
+                        url,
+                        status,
+                    })
+                    .unwrap_or_else(|e| eprintln!("Failed to send network message: {}", e));
+                // The page may be complete already, with nothing else to wake the UI
+                ctx.request_repaint();
+            });
+        }
+    }
+
+    /// Restyle the page of a tab or one of its frames with its stylesheets as far as they
+    /// have loaded, and request those it refers to that haven't been (including the
+    /// `@import`s of sheets that just arrived).
+    fn apply_stylesheets(&mut self, ctx: &egui::Context, tab_index: usize, frame: Option<FrameId>) {
+        let Some(tab) = self.tabs.get_mut(tab_index) else {
+            return;
+        };
+        let Some((ContentState::Loaded { url, document, .. }, _, loads)) = tab.context_mut(frame)
+        else {
+            return;
+        };
+        let collected = stylesheet::author_rules(document.page_sheets(), Some(url.as_str()), loads);
+        Arc::make_mut(document).set_stylesheet(collected.rules);
+        ctx.request_repaint();
+        self.request_stylesheets(ctx, tab_index, frame, collected.urls);
+    }
+
+    /// Apply queued document edits, then restyle and repaint only if something changed.
+    fn apply_dom_changes(&mut self, ctx: &egui::Context) {
+        let mut changed: Vec<(usize, Option<FrameId>)> = Vec::new();
+        for (tab_id, frame, change) in self.dom_changes.drain(..) {
+            let Some(tab) = self.tabs.iter_mut().find(|tab| tab.id == tab_id) else {
+                continue; // Tab was closed in the meantime
+            };
+            // The frame may have gone away with its <iframe> as well
+            if let Some((ContentState::Loaded { document, .. }, _, _)) = tab.context_mut(frame) {
+                // Copies the document only if the renderer still holds the old snapshot
+                change(Arc::make_mut(document));
+                if !changed.contains(&(tab_id, frame)) {
+                    changed.push((tab_id, frame));
+                }
+            }
+        }
+
+        for (tab_id, frame) in changed {
+            let Some(tab) = self.tabs.iter_mut().find(|tab| tab.id == tab_id) else {
+                continue;
+            };
+            let Some((ContentState::Loaded { document, .. }, audio_player, _)) =
+                tab.context_mut(frame)
+            else {
+                continue;
+            };
+            let document = Arc::make_mut(document);
+            // Changes to hover or focus state restyle without changing the tree
+            document.restyle();
+            if !document.take_layout_dirty().is_empty() {
+                ctx.request_repaint();
+            }
+            let records = document.take_mutations();
+            if records.is_empty() {
+                continue;
+            }
+            // Drop players whose <audio> element was removed from the page
+            let removes = |record: &MutationRecord| match record {
+                MutationRecord::ChildList { removed, .. } => !removed.is_empty(),
+                _ => false,
+            };
+            if records.iter().any(removes) {
+                audio_player.retain(|id, _| document.is_attached(*id));
+            }
+            // ...and frames whose <iframe> was
+            tab.prune_frames();
+        }
+    }
+
+    /// Report to the active tab's documents which of their elements are hovered, pressed
+    /// and focused, from where the pointer was while the page was drawn.
+    fn update_element_states(&mut self, ctx: &egui::Context) {
+        let (pressed, down) =
+            ctx.input(|i| (i.pointer.primary_pressed(), i.pointer.primary_down()));
+        let targets = std::mem::take(&mut self.pointer_targets);
+        let Some(tab) = self.tabs.get(self.active_tab_index) else {
+            return;
+        };
+        let documents: Vec<(Option<FrameId>, Arc<Document>)> = std::iter::once(None)
+            .chain(tab.frames.keys().copied().map(Some))
+            .filter_map(|frame| {
+                let state = match frame {
+                    None => &tab.content_state,
+                    Some(id) => &tab.frames.get(&id)?.content_state,
+                };
+                match state {
+                    ContentState::Loaded { document, .. } => Some((frame, Arc::clone(document))),
+                    _ => None,
                 }
+            })
+            .collect();
+        for (frame, document) in documents {
+            let hovered = targets.get(&frame).copied();
+            // An element stays active until the button is released, wherever the pointer goes
+            let active = match (pressed, down) {
+                (true, _) => hovered,
+                (false, true) => document.active(),
+                (false, false) => None,
+            };
+            // Pressing the mouse focuses what it is on, or takes focus away
+            let focused = if pressed {
+                hovered.and_then(|id| {
+                    let node = document.node(id);
+                    std::iter::once(node)
+                        .chain(node.ancestors())
+                        .find(|node| is_focusable(*node))
+                        .map(|node| node.id())
+                })
+            } else {
+                document.focused()
+            };
+            if hovered != document.hovered() {
+                self.queue_dom_change(frame, move |document| document.set_hovered(hovered));
             }
+            if active != document.active() {
+                self.queue_dom_change(frame, move |document| document.set_active(active));
+            }
+            if focused != document.focused() {
+                self.queue_dom_change(frame, move |document| document.set_focused(focused));
+            }
+        }
+    }
+
+    /// Remember that `element` is under the pointer, unless an element inside it already is.
+    fn track_pointer(&mut self, frame: Option<FrameId>, element: Option<NodeId>, hovered: bool) {
+        if hovered && let Some(element) = element {
+            self.pointer_targets.entry(frame).or_insert(element);
+        }
+    }
+
+    /// The Page Info window: the active page's address and what its `<head>` says about it.
+    fn show_page_info_window(&mut self, ctx: &egui::Context) {
+        let Some(tab) = self.tabs.get(self.active_tab_index) else {
+            return;
+        };
+        let url = match &tab.content_state {
+            ContentState::Loaded { url, .. } => url.as_str(),
+            _ => "",
+        };
+        let info = &tab.info;
+        let mut open = self.show_page_info;
+        let mut link_clicked: Option<String> = None;
+        egui::Window::new("Page Info")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("page_info").num_columns(2).show(ui, |ui| {
+                    ui.label("Title");
+                    ui.label(tab.display_title());
+                    ui.end_row();
+                    ui.label("Address");
+                    ui.label(url);
+                    ui.end_row();
+                    if let Some(canonical) = &info.canonical {
+                        ui.label("Canonical");
+                        if ui.link(canonical).clicked() {
+                            link_clicked = Some(canonical.clone());
+                        }
+                        ui.end_row();
+                    }
+                    for feed in &info.feeds {
+                        ui.label(if feed.atom { "Atom feed" } else { "RSS feed" });
+                        let text = feed.title.as_deref().unwrap_or(&feed.url);
+                        if ui.link(text).on_hover_text(&feed.url).clicked() {
+                            link_clicked = Some(feed.url.clone());
+                        }
+                        ui.end_row();
+                    }
+                    if let Some(icon) = &info.icon {
+                        ui.label("Icon");
+                        ui.label(icon);
+                        ui.end_row();
+                    }
+                    if let Some(viewport) = &info.viewport {
+                        let mut parts = Vec::new();
+                        if let Some(width) = &viewport.width {
+                            parts.push(format!("width {}", width));
+                        }
+                        if let Some(scale) = viewport.initial_scale {
+                            parts.push(format!("initial scale {}", scale));
+                        }
+                        if !viewport.user_scalable {
+                            parts.push("no zooming".to_string());
+                        }
+                        ui.label("Viewport");
+                        ui.label(parts.join(", "));
+                        ui.end_row();
+                    }
+                    if !info.color_schemes.is_empty() {
+                        ui.label("Color scheme");
+                        ui.label(info.color_schemes.join(" "));
+                        ui.end_row();
+                    }
+                    if let Some(refresh) = &info.refresh {
+                        ui.label("Refresh");
+                        ui.label(format!(
+                            "{} after {} s",
+                            refresh.url,
+                            refresh.delay.as_secs()
+                        ));
+                        ui.end_row();
+                    }
+                    for (sheet_url, status) in tab.stylesheets.iter() {
+                        ui.label("Stylesheet");
+                        match status {
+                            SheetStatus::Loading => ui.label(format!("{} (loading)", sheet_url)),
+                            SheetStatus::Loaded(_) => ui.label(sheet_url),
+                            SheetStatus::Failed(error) => ui.colored_label(
+                                egui::Color32::RED,
+                                format!("{} (failed: {})", sheet_url, error),
+                            ),
+                            SheetStatus::Blocked(reason) => ui.colored_label(
+                                egui::Color32::RED,
+                                format!("{} (blocked: {})", sheet_url, reason),
+                            ),
+                        };
+                        ui.end_row();
+                    }
+                });
+            });
+        self.show_page_info = open;
+        if let Some(url) = link_clicked {
+            self.add_new_tab();
+            self.start_loading(self.active_tab_index, url);
+        }
+    }
+
+    // Find tab index by tab ID
+    fn find_tab_index_by_id(&self, tab_id: usize) -> Option<usize> {
+        self.tabs.iter().position(|tab| tab.id == tab_id)
+    }
+}
+
+impl eframe::App for BrowserApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        ctx.set_debug_on_hover(true);
+        // --- Receive Network Results ---
+        self.receive_network_messages(ctx);
+
+        // Follow refreshes whose countdown has run out
+        let now = Instant::now();
+        let due: Vec<(usize, String)> = self
+            .tabs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, tab)| match &tab.refresh {
+                Some((at, url)) if *at <= now => Some((index, url.clone())),
+                _ => None,
+            })
+            .collect();
+        for (index, url) in due {
+            self.start_loading(index, url);
         }
 
         // --- Top Panel: Tab Bar and URL Bar ---
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // Tab Bar
             let mut tab_to_close_index: Option<usize> = None; // Keep track of which tab to close
+            let mut save_requested = false;
 
             // Handle keyboard shortcuts for tab management
             ctx.input_mut(|i| {
@@ -276,6 +1101,12 @@ impl eframe::App for BrowserApp {
                 {
                     tab_to_close_index = Some(self.active_tab_index);
                 }
+                // Ctrl/Cmd + S: Save Page As
+                else if i.consume_key(egui::Modifiers::COMMAND, egui::Key::S)
+                    || i.consume_key(egui::Modifiers::CTRL, egui::Key::S)
+                {
+                    save_requested = true;
+                }
                 // Ctrl/Cmd + Q: Close Browser
                 else if i.consume_key(egui::Modifiers::COMMAND, egui::Key::Q)
                     || i.consume_key(egui::Modifiers::CTRL, egui::Key::Q)
@@ -283,6 +1114,9 @@ impl eframe::App for BrowserApp {
                     ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                 }
             });
+            if save_requested {
+                self.open_save_dialog();
+            }
 
             ui.horizontal(|ui| {
                 // Tabs and New Tab button
@@ -298,6 +1132,20 @@ impl eframe::App for BrowserApp {
                     // Use a nested horizontal layout for the tab title and close button
                     ui.group(|ui| {
                         ui.horizontal(|ui| {
+                            // The favicon, once it has loaded
+                            if let Some(icon) = &tab.info.icon
+                                && let Ok(egui::load::TexturePoll::Ready { texture }) = ctx
+                                    .try_load_texture(
+                                        icon,
+                                        egui::TextureOptions::default(),
+                                        egui::SizeHint::Size(32, 32),
+                                    )
+                            {
+                                ui.add(
+                                    egui::Image::from_texture(texture)
+                                        .fit_to_exact_size(egui::vec2(16.0, 16.0)),
+                                );
+                            }
                             if ui
                                 .selectable_label(self.active_tab_index == index, truncated_title)
                                 .clicked()
@@ -357,6 +1205,15 @@ impl eframe::App for BrowserApp {
                 let mut url_to_load: Option<String> = None;
 
                 ui.horizontal(|ui| {
+                    if matches!(
+                        active_tab.content_state,
+                        ContentState::Loaded {
+                            complete: false,
+                            ..
+                        }
+                    ) {
+                        ui.spinner();
+                    }
                     ui.label("URL:");
                     let input = ui.add(
                         egui::TextEdit::singleline(&mut active_tab.url_input)
@@ -374,8 +1231,35 @@ impl eframe::App for BrowserApp {
                     if ui.button("Go").clicked() {
                         url_to_load = Some(active_tab.url_input.clone());
                     }
+                    if ui
+                        .selectable_label(self.show_page_info, "ℹ")
+                        .on_hover_text("Page Info")
+                        .clicked()
+                    {
+                        self.show_page_info = !self.show_page_info;
+                    }
                 });
 
+                // The countdown of a <meta> refresh, which the user can cancel
+                if let Some((at, url)) = &active_tab.refresh {
+                    let mut cancel = false;
+                    ui.horizontal(|ui| {
+                        let seconds = at.saturating_duration_since(Instant::now()).as_secs_f32();
+                        ui.label(format!(
+                            "This page will go to {} in {} s",
+                            url,
+                            seconds.ceil()
+                        ));
+                        if ui.button("Go now").clicked() {
+                            url_to_load = Some(url.clone());
+                        }
+                        cancel = ui.button("Cancel").clicked();
+                    });
+                    if cancel {
+                        active_tab.refresh = None;
+                    }
+                }
+
                 // Now that the closure (and its borrows) are done, actually start loading:
                 if let Some(url) = url_to_load {
                     self.start_loading(self.active_tab_index, url);
@@ -385,61 +1269,93 @@ impl eframe::App for BrowserApp {
             }
         });
 
-        // --- Central Panel: Content Display for Active Tab ---
-        egui::CentralPanel::default().show(ctx, |ui| {
-            // pull out a reference to the tab once…
-            if let Some(tab) = self.tabs.get_mut(self.active_tab_index) {
-                match &mut tab.content_state {
-                    ContentState::Idle => {
-                        ui.label("Enter a URL above and click 'Go' or press Enter.");
-                        return;
-                    }
-                    ContentState::Loading(url) => {
-                        ui.label(format!("Loading {}...", url));
-                        ui.spinner();
-                        return;
-                    }
-                    ContentState::Error(err) => {
-                        ui.colored_label(egui::Color32::RED, err);
-                        return;
-                    }
-                    ContentState::Loaded { root_node, .. } => {
-                        // Move the children out of the root_node
-                        let mut children = std::mem::take(&mut root_node.children);
-
-                        // Now we can release the borrow of `tab` and reuse `self`
-                        let _ = tab;
+        self.show_page_info_window(ctx);
+        self.show_save_dialog(ctx);
 
-                        for mut body in &mut children {
-                            if let NodeType::Element(HtmlTag::Body) = body.node_type {
-                                let mut initial_context = RenderContext::default();
+        // --- Central Panel: Content Display for Active Tab ---
+        // A page whose <meta name="color-scheme"> only supports dark colors is shown dark
+        let visuals = match self.tabs.get(self.active_tab_index) {
+            Some(tab) if tab.info.dark(ctx.style().visuals.dark_mode) => egui::Visuals::dark(),
+            _ => ctx.style().visuals.clone(),
+        };
+        let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(visuals.panel_fill);
+        egui::CentralPanel::default()
+            .frame(panel_frame)
+            .show(ctx, |ui| {
+                *ui.visuals_mut() = visuals;
+                // pull out a reference to the tab once…
+                if let Some(tab) = self.tabs.get_mut(self.active_tab_index) {
+                    match &mut tab.content_state {
+                        ContentState::Idle => {
+                            ui.label("Enter a URL above and click 'Go' or press Enter.");
+                        }
+                        ContentState::Loading(url) => {
+                            ui.label(format!("Loading {}...", url));
+                            ui.spinner();
+                        }
+                        ContentState::Error(err) => {
+                            ui.colored_label(egui::Color32::RED, err);
+                        }
+                        ContentState::Loaded { url, document, .. } => {
+                            // Media queries follow the window's size and the theme; this
+                            // restyles only if one of them flips
+                            let media = media_environment(ui);
+                            if *document.media() != media {
+                                Arc::make_mut(document).set_media(media);
+                            }
+                            // Take a snapshot of the document so we can release the borrow of
+                            // `tab` and reuse `self` while rendering
+                            let document = Arc::clone(document);
+
+                            if let Some(body) = document.body() {
+                                let mut initial_context = RenderContext {
+                                    base_url: Rc::from(url.as_str()),
+                                    ..Default::default()
+                                };
                                 egui::ScrollArea::vertical().show(ui, |ui| {
-                                    render_node(self, ui, ctx, &mut body, &mut initial_context);
+                                    render_node(self, ui, ctx, body, &mut initial_context);
                                     ui.allocate_space(ui.available_size());
                                 });
                             }
                         }
-
-                        // Put the children back into root_node
-                        if let Some(tab) = self.tabs.get_mut(self.active_tab_index) {
-                            if let ContentState::Loaded { root_node, .. } = &mut tab.content_state {
-                                root_node.children = children;
-                            }
-                        }
                     }
+                } else {
+                    ui.label("No tabs open.");
                 }
-            } else {
-                ui.label("No tabs open.");
-            }
-        });
+            });
 
-        // Request repaint if any tab is loading
-        if self
+        self.update_element_states(ctx);
+        self.apply_dom_changes(ctx);
+
+        // Request repaint if any tab or frame is loading
+        let loading = |state: &ContentState| {
+            matches!(
+                state,
+                ContentState::Loading(_)
+                    | ContentState::Loaded {
+                        complete: false,
+                        ..
+                    }
+            )
+        };
+        if self.tabs.iter().any(|tab| {
+            loading(&tab.content_state)
+                || tab
+                    .frames
+                    .values()
+                    .any(|frame| loading(&frame.content_state))
+        }) {
+            ctx.request_repaint();
+        }
+        // Keep refresh countdowns ticking
+        if let Some(next) = self
             .tabs
             .iter()
-            .any(|tab| matches!(tab.content_state, ContentState::Loading(_)))
+            .filter_map(|tab| tab.refresh.as_ref())
+            .map(|(at, _)| at.saturating_duration_since(Instant::now()))
+            .min()
         {
-            ctx.request_repaint();
+            ctx.request_repaint_after(next.min(Duration::from_secs(1)));
         }
     }
 }
@@ -448,721 +1364,1918 @@ impl eframe::App for BrowserApp {
 
 #[derive(Clone, Debug)]
 struct RenderContext {
-    text_color: Option<layout::Color>,
-    font_size: f32,
-    bold: bool,
+    style: Arc<ComputedStyle>, // of the innermost element or pseudo-element being drawn
     week: bool,
-    italic: bool,
-    strikethrough: bool,
-    underline: bool,
-    text_style: Option<egui::TextStyle>,
-    font_family: Option<egui::FontFamily>,
+    background: Option<layout::Color>, // highlight behind inline text
+    baseline_shift: f32,               // raised (< 0) or lowered (> 0) text, in points
     href: Option<String>,
-    abbr: Option<String>,
+    target: Option<String>, // where the link opens: `_blank`, `_top`, `_parent`, or here
+    base_url: Rc<str>,      // what relative links and image sources are resolved against
+    frame: Option<FrameId>, // the <iframe> whose page is drawn; `None` for the tab's page
+    element: Option<NodeId>, // the innermost element being drawn, for hover tracking
+    title: Option<String>,  // hover text
+    // The `::first-letter` and `::first-line` declarations of the blocks around, until
+    // their first letter and line have been drawn
+    first_letter: PendingStyle,
+    first_line: PendingStyle,
+    list_marker: Option<(ListMarker, bool)>, // set by the list for its next <li>; bool = inside
+    // Shared by all text in the current line: true at the start of the line or right after
+    // collapsible whitespace, so the next space is dropped even across element boundaries
+    after_space: Rc<Cell<bool>>,
+}
+
+/// Declarations shared by the render contexts of a block, taken by the first text that
+/// uses them.
+type PendingStyle = Rc<RefCell<Option<Rc<HashMap<String, layout::StyleProperty>>>>>;
+
+/// Apply `white-space` processing to the text of one text node. `after_space` carries the
+/// state over from the text before it in the same line (see `RenderContext::after_space`).
+fn collapse_whitespace(text: &str, mode: WhiteSpace, after_space: &mut bool) -> String {
+    if let WhiteSpace::Pre | WhiteSpace::PreWrap = mode {
+        if !text.is_empty() {
+            // Preserved spaces don't swallow the ones after them, but a new line still starts
+            *after_space = text.ends_with('\n');
+        }
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\n' && mode == WhiteSpace::PreLine {
+            // Spaces before a kept line break are removed
+            while out.ends_with(' ') {
+                out.pop();
+            }
+            out.push('\n');
+            *after_space = true;
+        } else if c.is_ascii_whitespace() {
+            if !*after_space {
+                out.push(' ');
+                *after_space = true;
+            }
+        } else {
+            out.push(c);
+            *after_space = false;
+        }
+    }
+    out
+}
+
+/// Marker drawn in front of a list item.
+#[derive(Clone, Debug)]
+enum ListMarker {
+    None,
+    Disc,
+    Circle,
+    Square,
+    Text(String), // "1.", "b.", "iv.", ...
+}
+
+impl RenderContext {
+    /// The color of text and markers: the computed `color`, or the theme's. Invisible
+    /// (`visibility: hidden`) content is drawn transparent, so it still takes its space.
+    fn text_color(&self, ui: &egui::Ui) -> egui::Color32 {
+        if !self.style.is_visible() {
+            return egui::Color32::TRANSPARENT;
+        }
+        self.style
+            .color
+            .as_ref()
+            .map_or(ui.visuals().text_color(), layout::Color::to_ecolor)
+    }
 }
 
 impl Default for RenderContext {
     fn default() -> Self {
         RenderContext {
-            text_color: None,
-            font_size: BASE_SIZE,
-            bold: false,
+            style: Arc::new(ComputedStyle::default()),
             week: false,
-            italic: false,
-            strikethrough: false,
-            underline: false,
-            text_style: None,
-            font_family: None,
+            background: None,
+            baseline_shift: 0.0,
             href: None,
-            abbr: None,
+            target: None,
+            base_url: Rc::from(""),
+            frame: None,
+            element: None,
+            title: None,
+            first_letter: Rc::new(RefCell::new(None)),
+            first_line: Rc::new(RefCell::new(None)),
+            list_marker: None,
+            after_space: Rc::new(Cell::new(true)),
         }
     }
 }
 
-/// Decide which tags count as “inline” (i.e. should live
-/// in a horizontal buffer).  Here we treat raw text
-/// and formatting tags (B, I, U, S, W, etc.) as inline;
-/// everything else (e.g. DIV, P, custom blocks) is block.
-fn is_inline(node: &HtmlNode) -> bool {
-    match &node.node_type {
-        NodeType::Text(_) => true,
-        NodeType::Element(tag) => {
-            for (property_name, properties) in node.style.clone() {
-                if property_name == "display" {
-                    if let layout::StyleProperty::Keyword(display) = properties {
-                        if display == "block" {
-                            return false;
-                        } else if display == "inline" {
-                            return true;
-                        }
-                    }
-                }
-            }
-            matches!(
-                tag,
-                HtmlTag::B
-                    | HtmlTag::I
-                    | HtmlTag::U
-                    | HtmlTag::S
-                    | HtmlTag::W
-                    | HtmlTag::A
-                    | HtmlTag::Br
-                    | HtmlTag::Span
-                    | HtmlTag::Strong
-                    | HtmlTag::Em
-                    | HtmlTag::Abbr
-                    | HtmlTag::Small
-                    | HtmlTag::Big
-                    | HtmlTag::Img
-            )
-        }
-    }
+/// Whether a node lives in a line of text rather than in a box of its own, by its
+/// `display`. The defaults of the tags that are blocks come from `ua.css`.
+fn is_inline(node: NodeRef) -> bool {
+    node.computed.is_inline()
 }
 
-fn set_node<'a>(
-    browser: &'a mut BrowserApp,
+fn set_node(
+    browser: &mut BrowserApp,
     ui: &mut egui::Ui,
     egui_ctx: &egui::Context,
-    node: &'a mut HtmlNode,
+    node: NodeRef,
     context: &mut RenderContext,
 ) -> egui::Frame {
-    // Initialize mutable frame properties
-    let mut inner_margin = egui::Margin::default();
-    let mut outer_margin = egui::Margin::default();
-    let mut stroke = egui::Stroke::NONE;
-    let mut rounding = egui::CornerRadius::ZERO;
-    let mut fill = egui::Color32::TRANSPARENT;
+    let frame = apply_style(ui, &node.computed, is_inline(node), context);
 
-    // Process styles before matching node type
-    for (property_name, properties) in node.style.clone() {
-        match property_name.as_str() {
-            "text-color" | "color" => {
-                if let layout::StyleProperty::Color(color) = properties {
-                    context.text_color = Some(color.clone());
-                }
-            }
-            "padding" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    let value = len.to_egui_value(context.font_size, ui.available_size().x);
-                    inner_margin = egui::Margin::same(value as i8);
-                }
-            }
-            "padding-top" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    inner_margin.top =
-                        len.to_egui_value(context.font_size, ui.available_size().y) as i8;
-                }
-            }
-            "padding-bottom" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    inner_margin.bottom =
-                        len.to_egui_value(context.font_size, ui.available_size().y) as i8;
-                }
-            }
-            "padding-left" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    inner_margin.left =
-                        len.to_egui_value(context.font_size, ui.available_size().x) as i8;
-                }
-            }
-            "padding-right" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    inner_margin.right =
-                        len.to_egui_value(context.font_size, ui.available_size().x) as i8;
-                }
-            }
-            "margin" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    let value = len.to_egui_value(context.font_size, ui.available_size().x);
-                    outer_margin = egui::Margin::same(value as i8);
-                }
-            }
-            "margin-top" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    outer_margin.top =
-                        len.to_egui_value(context.font_size, ui.available_size().y) as i8;
-                }
-            }
-            "margin-bottom" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    outer_margin.bottom =
-                        len.to_egui_value(context.font_size, ui.available_size().y) as i8;
-                }
-            }
-            "margin-left" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    outer_margin.left =
-                        len.to_egui_value(context.font_size, ui.available_size().x) as i8;
-                }
-            }
-            "margin-right" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    outer_margin.right =
-                        len.to_egui_value(context.font_size, ui.available_size().x) as i8;
-                }
-            }
-            "border-width" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    stroke.width = len.to_egui_value(context.font_size, ui.available_size().x);
-                }
-            }
-            "border-color" => {
-                if let layout::StyleProperty::Color(color) = properties {
-                    stroke.color = color.to_ecolor();
-                }
-            }
-            "border-radius" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    let radius = len.to_egui_value(context.font_size, ui.available_size().x);
-                    rounding = egui::CornerRadius::same(radius as u8);
-                }
-            }
-            "border-radius-ne" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    let radius = len.to_egui_value(context.font_size, ui.available_size().x);
-                    rounding.ne = radius as u8;
-                }
-            }
-            "border-radius-nw" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    let radius = len.to_egui_value(context.font_size, ui.available_size().x);
-                    rounding.nw = radius as u8;
-                }
-            }
-            "border-radius-se" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    let radius = len.to_egui_value(context.font_size, ui.available_size().x);
-                    rounding.se = radius as u8;
-                }
-            }
-            "border-radius-sw" => {
-                if let layout::StyleProperty::Length(len) = properties {
-                    let radius = len.to_egui_value(context.font_size, ui.available_size().x);
-                    rounding.sw = radius as u8;
-                }
-            }
-            "background-color" => {
-                if let layout::StyleProperty::Color(color) = properties {
-                    fill = color.to_ecolor();
-                }
-            }
-            "text-decoration" => {
-                if let layout::StyleProperty::Keyword(keyword) = properties {
-                    if keyword == "underline" {
-                        context.underline = true;
-                    } else if keyword == "nounderline" {
-                        context.underline = false;
-                    } else if keyword == "strikethrough" {
-                        context.strikethrough = true;
-                    } else if keyword == "nostrikethrough" {
-                        context.strikethrough = false;
-                    }
-                }
-            }
-            "font-weight" => {
-                if let layout::StyleProperty::Keyword(keyword) = properties {
-                    if keyword == "bold" {
-                        context.bold = true;
-                    } else if keyword == "bolder" {
-                        context.font_size *= 1.2;
-                    } else if keyword == "lighter" {
-                        context.font_size *= 0.8;
-                    }
-                }
-            }
-            "font-style" => {
-                if let layout::StyleProperty::Keyword(keyword) = properties {
-                    if keyword == "normal" {
-                        context.bold = false;
-                        context.italic = false;
-                        context.underline = false;
-                        context.strikethrough = false;
-                    } else if keyword == "italic" {
-                        context.italic = true;
-                    } else if keyword == "bold" {
-                        context.bold = true;
-                    } else if keyword == "underline" {
-                        context.underline = true;
-                    } else if keyword == "strikethrough" {
-                        context.strikethrough = true;
-                    }
-                }
-            }
-            _ => {
-                // Unhandled property
-            }
-        }
+    if node.is_element() {
+        context.element = Some(node.id());
     }
 
-    // Re-create the frame with calculated properties
-    let frame = egui::Frame::default()
-        .inner_margin(inner_margin)
-        .outer_margin(outer_margin)
-        .stroke(stroke)
-        .corner_radius(rounding)
-        .fill(fill);
+    // Any element can describe itself with a title, shown when hovering its text
+    if let Some(title) = node.attributes.get("title") {
+        context.title = Some(title.clone());
+    }
 
     match &node.node_type {
         NodeType::Text(text) => {
-            let mut rich = egui::RichText::new(text).size(context.font_size);
-            if context.bold {
-                rich = rich.strong();
-            }
-            if context.week {
-                rich = rich.weak();
-            }
-            if context.italic {
-                rich = rich.italics();
-            }
-            if context.underline {
-                rich = rich.underline();
-            }
-            if context.strikethrough {
-                rich = rich.strikethrough();
-            }
-            if let Some(ts) = &context.text_style {
-                rich = rich.text_style(ts.clone());
-            }
-            if let Some(ff) = &context.font_family {
-                rich = rich.family(ff.clone());
-            }
-            if let Some(c) = &context.text_color {
-                rich = rich.color(c.clone().to_ecolor());
-            }
-            let mut label = egui::Label::new(rich);
-            if let Some(_) = &context.href {
-                label = label.sense(egui::Sense::click());
-            }
-            let mut response = ui.add(label);
-            if let Some(href) = &context.href {
-                response = response.on_hover_cursor(egui::CursorIcon::PointingHand);
-                if response.clicked() {
-                    browser.add_new_tab();
-                    browser.start_loading(browser.active_tab_index, href.clone());
-                }
-            }
-            if let Some(title) = &context.abbr {
-                response.on_hover_text(title);
+            let mut after_space = context.after_space.get();
+            let text = collapse_whitespace(text, context.style.white_space, &mut after_space);
+            context.after_space.set(after_space);
+            if !text.is_empty() {
+                add_content_text(browser, ui, &text, context);
             }
         }
-        NodeType::Element(HtmlTag::Br) => ui.end_row(),
+        NodeType::Element(HtmlTag::Br) => {
+            ui.end_row();
+            context.after_space.set(true);
+            context.first_line.take();
+        }
         NodeType::Element(HtmlTag::Hr) => {
             ui.separator();
         }
-        NodeType::Element(HtmlTag::Big) => {
-            context.font_size *= 1.2;
-        }
-        NodeType::Element(HtmlTag::Small) => {
-            context.font_size *= 0.8;
-        }
         NodeType::Element(HtmlTag::W) => context.week = true,
-        NodeType::Element(HtmlTag::Strong | HtmlTag::B) => context.bold = true,
-        NodeType::Element(HtmlTag::Em | HtmlTag::I) => context.italic = true,
-        NodeType::Element(HtmlTag::S) => context.strikethrough = true,
-        NodeType::Element(HtmlTag::U) => context.underline = true,
         NodeType::Element(HtmlTag::A) => {
             if let Some(href) = node.attributes.get("href") {
-                context.text_color = Some(layout::Color::Rgb(127, 127, 255));
-                context.underline = true;
-                context.href = Some(href.clone());
+                let href = resolve_url(context, href);
+                // Links to pages loaded before are `:visited`
+                let visited = browser.history.contains(&href);
+                if visited != node.document().is_visited(node.id()) {
+                    let id = node.id();
+                    browser.queue_dom_change(context.frame, move |document| {
+                        document.set_visited(id, visited)
+                    });
+                }
+                context.href = Some(href);
+                context.target = node.attributes.get("target").cloned();
             }
         }
-        NodeType::Element(HtmlTag::Abbr) => {
-            if let Some(title) = node.attributes.get("title") {
-                context.abbr = Some(title.clone());
+        NodeType::Element(HtmlTag::Time) => {
+            // Show the machine-readable date unless there is a title to show
+            if let Some(datetime) = node.attributes.get("datetime")
+                && !node.attributes.contains_key("title")
+            {
+                context.title = Some(datetime.clone());
             }
         }
         NodeType::Element(HtmlTag::Img) => {
-            if let Some(src) = node.attributes.get("src") {
-                // Get the image from the network
-                let mut image =
-                    egui::Image::new(egui::ImageSource::Uri(std::borrow::Cow::Owned(src.clone())));
-                // Try parsing width and height from attributes
-                let width = node
-                    .attributes
-                    .get("width")
-                    .and_then(|w| w.parse::<f32>().ok());
-                let height = node
-                    .attributes
-                    .get("height")
-                    .and_then(|h| h.parse::<f32>().ok());
-
-                // Get the original size to compute aspect ratio if needed
-                if let Some(original_size) = image.size() {
-                    image = image.fit_to_exact_size(match (width, height) {
-                        (Some(w), Some(h)) => egui::Vec2::new(w, h),
-                        (Some(w), None) => {
-                            let h = w * original_size.y / original_size.x;
-                            egui::Vec2::new(w, h)
-                        }
-                        (None, Some(h)) => {
-                            let w = h * original_size.x / original_size.y;
-                            egui::Vec2::new(w, h)
-                        }
-                        (None, None) => original_size,
-                    });
-                } else {
-                    image = image.fit_to_original_size(1.);
-                }
-
-                // Apply hover sense if there’s an alt or href attribute
-                let has_title =
-                    node.attributes.contains_key("alt") || node.attributes.contains_key("title");
-                let is_clickable = context.href.is_some();
-
-                if is_clickable {
-                    image = image.sense(egui::Sense::click()); // ::click() senses both click & hover
-                } else if has_title {
-                    image = image.sense(egui::Sense::hover());
-                }
-
-                let mut response = ui.add(image);
-
-                if let Some(title) = node.attributes.get("title") {
-                    response = response.on_hover_text(egui::RichText::new(title));
-                } else if let Some(alt) = node.attributes.get("alt") {
-                    response = response.on_hover_text(egui::RichText::new(alt));
-                }
-
-                // Handle clicking the image like an anchor
-                if let Some(href) = &context.href {
-                    response = response.on_hover_cursor(egui::CursorIcon::PointingHand);
-                    if response.clicked() {
-                        browser.add_new_tab();
-                        browser.start_loading(browser.active_tab_index, href.clone());
-                    }
-                }
-            }
+            show_if_visible(ui, context, |ui| render_image(browser, ui, node, context));
         }
         NodeType::Element(HtmlTag::Audio) => {
-            if let Some(src) = node.attributes.get("src") {
-                let api = "audio player id".to_string();
-                if let None = node.attributes.get(&api) {
-                    if let Ok(audio_player) = AudioPlayer::new(
+            if let Some(src) = node.attributes.get("src")
+                && let Some(tab) = browser.tabs.get_mut(browser.active_tab_index)
+                && let Some((_, players, _)) = tab.context_mut(context.frame)
+            {
+                if !players.contains_key(&node.id())
+                    && let Ok(audio_player) = AudioPlayer::new(
                         src.clone(),
                         node.attributes.contains_key("autoplay"),
                         node.attributes.contains_key("loop"),
                         node.attributes.contains_key("controls"),
-                    ) {
-                        node.attributes.insert(api.clone(), audio_player.id.clone());
-                        if let Some(tab) = browser.tabs.get_mut(browser.active_tab_index) {
-                            tab.audio_player
-                                .insert(audio_player.id.clone(), audio_player);
-                        }
-                    }
+                    )
+                {
+                    players.insert(node.id(), audio_player);
                 }
-                if let Some(Some(audio_player)) = node.attributes.get(&api).map(|id| {
-                    if let Some(tab) = browser.tabs.get_mut(browser.active_tab_index) {
-                        return tab.audio_player.get(id);
-                    }
-                    None
-                }) {
+                if let Some(audio_player) = players.get(&node.id()) {
                     audio_player.ui(ui, egui_ctx);
                 }
             }
         }
         NodeType::Element(HtmlTag::Table) => {
-            let id: &String;
-            if node.attributes.contains_key("--id--") {
-                if let Some(id_) = node.attributes.get("--id--") {
-                    id = id_;
-                } else {
-                    panic!()
-                }
-            } else {
-                node.attributes
-                    .insert("--id--".to_string(), layout::get_next_id().to_string());
-                if let Some(id_) = node.attributes.get("--id--") {
-                    id = id_;
-                } else {
-                    panic!()
-                }
-            };
-            // 1. Extract (and remove) any <caption> child
-            let mut caption_node: Option<&mut HtmlNode> = None;
-            // 2. Gather all <tr>, <thead>, <tbody>, <tfoot> children into `row_containers`
-            let mut row_containers: Vec<&mut HtmlNode> = Vec::new();
-
-            // Partition children into caption vs. row containers
-            for child in &mut node.children {
-                match child.node_type {
-                    NodeType::Element(HtmlTag::Caption) => {
-                        // We only keep the first caption; if you have multiple captions you can adapt as needed
-                        if caption_node.is_none() {
-                            caption_node = Some(child);
-                        }
-                    }
-                    NodeType::Element(HtmlTag::Tr)
-                    | NodeType::Element(HtmlTag::Thead)
-                    | NodeType::Element(HtmlTag::Tbody)
-                    | NodeType::Element(HtmlTag::Tfoot) => {
-                        row_containers.push(child);
-                    }
-                    _ => {
-                        // ignore anything else (e.g. comment nodes, whitespace, etc.)
-                    }
-                }
-            }
-
-            // 3. Determine column count by looking at the first <tr> we can find
-            let mut max_column_count = 0;
-            for container in &row_containers {
-                match container.node_type {
-                    NodeType::Element(HtmlTag::Tr) => {
-                        let column_count = container
-                            .children
-                            .iter()
-                            .filter(|c| {
-                                matches!(
-                                    c.node_type,
-                                    NodeType::Element(HtmlTag::Th) | NodeType::Element(HtmlTag::Td)
-                                )
-                            })
-                            .count();
-                        if column_count > max_column_count {
-                            max_column_count = column_count;
-                        }
-                    }
-                    NodeType::Element(HtmlTag::Thead)
-                    | NodeType::Element(HtmlTag::Tbody)
-                    | NodeType::Element(HtmlTag::Tfoot) => {
-                        for sub in &container.children {
-                            if let NodeType::Element(HtmlTag::Tr) = sub.node_type {
-                                let column_count = sub
-                                    .children
-                                    .iter()
-                                    .filter(|c| {
-                                        matches!(
-                                            c.node_type,
-                                            NodeType::Element(HtmlTag::Th)
-                                                | NodeType::Element(HtmlTag::Td)
-                                        )
-                                    })
-                                    .count();
-                                if column_count > max_column_count {
-                                    max_column_count = column_count;
-                                }
-                            }
-                        }
-                    }
-                    _ => {}
-                }
-            }
-
-            // 4. Build a TableBuilder with `column_count` columns.
-            let mut table = egui_extras::TableBuilder::new(ui)
-                .id_salt(id)
-                .cell_layout(egui::Layout::left_to_right(egui::Align::Center));
-
-            table = table.columns(egui_extras::Column::remainder(), max_column_count);
-
-            table.body(|mut body| {
-                // 5. For each row container, dig into its <tr> children, then render each <th> or <td>
-                for container in row_containers {
-                    match container.node_type {
-                        NodeType::Element(HtmlTag::Tr) => {
-                            // Direct <tr> -> render it as one row
-                            body.row(24.0, |mut row_ui| {
-                                for cell in &mut container.children {
-                                    if matches!(
-                                        cell.node_type,
-                                        NodeType::Element(HtmlTag::Th)
-                                            | NodeType::Element(HtmlTag::Td)
-                                    ) {
-                                        row_ui.col(|ui| {
-                                            // Render the contents of <th> or <td>:
-                                            render_node(
-                                                browser,
-                                                ui,
-                                                egui_ctx,
-                                                &mut cell.clone(),
-                                                &mut context.clone(),
-                                            );
-                                        });
-                                    }
-                                }
-                            });
-                        }
-                        NodeType::Element(HtmlTag::Thead)
-                        | NodeType::Element(HtmlTag::Tbody)
-                        | NodeType::Element(HtmlTag::Tfoot) => {
-                            // If we see a <thead>/<tbody>/<tfoot>, look for nested <tr> children
-                            for sub in &mut container.children {
-                                if let NodeType::Element(HtmlTag::Tr) = sub.node_type {
-                                    body.row(24.0, |mut row_ui| {
-                                        for cell in &mut sub.children {
-                                            if matches!(
-                                                cell.node_type,
-                                                NodeType::Element(HtmlTag::Th)
-                                                    | NodeType::Element(HtmlTag::Td)
-                                            ) {
-                                                row_ui.col(|ui| {
-                                                    render_node(
-                                                        browser,
-                                                        ui,
-                                                        egui_ctx,
-                                                        cell,
-                                                        &mut context.clone(),
-                                                    );
-                                                });
-                                            }
-                                        }
-                                    });
-                                }
-                            }
-                        }
-                        _ => {
-                            // ignore any other tags inside table
-                        }
-                    }
-                }
+            frame.show(ui, |ui| render_table(browser, ui, egui_ctx, node, context));
+        }
+        NodeType::Element(HtmlTag::Svg) => {
+            frame.show(ui, |ui| {
+                show_if_visible(ui, context, |ui| render_svg(browser, ui, node, context))
             });
-
-            // 6. Finally, if there was a <caption>, render it below the table
-            if let Some(mut cap_node) = caption_node {
-                render_node(browser, ui, egui_ctx, &mut cap_node, context);
-            }
         }
-        NodeType::Element(tag) => {
-            let scale = match tag {
-                HtmlTag::H1 => Some(2.0),
-                HtmlTag::H2 => Some(1.8),
-                HtmlTag::H3 => Some(1.6),
-                HtmlTag::H4 => Some(1.4),
-                HtmlTag::H5 => Some(1.2),
-                HtmlTag::H6 => Some(1.1),
-                _ => None,
-            };
-
-            if let Some(scale) = scale {
-                context.text_style = Some(egui::TextStyle::Heading);
-                context.font_size *= scale;
-            }
+        NodeType::Element(HtmlTag::Iframe) => {
+            frame.show(ui, |ui| {
+                show_if_visible(ui, context, |ui| {
+                    render_iframe(browser, ui, egui_ctx, node, context)
+                })
+            });
         }
+        NodeType::Element(_) => {}
     }
 
     frame
 }
 
-fn render_node<'a>(
-    browser: &'a mut BrowserApp,
-    ui: &mut egui::Ui,
-    egui_ctx: &egui::Context,
-    node: &'a mut HtmlNode,
+/// Draw an element or pseudo-element with its computed style: text inside it takes its
+/// style from `context`, and the box it describes is returned. Inline boxes highlight their
+/// text with their background instead.
+fn apply_style(
+    ui: &egui::Ui,
+    style: &Arc<ComputedStyle>,
+    inline: bool,
     context: &mut RenderContext,
-) {
-    let frame = set_node(browser, ui, egui_ctx, node, context);
-    if let NodeType::Element(HtmlTag::Table) = node.node_type {
-        return;
+) -> egui::Frame {
+    context.style = style.clone();
+    let font_size = style.font_size;
+    // Percentages are of the width of the containing block, vertical ones included
+    let width = ui.available_size().x;
+    let length = |length: &layout::Length| length.to_egui_value(font_size, width);
+    let margin = |edges: &style::Edges| egui::Margin {
+        left: length(&edges.left) as i8,
+        right: length(&edges.right) as i8,
+        top: length(&edges.top) as i8,
+        bottom: length(&edges.bottom) as i8,
+    };
+
+    match &style.vertical_align {
+        VerticalAlign::Baseline => {}
+        VerticalAlign::Super => context.baseline_shift -= font_size * 0.4,
+        VerticalAlign::Sub => context.baseline_shift += font_size * 0.3,
+        VerticalAlign::Length(len) => {
+            context.baseline_shift -= len.to_egui_value(font_size, font_size)
+        }
     }
 
-    if frame != egui::Frame::default() {
-        frame.show(ui, |ui| {
-            ui.vertical(|ui| {
-                let mut i = 0;
-                while i < node.children.len() {
-                    if is_inline(&node.children[i]) {
-                        let start = i;
-                        while i < node.children.len() && is_inline(&node.children[i]) {
-                            i += 1;
-                        }
-                        let old_item_spacing = ui.style().spacing.item_spacing;
-                        ui.style_mut().spacing.item_spacing.x = 7.;
-                        ui.horizontal_wrapped(|ui| {
-                            for child in &mut node.children[start..i] {
-                                let mut context = context.clone();
-                                render_inline(browser, ui, egui_ctx, child, &mut context);
-                            }
-                        });
-                        ui.style_mut().spacing.item_spacing = old_item_spacing;
-                    } else {
-                        let mut context = context.clone();
-                        render_node(browser, ui, egui_ctx, &mut node.children[i], &mut context);
-                        i += 1;
-                    }
-                }
-            });
-        });
-    } else {
-        let mut i = 0;
-        while i < node.children.len() {
-            if is_inline(&node.children[i]) {
-                let start = i;
-                while i < node.children.len() && is_inline(&node.children[i]) {
-                    i += 1;
-                }
-                let old_item_spacing = ui.style().spacing.item_spacing;
-                ui.style_mut().spacing.item_spacing.x = 7.;
-                ui.horizontal_wrapped(|ui| {
-                    for child in &mut node.children[start..i] {
-                        let mut context = context.clone();
-                        render_inline(browser, ui, egui_ctx, child, &mut context);
-                    }
-                });
-                ui.style_mut().spacing.item_spacing = old_item_spacing;
-            } else {
-                let mut context = context.clone();
-                render_node(browser, ui, egui_ctx, &mut node.children[i], &mut context);
-                i += 1;
-            }
+    let mut fill = egui::Color32::TRANSPARENT;
+    if let Some(color) = &style.background_color {
+        // Inline elements highlight their text instead of drawing a box
+        if inline {
+            context.background = Some(color.clone());
+        } else {
+            fill = color.to_ecolor();
         }
     }
+    let mut stroke = egui::Stroke::new(
+        length(&style.border_width),
+        style
+            .border_color
+            .as_ref()
+            .map_or(egui::Color32::TRANSPARENT, layout::Color::to_ecolor),
+    );
+    if !style.is_visible() {
+        fill = egui::Color32::TRANSPARENT;
+        stroke = egui::Stroke::NONE;
+    }
+    let radius = &style.border_radius;
+    egui::Frame::default()
+        .inner_margin(margin(&style.padding))
+        .outer_margin(margin(&style.margin))
+        .stroke(stroke)
+        .corner_radius(egui::CornerRadius {
+            nw: length(&radius.nw) as u8,
+            ne: length(&radius.ne) as u8,
+            sw: length(&radius.sw) as u8,
+            se: length(&radius.se) as u8,
+        })
+        .fill(fill)
+}
+
+/// Draw replaced content (an image, `<svg>` or `<iframe>`), or with `visibility: hidden`
+/// only take its space.
+fn show_if_visible<R>(
+    ui: &mut egui::Ui,
+    context: &RenderContext,
+    add_contents: impl FnOnce(&mut egui::Ui) -> R,
+) -> R {
+    if context.style.is_visible() {
+        add_contents(ui)
+    } else {
+        ui.scope(|ui| {
+            ui.set_invisible();
+            add_contents(ui)
+        })
+        .inner
+    }
 }
 
-fn render_inline<'a>(
-    browser: &'a mut BrowserApp,
+/// The computed style of a pseudo-element from its declarations, inheriting from the
+/// element (or, for `::first-line` and `::first-letter`, the text) `context` is drawing.
+fn pseudo_style(
+    declarations: &HashMap<String, layout::StyleProperty>,
+    context: &RenderContext,
+) -> Arc<ComputedStyle> {
+    Arc::new(ComputedStyle::compute(declarations, Some(&context.style)))
+}
+
+/// Lay out a `<table>`. Columns are as wide as their content (or their `<col>` or `width`)
+/// and shrink to fit the available width; rows are as tall as their tallest cell.
+fn render_table(
+    browser: &mut BrowserApp,
     ui: &mut egui::Ui,
     egui_ctx: &egui::Context,
-    node: &'a mut HtmlNode,
-    context: &mut RenderContext,
+    node: NodeRef,
+    context: &RenderContext,
 ) {
-    let frame = set_node(browser, ui, egui_ctx, node, context);
-    if let NodeType::Element(HtmlTag::Table) = node.node_type {
-        return;
+    let grid = TableGrid::new(node);
+    let collapse = node.computed.border_collapse;
+    let spacing = match node.style.get("border-spacing") {
+        _ if collapse => 0.0,
+        Some(layout::StyleProperty::Length(len)) => len.to_egui_value(context.style.font_size, 0.0),
+        _ => BORDER_SPACING,
+    };
+    // The legacy `border` attribute outlines the table and every cell
+    let border = node
+        .get_attribute("border")
+        .map(|border| border.trim().parse().unwrap_or(1.0))
+        .unwrap_or(0.0);
+    let border_color = ui.visuals().widgets.noninteractive.bg_stroke.color;
+    let caption_bottom = grid
+        .caption
+        .is_some_and(|caption| caption.computed.caption_side == CaptionSide::Bottom);
+
+    if let Some(caption) = grid.caption
+        && !caption_bottom
+    {
+        render_node(browser, ui, egui_ctx, caption, &mut context.clone());
     }
 
-    if frame != egui::Frame::default() {
-        frame.show(ui, |ui| {
-            ui.horizontal_wrapped(|ui| {
-                let mut i = 0;
-                while i < node.children.len() {
-                    if is_inline(&node.children[i]) {
-                        let mut context = context.clone();
-                        render_inline(browser, ui, egui_ctx, &mut node.children[i], &mut context);
-                        i += 1;
-                    } else {
-                        let start = i;
-                        while i < node.children.len() && !is_inline(&node.children[i]) {
-                            i += 1;
-                        }
-                        ui.vertical(|ui| {
-                            for child in &mut node.children[start..i] {
-                                let mut context = context.clone();
-                                render_node(browser, ui, egui_ctx, child, &mut context);
-                            }
-                        });
-                    }
-                }
-            });
+    // Cells inherit the text formatting of their row group and row
+    let rows: Vec<(RenderContext, egui::Frame)> = grid
+        .rows
+        .iter()
+        .map(|row| {
+            let mut row_context = context.clone();
+            if let Some(group) = row.group {
+                let _frame = set_node(browser, ui, egui_ctx, group, &mut row_context);
+            }
+            let frame = set_node(browser, ui, egui_ctx, row.node, &mut row_context);
+            (row_context, frame)
+        })
+        .collect();
+    let columns = grid.column_count;
+    let available = ui.available_width();
+    let table_width = node
+        .dimension("width")
+        .map(|width| width.to_egui_value(context.style.font_size, available));
+    let base_width = table_width.unwrap_or(available);
+
+    // Widths cells needed last frame beyond what they were given (images, long words)
+    let memory_id = egui::Id::new((node.document().id(), node.id(), "table"));
+    let mut floors: Vec<f32> = ui.data(|data| data.get_temp(memory_id)).unwrap_or_default();
+    floors.resize(columns, 0.0);
+
+    // Column widths from <col>, then from cells that ask for one
+    let mut fixed: Vec<Option<f32>> = grid
+        .column_widths
+        .iter()
+        .map(|width| {
+            width
+                .as_ref()
+                .map(|w| w.to_egui_value(context.style.font_size, base_width))
+        })
+        .collect();
+    for cell in grid.cells.iter().filter(|cell| cell.column_span == 1) {
+        if grid.column_widths[cell.column].is_none()
+            && let Some(width) = cell.node.dimension("width")
+        {
+            let width = width.to_egui_value(context.style.font_size, base_width);
+            fixed[cell.column] = Some(fixed[cell.column].map_or(width, |old| old.max(width)));
+        }
+    }
+
+    // Measure how wide every cell would like to be; spanning cells widen their columns evenly
+    let mut natural = vec![0.0f32; columns];
+    let mut spanning = Vec::new();
+    for cell in &grid.cells {
+        let rect =
+            egui::Rect::from_min_size(ui.cursor().min, egui::vec2(MEASURE_WIDTH, f32::INFINITY));
+        let (_, size) = layout_table_cell(
+            browser,
+            ui,
+            egui_ctx,
+            cell.node,
+            rect,
+            &rows[cell.row].0,
+            true,
+        );
+        if cell.column_span == 1 {
+            natural[cell.column] = natural[cell.column].max(size.x);
+        } else {
+            spanning.push((cell, size.x));
+        }
+    }
+    spanning.sort_by_key(|(cell, _)| cell.column_span);
+    for (cell, width) in spanning {
+        let span = cell.column..cell.column + cell.column_span;
+        let current = natural[span.clone()].iter().sum::<f32>() + spacing * (span.len() - 1) as f32;
+        if width > current {
+            let extra = (width - current) / span.len() as f32;
+            natural[span].iter_mut().for_each(|w| *w += extra);
+        }
+    }
+
+    let mut widths: Vec<f32> = (0..columns)
+        .map(|c| fixed[c].unwrap_or(natural[c]).max(floors[c]))
+        .collect();
+    // Auto columns give or take the difference to the table's width
+    let gaps = spacing * (columns + 1) as f32;
+    let total = widths.iter().sum::<f32>() + gaps;
+    let target = table_width.unwrap_or(total.min(available));
+    let auto: Vec<usize> = (0..columns).filter(|&c| fixed[c].is_none()).collect();
+    let auto_width: f32 = auto.iter().map(|&c| widths[c]).sum();
+    if total != target && auto_width > 0.0 {
+        let scale = (auto_width + target - total).max(0.0) / auto_width;
+        for &c in &auto {
+            widths[c] = (widths[c] * scale).max(floors[c]);
+        }
+    }
+
+    let origin = ui.cursor().min;
+    let mut column_x = Vec::with_capacity(columns);
+    let mut x = origin.x + spacing;
+    for width in &widths {
+        column_x.push(x);
+        x += width + spacing;
+    }
+    let right = x;
+    let span_width = |cell: &table::GridCell| {
+        widths[cell.column..cell.column + cell.column_span]
+            .iter()
+            .sum::<f32>()
+            + spacing * (cell.column_span - 1) as f32
+    };
+
+    // Lay out row by row: a row is as tall as its cells, and the last row a spanning
+    // cell covers grows if the cell needs more room than its rows give it
+    let mut ending: Vec<Vec<usize>> = vec![Vec::new(); grid.rows.len()];
+    for (index, cell) in grid.cells.iter().enumerate() {
+        ending[cell.last_row()].push(index);
+    }
+    let mut row_top = vec![0.0; grid.rows.len()];
+    let mut row_height = vec![0.0; grid.rows.len()];
+    let mut placed = Vec::with_capacity(grid.cells.len());
+    let mut cells = grid.cells.iter().peekable();
+    let mut y = origin.y + spacing;
+    for (r, (row_context, _)) in rows.iter().enumerate() {
+        row_top[r] = y;
+        while let Some(cell) = cells.next_if(|cell| cell.row == r) {
+            // The background goes under the content but its size is only known at the end
+            let background = ui.painter().add(egui::Shape::Noop);
+            let rect = egui::Rect::from_min_size(
+                egui::pos2(column_x[cell.column], y),
+                egui::vec2(span_width(cell), f32::INFINITY),
+            );
+            let (frame, size) =
+                layout_table_cell(browser, ui, egui_ctx, cell.node, rect, row_context, false);
+            if cell.column_span == 1 && size.x > widths[cell.column] + 0.5 {
+                floors[cell.column] = size.x;
+            }
+            placed.push((background, frame, size.y));
+        }
+        let mut height: f32 = 0.0;
+        for &index in &ending[r] {
+            let cell = &grid.cells[index];
+            height = height.max(placed[index].2 - (y - row_top[cell.row]));
+        }
+        row_height[r] = height;
+        y += height + spacing;
+    }
+    let bottom = y;
+
+    let cell_rect = |cell: &table::GridCell| {
+        let last = cell.last_row();
+        egui::Rect::from_min_max(
+            egui::pos2(column_x[cell.column], row_top[cell.row]),
+            egui::pos2(
+                column_x[cell.column] + span_width(cell),
+                row_top[last] + row_height[last],
+            ),
+        )
+    };
+    let cell_shape = |cell: &table::GridCell, frame: &egui::Frame, rect: egui::Rect| {
+        let row_frame = &rows[cell.row].1;
+        let fill = if frame.fill != egui::Color32::TRANSPARENT {
+            frame.fill
+        } else {
+            row_frame.fill
+        };
+        let stroke = if frame.stroke != egui::Stroke::NONE {
+            frame.stroke
+        } else if border > 0.0 {
+            egui::Stroke::new(1.0, border_color)
+        } else {
+            row_frame.stroke
+        };
+        // Collapsed borders are drawn on the shared edge, so neighbours draw the same line
+        let kind = if collapse {
+            egui::StrokeKind::Middle
+        } else {
+            egui::StrokeKind::Inside
+        };
+        egui::Shape::Rect(egui::epaint::RectShape::new(
+            rect,
+            frame.corner_radius,
+            fill,
+            stroke,
+            kind,
+        ))
+    };
+    for (cell, (background, frame, _)) in grid.cells.iter().zip(&placed) {
+        ui.painter()
+            .set(*background, cell_shape(cell, frame, cell_rect(cell)));
+    }
+
+    let table_rect = egui::Rect::from_min_max(origin, egui::pos2(right, bottom));
+    if border > 0.0 {
+        ui.painter().rect_stroke(
+            table_rect,
+            0.0,
+            egui::Stroke::new(border, border_color),
+            egui::StrokeKind::Inside,
+        );
+    }
+    ui.allocate_rect(table_rect, egui::Sense::hover());
+
+    // Keep the header rows at the top of the view while the rest of the table scrolls by
+    let header_rows = grid.header_rows;
+    if header_rows > 0 && header_rows < grid.rows.len() && !ui.is_sizing_pass() {
+        let header_top = row_top[0];
+        let header_height = row_top[header_rows - 1] + row_height[header_rows - 1] - header_top;
+        let clip_top = ui.clip_rect().top();
+        if header_top < clip_top && clip_top + header_height < bottom - spacing {
+            let offset = egui::vec2(0.0, clip_top - header_top);
+            ui.painter().rect_filled(
+                egui::Rect::from_min_max(
+                    egui::pos2(origin.x, clip_top - spacing),
+                    egui::pos2(right, clip_top + header_height + spacing),
+                ),
+                0.0,
+                ui.visuals().panel_fill,
+            );
+            // The copy draws the same cells again, so its widgets need ids of their own
+            ui.push_id("sticky", |ui| {
+                for (cell, (_, frame, _)) in grid.cells.iter().zip(&placed) {
+                    if cell.row >= header_rows {
+                        break;
+                    }
+                    let rect = cell_rect(cell).translate(offset);
+                    ui.painter().add(cell_shape(cell, frame, rect));
+                    let rect = egui::Rect::from_min_size(
+                        rect.min,
+                        egui::vec2(rect.width(), f32::INFINITY),
+                    );
+                    let _cell = layout_table_cell(
+                        browser,
+                        ui,
+                        egui_ctx,
+                        cell.node,
+                        rect,
+                        &rows[cell.row].0,
+                        false,
+                    );
+                }
+            });
+        }
+    }
+
+    if !ui.is_sizing_pass() {
+        ui.data_mut(|data| data.insert_temp(memory_id, floors));
+    }
+
+    if let Some(caption) = grid.caption
+        && caption_bottom
+    {
+        render_node(browser, ui, egui_ctx, caption, &mut context.clone());
+    }
+}
+
+/// Render a table cell's content into `rect` (only its left, top and width are used) and
+/// return the cell's frame and the size the content and padding took. A `sizing` pass
+/// only measures: nothing is shown and nothing reacts to input.
+fn layout_table_cell(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    cell: NodeRef,
+    rect: egui::Rect,
+    context: &RenderContext,
+    sizing: bool,
+) -> (egui::Frame, egui::Vec2) {
+    let mut context = context.clone();
+    let frame = set_node(browser, ui, egui_ctx, cell, &mut context);
+    let margin = frame.inner_margin;
+    let content = egui::Rect::from_min_size(
+        rect.min + egui::vec2(margin.left as f32, margin.top as f32),
+        egui::vec2((rect.width() - margin.sum().x).max(0.0), f32::INFINITY),
+    );
+    let mut builder = egui::UiBuilder::new()
+        .max_rect(content)
+        .layout(egui::Layout::top_down(egui::Align::Min));
+    if sizing {
+        builder = builder.sizing_pass().invisible();
+    }
+    let mut child = ui.new_child(builder);
+    render_block_contents(browser, &mut child, egui_ctx, cell, &mut context);
+    (frame, child.min_rect().size() + margin.sum())
+}
+
+/// Resolve a link or image source against the page's URL.
+fn resolve_url(context: &RenderContext, href: &str) -> String {
+    network::resolve_url(&context.base_url, href).unwrap_or_else(|| href.to_string())
+}
+
+/// Open a clicked link. Links on the tab's page open in a new tab. Links inside an
+/// `<iframe>` navigate that frame only, unless their `target` names another context:
+/// `_top` is the tab, `_parent` the page around the frame and `_blank` a new tab.
+fn follow_link(browser: &mut BrowserApp, href: &str, context: &RenderContext) {
+    let target = context.target.as_deref().unwrap_or("").to_ascii_lowercase();
+    let Some(frame) = context.frame.filter(|_| target != "_blank") else {
+        browser.add_new_tab();
+        browser.start_loading(browser.active_tab_index, href.to_string());
+        return;
+    };
+    let destination = match target.as_str() {
+        "_top" => None,
+        "_parent" => browser
+            .tabs
+            .get(browser.active_tab_index)
+            .and_then(|tab| tab.frames.get(&frame))
+            .and_then(|frame| frame.parent),
+        _ => Some(frame),
+    };
+    match destination {
+        Some(frame) => browser.navigate_frame(frame, href.to_string()),
+        None => browser.start_loading(browser.active_tab_index, href.to_string()),
+    }
+}
+
+/// Draw an `<iframe>`: a box, 300×150 unless `width` and `height` say otherwise, holding a
+/// page of its own with its own scroll bars. The page is loaded the first time the frame is
+/// drawn, and again whenever `src` or `srcdoc` change.
+fn render_iframe(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    node: NodeRef,
+    context: &RenderContext,
+) {
+    context.after_space.set(false);
+    let size = match requested_size(ui, node, context) {
+        (Some(w), Some(h)) => egui::vec2(w, h),
+        (w, h) => egui::vec2(w.unwrap_or(FRAME_SIZE.x), h.unwrap_or(FRAME_SIZE.y)),
+    };
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::hover());
+    browser.track_pointer(
+        context.frame,
+        Some(node.id()),
+        ui.rect_contains_pointer(response.rect),
+    );
+    if !ui.is_rect_visible(rect) {
+        return;
+    }
+
+    let id: FrameId = (node.document().id(), node.id());
+    let source = match node.get_attribute("srcdoc") {
+        Some(html) => FrameSource::Srcdoc(html.to_string()),
+        None => match node
+            .get_attribute("src")
+            .filter(|src| !src.trim().is_empty())
+        {
+            Some(src) => FrameSource::Url(resolve_url(context, src.trim())),
+            None => FrameSource::Url("about:blank".to_string()),
+        },
+    };
+    browser.open_frame(egui_ctx, id, context.frame, source, &context.base_url);
+    let Some(state) = browser
+        .tabs
+        .get(browser.active_tab_index)
+        .and_then(|tab| tab.frames.get(&id))
+    else {
+        return;
+    };
+    // Take what is needed so `browser` is free again while the frame's page is drawn
+    let content = match &state.content_state {
+        ContentState::Loaded { url, document, .. } => Ok((url.clone(), Arc::clone(document))),
+        ContentState::Loading(_) => Err(None),
+        ContentState::Error(error) => Err(Some(error.clone())),
+        ContentState::Idle => return,
+    };
+
+    // Like other browsers, draw a border unless `frameborder="0"`
+    let border = node.get_attribute("frameborder").map(str::trim) != Some("0");
+    if border {
+        let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+        ui.painter()
+            .rect_stroke(rect, 0.0, stroke, egui::StrokeKind::Inside);
+    }
+    let inner = if border { rect.shrink(2.0) } else { rect };
+    let mut frame_ui = ui.new_child(
+        egui::UiBuilder::new()
+            .id_salt(("iframe", id))
+            .max_rect(inner)
+            .layout(egui::Layout::top_down(egui::Align::Min)),
+    );
+    frame_ui.set_clip_rect(inner.intersect(ui.clip_rect()));
+    match content {
+        Ok((url, document)) => {
+            // The frame is the viewport of its page's media queries. Its document can't be
+            // changed while the outer one is drawn, so the restyle comes with the next frame
+            let media = MediaEnvironment {
+                width: inner.width(),
+                height: inner.height(),
+                ..media_environment(ui)
+            };
+            if *document.media() != media {
+                let ctx = egui_ctx.clone();
+                browser.queue_dom_change(Some(id), move |document| {
+                    if document.set_media(media) {
+                        ctx.request_repaint();
+                    }
+                });
+            }
+            let Some(body) = document.body() else {
+                return;
+            };
+            // The page inherits nothing from the one around the frame
+            let mut frame_context = RenderContext {
+                base_url: Rc::from(url.as_str()),
+                frame: Some(id),
+                ..Default::default()
+            };
+            egui::ScrollArea::both()
+                .auto_shrink(false)
+                .show(&mut frame_ui, |ui| {
+                    render_node(browser, ui, egui_ctx, body, &mut frame_context);
+                });
+        }
+        Err(None) => {
+            frame_ui.centered_and_justified(|ui| ui.spinner());
+        }
+        Err(Some(error)) => {
+            frame_ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+}
+
+/// The current display, for evaluating media queries.
+fn media_environment(ui: &egui::Ui) -> MediaEnvironment {
+    let screen = ui.ctx().screen_rect();
+    MediaEnvironment {
+        width: screen.width(),
+        height: screen.height(),
+        resolution: ui.ctx().pixels_per_point(),
+        dark: ui.visuals().dark_mode, // follows the page's color scheme
+        reduced_motion: ui.style().animation_time == 0.0,
+    }
+}
+
+/// The `width` and `height` an image or drawing asks for, in points.
+fn requested_size(
+    ui: &egui::Ui,
+    node: NodeRef,
+    context: &RenderContext,
+) -> (Option<f32>, Option<f32>) {
+    let width = node
+        .dimension("width")
+        .map(|width| width.to_egui_value(context.style.font_size, ui.available_width()));
+    // There is no containing height to take a percentage of
+    let height = node
+        .dimension("height")
+        .filter(|height| !matches!(height, layout::Length::Percent(_)))
+        .map(|height| height.to_egui_value(context.style.font_size, 0.0));
+    (width, height)
+}
+
+/// An `<img>` source as far as it has loaded.
+enum ImageState {
+    Texture(egui::load::SizedTexture),
+    Scene(Arc<svg::Scene>), // an SVG file, drawn by `paint_scene`
+    Pending(Option<egui::Vec2>),
+    Failed, // no usable source, or it failed to load
+}
+
+/// Fetch and parse an SVG file. The parsed scene is kept, so this is cheap on later frames.
+fn load_svg(ctx: &egui::Context, url: &str) -> ImageState {
+    let id = egui::Id::new(("svg image", url));
+    if let Some(scene) = ctx.data(|data| data.get_temp::<Option<Arc<svg::Scene>>>(id)) {
+        return scene.map_or(ImageState::Failed, ImageState::Scene);
+    }
+    match ctx.try_load_bytes(url) {
+        Ok(egui::load::BytesPoll::Ready { bytes, .. }) => {
+            let document = html_parser::parse_html(&String::from_utf8_lossy(&bytes));
+            // An image doesn't inherit the text color of the page
+            let scene = svg::find_svg(document.root())
+                .map(|svg| Arc::new(svg::Scene::new(svg, egui::Color32::BLACK)));
+            if scene.is_none() {
+                eprintln!("Warning: No <svg> element in {}", url);
+            }
+            ctx.data_mut(|data| data.insert_temp(id, scene.clone()));
+            scene.map_or(ImageState::Failed, ImageState::Scene)
+        }
+        Ok(egui::load::BytesPoll::Pending { size }) => ImageState::Pending(size),
+        Err(_) => ImageState::Failed,
+    }
+}
+
+/// Draw an inline `<svg>`, sized by its `width` and `height` or else by its `viewBox`.
+fn render_svg(browser: &mut BrowserApp, ui: &mut egui::Ui, node: NodeRef, context: &RenderContext) {
+    context.after_space.set(false);
+    let color = context.text_color(ui);
+    let scene = svg::Scene::new(node, color);
+    let intrinsic = scene.intrinsic_size();
+    let size = match requested_size(ui, node, context) {
+        (Some(w), Some(h)) => egui::vec2(w, h),
+        (Some(w), None) => egui::vec2(w, w * intrinsic.y / intrinsic.x),
+        (None, Some(h)) => egui::vec2(h * intrinsic.x / intrinsic.y, h),
+        (None, None) => intrinsic,
+    };
+    let sense = if context.href.is_some() {
+        egui::Sense::click()
+    } else {
+        egui::Sense::hover()
+    };
+    let (rect, mut response) = ui.allocate_exact_size(size, sense);
+    browser.track_pointer(
+        context.frame,
+        Some(node.id()),
+        ui.rect_contains_pointer(response.rect),
+    );
+    if ui.is_rect_visible(rect) {
+        let id = egui::Id::new((node.document().id(), node.id(), "svg"));
+        paint_scene(ui, id, &scene, rect, rect);
+    }
+
+    // Drawings are described by a <title> child rather than an attribute
+    let title = node
+        .children()
+        .find(|child| matches!(child.node_type, NodeType::Element(HtmlTag::Title)))
+        .map(|title| title.text_content());
+    if let Some(title) = title.as_deref().or(context.title.as_deref()) {
+        response = response.on_hover_text(title.trim());
+    }
+    if let Some(href) = &context.href {
+        response = response.on_hover_cursor(egui::CursorIcon::PointingHand);
+        if response.clicked() {
+            follow_link(browser, href, context);
+        }
+    }
+}
+
+/// Paint `scene` stretched over `rect`, showing only what lies inside `clip`. The shapes are
+/// rasterized at the screen's pixel density, and the texture is kept (under `id`) until the
+/// drawing or its size changes.
+fn paint_scene(
+    ui: &egui::Ui,
+    id: egui::Id,
+    scene: &svg::Scene,
+    rect: egui::Rect,
+    clip: egui::Rect,
+) {
+    let pixels_per_point = ui.ctx().pixels_per_point();
+    let pixels = (rect.size() * pixels_per_point).round();
+    let key = (scene.fingerprint(), [pixels.x as u32, pixels.y as u32]);
+    let cached = ui
+        .data(|data| data.get_temp::<(u64, [u32; 2], egui::TextureHandle)>(id))
+        .filter(|(fingerprint, size, _)| (*fingerprint, *size) == key);
+    let texture = match cached {
+        Some((_, _, texture)) => texture,
+        None => {
+            let Some(image) = scene.rasterize(rect.size(), pixels_per_point) else {
+                return;
+            };
+            let texture =
+                ui.ctx()
+                    .load_texture(format!("svg {:?}", id), image, egui::TextureOptions::LINEAR);
+            ui.data_mut(|data| data.insert_temp(id, (key.0, key.1, texture.clone())));
+            texture
+        }
+    };
+
+    let painter = ui.painter().with_clip_rect(clip.intersect(ui.clip_rect()));
+    let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+    painter.image(texture.id(), rect, uv, egui::Color32::WHITE);
+    // Text goes on top, laid out by egui so it stays sharp
+    for text in &scene.texts {
+        let (baseline, font_size) = scene.place_text(text, rect.size());
+        let galley = painter.layout_no_wrap(
+            text.content.clone(),
+            egui::FontId::proportional(font_size),
+            text.color,
+        );
+        let ascent = galley
+            .rows
+            .first()
+            .and_then(|row| row.glyphs.first())
+            .map_or(galley.size().y, |glyph| glyph.pos.y);
+        let x = match text.anchor {
+            egui::Align::Min => 0.0,
+            egui::Align::Center => galley.size().x / 2.0,
+            egui::Align::Max => galley.size().x,
+        };
+        let position = rect.min + baseline.to_vec2() - egui::vec2(x, ascent);
+        painter.galley(position, galley, text.color);
+    }
+}
+
+/// Draw an `<img>`. Until the file has loaded its box is a placeholder sized by the `width`
+/// and `height` it asks for; if it fails to load the `alt` text is shown in a box instead.
+fn render_image(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    node: NodeRef,
+    context: &RenderContext,
+) {
+    // An image is content, so a space right after it is kept
+    context.after_space.set(false);
+    let (width, height) = requested_size(ui, node, context);
+    let box_size = |intrinsic: Option<egui::Vec2>| match (width, height, intrinsic) {
+        (Some(w), Some(h), _) => egui::vec2(w, h),
+        (Some(w), None, Some(size)) => egui::vec2(w, w * size.y / size.x),
+        (None, Some(h), Some(size)) => egui::vec2(h * size.x / size.y, h),
+        (None, None, Some(size)) => size,
+        (w, h, None) => egui::vec2(
+            w.unwrap_or(IMAGE_PLACEHOLDER_SIZE),
+            h.unwrap_or(IMAGE_PLACEHOLDER_SIZE),
+        ),
+    };
+    let sense = if context.href.is_some() {
+        egui::Sense::click()
+    } else {
+        egui::Sense::hover()
+    };
+
+    let choice = image::choose_image(node, &media_environment(ui));
+    let state = choice.as_ref().map_or(ImageState::Failed, |choice| {
+        let url = resolve_url(context, &choice.url);
+        // Lazy images start loading once they come close to the visible part of the page,
+        // and are remembered so scrolling away again doesn't forget their size
+        let requested = egui::Id::new(("image requested", &url));
+        let lazy = node
+            .get_attribute("loading")
+            .is_some_and(|loading| loading.eq_ignore_ascii_case("lazy"));
+        if lazy && !ui.data(|data| data.get_temp(requested).unwrap_or(false)) {
+            let rect = egui::Rect::from_min_size(ui.cursor().min, box_size(None));
+            if !ui.clip_rect().expand(LAZY_LOAD_MARGIN).intersects(rect) {
+                return ImageState::Pending(None);
+            }
+            ui.data_mut(|data| data.insert_temp(requested, true));
+        }
+        if image::is_svg(&url) {
+            return load_svg(ui.ctx(), &url);
+        }
+        match ui.ctx().try_load_texture(
+            &url,
+            egui::TextureOptions::default(),
+            egui::SizeHint::default(),
+        ) {
+            Ok(egui::load::TexturePoll::Ready { texture }) => ImageState::Texture(texture),
+            Ok(egui::load::TexturePoll::Pending { size }) => ImageState::Pending(size),
+            Err(_) => ImageState::Failed,
+        }
+    });
+
+    let fit = match node.style.get("object-fit") {
+        Some(layout::StyleProperty::Keyword(fit)) => fit.as_str(),
+        _ => "fill",
+    };
+    let mut response = match state {
+        ImageState::Texture(texture) => {
+            let density = choice.map_or(1.0, |choice| choice.density);
+            let intrinsic = texture.size / density;
+            let (rect, response) = ui.allocate_exact_size(box_size(Some(intrinsic)), sense);
+            let (rect, uv) = image::object_fit(fit, rect, intrinsic);
+            ui.painter()
+                .image(texture.id, rect, uv, egui::Color32::WHITE);
+            response
+        }
+        ImageState::Scene(scene) => {
+            let intrinsic = scene.intrinsic_size();
+            let (rect, response) = ui.allocate_exact_size(box_size(Some(intrinsic)), sense);
+            let (visible, uv) = image::object_fit(fit, rect, intrinsic);
+            // The whole drawing, of which `uv` is the part that shows in `visible`
+            let size = visible.size() / uv.size();
+            let drawing = egui::Rect::from_min_size(visible.min - uv.min.to_vec2() * size, size);
+            if ui.is_rect_visible(visible) {
+                let id = egui::Id::new((node.document().id(), node.id(), "svg"));
+                paint_scene(ui, id, &scene, drawing, visible);
+            }
+            response
+        }
+        ImageState::Pending(size) => {
+            let density = choice.map_or(1.0, |choice| choice.density);
+            let (rect, response) =
+                ui.allocate_exact_size(box_size(size.map(|s| s / density)), sense);
+            ui.painter()
+                .rect_filled(rect, 0.0, ui.visuals().faint_bg_color);
+            if ui.is_rect_visible(rect) {
+                let spinner = rect.size().min_elem().min(16.0);
+                egui::Spinner::new().size(spinner).paint_at(
+                    ui,
+                    egui::Rect::from_center_size(rect.center(), egui::Vec2::splat(spinner)),
+                );
+            }
+            response
+        }
+        ImageState::Failed => {
+            let alt = node.get_attribute("alt");
+            if alt == Some("") {
+                // An empty alt marks the image as decorative: nothing to show
+                return;
+            }
+            let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+            match alt {
+                Some(alt) => {
+                    let padding = egui::vec2(4.0, 2.0);
+                    let wrap = width.map_or(f32::INFINITY, |w| (w - 2.0 * padding.x).max(0.0));
+                    let galley = egui::WidgetText::from(rich_text(alt, context)).into_galley(
+                        ui,
+                        Some(egui::TextWrapMode::Wrap),
+                        wrap,
+                        egui::FontSelection::Default,
+                    );
+                    let size = egui::vec2(
+                        width.unwrap_or(galley.size().x + 2.0 * padding.x),
+                        height.unwrap_or(galley.size().y + 2.0 * padding.y),
+                    );
+                    let (rect, response) = ui.allocate_exact_size(size, sense);
+                    let painter = ui.painter().with_clip_rect(rect.intersect(ui.clip_rect()));
+                    painter.rect_stroke(rect, 0.0, stroke, egui::StrokeKind::Inside);
+                    painter.galley(rect.min + padding, galley, ui.visuals().text_color());
+                    response
+                }
+                None => {
+                    // Without alt text, a crossed box stands in for the image
+                    let (rect, response) = ui.allocate_exact_size(box_size(None), sense);
+                    let painter = ui.painter();
+                    painter.rect_stroke(rect, 0.0, stroke, egui::StrokeKind::Inside);
+                    let cross = rect.shrink(rect.size().min_elem() * 0.25);
+                    painter.line_segment([cross.left_top(), cross.right_bottom()], stroke);
+                    painter.line_segment([cross.right_top(), cross.left_bottom()], stroke);
+                    response
+                }
+            }
+        }
+    };
+
+    browser.track_pointer(
+        context.frame,
+        Some(node.id()),
+        ui.rect_contains_pointer(response.rect),
+    );
+    if let Some(title) = node.get_attribute("title") {
+        response = response.on_hover_text(title);
+    } else if let Some(alt) = node.get_attribute("alt").filter(|alt| !alt.is_empty()) {
+        response = response.on_hover_text(alt);
+    }
+    // Handle clicking the image like an anchor
+    if let Some(href) = &context.href {
+        response = response.on_hover_cursor(egui::CursorIcon::PointingHand);
+        if response.clicked() {
+            follow_link(browser, href, context);
+        }
+    }
+}
+
+/// Text styled by the inherited formatting in `context`.
+fn rich_text(text: &str, context: &RenderContext) -> egui::RichText {
+    let style = &context.style;
+    let mut rich = egui::RichText::new(text)
+        .size(style.font_size)
+        .family(style.font_family.clone())
+        .line_height(style.line_height.resolve(style.font_size));
+    if style.is_bold() {
+        rich = rich.strong();
+    }
+    if context.week {
+        rich = rich.weak();
+    }
+    if style.italic {
+        rich = rich.italics();
+    }
+    if style.underline {
+        rich = rich.underline();
+    }
+    if style.line_through {
+        rich = rich.strikethrough();
+    }
+    if context.baseline_shift < 0.0 {
+        rich = rich.raised();
+    }
+    if let Some(bg) = &context.background
+        && style.is_visible()
+    {
+        rich = rich.background_color(bg.to_ecolor());
+    }
+    if let Some(color) = &style.color {
+        rich = rich.color(color.to_ecolor());
+    }
+    if !style.is_visible() {
+        rich = rich.color(egui::Color32::TRANSPARENT);
+    }
+    rich
+}
+
+/// Add a run of text styled by `context`, clickable if it is inside a link.
+fn add_text(browser: &mut BrowserApp, ui: &mut egui::Ui, text: &str, context: &RenderContext) {
+    let rich = rich_text(text, context);
+    let mut response = if context.baseline_shift == 0.0 {
+        let mut label = egui::Label::new(rich);
+        if !context.style.white_space.wraps() {
+            label = label.extend();
+        }
+        if context.href.is_some() {
+            label = label.sense(egui::Sense::click());
+        }
+        ui.add(label)
+    } else {
+        // Superscript and subscript take their place in the line but are painted shifted
+        let galley = egui::WidgetText::from(rich).into_galley(
+            ui,
+            Some(egui::TextWrapMode::Extend),
+            f32::INFINITY,
+            egui::FontSelection::Default,
+        );
+        let sense = if context.href.is_some() {
+            egui::Sense::click()
+        } else {
+            egui::Sense::hover()
+        };
+        let (rect, response) = ui.allocate_exact_size(galley.size(), sense);
+        let pos = rect.min + egui::vec2(0.0, context.baseline_shift);
+        ui.painter().galley(pos, galley, ui.visuals().text_color());
+        response
+    };
+    browser.track_pointer(
+        context.frame,
+        context.element,
+        ui.rect_contains_pointer(response.rect),
+    );
+    if let Some(href) = &context.href {
+        response = response.on_hover_cursor(egui::CursorIcon::PointingHand);
+        if response.clicked() {
+            follow_link(browser, href, context);
+        }
+    }
+    if let Some(title) = &context.title {
+        response.on_hover_text(title);
+    }
+}
+
+/// Add text from the content of an element. The first letter and the first line of a block
+/// are styled by its `::first-letter` and `::first-line` rules, if it has them.
+fn add_content_text(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    text: &str,
+    context: &RenderContext,
+) {
+    let first_line = context.first_line.borrow().clone();
+    let mut line_context = context.clone();
+    if let Some(style) = &first_line {
+        // Box properties do not apply to a line, only the text ones
+        let style = pseudo_style(style, &line_context);
+        let _ = apply_style(ui, &style, true, &mut line_context);
+    }
+    let mut text = text;
+    if let Some(style) = context.first_letter.take() {
+        let end = first_letter_len(text);
+        if end == 0 {
+            // No letter here; wait for the next text
+            context.first_letter.replace(Some(style));
+        } else {
+            let mut letter_context = line_context.clone();
+            let style = pseudo_style(&style, &letter_context);
+            let frame = apply_style(ui, &style, true, &mut letter_context);
+            if frame != egui::Frame::default() {
+                frame.show(ui, |ui| {
+                    add_text(browser, ui, &text[..end], &letter_context)
+                });
+            } else {
+                add_text(browser, ui, &text[..end], &letter_context);
+            }
+            text = &text[end..];
+        }
+    }
+    if first_line.is_some() && !text.is_empty() {
+        let end = first_row_len(ui, text, &line_context);
+        add_text(browser, ui, &text[..end], &line_context);
+        text = &text[end..];
+        if !text.is_empty() {
+            // The line ended inside this text
+            context.first_line.take();
+        }
+    }
+    if !text.is_empty() {
+        add_text(browser, ui, text, context);
+    }
+}
+
+/// The length in bytes of the first letter of `text` with the punctuation around it, as in
+/// `“A”`, or 0 if there is no letter.
+fn first_letter_len(text: &str) -> usize {
+    let is_punctuation = |c: char| {
+        c.is_ascii_punctuation() || matches!(c, '“' | '”' | '‘' | '’' | '«' | '»' | '„' | '‹' | '›')
+    };
+    let Some((start, letter)) = text
+        .char_indices()
+        .find(|&(_, c)| !c.is_whitespace() && !is_punctuation(c))
+    else {
+        return 0;
+    };
+    let end = start + letter.len_utf8();
+    end + text[end..]
+        .chars()
+        .take_while(|&c| is_punctuation(c))
+        .map(char::len_utf8)
+        .sum::<usize>()
+}
+
+/// How much of `text`, in bytes, fits on what is left of the current line.
+fn first_row_len(ui: &egui::Ui, text: &str, context: &RenderContext) -> usize {
+    if !context.style.white_space.wraps() {
+        return text.len();
+    }
+    let galley = egui::WidgetText::from(rich_text(text, context)).into_galley(
+        ui,
+        Some(egui::TextWrapMode::Wrap),
+        ui.available_width(),
+        egui::FontSelection::Default,
+    );
+    let chars = galley
+        .rows
+        .first()
+        .map_or(0, |row| row.char_count_including_newline());
+    text.char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(index, _)| index)
+}
+
+fn render_node(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    node: NodeRef,
+    context: &mut RenderContext,
+) {
+    let frame = set_node(browser, ui, egui_ctx, node, context);
+    // These drew themselves; the children of an <svg> are its drawing, and those of an
+    // <iframe> fallback content for browsers without frames
+    if let NodeType::Element(HtmlTag::Table | HtmlTag::Svg | HtmlTag::Iframe) = node.node_type {
+        return;
+    }
+
+    // The first letter and line of this block, if it styles them; a block nested at its
+    // start has the same first line, so the rules of both apply there
+    for (pseudo, pending) in [
+        (PseudoElement::FirstLetter, &mut context.first_letter),
+        (PseudoElement::FirstLine, &mut context.first_line),
+    ] {
+        if let Some(style) = node.pseudo_style.get(&pseudo) {
+            let mut merged = pending
+                .take()
+                .map(|outer| (*outer).clone())
+                .unwrap_or_default();
+            merged.extend(style.clone());
+            *pending = Rc::new(RefCell::new(Some(Rc::new(merged))));
+        }
+    }
+
+    if frame != egui::Frame::default() {
+        let response = frame.show(ui, |ui| {
+            ui.vertical(|ui| {
+                render_block_contents(browser, ui, egui_ctx, node, context);
+            });
         });
+        // The box itself, where the pointer isn't over any of its content
+        let hovered = ui.rect_contains_pointer(response.response.rect);
+        browser.track_pointer(context.frame, context.element, hovered);
+    } else {
+        render_block_contents(browser, ui, egui_ctx, node, context);
+    }
+}
+
+/// Whether clicking `node` focuses it: links, form controls, `<summary>`, `<iframe>` and
+/// anything with a `tabindex`.
+fn is_focusable(node: NodeRef) -> bool {
+    match node.tag() {
+        Some(HtmlTag::A) => node.has_attribute("href"),
+        Some(HtmlTag::Summary | HtmlTag::Iframe) => true,
+        Some(HtmlTag::Custom(name)) => match name.to_ascii_lowercase().as_str() {
+            "area" => node.has_attribute("href"),
+            "button" | "input" | "select" | "textarea" => !node.has_attribute("disabled"),
+            _ => node.has_attribute("tabindex"),
+        },
+        _ => node.has_attribute("tabindex"),
+    }
+}
+
+/// Render the inside of a block element, placing list markers and indentation first.
+fn render_block_contents(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    node: NodeRef,
+    context: &mut RenderContext,
+) {
+    // The marker is meant for this item only, not for anything nested in it
+    let list_marker = context.list_marker.take();
+    match node.node_type {
+        NodeType::Element(HtmlTag::Ul | HtmlTag::Ol) => {
+            render_list_items(browser, ui, egui_ctx, node, context);
+        }
+        NodeType::Element(HtmlTag::Li) if list_marker.is_some() => {
+            let (mut marker, inside) = list_marker.unwrap();
+            // `::marker` can restyle the marker, and replace it with its `content`
+            let mut marker_context = context.clone();
+            if let Some(style) = node.pseudo_style.get(&PseudoElement::Marker) {
+                // A marker has no box of its own
+                let style = pseudo_style(style, &marker_context);
+                let _ = apply_style(ui, &style, true, &mut marker_context);
+            }
+            if let Some(text) = node
+                .document()
+                .generated_content()
+                .get(node.id(), PseudoElement::Marker)
+            {
+                marker = ListMarker::Text(text.to_string());
+            }
+            ui.horizontal_top(|ui| {
+                if inside {
+                    ui.add_space(LIST_INDENT);
+                    paint_list_marker(ui, &marker, &marker_context, None);
+                } else {
+                    paint_list_marker(ui, &marker, &marker_context, Some(LIST_INDENT));
+                }
+                ui.vertical(|ui| {
+                    render_children(browser, ui, egui_ctx, node, context);
+                });
+            });
+        }
+        NodeType::Element(HtmlTag::Details) => {
+            render_details(browser, ui, egui_ctx, node, context);
+        }
+        // Blocks of text that keep their line breaks are laid out in one piece
+        _ if context.style.white_space.keeps_line_breaks() && node.children().all(is_inline) => {
+            render_preformatted(browser, ui, egui_ctx, node, context);
+        }
+        _ => render_children(browser, ui, egui_ctx, node, context),
+    }
+}
+
+/// Render a `<details>`: its `<summary>` with a disclosure triangle, and the rest of its
+/// content only while the element has the `open` attribute. Clicking the summary toggles it.
+fn render_details(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    node: NodeRef,
+    context: &mut RenderContext,
+) {
+    let open = node.has_attribute("open");
+    let is_summary =
+        |child: &NodeRef| matches!(child.node_type, NodeType::Element(HtmlTag::Summary));
+    let summary = node.children().find(is_summary);
+
+    let row = ui.horizontal_top(|ui| {
+        let color = context.text_color(ui);
+        let size = context.style.font_size * 0.6;
+        let font = egui::FontId::proportional(context.style.font_size);
+        let row_height = ui.fonts(|fonts| fonts.row_height(&font));
+        let (rect, _) = ui.allocate_exact_size(
+            egui::vec2(context.style.font_size, row_height),
+            egui::Sense::hover(),
+        );
+        let center = rect.center();
+        let half = size / 2.0;
+        let points = if open {
+            // Pointing down
+            vec![
+                center + egui::vec2(-half, -half * 0.6),
+                center + egui::vec2(half, -half * 0.6),
+                center + egui::vec2(0.0, half * 0.8),
+            ]
+        } else {
+            // Pointing right
+            vec![
+                center + egui::vec2(-half * 0.6, -half),
+                center + egui::vec2(half * 0.8, 0.0),
+                center + egui::vec2(-half * 0.6, half),
+            ]
+        };
+        ui.painter().add(egui::Shape::convex_polygon(
+            points,
+            color,
+            egui::Stroke::NONE,
+        ));
+        ui.vertical(|ui| match summary {
+            Some(summary) => {
+                let mut context = context.clone();
+                render_node(browser, ui, egui_ctx, summary, &mut context);
+            }
+            // Browsers show a generic label when there is no <summary>
+            None => {
+                ui.label(rich_text("Details", context));
+            }
+        });
+    });
+
+    // Added after the summary's widgets, so it gets the click rather than the labels
+    let toggle = ui
+        .interact(
+            row.response.rect,
+            ui.id().with((node.id(), "details")),
+            egui::Sense::click(),
+        )
+        .on_hover_cursor(egui::CursorIcon::PointingHand);
+    if toggle.clicked() {
+        let id = node.id();
+        browser.queue_dom_change(context.frame, move |document| {
+            if open {
+                document.remove_attribute(id, "open");
+            } else {
+                document.set_attribute(id, "open", "");
+            }
+        });
+    }
+
+    if open {
+        let content: Vec<FlowItem> = node
+            .children()
+            .filter(|child| Some(*child) != summary)
+            .map(FlowItem::Node)
+            .collect();
+        render_flow(browser, ui, egui_ctx, &content, context);
+    }
+}
+
+/// Render a block whose text keeps its line breaks (`<pre>`, `white-space: pre*`) as a
+/// single text layout. With `white-space: pre` long lines scroll sideways instead of wrapping.
+fn render_preformatted(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    node: NodeRef,
+    context: &mut RenderContext,
+) {
+    let mut job = egui::text::LayoutJob::default();
+    let mut after_space = true;
+    append_preformatted(
+        browser,
+        ui,
+        egui_ctx,
+        node,
+        context,
+        &mut job,
+        &mut after_space,
+    );
+    if context.style.white_space.wraps() {
+        job.wrap.max_width = ui.available_width();
+        ui.add(egui::Label::new(job).wrap());
+    } else {
+        egui::ScrollArea::horizontal()
+            .id_salt((node.document().id(), node.id()))
+            .show(ui, |ui| {
+                ui.add(egui::Label::new(job).extend());
+            });
+    }
+}
+
+/// Append the text below `node` to `job`, formatted by the elements it is nested in.
+fn append_preformatted(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    node: NodeRef,
+    context: &RenderContext,
+    job: &mut egui::text::LayoutJob,
+    after_space: &mut bool,
+) {
+    for item in flow_items(node) {
+        let mut context = context.clone();
+        let child = match item {
+            FlowItem::Node(child) => child,
+            FlowItem::Generated(node, pseudo) => {
+                let text = node.document().generated_content().get(node.id(), pseudo);
+                if let Some(style) = node.pseudo_style.get(&pseudo) {
+                    let style = pseudo_style(style, &context);
+                    let _frame = apply_style(ui, &style, true, &mut context);
+                }
+                let text = collapse_whitespace(
+                    text.unwrap_or_default(),
+                    context.style.white_space,
+                    after_space,
+                );
+                rich_text(&text, &context).append_to(
+                    job,
+                    ui.style(),
+                    egui::FontSelection::Default,
+                    egui::Align::Min,
+                );
+                continue;
+            }
+        };
+        let text = match &child.node_type {
+            NodeType::Text(text) => {
+                collapse_whitespace(text, context.style.white_space, after_space)
+            }
+            NodeType::Element(HtmlTag::Br) => {
+                *after_space = true;
+                "\n".to_string()
+            }
+            // These draw widgets of their own and can't be part of a text layout
+            NodeType::Element(
+                HtmlTag::Img
+                | HtmlTag::Svg
+                | HtmlTag::Iframe
+                | HtmlTag::Audio
+                | HtmlTag::Table
+                | HtmlTag::Hr,
+            ) => {
+                continue;
+            }
+            NodeType::Element(_) => {
+                // Only the text formatting applies here; box properties are ignored
+                let _frame = set_node(browser, ui, egui_ctx, child, &mut context);
+                append_preformatted(browser, ui, egui_ctx, child, &context, job, after_space);
+                continue;
+            }
+        };
+        rich_text(&text, &context).append_to(
+            job,
+            ui.style(),
+            egui::FontSelection::Default,
+            egui::Align::Min,
+        );
+    }
+}
+
+/// Lay out a block's children: runs of inline children share a wrapped line, blocks stack.
+fn render_children(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    node: NodeRef,
+    context: &mut RenderContext,
+) {
+    render_flow(browser, ui, egui_ctx, &flow_items(node), context);
+}
+
+/// Lay out a sequence of sibling boxes like `render_children` does.
+fn render_flow(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    children: &[FlowItem],
+    context: &mut RenderContext,
+) {
+    let mut i = 0;
+    while i < children.len() {
+        if children[i].is_inline() {
+            let start = i;
+            while i < children.len() && children[i].is_inline() {
+                i += 1;
+            }
+            let run = &children[start..i];
+            // Whitespace between blocks (e.g. the indentation of the source) is not content
+            let blank = !context.style.white_space.keeps_line_breaks()
+                && run.iter().all(|child| match child {
+                    FlowItem::Node(child) => match &child.node_type {
+                        NodeType::Text(text) => html_parser::is_blank(text),
+                        NodeType::Element(_) => false,
+                    },
+                    FlowItem::Generated(..) => false,
+                });
+            if blank {
+                continue;
+            }
+            // Each run starts a new line. Spaces between words come from the text itself.
+            let mut run_context = context.clone();
+            run_context.after_space = Rc::new(Cell::new(true));
+            // egui can't align the rows of a wrapping layout, so for `text-align` a run
+            // that fits on one line is moved over by the room it left the last time
+            let align = match context.style.text_align {
+                TextAlign::Left | TextAlign::Justify => 0.0,
+                TextAlign::Center => 0.5,
+                TextAlign::Right => 1.0,
+            };
+            let width_id = ui.next_auto_id().with("run width");
+            let indent = match ui.data(|data| data.get_temp::<f32>(width_id)) {
+                Some(width) if align > 0.0 => (ui.available_width() - width).max(0.0) * align,
+                _ => 0.0,
+            };
+            let old_item_spacing = ui.style().spacing.item_spacing;
+            ui.style_mut().spacing.item_spacing.x = 0.;
+            let add_run = |ui: &mut egui::Ui| {
+                ui.add_space(indent);
+                for &child in run {
+                    let mut context = run_context.clone();
+                    render_inline_item(browser, ui, egui_ctx, child, &mut context);
+                }
+            };
+            let response = if context.style.white_space.wraps() {
+                ui.horizontal_wrapped(add_run).response
+            } else {
+                ui.horizontal(add_run).response
+            };
+            ui.style_mut().spacing.item_spacing = old_item_spacing;
+            if align > 0.0 {
+                let width = response.rect.width() - indent;
+                let last = ui.data(|data| data.get_temp::<f32>(width_id));
+                if last.is_none_or(|last| (last - width).abs() > 0.5) {
+                    ui.data_mut(|data| data.insert_temp(width_id, width));
+                    ui.ctx().request_discard("text-align");
+                }
+            }
+            // The first line of the block ends with its first run of text at the latest
+            context.first_line.take();
+        } else {
+            if let FlowItem::Node(child) = children[i] {
+                let mut context = context.clone();
+                render_node(browser, ui, egui_ctx, child, &mut context);
+            }
+            i += 1;
+        }
+    }
+}
+
+/// A box in the content of an element: a child node, or a `::before` or `::after` box the
+/// element generates.
+#[derive(Clone, Copy)]
+enum FlowItem<'a> {
+    Node(NodeRef<'a>),
+    Generated(NodeRef<'a>, PseudoElement), // the element, and which of its boxes
+}
+
+impl FlowItem<'_> {
+    /// Generated boxes are always inline.
+    fn is_inline(self) -> bool {
+        match self {
+            FlowItem::Node(node) => is_inline(node),
+            FlowItem::Generated(..) => true,
+        }
+    }
+}
+
+/// The children of `node`, between the `::before` and `::after` boxes it generates. Those
+/// with `display: none` have no box.
+fn flow_items(node: NodeRef) -> Vec<FlowItem> {
+    let generated = node.document().generated_content();
+    let generated_box = |pseudo| {
+        generated
+            .get(node.id(), pseudo)
+            .map(|_| FlowItem::Generated(node, pseudo))
+    };
+    generated_box(PseudoElement::Before)
+        .into_iter()
+        .chain(
+            node.children()
+                .filter(|child| child.computed.display != Some(style::Display::None))
+                .map(FlowItem::Node),
+        )
+        .chain(generated_box(PseudoElement::After))
+        .collect()
+}
+
+fn render_inline_item(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    item: FlowItem,
+    context: &mut RenderContext,
+) {
+    match item {
+        FlowItem::Node(node) => render_inline(browser, ui, egui_ctx, node, context),
+        FlowItem::Generated(node, pseudo) => render_generated(browser, ui, node, pseudo, context),
+    }
+}
+
+/// Draw the `::before` or `::after` box of `node`: its generated text, styled by the
+/// pseudo-element's rules on top of what it inherits from the element.
+fn render_generated(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    node: NodeRef,
+    pseudo: PseudoElement,
+    context: &RenderContext,
+) {
+    let Some(text) = node.document().generated_content().get(node.id(), pseudo) else {
+        return;
+    };
+    let mut context = context.clone();
+    let frame = match node.pseudo_style.get(&pseudo) {
+        Some(style) => apply_style(ui, &pseudo_style(style, &context), true, &mut context),
+        None => egui::Frame::default(),
+    };
+    let mut after_space = context.after_space.get();
+    let text = collapse_whitespace(text, context.style.white_space, &mut after_space);
+    context.after_space.set(after_space);
+    if text.is_empty() {
+        return;
+    }
+    if frame != egui::Frame::default() {
+        frame.show(ui, |ui| add_content_text(browser, ui, &text, &context));
+    } else {
+        add_content_text(browser, ui, &text, &context);
+    }
+}
+
+/// Render the children of a `<ul>` or `<ol>`, numbering the `<li>`s.
+fn render_list_items(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    list: NodeRef,
+    context: &mut RenderContext,
+) {
+    let ordered = matches!(list.node_type, NodeType::Element(HtmlTag::Ol));
+    let is_item = |node: &NodeRef| matches!(node.node_type, NodeType::Element(HtmlTag::Li));
+    // Items are numbered by the `list-item` counter, so `start`, `reversed` and `value`
+    // agree with `counter(list-item)` and `::marker`
+    let generated = list.document().generated_content();
+    // Nesting level picks the default bullet: disc, then circle, then square
+    let depth = list
+        .ancestors()
+        .filter(|node| matches!(node.node_type, NodeType::Element(HtmlTag::Ul | HtmlTag::Ol)))
+        .count();
+
+    for child in list.children() {
+        let mut child_context = context.clone();
+        if is_item(&child) {
+            let ordinal = generated.ordinal(child.id()).unwrap_or(1);
+            let style_type = list_style_type(list, child, ordered, depth);
+            let inside = child.computed.list_style_position == ListStylePosition::Inside;
+            child_context.list_marker = Some((list_marker(&style_type, ordinal), inside));
+            render_node(browser, ui, egui_ctx, child, &mut child_context);
+        } else {
+            ui.horizontal_top(|ui| {
+                ui.add_space(LIST_INDENT);
+                ui.vertical(|ui| {
+                    render_node(browser, ui, egui_ctx, child, &mut child_context);
+                });
+            });
+        }
+    }
+}
+
+/// `list-style-type` of an item: its computed value, then the legacy `type` attributes,
+/// then the default for the list kind and depth.
+fn list_style_type(list: NodeRef, item: NodeRef, ordered: bool, depth: usize) -> String {
+    // `type="a"` and `type="A"` differ, so these are matched case-sensitively
+    let type_attribute = |node: NodeRef| {
+        node.get_attribute("type").and_then(|value| match value {
+            "1" => Some("decimal".to_string()),
+            "a" => Some("lower-alpha".to_string()),
+            "A" => Some("upper-alpha".to_string()),
+            "i" => Some("lower-roman".to_string()),
+            "I" => Some("upper-roman".to_string()),
+            _ => Some(value.to_ascii_lowercase())
+                .filter(|value| style::LIST_STYLE_TYPES.contains(&value.as_str())),
+        })
+    };
+    item.computed
+        .list_style_type
+        .clone()
+        .or_else(|| type_attribute(item))
+        .or_else(|| type_attribute(list))
+        .unwrap_or_else(|| {
+            let default = match (ordered, depth) {
+                (true, _) => "decimal",
+                (false, 0) => "disc",
+                (false, 1) => "circle",
+                (false, _) => "square",
+            };
+            default.to_string()
+        })
+}
+
+/// Marker for the item with number `ordinal` in a list of the given `list-style-type`.
+fn list_marker(style_type: &str, ordinal: i64) -> ListMarker {
+    match style_type {
+        "none" => ListMarker::None,
+        "disc" => ListMarker::Disc,
+        "circle" => ListMarker::Circle,
+        "square" => ListMarker::Square,
+        _ => ListMarker::Text(format!("{}.", generated::counter_text(ordinal, style_type))),
+    }
+}
+
+/// Draw a list marker on the item's first line. With a `gutter` width the marker is
+/// right-aligned in a box of that width (outside markers); otherwise it takes its own width.
+fn paint_list_marker(
+    ui: &mut egui::Ui,
+    marker: &ListMarker,
+    context: &RenderContext,
+    gutter: Option<f32>,
+) {
+    let color = context.text_color(ui);
+    let font = egui::FontId::new(context.style.font_size, context.style.font_family.clone());
+    let row_height = ui.fonts(|fonts| fonts.row_height(&font));
+    let gap = context.style.font_size * 0.5;
+    let bullet = context.style.font_size * 0.35;
+
+    match marker {
+        ListMarker::None => {
+            if let Some(width) = gutter {
+                ui.add_space(width);
+            }
+        }
+        ListMarker::Text(text) => {
+            let galley = ui.painter().layout_no_wrap(text.clone(), font, color);
+            let width = gutter.unwrap_or(galley.size().x + gap);
+            let (rect, _) =
+                ui.allocate_exact_size(egui::vec2(width, row_height), egui::Sense::hover());
+            let pos = egui::pos2(rect.right() - gap - galley.size().x, rect.top());
+            ui.painter().galley(pos, galley, color);
+        }
+        ListMarker::Disc | ListMarker::Circle | ListMarker::Square => {
+            let width = gutter.unwrap_or(bullet + gap);
+            let (rect, _) =
+                ui.allocate_exact_size(egui::vec2(width, row_height), egui::Sense::hover());
+            let center = egui::pos2(rect.right() - gap - bullet / 2.0, rect.center().y);
+            let painter = ui.painter();
+            match marker {
+                ListMarker::Disc => {
+                    painter.circle_filled(center, bullet / 2.0, color);
+                }
+                ListMarker::Circle => {
+                    painter.circle_stroke(center, bullet / 2.0, egui::Stroke::new(1.0, color));
+                }
+                _ => {
+                    painter.rect_filled(
+                        egui::Rect::from_center_size(center, egui::vec2(bullet, bullet)),
+                        0.0,
+                        color,
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn render_inline(
+    browser: &mut BrowserApp,
+    ui: &mut egui::Ui,
+    egui_ctx: &egui::Context,
+    node: NodeRef,
+    context: &mut RenderContext,
+) {
+    let frame = set_node(browser, ui, egui_ctx, node, context);
+    // These drew themselves; the children of an <svg> are its drawing, and those of an
+    // <iframe> fallback content for browsers without frames
+    if let NodeType::Element(HtmlTag::Table | HtmlTag::Svg | HtmlTag::Iframe) = node.node_type {
+        return;
+    }
+    let children = flow_items(node);
+
+    if frame != egui::Frame::default() {
+        let response = frame.show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                let mut i = 0;
+                while i < children.len() {
+                    if children[i].is_inline() {
+                        let mut context = context.clone();
+                        render_inline_item(browser, ui, egui_ctx, children[i], &mut context);
+                        i += 1;
+                    } else {
+                        let start = i;
+                        while i < children.len() && !children[i].is_inline() {
+                            i += 1;
+                        }
+                        ui.vertical(|ui| {
+                            for &child in &children[start..i] {
+                                if let FlowItem::Node(child) = child {
+                                    let mut context = context.clone();
+                                    render_node(browser, ui, egui_ctx, child, &mut context);
+                                }
+                            }
+                        });
+                        context.after_space.set(true);
+                    }
+                }
+            });
+        });
+        let hovered = ui.rect_contains_pointer(response.response.rect);
+        browser.track_pointer(context.frame, context.element, hovered);
     } else {
         let mut i = 0;
-        while i < node.children.len() {
-            if is_inline(&node.children[i]) {
+        while i < children.len() {
+            if children[i].is_inline() {
                 let mut context = context.clone();
-                render_inline(browser, ui, egui_ctx, &mut node.children[i], &mut context);
+                render_inline_item(browser, ui, egui_ctx, children[i], &mut context);
                 i += 1;
             } else {
                 let start = i;
-                while i < node.children.len() && !is_inline(&node.children[i]) {
+                while i < children.len() && !children[i].is_inline() {
                     i += 1;
                 }
                 ui.vertical(|ui| {
-                    for child in &mut node.children[start..i] {
-                        let mut context = context.clone();
-                        render_node(browser, ui, egui_ctx, child, &mut context);
+                    for &child in &children[start..i] {
+                        if let FlowItem::Node(child) = child {
+                            let mut context = context.clone();
+                            render_node(browser, ui, egui_ctx, child, &mut context);
+                        }
                     }
                 });
+                context.after_space.set(true);
             }
         }
     }
@@ -1,77 +1,264 @@
-use crate::layout::{Color, CssRule, Length, Selector, StyleProperty};
-use std::collections::HashMap;
+use crate::css_tokenizer::{Token, Tokenizer};
+use crate::layout::{
+    AttributeOperator, Color, Combinator, CssRule, Import, InlineStyle, Length, PseudoClass,
+    PseudoElement, Selector, SimpleSelector, StyleProperty, StyleSheet,
+};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
 
-/// A CSS parser implemented in an object-oriented style using a Parser struct
+/// A CSS parser following CSS Syntax Level 3: the stylesheet is tokenized first, and the
+/// rules and declarations are read from the tokens with the spec's error recovery, so a
+/// broken rule or declaration is dropped without taking the rest of the sheet with it.
 pub struct CssParser<'a> {
     input: &'a str,
-    position: usize,
+    tokens: Vec<(Token, Range<usize>)>,
 }
 
 impl<'a> CssParser<'a> {
     /// Create a new parser for the given CSS input
     pub fn new(input: &'a str) -> Self {
-        Self { input, position: 0 }
+        Self {
+            input,
+            tokens: Tokenizer::new(input).collect(),
+        }
     }
 
-    /// Parse all rules from the stylesheet
-    pub fn parse_rules(&mut self) -> Vec<CssRule> {
-        let mut rules = Vec::new();
-        let len = self.input.len();
-        while self.position < len {
-            self.skip_whitespace();
-            if let Some(rule) = self.parse_rule() {
-                rules.push(rule);
-            } else {
-                break;
+    /// Parse the stylesheet: its `@import`s and its rules
+    pub fn parse_stylesheet(&mut self) -> StyleSheet {
+        let tokens = &self.tokens;
+        let mut sheet = StyleSheet::default();
+        let mut imports_allowed = true; // `@import` must come before everything but `@charset`
+        let mut i = 0;
+        while i < tokens.len() {
+            match &tokens[i].0 {
+                // `<!--` and `-->` are allowed around a sheet embedded in HTML
+                Token::Whitespace | Token::Cdo | Token::Cdc => i += 1,
+                Token::AtKeyword(name) if name.eq_ignore_ascii_case("import") => {
+                    let end = at_rule_end(tokens, i);
+                    if imports_allowed {
+                        sheet.imports.extend(self.parse_import(&tokens[i + 1..end]));
+                    }
+                    i = end;
+                }
+                Token::AtKeyword(name) if name.eq_ignore_ascii_case("charset") => {
+                    i = at_rule_end(tokens, i);
+                }
+                _ => {
+                    imports_allowed = false;
+                    i = self.parse_item(i, tokens.len(), &[], &mut sheet.rules);
+                }
             }
         }
-        rules
+        sheet
     }
 
-    /// Parse a single rule: selectors { properties }
-    fn parse_rule(&mut self) -> Option<CssRule> {
-        let selector_text = self.consume_until('{')?;
-        self.position += 1; // skip '{'
-        let body_text = self.consume_until('}')?;
-        self.position += 1; // skip '}'
+    /// Parse the rules in `range`, the contents of a conditional group rule, into `rules`.
+    fn parse_rules(
+        &self,
+        range: Range<usize>,
+        media: &[Arc<MediaQueryList>],
+        rules: &mut Vec<CssRule>,
+    ) {
+        let mut i = range.start;
+        while i < range.end {
+            match &self.tokens[i].0 {
+                Token::Whitespace => i += 1,
+                _ => i = self.parse_item(i, range.end, media, rules),
+            }
+        }
+    }
 
-        let selectors = self.parse_selectors(&selector_text);
-        let properties = self.parse_properties(&body_text);
+    /// Parse the rule or at-rule at `start`, which ends by `end`, into `rules`. `media` are
+    /// the conditions of the `@media` blocks it is in. Returns the index after it.
+    fn parse_item(
+        &self,
+        start: usize,
+        end: usize,
+        media: &[Arc<MediaQueryList>],
+        rules: &mut Vec<CssRule>,
+    ) -> usize {
+        let tokens = &self.tokens[..end];
+        let Token::AtKeyword(name) = &tokens[start].0 else {
+            let (next, rule) = self.parse_rule(start, end, media);
+            rules.extend(rule);
+            return next;
+        };
+        let next = at_rule_end(tokens, start);
+        let mut open = start + 1;
+        while open < next && tokens[open].0 != Token::OpenCurly {
+            open = component_value_end(tokens, open);
+        }
+        if open == next {
+            // A statement at-rule; `@import` is only allowed at the start of the sheet
+            return next;
+        }
+        let prelude = &tokens[start + 1..open];
+        let contents = open + 1..block_end(tokens, open, next);
+        match name.to_ascii_lowercase().as_str() {
+            "media" => {
+                let mut media = media.to_vec();
+                media.push(Arc::new(MediaQueryList::from_tokens(prelude)));
+                self.parse_rules(contents, &media, rules);
+            }
+            // Whether the browser supports something doesn't change, so the condition is
+            // settled here
+            "supports" if self.supports_condition(prelude).unwrap_or(false) => {
+                self.parse_rules(contents, media, rules);
+            }
+            // Other at-rules aren't supported yet; they are skipped with their blocks, as
+            // are `@supports` blocks whose condition is false
+            _ => {}
+        }
+        next
+    }
 
-        Some(CssRule {
-            selectors,
-            properties,
+    /// Parse what follows `@import`: a string or `url()`, then an optional media query
+    /// list, up to the `;`. `None` if it is invalid.
+    fn parse_import(&self, tokens: &[(Token, Range<usize>)]) -> Option<Import> {
+        // An `@import` with a block is invalid; one at the end of the sheet needs no `;`
+        if tokens.iter().any(|(token, _)| *token == Token::OpenCurly) {
+            return None;
+        }
+        let tokens = match tokens.split_last() {
+            Some(((Token::Semicolon, _), prelude)) => prelude,
+            _ => tokens,
+        };
+        let tokens = trim_whitespace(tokens);
+        let (href, rest) = match tokens {
+            [(Token::String(href) | Token::Url(href), _), rest @ ..] => (href.clone(), rest),
+            [(Token::Function(name), _), ..] if name.eq_ignore_ascii_case("url") => {
+                let end = component_value_end(tokens, 0);
+                let arguments = match tokens[1..end].split_last() {
+                    Some(((Token::CloseParen, _), arguments)) => arguments,
+                    _ => &tokens[1..end],
+                };
+                let [(Token::String(href), _)] = trim_whitespace(arguments) else {
+                    return None;
+                };
+                (href.clone(), &tokens[end..])
+            }
+            _ => return None,
+        };
+        Some(Import {
+            href,
+            media: self.text(rest),
         })
     }
 
-    /// Split and parse selectors
-    fn parse_selectors(&self, text: &str) -> Vec<Selector> {
-        text.split(',')
-            .map(|s| s.trim())
-            .filter_map(|s| match s {
-                "*" => Some(Selector::Universal),
-                _ if s.starts_with('.') => Some(Selector::Class(s[1..].to_string())),
-                _ if s.starts_with('#') => Some(Selector::Id(s[1..].to_string())),
-                _ => Some(Selector::Type(s.to_string())),
-            })
-            .collect()
+    /// Parse a single rule, selectors { properties }, which ends by `end`. Returns the index
+    /// of the token after it; the rule is `None` if it was invalid.
+    fn parse_rule(
+        &self,
+        start: usize,
+        end: usize,
+        media: &[Arc<MediaQueryList>],
+    ) -> (usize, Option<CssRule>) {
+        let tokens = &self.tokens[..end];
+        let mut i = start;
+        while i < tokens.len() {
+            if tokens[i].0 != Token::OpenCurly {
+                i = component_value_end(tokens, i);
+                continue;
+            }
+            let end = component_value_end(tokens, i);
+            let prelude = &tokens[start..i];
+            let Some(selectors) = parse_selector_list(self.input, prelude) else {
+                return (end, None);
+            };
+            let block = &tokens[i + 1..block_end(tokens, i, end)];
+            let (properties, important) = self.parse_properties(block);
+            let rule = CssRule {
+                selectors,
+                properties,
+                important,
+                media: media.to_vec(),
+            };
+            return (end, Some(rule));
+        }
+        // A selector without a block at the end of the sheet or group
+        (tokens.len(), None)
     }
 
-    /// Split and parse property declarations
-    fn parse_properties(&self, text: &str) -> HashMap<String, StyleProperty> {
+    /// Parse the declarations of a block, and the names of those marked `!important`
+    fn parse_properties(
+        &self,
+        tokens: &[(Token, Range<usize>)],
+    ) -> (HashMap<String, StyleProperty>, HashSet<String>) {
         let mut map = HashMap::new();
-        for decl in text.split(';') {
-            let decl = decl.trim();
-            if decl.is_empty() {
-                continue;
-            }
-            if let Some((name, value)) = decl.split_once(':') {
-                if let Some(prop) = self.parse_value(value.trim()) {
-                    map.insert(name.trim().to_string(), prop);
+        let mut important = HashSet::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            match &tokens[i].0 {
+                Token::Whitespace | Token::Semicolon => i += 1,
+                Token::AtKeyword(_) => i = at_rule_end(tokens, i),
+                _ => {
+                    // A declaration runs to the next `;` that isn't nested in a block
+                    let start = i;
+                    while i < tokens.len() && tokens[i].0 != Token::Semicolon {
+                        i = component_value_end(tokens, i);
+                    }
+                    if let Some((name, prop, is_important)) =
+                        self.parse_declaration(&tokens[start..i])
+                    {
+                        // A later declaration only replaces an important one if it is
+                        // important too
+                        if is_important {
+                            important.insert(name.clone());
+                        } else if important.contains(&name) {
+                            continue;
+                        }
+                        map.insert(name, prop);
+                    }
                 }
             }
         }
-        map
+        (map, important)
+    }
+
+    /// Parse `name: value` with its `!important` flag, or `None` if it isn't a valid
+    /// declaration.
+    fn parse_declaration(
+        &self,
+        tokens: &[(Token, Range<usize>)],
+    ) -> Option<(String, StyleProperty, bool)> {
+        let (Token::Ident(name), _) = tokens.first()? else {
+            return None;
+        };
+        let rest = trim_whitespace(&tokens[1..]);
+        let (Token::Colon, _) = rest.first()? else {
+            return None;
+        };
+        let mut value = trim_whitespace(&rest[1..]);
+        let mut important = false;
+        if let [before @ .., (Token::Delim('!'), _), (Token::Ident(flag), _)]
+        | [
+            before @ ..,
+            (Token::Delim('!'), _),
+            (Token::Whitespace, _),
+            (Token::Ident(flag), _),
+        ] = value
+            && flag.eq_ignore_ascii_case("important")
+        {
+            value = trim_whitespace(before);
+            important = true;
+        }
+        // Property names are case-insensitive, custom properties (`--name`) are not. Their
+        // value is kept as written, and can be empty
+        if name.starts_with("--") {
+            let value = StyleProperty::Keyword(self.text(value));
+            return Some((name.clone(), value, important));
+        }
+        if value.is_empty() {
+            return None;
+        }
+        let name = name.to_ascii_lowercase();
+        // A value using `var()` can only be parsed once the custom properties are known
+        if uses_var(value) {
+            return Some((name, StyleProperty::Unresolved(self.text(value)), important));
+        }
+        Some((name, self.parse_value(&self.text(value))?, important))
     }
 
     /// Parse a CSS value into a StyleProperty, supporting lengths, colors, keywords
@@ -88,27 +275,253 @@ impl<'a> CssParser<'a> {
         Some(StyleProperty::Keyword(s.to_string()))
     }
 
-    /// Advance until a given delimiter, returning the consumed slice (without the delimiter)
-    fn consume_until(&mut self, delim: char) -> Option<String> {
-        if let Some(idx) = self.input[self.position..].find(delim) {
-            let result = &self.input[self.position..self.position + idx];
-            self.position += idx;
-            Some(result.trim().to_string())
+    /// Evaluate the condition of an `@supports` rule: whether this browser understands the
+    /// declarations and selectors it tests. `None` if the condition can't be parsed, which
+    /// makes it false.
+    fn supports_condition(&self, tokens: &[(Token, Range<usize>)]) -> Option<bool> {
+        let tokens = trim_whitespace(tokens);
+        let skip_whitespace = |i: usize| {
+            tokens[i..]
+                .iter()
+                .position(|(token, _)| *token != Token::Whitespace)
+                .map_or(tokens.len(), |n| i + n)
+        };
+        if let Some((Token::Ident(not), _)) = tokens.first()
+            && not.eq_ignore_ascii_case("not")
+        {
+            let (result, end) = self.supports_in_parens(tokens, skip_whitespace(1))?;
+            return (end == tokens.len()).then_some(!result);
+        }
+        let (mut result, mut i) = self.supports_in_parens(tokens, 0)?;
+        let mut joiner: Option<String> = None;
+        loop {
+            i = skip_whitespace(i);
+            if i == tokens.len() {
+                return Some(result);
+            }
+            let (Token::Ident(word), _) = &tokens[i] else {
+                return None;
+            };
+            let word = word.to_ascii_lowercase();
+            if !matches!(word.as_str(), "and" | "or")
+                || joiner.as_ref().is_some_and(|joiner| *joiner != word)
+            {
+                return None;
+            }
+            let (next, end) = self.supports_in_parens(tokens, skip_whitespace(i + 1))?;
+            result = if word == "and" {
+                result && next
+            } else {
+                result || next
+            };
+            joiner = Some(word);
+            i = end;
+        }
+    }
+
+    /// Evaluate the `(condition)`, `(name: value)` or `selector(...)` at `start`, with the
+    /// index after it. Other functions are never supported.
+    fn supports_in_parens(
+        &self,
+        tokens: &[(Token, Range<usize>)],
+        start: usize,
+    ) -> Option<(bool, usize)> {
+        let (token, _) = tokens.get(start)?;
+        let end = component_value_end(tokens, start);
+        let inner = match tokens[start + 1..end].split_last() {
+            Some(((Token::CloseParen, _), inner)) => trim_whitespace(inner),
+            _ => trim_whitespace(&tokens[start + 1..end]),
+        };
+        let result = match token {
+            Token::OpenParen => match self.parse_declaration(inner) {
+                Some((name, value, _)) => supports_declaration(&name, &value),
+                None => self.supports_condition(inner).unwrap_or(false),
+            },
+            Token::Function(name) if name.eq_ignore_ascii_case("selector") => {
+                parse_selector_list(self.input, inner).is_some_and(|list| list.len() == 1)
+            }
+            Token::Function(_) => false,
+            _ => return None,
+        };
+        Some((result, end))
+    }
+
+    /// The source text of some tokens, without comments and with whitespace collapsed.
+    fn text(&self, tokens: &[(Token, Range<usize>)]) -> String {
+        let mut text = String::new();
+        for (token, range) in trim_whitespace(tokens) {
+            match token {
+                Token::Whitespace => text.push(' '),
+                _ => text.push_str(&self.input[range.clone()]),
+            }
+        }
+        text
+    }
+}
+
+/// The index after the component value starting at `start`: one token, or a whole block
+/// or function with everything nested in it.
+fn component_value_end(tokens: &[(Token, Range<usize>)], start: usize) -> usize {
+    let mut closers = Vec::new();
+    let mut i = start;
+    while let Some((token, _)) = tokens.get(i) {
+        i += 1;
+        match token {
+            Token::OpenCurly => closers.push(Token::CloseCurly),
+            Token::OpenSquare => closers.push(Token::CloseSquare),
+            Token::OpenParen | Token::Function(_) => closers.push(Token::CloseParen),
+            token if closers.last() == Some(token) => {
+                closers.pop();
+            }
+            _ => {}
+        }
+        if closers.is_empty() {
+            break;
+        }
+    }
+    i
+}
+
+/// The index after an at-rule: after its `;`, or after its block.
+fn at_rule_end(tokens: &[(Token, Range<usize>)], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < tokens.len() {
+        match tokens[i].0 {
+            Token::Semicolon => return i + 1,
+            Token::OpenCurly => return component_value_end(tokens, i),
+            _ => i = component_value_end(tokens, i),
+        }
+    }
+    i
+}
+
+/// Where the contents of the block opened at `open` end, given the index after the block:
+/// before its `}`, or at the end of the sheet if it was never closed.
+fn block_end(tokens: &[(Token, Range<usize>)], open: usize, end: usize) -> usize {
+    if end > open + 1 && tokens[end - 1].0 == Token::CloseCurly {
+        end - 1
+    } else {
+        end
+    }
+}
+
+fn trim_whitespace(tokens: &[(Token, Range<usize>)]) -> &[(Token, Range<usize>)] {
+    let start = tokens
+        .iter()
+        .position(|(token, _)| *token != Token::Whitespace)
+        .unwrap_or(tokens.len());
+    let end = tokens
+        .iter()
+        .rposition(|(token, _)| *token != Token::Whitespace)
+        .map_or(start, |last| last + 1);
+    &tokens[start..end]
+}
+
+/// Split `tokens` at the top level `separator`s, leaving those nested in blocks and
+/// functions alone.
+fn split_top_level<'t>(
+    tokens: &'t [(Token, Range<usize>)],
+    separator: &Token,
+) -> Vec<&'t [(Token, Range<usize>)]> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i].0 == *separator {
+            parts.push(&tokens[start..i]);
+            start = i + 1;
+            i += 1;
         } else {
-            None
+            i = component_value_end(tokens, i);
         }
     }
+    parts.push(&tokens[start..]);
+    parts
+}
 
-    /// Skip whitespace characters
-    fn skip_whitespace(&mut self) {
-        while let Some(ch) = self.input[self.position..].chars().next() {
-            if ch.is_whitespace() {
-                self.position += ch.len_utf8();
-            } else {
-                break;
+/// Whether some tokens use `var()`, at any depth.
+fn uses_var(tokens: &[(Token, Range<usize>)]) -> bool {
+    tokens.iter().any(
+        |(token, _)| matches!(token, Token::Function(name) if name.eq_ignore_ascii_case("var")),
+    )
+}
+
+/// The most tokens a value may have once its `var()`s are substituted. Each reference can
+/// double the size of a value, so a short chain of them would otherwise build one too big
+/// to parse.
+const MAX_SUBSTITUTED_TOKENS: usize = 1 << 16;
+
+/// Replace the `var(--name)` and `var(--name, fallback)` references in a value with what
+/// `lookup` gives for the name, or else with the fallback (its own references replaced in
+/// turn). `None` if a reference has neither, or isn't a valid `var()`, or if the result
+/// would be longer than `MAX_SUBSTITUTED_TOKENS`.
+pub fn substitute_vars(
+    value: &str,
+    lookup: &mut dyn FnMut(&str) -> Option<String>,
+) -> Option<String> {
+    let tokens: Vec<_> = Tokenizer::new(value).collect();
+    let mut result = String::new();
+    let mut count = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let (token, range) = &tokens[i];
+        if !matches!(token, Token::Function(name) if name.eq_ignore_ascii_case("var")) {
+            result.push_str(&value[range.clone()]);
+            count += 1;
+            if count > MAX_SUBSTITUTED_TOKENS {
+                return None;
+            }
+            i += 1;
+            continue;
+        }
+        let end = component_value_end(&tokens, i);
+        let arguments = match tokens[i + 1..end].split_last() {
+            Some(((Token::CloseParen, _), arguments)) => arguments,
+            _ => &tokens[i + 1..end],
+        };
+        let (name, fallback) = match arguments
+            .iter()
+            .position(|(token, _)| *token == Token::Comma)
+        {
+            Some(comma) => (&arguments[..comma], Some(&arguments[comma + 1..])),
+            None => (arguments, None),
+        };
+        let [(Token::Ident(name), _)] = trim_whitespace(name) else {
+            return None;
+        };
+        if !name.starts_with("--") {
+            return None;
+        }
+        let substituted = match lookup(name) {
+            Some(substituted) => substituted,
+            None => {
+                let fallback = fallback?;
+                let fallback = match (fallback.first(), fallback.last()) {
+                    (Some((_, first)), Some((_, last))) => &value[first.start..last.end],
+                    _ => "",
+                };
+                substitute_vars(fallback, lookup)?
             }
+        };
+        count += Tokenizer::new(&substituted).count();
+        if count > MAX_SUBSTITUTED_TOKENS {
+            return None;
         }
+        result.push_str(&substituted);
+        i = end;
+    }
+    Some(result)
+}
+
+/// Parse a property value put together by `var()` substitution, as a declaration's value
+/// would be. `None` if it is empty.
+pub fn parse_property_value(value: &str) -> Option<StyleProperty> {
+    let parser = CssParser::new(value);
+    let tokens = trim_whitespace(&parser.tokens);
+    if tokens.is_empty() {
+        return None;
     }
+    parser.parse_value(&parser.text(tokens))
 }
 
 /// Helper struct for parsing length units
@@ -141,50 +554,49 @@ impl ColorParser {
         }
         if let Some(inner) = s.strip_prefix("rgb(").and_then(|s| s.strip_suffix(')')) {
             let parts: Vec<_> = inner.split(',').map(str::trim).collect();
-            if parts.len() == 3 {
-                if let (Ok(r), Ok(g), Ok(b)) =
+            if parts.len() == 3
+                && let (Ok(r), Ok(g), Ok(b)) =
                     (parts[0].parse(), parts[1].parse(), parts[2].parse())
-                {
-                    return Some(Color::Rgb(r, g, b));
-                }
+            {
+                return Some(Color::Rgb(r, g, b));
             }
         }
         if let Some(inner) = s.strip_prefix("rgba(").and_then(|s| s.strip_suffix(')')) {
             let parts: Vec<_> = inner.split(',').map(str::trim).collect();
-            if parts.len() == 4 {
-                if let (Ok(r), Ok(g), Ok(b), Ok(a)) = (
+            if parts.len() == 4
+                && let (Ok(r), Ok(g), Ok(b), Ok(a)) = (
                     parts[0].parse(),
                     parts[1].parse(),
                     parts[2].parse(),
                     parts[3].parse(),
-                ) {
-                    return Some(Color::Rgba(r, g, b, a));
-                }
+                )
+            {
+                return Some(Color::Rgba(r, g, b, a));
             }
         }
         if let Some(inner) = s.strip_prefix("hsl(").and_then(|s| s.strip_suffix(')')) {
             let parts: Vec<_> = inner.split(',').map(str::trim).collect();
-            if parts.len() == 3 {
-                if let (Some(h), Some(sat), Some(light)) = (
+            if parts.len() == 3
+                && let (Some(h), Some(sat), Some(light)) = (
                     parts[0].parse().ok(),
                     parts[1].strip_suffix('%').and_then(|s| s.parse().ok()),
                     parts[2].strip_suffix('%').and_then(|s| s.parse().ok()),
-                ) {
-                    return Some(Color::Hsl(h, sat, light));
-                }
+                )
+            {
+                return Some(Color::Hsl(h, sat, light));
             }
         }
         if let Some(inner) = s.strip_prefix("hsla(").and_then(|s| s.strip_suffix(')')) {
             let parts: Vec<_> = inner.split(',').map(str::trim).collect();
-            if parts.len() == 4 {
-                if let (Some(h), Some(sat), Some(light), Some(a)) = (
+            if parts.len() == 4
+                && let (Some(h), Some(sat), Some(light), Some(a)) = (
                     parts[0].parse().ok(),
                     parts[1].strip_suffix('%').and_then(|s| s.parse().ok()),
                     parts[2].strip_suffix('%').and_then(|s| s.parse().ok()),
                     parts[3].parse().ok(),
-                ) {
-                    return Some(Color::Hsla(h, sat, light, a));
-                }
+                )
+            {
+                return Some(Color::Hsla(h, sat, light, a));
             }
         }
 
@@ -342,8 +754,1047 @@ impl ColorParser {
     }
 }
 
-// simple function delegate
-pub fn parse_css(input: &str) -> Vec<CssRule> {
+/// Parse a color value (`red`, `#f80`, `rgb(255, 128, 0)`, ...) outside of a stylesheet.
+pub fn parse_color(value: &str) -> Option<Color> {
+    ColorParser::parse(value)
+}
+
+/// Parse a stylesheet with its `@import`s.
+pub fn parse_stylesheet(input: &str) -> StyleSheet {
     let mut parser = CssParser::new(input);
-    parser.parse_rules()
+    parser.parse_stylesheet()
+}
+
+// simple function delegate; for the browser's own sheets, whose `@import`s aren't loaded
+pub fn parse_css(input: &str) -> Vec<CssRule> {
+    parse_stylesheet(input).rules
+}
+
+/// Parse the declarations of a `style` attribute, with the same error recovery as a block.
+pub fn parse_inline_style(input: &str) -> InlineStyle {
+    let parser = CssParser::new(input);
+    let (properties, important) = parser.parse_properties(&parser.tokens);
+    InlineStyle {
+        properties,
+        important,
+    }
+}
+
+/// The browser's default styles (`src/ua.css`), applied before the page's own rules.
+/// Parsed once on first use.
+pub fn user_agent_stylesheet() -> &'static [CssRule] {
+    static RULES: OnceLock<Vec<CssRule>> = OnceLock::new();
+    RULES.get_or_init(|| parse_css(include_str!("ua.css")))
+}
+
+/// The reader's own styles, from `$XDG_CONFIG_HOME/berus/user.css` (`~/.config/berus/user.css`
+/// by default). They override the browser's defaults, and the page's rules override them
+/// unless they are `!important`. Read once on first use; there are none if the file is
+/// missing.
+pub fn user_stylesheet() -> &'static [CssRule] {
+    static RULES: OnceLock<Vec<CssRule>> = OnceLock::new();
+    RULES.get_or_init(|| {
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .map(std::path::PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+        let Some(path) = config.map(|config| config.join("berus").join("user.css")) else {
+            return Vec::new();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(css) => parse_css(&css),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                eprintln!("Failed to read user stylesheet {}: {}", path.display(), err);
+                Vec::new()
+            }
+        }
+    })
+}
+
+/// Split and parse a comma separated selector list. A list with an invalid selector in it
+/// matches nothing.
+pub fn parse_selectors(text: &str) -> Vec<Selector> {
+    let tokens: Vec<_> = Tokenizer::new(text).collect();
+    parse_selector_list(text, &tokens).unwrap_or_default()
+}
+
+/// Parse the selector list of a rule, or `None` if any selector in it is invalid, in which
+/// case the whole rule is dropped. `input` is the source the tokens' ranges point into.
+fn parse_selector_list(input: &str, tokens: &[(Token, Range<usize>)]) -> Option<Vec<Selector>> {
+    split_top_level(tokens, &Token::Comma)
+        .into_iter()
+        .map(|tokens| parse_selector(input, trim_whitespace(tokens)))
+        .collect()
+}
+
+/// Parse a complex selector: compound selectors separated by combinators.
+fn parse_selector(input: &str, tokens: &[(Token, Range<usize>)]) -> Option<Selector> {
+    let mut compounds = Vec::new();
+    let mut combinators = Vec::new();
+    let mut i = 0;
+    let pseudo_element = loop {
+        let (compound, pseudo_element, end) = parse_compound(input, tokens, i)?;
+        compounds.push(compound);
+        i = end;
+        if i == tokens.len() {
+            break pseudo_element;
+        }
+        // A pseudo-element can only end the selector
+        if pseudo_element.is_some() {
+            return None;
+        }
+        // Whitespace alone is the descendant combinator; around the others it is ignored
+        let mut combinator = Combinator::Descendant;
+        while let Some((token, _)) = tokens.get(i) {
+            match token {
+                Token::Whitespace => {}
+                Token::Delim('>') if combinator == Combinator::Descendant => {
+                    combinator = Combinator::Child
+                }
+                Token::Delim('+') if combinator == Combinator::Descendant => {
+                    combinator = Combinator::NextSibling
+                }
+                Token::Delim('~') if combinator == Combinator::Descendant => {
+                    combinator = Combinator::SubsequentSibling
+                }
+                _ => break,
+            }
+            i += 1;
+        }
+        combinators.push(combinator);
+    };
+    // Store it right to left, the order it is matched in
+    let subject = compounds.pop()?;
+    let context = combinators
+        .into_iter()
+        .rev()
+        .zip(compounds.into_iter().rev())
+        .collect();
+    Some(Selector {
+        subject,
+        context,
+        pseudo_element,
+    })
+}
+
+/// Parse a compound selector (`a.external[href]`, `p::first-line`) starting at `start`.
+/// Returns it, the pseudo-element it ends with, and the index of the token after it, or
+/// `None` if it is empty or invalid.
+fn parse_compound(
+    input: &str,
+    tokens: &[(Token, Range<usize>)],
+    start: usize,
+) -> Option<(Vec<SimpleSelector>, Option<PseudoElement>, usize)> {
+    let mut compound = Vec::new();
+    let mut i = start;
+    while let Some((token, _)) = tokens.get(i) {
+        // `::name`, or `:name` for the pseudo-elements of CSS 2
+        match tokens.get(i..(i + 3).min(tokens.len())) {
+            Some(
+                [
+                    (Token::Colon, _),
+                    (Token::Colon, _),
+                    (Token::Ident(name), _),
+                    ..,
+                ],
+            ) => {
+                let pseudo = PseudoElement::from_name(name)?;
+                return Some((compound, Some(pseudo), i + 3));
+            }
+            Some([(Token::Colon, _), (Token::Ident(name), _), ..])
+                if matches!(
+                    PseudoElement::from_name(name),
+                    Some(
+                        PseudoElement::Before
+                            | PseudoElement::After
+                            | PseudoElement::FirstLine
+                            | PseudoElement::FirstLetter
+                    )
+                ) =>
+            {
+                return Some((compound, PseudoElement::from_name(name), i + 2));
+            }
+            _ => {}
+        }
+        let simple = match token {
+            // A type or universal selector can only come first
+            Token::Ident(name) if i == start => SimpleSelector::Type(name.clone()),
+            Token::Delim('*') if i == start => SimpleSelector::Universal,
+            Token::Hash(name, true) => SimpleSelector::Id(name.clone()),
+            Token::Delim('.') => match tokens.get(i + 1) {
+                Some((Token::Ident(name), _)) => {
+                    i += 1;
+                    SimpleSelector::Class(name.clone())
+                }
+                _ => return None,
+            },
+            Token::OpenSquare => {
+                let end = component_value_end(tokens, i);
+                if end == i + 1 || tokens[end - 1].0 != Token::CloseSquare {
+                    return None;
+                }
+                let contents = &tokens[i + 1..end - 1];
+                i = end - 1;
+                parse_attribute_selector(trim_whitespace(contents))?
+            }
+            Token::Colon => {
+                let end = component_value_end(tokens, i + 1);
+                let pseudo = parse_pseudo_class(input, tokens.get(i + 1..end)?)?;
+                i = end - 1;
+                SimpleSelector::PseudoClass(pseudo)
+            }
+            Token::Whitespace | Token::Delim('>' | '+' | '~') => break,
+            // Anything else isn't supported
+            _ => return None,
+        };
+        compound.push(simple);
+        i += 1;
+    }
+    (!compound.is_empty()).then_some((compound, None, i))
+}
+
+/// Parse a pseudo-class after its `:`: a name (`hover`) or a function with its arguments
+/// and closing parenthesis (`nth-child(2n+1)`).
+fn parse_pseudo_class(input: &str, tokens: &[(Token, Range<usize>)]) -> Option<PseudoClass> {
+    let (first, rest) = tokens.split_first()?;
+    let pseudo = match &first.0 {
+        Token::Ident(name) => match name.to_ascii_lowercase().as_str() {
+            "root" => PseudoClass::Root,
+            "empty" => PseudoClass::Empty,
+            "first-child" => PseudoClass::FirstChild,
+            "last-child" => PseudoClass::LastChild,
+            "only-child" => PseudoClass::OnlyChild,
+            "first-of-type" => PseudoClass::FirstOfType,
+            "last-of-type" => PseudoClass::LastOfType,
+            "only-of-type" => PseudoClass::OnlyOfType,
+            "any-link" => PseudoClass::AnyLink,
+            "link" => PseudoClass::Link,
+            "visited" => PseudoClass::Visited,
+            "checked" => PseudoClass::Checked,
+            "disabled" => PseudoClass::Disabled,
+            "enabled" => PseudoClass::Enabled,
+            "hover" => PseudoClass::Hover,
+            "active" => PseudoClass::Active,
+            "focus" => PseudoClass::Focus,
+            "focus-within" => PseudoClass::FocusWithin,
+            _ => return None,
+        },
+        Token::Function(name) => {
+            // An unclosed function runs to the end of the selector
+            let arguments = match rest.split_last() {
+                Some(((Token::CloseParen, _), arguments)) => arguments,
+                _ => rest,
+            };
+            let arguments = trim_whitespace(arguments);
+            let nth = || parse_nth(input, arguments);
+            return match name.to_ascii_lowercase().as_str() {
+                "nth-child" => nth().map(|(a, b)| PseudoClass::NthChild(a, b)),
+                "nth-last-child" => nth().map(|(a, b)| PseudoClass::NthLastChild(a, b)),
+                "nth-of-type" => nth().map(|(a, b)| PseudoClass::NthOfType(a, b)),
+                "nth-last-of-type" => nth().map(|(a, b)| PseudoClass::NthLastOfType(a, b)),
+                "not" => parse_selector_list(input, arguments)
+                    .filter(|list| {
+                        list.iter()
+                            .all(|selector| selector.pseudo_element.is_none())
+                    })
+                    .map(PseudoClass::Not),
+                // `:is()` and `:where()` are forgiving: invalid selectors in them are dropped
+                // rather than invalidating the rule
+                "is" | "matches" => Some(PseudoClass::Is(parse_forgiving_list(input, arguments))),
+                "where" => Some(PseudoClass::Where(parse_forgiving_list(input, arguments))),
+                _ => None,
+            };
+        }
+        _ => return None,
+    };
+    rest.is_empty().then_some(pseudo)
+}
+
+fn parse_forgiving_list(input: &str, tokens: &[(Token, Range<usize>)]) -> Vec<Selector> {
+    split_top_level(tokens, &Token::Comma)
+        .into_iter()
+        .filter_map(|tokens| parse_selector(input, trim_whitespace(tokens)))
+        .filter(|selector| selector.pseudo_element.is_none())
+        .collect()
+}
+
+/// Parse the `An+B` argument of the `:nth-*` pseudo-classes: `odd`, `even`, `3`, `2n+1`,
+/// `-n+3`. The tokenizer splits these in odd places (`2n-1` is one dimension), so this works
+/// on the source text instead.
+fn parse_nth(input: &str, tokens: &[(Token, Range<usize>)]) -> Option<(i32, i32)> {
+    let range = tokens.first()?.1.start..tokens.last()?.1.end;
+    let text: String = input[range]
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match text.as_str() {
+        "odd" => return Some((2, 1)),
+        "even" => return Some((2, 0)),
+        _ => {}
+    }
+    let Some((a, b)) = text.split_once('n') else {
+        return Some((0, text.parse().ok()?));
+    };
+    let a = match a {
+        "" | "+" => 1,
+        "-" => -1,
+        a => a.parse().ok()?,
+    };
+    // After the `n` comes nothing or a signed integer
+    let b = match b.chars().next() {
+        None => 0,
+        Some('+' | '-') => b.parse().ok()?,
+        Some(_) => return None,
+    };
+    Some((a, b))
+}
+
+/// Parse the inside of `[...]`: `name`, or `name op value` with an optional `i` or `s` flag.
+fn parse_attribute_selector(tokens: &[(Token, Range<usize>)]) -> Option<SimpleSelector> {
+    let (Token::Ident(name), _) = tokens.first()? else {
+        return None;
+    };
+    let name = name.to_ascii_lowercase();
+    let rest = trim_whitespace(&tokens[1..]);
+    if rest.is_empty() {
+        return Some(SimpleSelector::Attribute {
+            name,
+            value: None,
+            case_insensitive: false,
+        });
+    }
+    let (operator, rest) = match rest {
+        [(Token::Delim('='), _), rest @ ..] => (AttributeOperator::Equals, rest),
+        [(Token::Delim(c), _), (Token::Delim('='), _), rest @ ..] => {
+            let operator = match c {
+                '~' => AttributeOperator::Includes,
+                '|' => AttributeOperator::DashMatch,
+                '^' => AttributeOperator::Prefix,
+                '$' => AttributeOperator::Suffix,
+                '*' => AttributeOperator::Substring,
+                _ => return None,
+            };
+            (operator, rest)
+        }
+        _ => return None,
+    };
+    let rest = trim_whitespace(rest);
+    let value = match rest.first() {
+        Some((Token::Ident(value) | Token::String(value), _)) => value.clone(),
+        _ => return None,
+    };
+    let case_insensitive = match trim_whitespace(&rest[1..]) {
+        [] => false,
+        [(Token::Ident(flag), _)] if flag.eq_ignore_ascii_case("i") => true,
+        [(Token::Ident(flag), _)] if flag.eq_ignore_ascii_case("s") => false,
+        _ => return None,
+    };
+    Some(SimpleSelector::Attribute {
+        name,
+        value: Some((operator, value)),
+        case_insensitive,
+    })
+}
+
+/// The properties of the display that media queries are evaluated against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MediaEnvironment {
+    pub width: f32,
+    pub height: f32,
+    pub resolution: f32, // device pixels per CSS pixel
+    pub dark: bool,
+    pub reduced_motion: bool, // animations are turned off
+}
+
+impl Default for MediaEnvironment {
+    /// A light 1280×800 screen at one device pixel per CSS pixel, for documents that
+    /// haven't been shown yet.
+    fn default() -> Self {
+        MediaEnvironment {
+            width: 1280.0,
+            height: 800.0,
+            resolution: 1.0,
+            dark: false,
+            reduced_motion: false,
+        }
+    }
+}
+
+/// Evaluate a media query list such as `screen and (max-width: 600px), print`.
+/// An empty list matches; unknown media types and features don't.
+pub fn media_matches(query: &str, env: &MediaEnvironment) -> bool {
+    MediaQueryList::parse(query).matches(env)
+}
+
+/// A parsed media query list, as in `@media`, `@import` or a `media` attribute. It matches
+/// if any of its queries does; an empty list matches everything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQueryList(Vec<MediaQuery>);
+
+/// One query of a list: a media type and an optional condition, possibly negated.
+#[derive(Debug, Clone, PartialEq)]
+struct MediaQuery {
+    negated: bool,
+    media_type: String, // `all` if the query only has a condition
+    condition: Option<MediaCondition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+    Unknown, // something else in parentheses, or a function; never known to hold
+}
+
+/// A test of one media feature, by name.
+#[derive(Debug, Clone, PartialEq)]
+enum MediaFeature {
+    Boolean(String),           // `(hover)`: the value isn't zero or `none`
+    Plain(String, MediaValue), // `(min-width: 600px)`
+    // `(400px <= width < 700px)`, stored as `width > 400px` and `width < 700px`
+    Range(String, Vec<(Comparison, MediaValue)>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Less,
+    LessEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+}
+
+/// A media feature value. Lengths are in px and resolutions in dppx; ratios are divided
+/// out.
+#[derive(Debug, Clone, PartialEq)]
+enum MediaValue {
+    Number(f32),
+    Ident(String),
+}
+
+impl MediaQueryList {
+    /// Parse a media query list. A query that can't be parsed is `not all`: it matches
+    /// nothing, but the rest of the list still counts.
+    pub fn parse(input: &str) -> Self {
+        let tokens: Vec<_> = Tokenizer::new(input).collect();
+        Self::from_tokens(&tokens)
+    }
+
+    fn from_tokens(tokens: &[(Token, Range<usize>)]) -> Self {
+        if trim_whitespace(tokens).is_empty() {
+            return MediaQueryList(Vec::new());
+        }
+        let queries = split_top_level(tokens, &Token::Comma)
+            .into_iter()
+            .map(|query| {
+                let query: Vec<_> = query
+                    .iter()
+                    .filter(|(token, _)| *token != Token::Whitespace)
+                    .cloned()
+                    .collect();
+                MediaQuery::parse(&query).unwrap_or(MediaQuery {
+                    negated: true,
+                    media_type: "all".to_string(),
+                    condition: None,
+                })
+            })
+            .collect();
+        MediaQueryList(queries)
+    }
+
+    pub fn matches(&self, env: &MediaEnvironment) -> bool {
+        self.0.is_empty() || self.0.iter().any(|query| query.matches(env))
+    }
+}
+
+impl MediaQuery {
+    /// Parse `[not | only] type [and condition]`, or a bare condition. The tokens are
+    /// without whitespace.
+    fn parse(tokens: &[(Token, Range<usize>)]) -> Option<Self> {
+        let ident = |i: usize| match tokens.get(i) {
+            Some((Token::Ident(name), _)) => Some(name.to_ascii_lowercase()),
+            _ => None,
+        };
+        let opens_condition = |i: usize| matches!(tokens.get(i), Some((Token::OpenParen, _)));
+        if opens_condition(0) || (ident(0).as_deref() == Some("not") && opens_condition(1)) {
+            return Some(MediaQuery {
+                negated: false,
+                media_type: "all".to_string(),
+                condition: Some(parse_media_condition(tokens, true)?),
+            });
+        }
+        let (negated, i) = match ident(0).as_deref() {
+            Some("not") => (true, 1),
+            Some("only") => (false, 1),
+            _ => (false, 0),
+        };
+        let media_type = ident(i)?;
+        if matches!(media_type.as_str(), "not" | "only" | "and" | "or" | "layer") {
+            return None;
+        }
+        let condition = match ident(i + 1).as_deref() {
+            None if tokens.len() == i + 1 => None,
+            // After a media type, conditions can't be joined with `or`
+            Some("and") => Some(parse_media_condition(&tokens[i + 2..], false)?),
+            _ => return None,
+        };
+        Some(MediaQuery {
+            negated,
+            media_type,
+            condition,
+        })
+    }
+
+    fn matches(&self, env: &MediaEnvironment) -> bool {
+        // Only screens are rendered; `print` and the rest never match
+        let result = if matches!(self.media_type.as_str(), "all" | "screen") {
+            self.condition
+                .as_ref()
+                .map_or(Some(true), |condition| condition.evaluate(env))
+        } else {
+            Some(false)
+        };
+        // An unknown result is false, negated or not
+        result.is_some_and(|result| result != self.negated)
+    }
+}
+
+impl MediaCondition {
+    /// Whether the condition holds, or `None` if that is unknown because it tests a
+    /// feature or value this browser doesn't know. `not` keeps an unknown result, and
+    /// `and` and `or` only give one when the known parts don't decide.
+    fn evaluate(&self, env: &MediaEnvironment) -> Option<bool> {
+        match self {
+            MediaCondition::Feature(feature) => feature.evaluate(env),
+            MediaCondition::Not(condition) => condition.evaluate(env).map(|result| !result),
+            MediaCondition::And(conditions) => {
+                let results: Vec<_> = conditions.iter().map(|c| c.evaluate(env)).collect();
+                if results.contains(&Some(false)) {
+                    Some(false)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(true)
+                }
+            }
+            MediaCondition::Or(conditions) => {
+                let results: Vec<_> = conditions.iter().map(|c| c.evaluate(env)).collect();
+                if results.contains(&Some(true)) {
+                    Some(true)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(false)
+                }
+            }
+            MediaCondition::Unknown => None,
+        }
+    }
+}
+
+impl MediaFeature {
+    fn evaluate(&self, env: &MediaEnvironment) -> Option<bool> {
+        match self {
+            MediaFeature::Boolean(name) => Some(match media_feature_value(name, env)? {
+                MediaValue::Number(value) => value != 0.0,
+                MediaValue::Ident(value) => value != "none" && value != "no-preference",
+            }),
+            MediaFeature::Plain(name, value) => {
+                let (name, comparison) = if let Some(name) = name.strip_prefix("min-") {
+                    (name, Comparison::GreaterEqual)
+                } else if let Some(name) = name.strip_prefix("max-") {
+                    (name, Comparison::LessEqual)
+                } else {
+                    (name.as_str(), Comparison::Equal)
+                };
+                match (media_feature_value(name, env)?, value) {
+                    (MediaValue::Number(actual), MediaValue::Number(value)) => {
+                        Some(comparison.holds(actual, *value))
+                    }
+                    // `min-` and `max-` only go with numeric features
+                    (MediaValue::Ident(actual), MediaValue::Ident(value))
+                        if comparison == Comparison::Equal =>
+                    {
+                        Some(actual == *value)
+                    }
+                    _ => None,
+                }
+            }
+            MediaFeature::Range(name, comparisons) => {
+                let MediaValue::Number(actual) = media_feature_value(name, env)? else {
+                    return None;
+                };
+                let mut result = true;
+                for (comparison, value) in comparisons {
+                    let MediaValue::Number(value) = value else {
+                        return None;
+                    };
+                    result &= comparison.holds(actual, *value);
+                }
+                Some(result)
+            }
+        }
+    }
+}
+
+impl Comparison {
+    /// The comparison with its sides swapped: `a < b` is `b > a`.
+    fn flip(self) -> Self {
+        match self {
+            Comparison::Less => Comparison::Greater,
+            Comparison::LessEqual => Comparison::GreaterEqual,
+            Comparison::Equal => Comparison::Equal,
+            Comparison::GreaterEqual => Comparison::LessEqual,
+            Comparison::Greater => Comparison::Less,
+        }
+    }
+
+    fn holds(self, a: f32, b: f32) -> bool {
+        // Near enough is equal, for ratios such as `16/9`
+        let equal = (a - b).abs() < 1e-3;
+        match self {
+            Comparison::Less => a < b && !equal,
+            Comparison::LessEqual => a < b || equal,
+            Comparison::Equal => equal,
+            Comparison::GreaterEqual => a > b || equal,
+            Comparison::Greater => a > b && !equal,
+        }
+    }
+}
+
+/// The value of the media feature `name` on this display, or `None` for a feature this
+/// browser doesn't know.
+fn media_feature_value(name: &str, env: &MediaEnvironment) -> Option<MediaValue> {
+    let keyword = |value: &str| Some(MediaValue::Ident(value.to_string()));
+    match name {
+        "width" | "device-width" => Some(MediaValue::Number(env.width)),
+        "height" | "device-height" => Some(MediaValue::Number(env.height)),
+        "aspect-ratio" | "device-aspect-ratio" => Some(MediaValue::Number(env.width / env.height)),
+        "resolution" => Some(MediaValue::Number(env.resolution)),
+        "color" => Some(MediaValue::Number(8.0)), // bits per color component
+        "color-index" | "monochrome" | "grid" => Some(MediaValue::Number(0.0)),
+        "orientation" if env.height >= env.width => keyword("portrait"),
+        "orientation" => keyword("landscape"),
+        "prefers-color-scheme" if env.dark => keyword("dark"),
+        "prefers-color-scheme" => keyword("light"),
+        "prefers-reduced-motion" if env.reduced_motion => keyword("reduce"),
+        "prefers-reduced-motion" | "prefers-contrast" | "prefers-reduced-transparency" => {
+            keyword("no-preference")
+        }
+        "forced-colors" | "inverted-colors" | "scripting" => keyword("none"),
+        "hover" | "any-hover" => keyword("hover"),
+        "pointer" | "any-pointer" => keyword("fine"),
+        "update" => keyword("fast"),
+        "overflow-block" | "overflow-inline" => keyword("scroll"),
+        "display-mode" => keyword("browser"),
+        "color-gamut" => keyword("srgb"),
+        "dynamic-range" | "video-dynamic-range" => keyword("standard"),
+        _ => None,
+    }
+}
+
+/// Parse a media condition that makes up all of `tokens`: `not` and a condition in
+/// parentheses, or conditions in parentheses joined by `and`s or by `or`s.
+fn parse_media_condition(
+    tokens: &[(Token, Range<usize>)],
+    allow_or: bool,
+) -> Option<MediaCondition> {
+    if let Some((Token::Ident(not), _)) = tokens.first()
+        && not.eq_ignore_ascii_case("not")
+    {
+        let (condition, end) = parse_media_in_parens(tokens, 1)?;
+        return (end == tokens.len()).then(|| MediaCondition::Not(Box::new(condition)));
+    }
+    let (first, mut i) = parse_media_in_parens(tokens, 0)?;
+    let mut conditions = vec![first];
+    let mut joiner: Option<String> = None;
+    while i < tokens.len() {
+        let (Token::Ident(word), _) = &tokens[i] else {
+            return None;
+        };
+        let word = word.to_ascii_lowercase();
+        if !(word == "and" || (word == "or" && allow_or))
+            || joiner.as_ref().is_some_and(|joiner| *joiner != word)
+        {
+            return None;
+        }
+        let (condition, end) = parse_media_in_parens(tokens, i + 1)?;
+        conditions.push(condition);
+        joiner = Some(word);
+        i = end;
+    }
+    Some(match joiner.as_deref() {
+        None => conditions.pop()?,
+        Some("and") => MediaCondition::And(conditions),
+        _ => MediaCondition::Or(conditions),
+    })
+}
+
+/// Parse the condition or feature in the parentheses at `start`, with the index after
+/// them. Anything else in parentheses, and any function, is an unknown condition rather
+/// than an error.
+fn parse_media_in_parens(
+    tokens: &[(Token, Range<usize>)],
+    start: usize,
+) -> Option<(MediaCondition, usize)> {
+    let end = component_value_end(tokens, start);
+    let condition = match &tokens.get(start)?.0 {
+        Token::OpenParen => {
+            let inner = match tokens[start + 1..end].split_last() {
+                Some(((Token::CloseParen, _), inner)) => inner,
+                _ => &tokens[start + 1..end],
+            };
+            parse_media_condition(inner, true)
+                .or_else(|| parse_media_feature(inner).map(MediaCondition::Feature))
+                .unwrap_or(MediaCondition::Unknown)
+        }
+        Token::Function(_) => MediaCondition::Unknown,
+        _ => return None,
+    };
+    Some((condition, end))
+}
+
+/// Parse what is inside `(name)`, `(name: value)` or a range such as `(width >= 600px)`.
+fn parse_media_feature(tokens: &[(Token, Range<usize>)]) -> Option<MediaFeature> {
+    let name = |tokens: &[(Token, Range<usize>)]| match tokens {
+        [(Token::Ident(name), _)] => Some(name.to_ascii_lowercase()),
+        _ => None,
+    };
+    if let Some(name) = name(tokens) {
+        return Some(MediaFeature::Boolean(name));
+    }
+    if let [(Token::Ident(name), _), (Token::Colon, _), value @ ..] = tokens {
+        return Some(MediaFeature::Plain(
+            name.to_ascii_lowercase(),
+            parse_media_value(value)?,
+        ));
+    }
+    // A range: a feature and one or two values, separated by comparisons
+    let mut operands = Vec::new();
+    let mut comparisons = Vec::new();
+    let (mut start, mut i) = (0, 0);
+    while i < tokens.len() {
+        let (comparison, len) = match (&tokens[i].0, tokens.get(i + 1).map(|(token, _)| token)) {
+            (Token::Delim('<'), Some(Token::Delim('='))) => (Comparison::LessEqual, 2),
+            (Token::Delim('>'), Some(Token::Delim('='))) => (Comparison::GreaterEqual, 2),
+            (Token::Delim('<'), _) => (Comparison::Less, 1),
+            (Token::Delim('>'), _) => (Comparison::Greater, 1),
+            (Token::Delim('='), _) => (Comparison::Equal, 1),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        operands.push(&tokens[start..i]);
+        comparisons.push(comparison);
+        i += len;
+        start = i;
+    }
+    operands.push(&tokens[start..]);
+    match (operands.as_slice(), comparisons.as_slice()) {
+        ([left, right], [comparison]) => match name(left) {
+            Some(name) => Some(MediaFeature::Range(
+                name,
+                vec![(*comparison, parse_media_value(right)?)],
+            )),
+            None => Some(MediaFeature::Range(
+                name(right)?,
+                vec![(comparison.flip(), parse_media_value(left)?)],
+            )),
+        },
+        // `low < name < high`, or the other way round; both comparisons point the same way
+        ([low, feature, high], [first, second]) => {
+            let less = |c: &Comparison| matches!(c, Comparison::Less | Comparison::LessEqual);
+            let greater =
+                |c: &Comparison| matches!(c, Comparison::Greater | Comparison::GreaterEqual);
+            if !(less(first) && less(second) || greater(first) && greater(second)) {
+                return None;
+            }
+            Some(MediaFeature::Range(
+                name(feature)?,
+                vec![
+                    (first.flip(), parse_media_value(low)?),
+                    (*second, parse_media_value(high)?),
+                ],
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Parse a media feature value: a number, a length, a resolution, a ratio such as `16/9`,
+/// or a keyword.
+fn parse_media_value(tokens: &[(Token, Range<usize>)]) -> Option<MediaValue> {
+    Some(match tokens {
+        [(Token::Number(number), _)] => MediaValue::Number(*number),
+        [
+            (Token::Number(width), _),
+            (Token::Delim('/'), _),
+            (Token::Number(height), _),
+        ] => MediaValue::Number(width / height),
+        [(Token::Dimension(number, unit), _)] => {
+            // em and rem in media queries are relative to the default font size
+            let scale = match unit.to_ascii_lowercase().as_str() {
+                "px" | "dppx" | "x" => 1.0,
+                "em" | "rem" => 16.0,
+                "in" => 96.0,
+                "cm" => 96.0 / 2.54,
+                "mm" => 96.0 / 25.4,
+                "q" => 96.0 / 101.6,
+                "pt" => 96.0 / 72.0,
+                "pc" => 16.0,
+                "dpi" => 1.0 / 96.0,
+                "dpcm" => 2.54 / 96.0,
+                _ => return None,
+            };
+            MediaValue::Number(number * scale)
+        }
+        [(Token::Ident(keyword), _)] => MediaValue::Ident(keyword.to_ascii_lowercase()),
+        _ => return None,
+    })
+}
+
+/// Whether a declaration would have an effect here: custom properties always do, other
+/// properties if the renderer reads them, and `display` only with a value it knows.
+fn supports_declaration(name: &str, value: &StyleProperty) -> bool {
+    match (name, value) {
+        _ if name.starts_with("--") => true,
+        (_, StyleProperty::Unresolved(_)) => SUPPORTED_PROPERTIES.contains(&name),
+        ("display", StyleProperty::Keyword(display)) => {
+            matches!(
+                display.to_ascii_lowercase().as_str(),
+                "block" | "inline" | "none"
+            )
+        }
+        ("display", _) => false,
+        _ => SUPPORTED_PROPERTIES.contains(&name),
+    }
+}
+
+/// The properties the renderer reads, for `@supports`.
+const SUPPORTED_PROPERTIES: &[&str] = &[
+    "background-color",
+    "border-collapse",
+    "border-color",
+    "border-radius",
+    "border-spacing",
+    "border-width",
+    "caption-side",
+    "color",
+    "content",
+    "counter-increment",
+    "counter-reset",
+    "counter-set",
+    "display",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-weight",
+    "line-height",
+    "list-style",
+    "list-style-position",
+    "list-style-type",
+    "margin",
+    "margin-bottom",
+    "margin-left",
+    "margin-right",
+    "margin-top",
+    "object-fit",
+    "padding",
+    "padding-bottom",
+    "padding-left",
+    "padding-right",
+    "padding-top",
+    "quotes",
+    "text-align",
+    "text-decoration",
+    "vertical-align",
+    "visibility",
+    "white-space",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The type selector of each rule's first selector, e.g. `["p", "h1"]`.
+    fn rule_names(rules: &[CssRule]) -> Vec<&str> {
+        rules
+            .iter()
+            .map(|rule| match rule.selectors[0].subject.as_slice() {
+                [SimpleSelector::Type(name), ..] => name.as_str(),
+                _ => "?",
+            })
+            .collect()
+    }
+
+    fn keyword<'r>(rule: &'r CssRule, name: &str) -> Option<&'r str> {
+        match rule.properties.get(name)? {
+            StyleProperty::Keyword(value) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn stray_close_brace_drops_only_the_next_rule() {
+        // The `}` becomes part of the next rule's selector, which makes it invalid
+        let rules = parse_css("a { display: block } } b { display: block } i { display: none }");
+        assert_eq!(rule_names(&rules), ["a", "i"]);
+    }
+
+    #[test]
+    fn braces_and_semicolons_in_strings() {
+        let rules = parse_css(
+            r#"q::before { content: "}"; quotes: "{" ";" } p { content: ';{'; display: none }"#,
+        );
+        assert_eq!(rule_names(&rules), ["q", "p"]);
+        assert_eq!(keyword(&rules[0], "content"), Some(r#""}""#));
+        assert_eq!(keyword(&rules[0], "quotes"), Some(r#""{" ";""#));
+        assert_eq!(keyword(&rules[1], "content"), Some("';{'"));
+        assert_eq!(keyword(&rules[1], "display"), Some("none"));
+    }
+
+    #[test]
+    fn comments_are_ignored() {
+        let rules = parse_css(
+            "/* } p { */ a /* { */ { /* ; */ display: /**/ block; /* } */ white-space: pre }",
+        );
+        assert_eq!(rule_names(&rules), ["a"]);
+        assert_eq!(keyword(&rules[0], "display"), Some("block"));
+        assert_eq!(keyword(&rules[0], "white-space"), Some("pre"));
+    }
+
+    #[test]
+    fn nested_at_rule_blocks() {
+        let rules = parse_css(
+            "@media screen {
+                @supports (display: block) { p { display: block } }
+                @media print { q { display: none } }
+                @supports (display: grid) { s { display: none } }
+            }
+            @keyframes spin { from { top: 0 } to { top: 1px } }
+            @font-face { font-family: x; src: url(x.woff) }
+            em { display: inline }",
+        );
+        assert_eq!(rule_names(&rules), ["p", "q", "em"]);
+        assert_eq!(rules[0].media.len(), 1);
+        assert_eq!(rules[1].media.len(), 2);
+        assert!(rules[2].media.is_empty());
+    }
+
+    #[test]
+    fn data_url_with_semicolon() {
+        let rules = parse_css(
+            "a { background-image: url(data:image/png;base64,iVBORw0KGgo=); display: block }",
+        );
+        assert_eq!(
+            keyword(&rules[0], "background-image"),
+            Some("url(data:image/png;base64,iVBORw0KGgo=)")
+        );
+        assert_eq!(keyword(&rules[0], "display"), Some("block"));
+    }
+
+    #[test]
+    fn invalid_declaration_is_dropped_alone() {
+        let rules = parse_css("a { display block; : none; white-space: nowrap }");
+        assert_eq!(rules[0].properties.len(), 1);
+        assert_eq!(keyword(&rules[0], "white-space"), Some("nowrap"));
+    }
+
+    fn env() -> MediaEnvironment {
+        MediaEnvironment {
+            width: 800.0,
+            height: 600.0,
+            ..MediaEnvironment::default()
+        }
+    }
+
+    #[test]
+    fn media_range_syntax() {
+        let env = env();
+        assert!(media_matches("(width >= 600px)", &env));
+        assert!(media_matches("(width <= 800px)", &env));
+        assert!(!media_matches("(width < 800px)", &env));
+        assert!(!media_matches("(800px < width)", &env));
+        assert!(media_matches("(600px < width <= 800px)", &env));
+        assert!(!media_matches("(1000px > width > 50em)", &env));
+        assert!(media_matches("(1000px > width > 700px)", &env));
+        assert!(!media_matches("(400px <= width < 600px)", &env));
+        assert!(media_matches("(aspect-ratio > 1/1)", &env));
+        // The comparisons of a double range must point the same way
+        assert!(!media_matches("(600px < width > 700px)", &env));
+        assert!(!media_matches("not (600px < width > 700px)", &env));
+    }
+
+    #[test]
+    fn media_plain_features() {
+        let env = env();
+        assert!(media_matches("(width: 800px)", &env));
+        assert!(media_matches("(min-width: 50em)", &env));
+        assert!(!media_matches("(max-width: 600px)", &env));
+        assert!(media_matches("(aspect-ratio: 4/3)", &env));
+        assert!(media_matches("(orientation: landscape)", &env));
+        // `min-` and `max-` don't go with keywords
+        assert!(!media_matches("(min-orientation: landscape)", &env));
+    }
+
+    #[test]
+    fn media_not_and_or() {
+        let env = env();
+        assert!(!media_matches("not screen", &env));
+        assert!(media_matches("not print", &env));
+        assert!(media_matches("only screen", &env));
+        assert!(media_matches("not (width < 100px)", &env));
+        assert!(media_matches(
+            "screen and (min-width: 600px) and (orientation: landscape)",
+            &env
+        ));
+        assert!(media_matches(
+            "(max-width: 600px) or (orientation: landscape)",
+            &env
+        ));
+        assert!(media_matches(
+            "not ((max-width: 600px) or (hover: none))",
+            &env
+        ));
+        assert!(media_matches("print, (min-width: 600px)", &env));
+    }
+
+    #[test]
+    fn invalid_media_queries_match_nothing() {
+        let env = env();
+        // `or` can't follow a media type, and `and` and `or` can't be mixed
+        assert!(!media_matches(
+            "screen and (max-width: 600px) or (hover)",
+            &env
+        ));
+        assert!(!media_matches("(width > 0px) and (hover) or (color)", &env));
+        assert!(!media_matches("not not screen", &env));
+        // ...but only the broken query in a list is dropped
+        assert!(media_matches("screen and or, (hover)", &env));
+        assert!(media_matches("", &env));
+    }
+
+    #[test]
+    fn unknown_media_features_are_neither_true_nor_false() {
+        let env = env();
+        assert!(!media_matches("(unknown-feature)", &env));
+        assert!(!media_matches("not (unknown-feature)", &env));
+        assert!(media_matches("(unknown-feature) or (width > 100px)", &env));
+        assert!(!media_matches(
+            "(unknown-feature) and (width > 100px)",
+            &env
+        ));
+        assert!(!media_matches(
+            "not ((unknown-feature) and (width > 100px))",
+            &env
+        ));
+        assert!(media_matches(
+            "not ((unknown-feature) and (width < 100px))",
+            &env
+        ));
+    }
 }
@@ -0,0 +1,418 @@
+// css_tokenizer.rs
+use std::ops::Range;
+
+/// A token of CSS Syntax Level 3. Comments are skipped and never produce a token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Function(String), // `name(`; the arguments follow as tokens, up to a `)`
+    AtKeyword(String),
+    Hash(String, bool), // the name, and whether it would be a valid identifier (`#id`)
+    String(String),
+    BadString,   // a string broken by a newline
+    Url(String), // `url(...)` without quotes; `url("...")` is a function
+    BadUrl,
+    Delim(char),
+    Number(f32),
+    Percentage(f32),
+    Dimension(f32, String),
+    Whitespace,
+    Cdo, // `<!--`
+    Cdc, // `-->`
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+}
+
+/// Splits a stylesheet into tokens, each with the byte range of the source it came from.
+/// Never fails: malformed input turns into `BadString`, `BadUrl` or `Delim` tokens that the
+/// parser recovers from.
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self { input, position: 0 }
+    }
+
+    fn peek(&self, n: usize) -> Option<char> {
+        self.input[self.position..].chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.input[self.position..].chars().next()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_comments(&mut self) {
+        while self.input[self.position..].starts_with("/*") {
+            self.position = match self.input[self.position + 2..].find("*/") {
+                Some(end) => self.position + 2 + end + 2,
+                None => self.input.len(), // an unclosed comment runs to the end
+            };
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek(0).is_some_and(is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn starts_identifier(&self) -> bool {
+        starts_identifier(self.peek(0), self.peek(1), self.peek(2))
+    }
+
+    fn starts_number(&self) -> bool {
+        starts_number(self.peek(0), self.peek(1), self.peek(2))
+    }
+
+    fn consume_token(&mut self) -> Option<Token> {
+        let c = self.peek(0)?;
+        if is_whitespace(c) {
+            self.skip_whitespace();
+            return Some(Token::Whitespace);
+        }
+        if c.is_ascii_digit() || (matches!(c, '+' | '-' | '.') && self.starts_number()) {
+            return Some(self.consume_numeric());
+        }
+        if self.input[self.position..].starts_with("-->") {
+            self.position += 3;
+            return Some(Token::Cdc);
+        }
+        if is_name_start(c) || ((c == '-' || c == '\\') && self.starts_identifier()) {
+            return Some(self.consume_ident_like());
+        }
+        self.bump();
+        let token = match c {
+            '"' | '\'' => self.consume_string(c),
+            '#' if self.peek(0).is_some_and(is_name)
+                || valid_escape(self.peek(0), self.peek(1)) =>
+            {
+                let id = self.starts_identifier();
+                Token::Hash(self.consume_name(), id)
+            }
+            '<' if self.input[self.position..].starts_with("!--") => {
+                self.position += 3;
+                Token::Cdo
+            }
+            '@' if self.starts_identifier() => Token::AtKeyword(self.consume_name()),
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '[' => Token::OpenSquare,
+            ']' => Token::CloseSquare,
+            '{' => Token::OpenCurly,
+            '}' => Token::CloseCurly,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            c => Token::Delim(c),
+        };
+        Some(token)
+    }
+
+    /// A number, percentage or dimension (`12`, `-0.5e2`, `50%`, `1.5em`).
+    fn consume_numeric(&mut self) -> Token {
+        let start = self.position;
+        if matches!(self.peek(0), Some('+' | '-')) {
+            self.bump();
+        }
+        let digits = |tokenizer: &mut Self| {
+            while tokenizer.peek(0).is_some_and(|c| c.is_ascii_digit()) {
+                tokenizer.bump();
+            }
+        };
+        digits(self);
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+            self.bump();
+            digits(self);
+        }
+        if matches!(self.peek(0), Some('e' | 'E')) {
+            let sign = matches!(self.peek(1), Some('+' | '-')) as usize;
+            if self.peek(1 + sign).is_some_and(|c| c.is_ascii_digit()) {
+                self.position += 1 + sign;
+                digits(self);
+            }
+        }
+        let value = self.input[start..self.position].parse().unwrap_or(0.0);
+        if self.starts_identifier() {
+            Token::Dimension(value, self.consume_name())
+        } else if self.peek(0) == Some('%') {
+            self.bump();
+            Token::Percentage(value)
+        } else {
+            Token::Number(value)
+        }
+    }
+
+    /// An identifier, a function name or a `url(...)`.
+    fn consume_ident_like(&mut self) -> Token {
+        let name = self.consume_name();
+        if self.peek(0) != Some('(') {
+            return Token::Ident(name);
+        }
+        self.bump();
+        if name.eq_ignore_ascii_case("url") {
+            let arguments = self.position;
+            self.skip_whitespace();
+            if !matches!(self.peek(0), Some('"' | '\'')) {
+                return self.consume_url();
+            }
+            // A quoted URL is an ordinary function with a string argument
+            self.position = arguments;
+        }
+        Token::Function(name)
+    }
+
+    /// The rest of an unquoted `url(`, which may contain `;`, `,` and other punctuation.
+    fn consume_url(&mut self) -> Token {
+        let mut url = String::new();
+        loop {
+            match self.bump() {
+                None | Some(')') => return Token::Url(url),
+                Some(c) if is_whitespace(c) => {
+                    self.skip_whitespace();
+                    if matches!(self.peek(0), None | Some(')')) {
+                        self.bump();
+                        return Token::Url(url);
+                    }
+                    break;
+                }
+                Some('"' | '\'' | '(') => break,
+                Some(c) if is_non_printable(c) => break,
+                Some('\\') => {
+                    if valid_escape(Some('\\'), self.peek(0)) {
+                        url.push(self.consume_escape());
+                    } else {
+                        break;
+                    }
+                }
+                Some(c) => url.push(c),
+            }
+        }
+        // Skip what is left of the broken URL, up to its closing parenthesis
+        loop {
+            match self.bump() {
+                None | Some(')') => return Token::BadUrl,
+                Some('\\') if valid_escape(Some('\\'), self.peek(0)) => {
+                    self.consume_escape();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// A string after its opening quote. A newline ends it as a `BadString`.
+    fn consume_string(&mut self, quote: char) -> Token {
+        let mut value = String::new();
+        loop {
+            match self.peek(0) {
+                None => return Token::String(value),
+                Some(c) if c == quote => {
+                    self.bump();
+                    return Token::String(value);
+                }
+                Some(c) if is_newline(c) => return Token::BadString,
+                Some('\\') => {
+                    self.bump();
+                    match self.peek(0) {
+                        None => {}
+                        // An escaped newline continues the string on the next line
+                        Some('\r') => {
+                            self.bump();
+                            if self.peek(0) == Some('\n') {
+                                self.bump();
+                            }
+                        }
+                        Some(c) if is_newline(c) => {
+                            self.bump();
+                        }
+                        Some(_) => value.push(self.consume_escape()),
+                    }
+                }
+                Some(c) => {
+                    self.bump();
+                    value.push(c);
+                }
+            }
+        }
+    }
+
+    fn consume_name(&mut self) -> String {
+        let mut name = String::new();
+        loop {
+            match self.peek(0) {
+                Some(c) if is_name(c) => {
+                    self.bump();
+                    name.push(c);
+                }
+                Some('\\') if valid_escape(Some('\\'), self.peek(1)) => {
+                    self.bump();
+                    name.push(self.consume_escape());
+                }
+                _ => return name,
+            }
+        }
+    }
+
+    /// The character of an escape, after its backslash: up to six hex digits and an optional
+    /// space (`\201C `), or any other character as itself.
+    fn consume_escape(&mut self) -> char {
+        let Some(c) = self.bump() else {
+            return char::REPLACEMENT_CHARACTER;
+        };
+        if !c.is_ascii_hexdigit() {
+            return c;
+        }
+        let mut hex = String::from(c);
+        while hex.len() < 6 && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+            hex.extend(self.bump());
+        }
+        if self.peek(0).is_some_and(is_whitespace) {
+            self.bump();
+        }
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .filter(|&code| code != 0)
+            .and_then(char::from_u32)
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+}
+
+impl Iterator for Tokenizer<'_> {
+    type Item = (Token, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.skip_comments();
+        let start = self.position;
+        let token = self.consume_token()?;
+        Some((token, start..self.position))
+    }
+}
+
+fn is_newline(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\x0C')
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t') || is_newline(c)
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_name(c: char) -> bool {
+    is_name_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\0'..='\x08' | '\x0B' | '\x0E'..='\x1F' | '\x7F')
+}
+
+fn valid_escape(first: Option<char>, second: Option<char>) -> bool {
+    first == Some('\\') && second.is_some_and(|c| !is_newline(c))
+}
+
+fn starts_identifier(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    match first {
+        Some('-') => {
+            second.is_some_and(|c| is_name_start(c) || c == '-') || valid_escape(second, third)
+        }
+        Some('\\') => valid_escape(first, second),
+        Some(c) => is_name_start(c),
+        None => false,
+    }
+}
+
+fn starts_number(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    let digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+    match first {
+        Some('+' | '-') => digit(second) || (second == Some('.') && digit(third)),
+        Some('.') => digit(second),
+        c => digit(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token> {
+        Tokenizer::new(input).map(|(token, _)| token).collect()
+    }
+
+    #[test]
+    fn strings_hide_braces_and_semicolons() {
+        assert_eq!(tokens(r#""a{b};c""#), [Token::String("a{b};c".into())]);
+        assert_eq!(
+            tokens(r"'}' ';'"),
+            [
+                Token::String("}".into()),
+                Token::Whitespace,
+                Token::String(";".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn newline_breaks_a_string() {
+        assert_eq!(
+            tokens("\"abc\n}"),
+            [Token::BadString, Token::Whitespace, Token::CloseCurly]
+        );
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(
+            tokens("a/* } ; */b"),
+            [Token::Ident("a".into()), Token::Ident("b".into())]
+        );
+        // An unclosed comment runs to the end
+        assert_eq!(
+            tokens("a /* { p"),
+            [Token::Ident("a".into()), Token::Whitespace]
+        );
+        // Not inside strings, though
+        assert_eq!(tokens(r#""/* x */""#), [Token::String("/* x */".into())]);
+    }
+
+    #[test]
+    fn unquoted_url_keeps_punctuation() {
+        assert_eq!(
+            tokens("url(data:image/png;base64,iVBORw0KGgo=)"),
+            [Token::Url("data:image/png;base64,iVBORw0KGgo=".into())]
+        );
+        assert_eq!(tokens("url( a.png )"), [Token::Url("a.png".into())]);
+        // A quoted URL is a function
+        assert_eq!(
+            tokens("url('a;b')"),
+            [
+                Token::Function("url".into()),
+                Token::String("a;b".into()),
+                Token::CloseParen,
+            ]
+        );
+        assert_eq!(tokens("url(a b)"), [Token::BadUrl]);
+    }
+
+    #[test]
+    fn ranges_cover_the_source() {
+        let input = "a { color: /**/ red }";
+        for (token, range) in Tokenizer::new(input) {
+            if let Token::Ident(name) = token {
+                assert_eq!(&input[range], name);
+            }
+        }
+    }
+}
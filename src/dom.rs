@@ -0,0 +1,1324 @@
+// dom.rs
+use crate::css_parser::{
+    MediaEnvironment, parse_inline_style, parse_selectors, user_agent_stylesheet, user_stylesheet,
+};
+use crate::generated::{self, GeneratedContent};
+use crate::layout::{
+    Combinator, CssRule, HtmlNode, HtmlTag, InlineStyle, NodeType, PseudoClass, PseudoElement,
+    Selector, SimpleSelector, StyleProperty, get_next_id,
+};
+use crate::style::ComputedStyle;
+use crate::stylesheet::PageSheet;
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
+
+/// Handle to a node stored in a `Document`. Only meaningful for the document that created it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// A node in the document arena: the node's own data plus its tree links.
+#[derive(Debug, Clone)]
+pub struct NodeData {
+    pub node_type: NodeType,
+    pub attributes: HashMap<String, String>,
+    pub style: HashMap<String, StyleProperty>, // property_name: value, as resolved by the cascade
+    pub pseudo_style: HashMap<PseudoElement, HashMap<String, StyleProperty>>, // ::before, ...
+    pub computed: Arc<ComputedStyle>,          // resolved values, with inheritance
+    pub inline_style: InlineStyle,             // the parsed `style` attribute, kept in sync with it
+    parent: Option<NodeId>,
+    first_child: Option<NodeId>,
+    last_child: Option<NodeId>,
+    prev_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+    style_dirty: bool,  // style must be recomputed by `Document::restyle`
+    layout_dirty: bool, // this box or one below it changed since the last layout
+}
+
+impl NodeData {
+    fn new(node_type: NodeType, attributes: HashMap<String, String>) -> Self {
+        let inline_style = attributes
+            .get("style")
+            .map(|style| parse_inline_style(style))
+            .unwrap_or_default();
+        NodeData {
+            node_type,
+            attributes,
+            style: HashMap::new(),
+            pseudo_style: HashMap::new(),
+            computed: Arc::new(ComputedStyle::default()),
+            inline_style,
+            parent: None,
+            first_child: None,
+            last_child: None,
+            prev_sibling: None,
+            next_sibling: None,
+            style_dirty: false,
+            layout_dirty: false,
+        }
+    }
+}
+
+/// One change made through the mutation API, in the order it happened. The browser
+/// only looks at removals so far; the rest is there for observers of the document.
+#[derive(Debug, Clone)]
+pub enum MutationRecord {
+    /// Children were added to or removed from `target`.
+    ChildList {
+        #[allow(dead_code)]
+        target: NodeId,
+        #[allow(dead_code)]
+        added: Vec<NodeId>,
+        removed: Vec<NodeId>,
+    },
+    /// An attribute of `target` was set or removed. `old_value` is `None` if it was absent.
+    #[allow(dead_code)]
+    Attribute {
+        target: NodeId,
+        name: String,
+        old_value: Option<String>,
+    },
+    /// The text of a text node changed.
+    #[allow(dead_code)]
+    CharacterData { target: NodeId, old_value: String },
+}
+
+/// How far a change to one interactive state reaches, going by what follows the
+/// pseudo-class in the selectors that test it.
+#[derive(Debug, Clone, Copy, Default)]
+struct StateReach {
+    tested: bool,   // some selector tests the state at all
+    subtree: bool,  // a descendant or child combinator follows it, as in `:hover p`
+    siblings: bool, // a sibling combinator follows it, as in `:hover + p`
+}
+
+impl StateReach {
+    fn of<'a>(rules: impl Iterator<Item = &'a CssRule>, f: impl Fn(&PseudoClass) -> bool) -> Self {
+        let mut reach = StateReach::default();
+        for selector in rules.flat_map(|rule| &rule.selectors) {
+            reach.add(selector, false, false, &f);
+        }
+        reach
+    }
+
+    /// Take in the places `selector` tests the state. `subtree` and `siblings` say what
+    /// follows the `:is()`, `:where()` or `:not()` it is nested in.
+    fn add(
+        &mut self,
+        selector: &Selector,
+        mut subtree: bool,
+        mut siblings: bool,
+        f: &impl Fn(&PseudoClass) -> bool,
+    ) {
+        // Right to left, so the combinators seen so far are the ones to the right
+        let compounds = std::iter::once((None, &selector.subject)).chain(
+            selector
+                .context
+                .iter()
+                .map(|(combinator, compound)| (Some(*combinator), compound)),
+        );
+        for (combinator, compound) in compounds {
+            match combinator {
+                Some(Combinator::Descendant | Combinator::Child) => subtree = true,
+                Some(Combinator::NextSibling | Combinator::SubsequentSibling) => siblings = true,
+                None => {}
+            }
+            for simple in compound {
+                let SimpleSelector::PseudoClass(pseudo) = simple else {
+                    continue;
+                };
+                if f(pseudo) {
+                    self.tested = true;
+                    self.subtree |= subtree;
+                    self.siblings |= siblings;
+                }
+                if let PseudoClass::Not(list) | PseudoClass::Is(list) | PseudoClass::Where(list) =
+                    pseudo
+                {
+                    for inner in list {
+                        self.add(inner, subtree, siblings, f);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The reach of each interactive state under a document's rules.
+#[derive(Debug, Clone, Copy, Default)]
+struct StateReaches {
+    hover: StateReach,
+    active: StateReach,
+    focus: StateReach,
+    focus_within: StateReach,
+    link: StateReach, // `:link` and `:visited`
+}
+
+impl StateReaches {
+    fn new(stylesheet: &[CssRule]) -> Self {
+        let rules = || {
+            user_agent_stylesheet()
+                .iter()
+                .chain(user_stylesheet())
+                .chain(stylesheet)
+        };
+        StateReaches {
+            hover: StateReach::of(rules(), |pseudo| matches!(pseudo, PseudoClass::Hover)),
+            active: StateReach::of(rules(), |pseudo| matches!(pseudo, PseudoClass::Active)),
+            focus: StateReach::of(rules(), |pseudo| matches!(pseudo, PseudoClass::Focus)),
+            focus_within: StateReach::of(rules(), |pseudo| {
+                matches!(pseudo, PseudoClass::FocusWithin)
+            }),
+            link: StateReach::of(rules(), |pseudo| {
+                matches!(pseudo, PseudoClass::Link | PseudoClass::Visited)
+            }),
+        }
+    }
+}
+
+/// An HTML document stored as an arena of nodes addressed by `NodeId`.
+///
+/// Nodes link to their parent and siblings, so selectors and event handling can walk
+/// upwards and sideways. The browser keeps documents behind an `Arc`, which makes handing a
+/// snapshot to the renderer (or another thread) a pointer copy.
+#[derive(Debug, Clone)]
+pub struct Document {
+    /// Unique per loaded document; used to key per-document UI state
+    id: usize,
+    nodes: Vec<NodeData>,
+    root: NodeId,
+    /// Author rules the document was styled with; kept so mutations can restyle incrementally
+    stylesheet: Vec<CssRule>,
+    /// The `<style>` and `<link rel="stylesheet">` sheets the rules are collected from
+    page_sheets: Vec<PageSheet>,
+    /// The display the document is shown on, for the rules' media queries
+    media: MediaEnvironment,
+    /// Changes since the last `take_mutations`
+    mutations: Vec<MutationRecord>,
+    /// Interaction state the browser reports, for `:hover`, `:active`, `:focus` and `:visited`
+    hovered: Option<NodeId>,
+    active: Option<NodeId>,
+    focused: Option<NodeId>,
+    visited: HashSet<NodeId>, // links whose URL is in the browser's history
+    /// Which elements a change to each of those states restyles; worked out whenever the
+    /// rules are replaced
+    states: StateReaches,
+    /// The text of `::before`, `::after` and `::marker` boxes; worked out when first asked
+    /// for after a restyle
+    generated: OnceLock<GeneratedContent>,
+}
+
+impl Document {
+    /// Build a document from an owned tree, e.g. the output of the HTML parser.
+    pub fn from_tree(tree: HtmlNode) -> Self {
+        let mut document = Document {
+            id: get_next_id(),
+            nodes: Vec::new(),
+            root: NodeId(0),
+            stylesheet: Vec::new(),
+            page_sheets: Vec::new(),
+            media: MediaEnvironment::default(),
+            mutations: Vec::new(),
+            hovered: None,
+            active: None,
+            focused: None,
+            visited: HashSet::new(),
+            states: StateReaches::new(&[]),
+            generated: OnceLock::new(),
+        };
+        document.root = document.insert_tree(tree);
+        document
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The `<html>` element.
+    pub fn root(&self) -> NodeRef<'_> {
+        self.node(self.root)
+    }
+
+    pub fn head(&self) -> Option<NodeRef<'_>> {
+        self.root()
+            .children()
+            .find(|node| matches!(node.node_type, NodeType::Element(HtmlTag::Head)))
+    }
+
+    pub fn body(&self) -> Option<NodeRef<'_>> {
+        self.root()
+            .children()
+            .find(|node| matches!(node.node_type, NodeType::Element(HtmlTag::Body)))
+    }
+
+    pub fn node(&self, id: NodeId) -> NodeRef<'_> {
+        NodeRef { document: self, id }
+    }
+
+    pub fn get(&self, id: NodeId) -> &NodeData {
+        &self.nodes[id.0]
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> &mut NodeData {
+        &mut self.nodes[id.0]
+    }
+
+    /// Ids of all nodes that are attached to the tree, in document order.
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        std::iter::once(self.root)
+            .chain(self.root().descendants().map(|node| node.id))
+            .collect()
+    }
+
+    pub fn stylesheet(&self) -> &[CssRule] {
+        &self.stylesheet
+    }
+
+    /// Replace the stored rules. Callers restyle afterwards (see `Document::set_stylesheet`).
+    pub(crate) fn replace_stylesheet(&mut self, rules: Vec<CssRule>) {
+        self.states = StateReaches::new(&rules);
+        self.stylesheet = rules;
+        self.generated = OnceLock::new();
+    }
+
+    /// The page's own stylesheets, in document order.
+    pub fn page_sheets(&self) -> &[PageSheet] {
+        &self.page_sheets
+    }
+
+    pub(crate) fn set_page_sheets(&mut self, sheets: Vec<PageSheet>) {
+        self.page_sheets = sheets;
+    }
+
+    pub fn media(&self) -> &MediaEnvironment {
+        &self.media
+    }
+
+    /// Replace the media environment, returning the old one. Callers restyle afterwards if
+    /// it matters (see `Document::set_media`).
+    pub(crate) fn replace_media(&mut self, env: MediaEnvironment) -> MediaEnvironment {
+        let old = std::mem::replace(&mut self.media, env);
+        if old != env {
+            self.generated = OnceLock::new();
+        }
+        old
+    }
+
+    /// What the `content` of the document's pseudo-elements comes to, with counters and
+    /// quotes numbered in document order.
+    pub fn generated_content(&self) -> &GeneratedContent {
+        self.generated.get_or_init(|| generated::generate(self))
+    }
+
+    /// Whether the node is reachable from the root. Removed nodes stay in the arena (their
+    /// ids must not be reused while the UI may still hold them) but are no longer attached.
+    pub fn is_attached(&self, id: NodeId) -> bool {
+        std::iter::successors(Some(id), |id| self.nodes[id.0].parent).any(|id| id == self.root)
+    }
+
+    /// Append a subtree without recording a mutation. For the parser, which builds the
+    /// document before anything observes it. The new nodes are marked for restyling.
+    pub(crate) fn append_parsed(&mut self, parent: NodeId, tree: HtmlNode) -> NodeId {
+        let id = self.insert_tree(tree);
+        self.append(parent, id);
+        self.mark_style_dirty(id);
+        id
+    }
+
+    /// Add attributes the element doesn't have yet, without recording a mutation. For the
+    /// parser, which merges the attributes of repeated `<html>` and `<body>` tags.
+    pub(crate) fn merge_parsed_attributes(
+        &mut self,
+        id: NodeId,
+        attributes: HashMap<String, String>,
+    ) {
+        let node = &mut self.nodes[id.0];
+        for (name, value) in attributes {
+            node.attributes.entry(name).or_insert(value);
+        }
+        node.inline_style = node
+            .attributes
+            .get("style")
+            .map(|style| parse_inline_style(style))
+            .unwrap_or_default();
+        self.mark_style_dirty(id);
+    }
+
+    /// Copy an owned tree into the arena as a detached subtree and return its root.
+    fn insert_tree(&mut self, tree: HtmlNode) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes
+            .push(NodeData::new(tree.node_type, tree.attributes));
+        for child in tree.children {
+            let child_id = self.insert_tree(child);
+            self.append(id, child_id);
+        }
+        id
+    }
+
+    /// Link a detached node as the last child of `parent`.
+    fn append(&mut self, parent: NodeId, child: NodeId) {
+        let last = self.nodes[parent.0].last_child;
+        {
+            let child_data = &mut self.nodes[child.0];
+            child_data.parent = Some(parent);
+            child_data.prev_sibling = last;
+            child_data.next_sibling = None;
+        }
+        match last {
+            Some(last) => self.nodes[last.0].next_sibling = Some(child),
+            None => self.nodes[parent.0].first_child = Some(child),
+        }
+        self.nodes[parent.0].last_child = Some(child);
+    }
+
+    /// Unlink a node (and its subtree) from its parent and siblings.
+    fn detach(&mut self, id: NodeId) {
+        let NodeData {
+            parent,
+            prev_sibling,
+            next_sibling,
+            ..
+        } = self.nodes[id.0];
+        let Some(parent) = parent else {
+            return;
+        };
+        match prev_sibling {
+            Some(prev) => self.nodes[prev.0].next_sibling = next_sibling,
+            None => self.nodes[parent.0].first_child = next_sibling,
+        }
+        match next_sibling {
+            Some(next) => self.nodes[next.0].prev_sibling = prev_sibling,
+            None => self.nodes[parent.0].last_child = prev_sibling,
+        }
+        let node = &mut self.nodes[id.0];
+        node.parent = None;
+        node.prev_sibling = None;
+        node.next_sibling = None;
+    }
+}
+
+/// Mutation API. Every operation appends a `MutationRecord` and marks the affected nodes
+/// dirty, so `restyle` only recomputes what changed and the renderer knows what to lay out again.
+/// The tree operations have no caller in the browser yet, only `<details>` toggling its
+/// attribute does.
+impl Document {
+    /// Create a detached element. Attach it with `append_child` or `insert_before`.
+    #[allow(dead_code)]
+    pub fn create_element(&mut self, tag: HtmlTag, attributes: HashMap<String, String>) -> NodeId {
+        self.insert_tree(HtmlNode::new_element(tag, attributes, Vec::new()))
+    }
+
+    /// Create a detached text node.
+    #[allow(dead_code)]
+    pub fn create_text(&mut self, text: String) -> NodeId {
+        self.insert_tree(HtmlNode::new_text(text))
+    }
+
+    /// Copy an owned tree (e.g. a parsed fragment) into the document as a detached subtree.
+    #[allow(dead_code)]
+    pub fn create_subtree(&mut self, tree: HtmlNode) -> NodeId {
+        self.insert_tree(tree)
+    }
+
+    /// Move `child` to the end of `parent`'s children. Returns false if `child` is
+    /// `parent` or one of its ancestors.
+    #[allow(dead_code)]
+    pub fn append_child(&mut self, parent: NodeId, child: NodeId) -> bool {
+        self.insert_before(parent, child, None)
+    }
+
+    /// Move `child` into `parent` in front of `reference`, or to the end if `reference` is
+    /// `None`. A node that is already attached somewhere is moved, as in the DOM. Returns
+    /// false, changing nothing, if `child` is `parent` or one of its ancestors, or if
+    /// `reference` isn't a child of `parent`.
+    #[allow(dead_code)]
+    pub fn insert_before(
+        &mut self,
+        parent: NodeId,
+        child: NodeId,
+        reference: Option<NodeId>,
+    ) -> bool {
+        // A node can't become its own descendant
+        if std::iter::successors(Some(parent), |id| self.nodes[id.0].parent).any(|id| id == child) {
+            return false;
+        }
+        if let Some(reference) = reference
+            && self.nodes[reference.0].parent != Some(parent)
+        {
+            return false;
+        }
+        // Inserting a node in front of itself leaves it where it is
+        if reference == Some(child) {
+            return true;
+        }
+        if let Some(old_parent) = self.nodes[child.0].parent {
+            self.remove_child(old_parent, child);
+        }
+
+        match reference {
+            None => self.append(parent, child),
+            Some(reference) => {
+                let prev = self.nodes[reference.0].prev_sibling;
+                {
+                    let child_data = &mut self.nodes[child.0];
+                    child_data.parent = Some(parent);
+                    child_data.prev_sibling = prev;
+                    child_data.next_sibling = Some(reference);
+                }
+                self.nodes[reference.0].prev_sibling = Some(child);
+                match prev {
+                    Some(prev) => self.nodes[prev.0].next_sibling = Some(child),
+                    None => self.nodes[parent.0].first_child = Some(child),
+                }
+            }
+        }
+
+        self.children_changed(parent);
+        self.mark_style_dirty(child);
+        self.mutations.push(MutationRecord::ChildList {
+            target: parent,
+            added: vec![child],
+            removed: Vec::new(),
+        });
+        true
+    }
+
+    /// Detach `child` from `parent`. Returns false if it isn't a child of `parent`.
+    #[allow(dead_code)]
+    pub fn remove_child(&mut self, parent: NodeId, child: NodeId) -> bool {
+        if self.nodes[child.0].parent != Some(parent) {
+            return false;
+        }
+        self.detach(child);
+        self.children_changed(parent);
+        self.mutations.push(MutationRecord::ChildList {
+            target: parent,
+            added: Vec::new(),
+            removed: vec![child],
+        });
+        true
+    }
+
+    /// Put a copy of `tree` where `id` is and remove `id`. Returns the new subtree's root,
+    /// or `None` if `id` is the document root or detached.
+    #[allow(dead_code)]
+    pub fn replace_subtree(&mut self, id: NodeId, tree: HtmlNode) -> Option<NodeId> {
+        let parent = self.nodes[id.0].parent?;
+        let new_id = self.insert_tree(tree);
+        let next = self.nodes[id.0].next_sibling;
+        // Record it as a single change rather than an insert plus a remove
+        let mark = self.mutations.len();
+        self.remove_child(parent, id);
+        self.insert_before(parent, new_id, next);
+        self.mutations.truncate(mark);
+        self.mutations.push(MutationRecord::ChildList {
+            target: parent,
+            added: vec![new_id],
+            removed: vec![id],
+        });
+        Some(new_id)
+    }
+
+    pub fn set_attribute(&mut self, id: NodeId, name: &str, value: &str) {
+        let name = name.to_ascii_lowercase();
+        let old_value = self.nodes[id.0]
+            .attributes
+            .insert(name.clone(), value.to_string());
+        if old_value.as_deref() == Some(value) {
+            return;
+        }
+        self.attribute_changed(id, name, old_value);
+    }
+
+    pub fn remove_attribute(&mut self, id: NodeId, name: &str) {
+        let name = name.to_ascii_lowercase();
+        if let Some(old_value) = self.nodes[id.0].attributes.remove(&name) {
+            self.attribute_changed(id, name, Some(old_value));
+        }
+    }
+
+    /// Change the text of a text node. On an element this replaces all of its children with
+    /// a single text node, like setting `textContent`.
+    #[allow(dead_code)]
+    pub fn set_text(&mut self, id: NodeId, text: String) {
+        if let NodeType::Text(old) = &mut self.nodes[id.0].node_type {
+            if *old == text {
+                return;
+            }
+            let old_value = std::mem::replace(old, text);
+            self.mark_layout_dirty(id);
+            self.mutations.push(MutationRecord::CharacterData {
+                target: id,
+                old_value,
+            });
+            return;
+        }
+        while let Some(child) = self.nodes[id.0].first_child {
+            self.remove_child(id, child);
+        }
+        if !text.is_empty() {
+            let text_id = self.create_text(text);
+            self.append_child(id, text_id);
+        }
+    }
+
+    /// Changes recorded since the last call, oldest first.
+    pub fn take_mutations(&mut self) -> Vec<MutationRecord> {
+        std::mem::take(&mut self.mutations)
+    }
+
+    /// Attached nodes whose style must be recomputed, in document order. Clears their flags.
+    pub fn take_style_dirty(&mut self) -> Vec<NodeId> {
+        let dirty: Vec<NodeId> = self
+            .node_ids()
+            .into_iter()
+            .filter(|id| self.nodes[id.0].style_dirty)
+            .collect();
+        for id in &dirty {
+            self.nodes[id.0].style_dirty = false;
+        }
+        // Any restyle can change counters and quotes anywhere after it
+        if !dirty.is_empty() {
+            self.generated = OnceLock::new();
+        }
+        dirty
+    }
+
+    /// Attached nodes that need to be laid out again, in document order. Ancestors of a
+    /// changed node are included because their size may depend on it. Clears the flags.
+    pub fn take_layout_dirty(&mut self) -> Vec<NodeId> {
+        let ids = self.node_ids();
+        let dirty: Vec<NodeId> = ids
+            .iter()
+            .copied()
+            .filter(|id| self.nodes[id.0].layout_dirty)
+            .collect();
+        // Flags on detached nodes are dropped too; they will be set again if reattached
+        for node in &mut self.nodes {
+            node.layout_dirty = false;
+        }
+        dirty
+    }
+
+    /// Whether anything in the document needs laying out again.
+    #[allow(dead_code)]
+    pub fn needs_layout(&self) -> bool {
+        self.nodes[self.root.0].layout_dirty
+    }
+
+    fn attribute_changed(&mut self, id: NodeId, name: String, old_value: Option<String>) {
+        if name == "style" {
+            let node = &mut self.nodes[id.0];
+            node.inline_style = node
+                .attributes
+                .get("style")
+                .map(|style| parse_inline_style(style))
+                .unwrap_or_default();
+        }
+        self.mark_selector_dependents(id);
+        self.mutations.push(MutationRecord::Attribute {
+            target: id,
+            name,
+            old_value,
+        });
+    }
+
+    /// A child list changed: siblings, and through combinators everything inside them, may
+    /// match different selectors now.
+    fn children_changed(&mut self, parent: NodeId) {
+        self.nodes[parent.0].style_dirty = true;
+        let mut child = self.nodes[parent.0].first_child;
+        while let Some(id) = child {
+            self.mark_style_dirty(id);
+            child = self.nodes[id.0].next_sibling;
+        }
+        self.mark_layout_dirty(parent);
+    }
+
+    /// Something selectors can test changed on `id`. That affects the node itself, and
+    /// through combinators its descendants and later siblings.
+    fn mark_selector_dependents(&mut self, id: NodeId) {
+        self.mark_style_dirty(id);
+        let mut sibling = self.nodes[id.0].next_sibling;
+        while let Some(next) = sibling {
+            self.mark_style_dirty(next);
+            sibling = self.nodes[next.0].next_sibling;
+        }
+    }
+
+    /// Mark a subtree for restyling, and its boxes for layout.
+    fn mark_style_dirty(&mut self, id: NodeId) {
+        let subtree: Vec<NodeId> = std::iter::once(id)
+            .chain(self.node(id).descendants().map(|node| node.id))
+            .collect();
+        for id in subtree {
+            let node = &mut self.nodes[id.0];
+            node.style_dirty = true;
+            node.layout_dirty = true;
+        }
+        self.mark_layout_dirty(id);
+    }
+
+    /// Mark just one node for restyling, and its box for layout.
+    fn mark_node_dirty(&mut self, id: NodeId) {
+        self.nodes[id.0].style_dirty = true;
+        self.mark_layout_dirty(id);
+    }
+
+    /// Mark a node and its ancestors for layout.
+    fn mark_layout_dirty(&mut self, id: NodeId) {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            self.nodes[id.0].layout_dirty = true;
+            current = self.nodes[id.0].parent;
+        }
+    }
+}
+
+/// Element state set by the browser from the user's interaction. Changing it restyles the
+/// elements whose `:hover`, `:active`, `:focus` or `:visited` rules may now match differently.
+impl Document {
+    /// The deepest element under the pointer; its ancestors are hovered too.
+    pub fn hovered(&self) -> Option<NodeId> {
+        self.hovered
+    }
+
+    /// The element being pressed.
+    pub fn active(&self) -> Option<NodeId> {
+        self.active
+    }
+
+    pub fn focused(&self) -> Option<NodeId> {
+        self.focused
+    }
+
+    pub fn is_visited(&self, id: NodeId) -> bool {
+        self.visited.contains(&id)
+    }
+
+    pub fn set_hovered(&mut self, id: Option<NodeId>) {
+        let old = std::mem::replace(&mut self.hovered, id);
+        self.ancestor_state_changed(old, id, self.states.hover);
+    }
+
+    pub fn set_active(&mut self, id: Option<NodeId>) {
+        let old = std::mem::replace(&mut self.active, id);
+        self.ancestor_state_changed(old, id, self.states.active);
+    }
+
+    pub fn set_focused(&mut self, id: Option<NodeId>) {
+        let old = std::mem::replace(&mut self.focused, id);
+        for id in old.into_iter().chain(id) {
+            self.state_changed(id, self.states.focus);
+        }
+        self.ancestor_state_changed(old, id, self.states.focus_within);
+    }
+
+    pub fn set_visited(&mut self, id: NodeId, visited: bool) {
+        let changed = if visited {
+            self.visited.insert(id)
+        } else {
+            self.visited.remove(&id)
+        };
+        if changed {
+            self.state_changed(id, self.states.link);
+        }
+    }
+
+    /// A state that also applies to the ancestors moved from `old` to `new`: restyle the
+    /// elements that are in one chain of ancestors but not the other.
+    fn ancestor_state_changed(
+        &mut self,
+        old: Option<NodeId>,
+        new: Option<NodeId>,
+        reach: StateReach,
+    ) {
+        if !reach.tested {
+            return;
+        }
+        let chain = |id: Option<NodeId>| -> Vec<NodeId> {
+            std::iter::successors(id, |id| self.nodes[id.0].parent).collect()
+        };
+        let (old, new) = (chain(old), chain(new));
+        for id in old
+            .iter()
+            .filter(|id| !new.contains(id))
+            .chain(new.iter().filter(|id| !old.contains(id)))
+        {
+            self.state_changed(*id, reach);
+        }
+    }
+
+    /// The state of `id` changed: restyle the element, and its subtree or later siblings
+    /// only if selectors with the state reach that far. Children whose parent's style
+    /// changes are restyled anyway (see `Document::restyle`).
+    fn state_changed(&mut self, id: NodeId, reach: StateReach) {
+        if !reach.tested || !self.is_attached(id) {
+            return;
+        }
+        let mark = |document: &mut Document, id: NodeId| {
+            if reach.subtree {
+                document.mark_style_dirty(id);
+            } else {
+                document.mark_node_dirty(id);
+            }
+        };
+        mark(self, id);
+        if reach.siblings {
+            let mut sibling = self.nodes[id.0].next_sibling;
+            while let Some(next) = sibling {
+                mark(self, next);
+                sibling = self.nodes[next.0].next_sibling;
+            }
+        }
+    }
+}
+
+/// Borrowed view of one node in a `Document`.
+///
+/// Derefs to `NodeData`, so `node.node_type`, `node.attributes` and `node.style` read the
+/// same as they did on the owned `HtmlNode` tree; children are reached with `children()`.
+#[derive(Clone, Copy)]
+pub struct NodeRef<'a> {
+    document: &'a Document,
+    id: NodeId,
+}
+
+impl<'a> Deref for NodeRef<'a> {
+    type Target = NodeData;
+
+    fn deref(&self) -> &NodeData {
+        self.document.get(self.id)
+    }
+}
+
+impl std::fmt::Debug for NodeRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NodeRef")
+            .field("id", &self.id)
+            .field("node_type", &self.node_type)
+            .finish()
+    }
+}
+
+impl PartialEq for NodeRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.document, other.document) && self.id == other.id
+    }
+}
+
+/// Iterator over a node's children, following the sibling links.
+pub struct Children<'a> {
+    document: &'a Document,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = NodeRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.document.node(self.next?);
+        self.next = node.next_sibling;
+        Some(node)
+    }
+}
+
+/// Pre-order iterator over a node's descendants (the node itself is not included).
+pub struct Descendants<'a> {
+    document: &'a Document,
+    root: NodeId,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = NodeRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.document.node(self.next?);
+        // Descend first, then move to the next sibling, climbing back up as needed
+        self.next = node.first_child.or_else(|| {
+            let mut current = node;
+            loop {
+                if current.id == self.root {
+                    return None;
+                }
+                if let Some(sibling) = current.next_sibling {
+                    return Some(sibling);
+                }
+                current = current.parent()?;
+            }
+        });
+        Some(node)
+    }
+}
+
+impl<'a> NodeRef<'a> {
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn document(&self) -> &'a Document {
+        self.document
+    }
+
+    fn link(&self, id: Option<NodeId>) -> Option<NodeRef<'a>> {
+        id.map(|id| self.document.node(id))
+    }
+
+    pub fn parent(&self) -> Option<NodeRef<'a>> {
+        self.link(self.document.get(self.id).parent)
+    }
+
+    pub fn first_child(&self) -> Option<NodeRef<'a>> {
+        self.link(self.document.get(self.id).first_child)
+    }
+
+    #[allow(dead_code)]
+    pub fn last_child(&self) -> Option<NodeRef<'a>> {
+        self.link(self.document.get(self.id).last_child)
+    }
+
+    pub fn prev_sibling(&self) -> Option<NodeRef<'a>> {
+        self.link(self.document.get(self.id).prev_sibling)
+    }
+
+    pub fn next_sibling(&self) -> Option<NodeRef<'a>> {
+        self.link(self.document.get(self.id).next_sibling)
+    }
+
+    pub fn children(&self) -> Children<'a> {
+        Children {
+            document: self.document,
+            next: self.document.get(self.id).first_child,
+        }
+    }
+
+    /// Parent, grandparent, ... up to the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = NodeRef<'a>> + use<'a> {
+        std::iter::successors(self.parent(), |node| node.parent())
+    }
+
+    /// All descendants in document (pre-)order, not including `self`.
+    pub fn descendants(&self) -> Descendants<'a> {
+        Descendants {
+            document: self.document,
+            root: self.id,
+            next: self.document.get(self.id).first_child,
+        }
+    }
+
+    /// The element's tag, or `None` for text nodes.
+    pub fn tag(&self) -> Option<&'a HtmlTag> {
+        match &self.document.get(self.id).node_type {
+            NodeType::Element(tag) => Some(tag),
+            NodeType::Text(_) => None,
+        }
+    }
+
+    pub fn is_element(&self) -> bool {
+        self.tag().is_some()
+    }
+
+    /// Value of an attribute. Attribute names are stored lowercase by the parser.
+    pub fn get_attribute(&self, name: &str) -> Option<&'a str> {
+        self.document
+            .get(self.id)
+            .attributes
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.get_attribute(name).is_some()
+    }
+
+    /// The whitespace separated entries of the `class` attribute.
+    pub fn class_list(&self) -> impl Iterator<Item = &'a str> + use<'a> {
+        self.get_attribute("class")
+            .unwrap_or_default()
+            .split_whitespace()
+    }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        self.class_list().any(|c| c == class)
+    }
+
+    /// Concatenated text of all descendant text nodes, in document order.
+    pub fn text_content(&self) -> String {
+        match &self.node_type {
+            NodeType::Text(text) => text.clone(),
+            NodeType::Element(_) => self
+                .descendants()
+                .filter_map(|node| match &node.document.get(node.id).node_type {
+                    NodeType::Text(text) => Some(text.as_str()),
+                    NodeType::Element(_) => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether this element matches any selector in the comma separated list.
+    #[allow(dead_code)]
+    pub fn matches(&self, selectors: &str) -> bool {
+        let selectors = parse_selectors(selectors);
+        self.matches_any(&selectors)
+    }
+
+    /// First descendant element matching the selector list, in document order.
+    pub fn query_selector(&self, selectors: &str) -> Option<NodeRef<'a>> {
+        let selectors = parse_selectors(selectors);
+        self.descendants().find(|node| node.matches_any(&selectors))
+    }
+
+    /// All descendant elements matching the selector list, in document order.
+    pub fn query_selector_all(&self, selectors: &str) -> Vec<NodeRef<'a>> {
+        let selectors = parse_selectors(selectors);
+        self.descendants()
+            .filter(|node| node.matches_any(&selectors))
+            .collect()
+    }
+
+    /// First descendant element whose `id` attribute equals `id`.
+    #[allow(dead_code)]
+    pub fn get_element_by_id(&self, id: &str) -> Option<NodeRef<'a>> {
+        self.descendants()
+            .find(|node| node.get_attribute("id") == Some(id))
+    }
+
+    /// Nearest inclusive ancestor that matches the selector list.
+    #[allow(dead_code)]
+    pub fn closest(&self, selectors: &str) -> Option<NodeRef<'a>> {
+        let selectors = parse_selectors(selectors);
+        std::iter::once(*self)
+            .chain(self.ancestors())
+            .find(|node| node.matches_any(&selectors))
+    }
+
+    fn matches_any(&self, selectors: &[Selector]) -> bool {
+        self.is_element() && selectors.iter().any(|sel| self.matches_selector(sel))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_parser::parse_html;
+
+    const PAGE: &str = r#"<div id="outer" class="box">
+        <p id="first" class="note">One <em>two</em></p>
+        <p id="second">Three</p>
+        <ul><li id="item"><a href="/x" id="link">link</a></li></ul>
+    </div>"#;
+
+    fn ids(nodes: Vec<NodeRef>) -> Vec<&str> {
+        nodes
+            .into_iter()
+            .map(|node| node.get_attribute("id").unwrap_or("?"))
+            .collect()
+    }
+
+    #[test]
+    fn get_element_by_id() {
+        let document = parse_html(PAGE);
+        let root = document.root();
+        let second = root.get_element_by_id("second").unwrap();
+        assert_eq!(second.text_content(), "Three");
+        assert!(root.get_element_by_id("missing").is_none());
+        // Only descendants are searched
+        assert!(second.get_element_by_id("second").is_none());
+        assert_eq!(
+            root.get_element_by_id("outer")
+                .and_then(|outer| outer.get_element_by_id("link"))
+                .map(|link| link.id()),
+            root.query_selector("a").map(|link| link.id())
+        );
+    }
+
+    #[test]
+    fn closest() {
+        let document = parse_html(PAGE);
+        let link = document.root().get_element_by_id("link").unwrap();
+        // The element itself counts
+        assert_eq!(link.closest("a").map(|node| node.id()), Some(link.id()));
+        let closest = |selectors: &str| {
+            link.closest(selectors)
+                .and_then(|node| node.get_attribute("id"))
+        };
+        assert_eq!(closest("li"), Some("item"));
+        assert_eq!(closest(".box, ul > li"), Some("item"));
+        assert_eq!(closest("div.box"), Some("outer"));
+        assert_eq!(closest("p"), None);
+        assert_eq!(closest("!invalid"), None);
+    }
+
+    #[test]
+    fn matches() {
+        let document = parse_html(PAGE);
+        let first = document.root().get_element_by_id("first").unwrap();
+        assert!(first.matches("p"));
+        assert!(first.matches("div > p.note:first-child"));
+        assert!(first.matches("h1, #first"));
+        assert!(!first.matches("p + p"));
+        assert!(!first.matches("!invalid"));
+        // Text nodes match nothing
+        assert!(!first.first_child().unwrap().matches("*"));
+    }
+
+    #[test]
+    fn query_selector_all_in_document_order() {
+        let document = parse_html(PAGE);
+        let root = document.root();
+        assert_eq!(
+            ids(root.query_selector_all("p, li")),
+            ["first", "second", "item"]
+        );
+        assert_eq!(ids(root.query_selector_all("p ~ p")), ["second"]);
+        assert_eq!(
+            root.get_element_by_id("first").unwrap().text_content(),
+            "One two"
+        );
+    }
+
+    #[test]
+    fn tree_links() {
+        let document = parse_html(PAGE);
+        let outer = document.root().get_element_by_id("outer").unwrap();
+        let last = outer.last_child().unwrap();
+        // The whitespace after `</ul>` is the last child
+        assert!(!last.is_element());
+        assert!(matches!(
+            last.prev_sibling().and_then(|node| node.tag()),
+            Some(HtmlTag::Ul)
+        ));
+        assert_eq!(
+            outer.children().last().map(|node| node.id()),
+            Some(last.id())
+        );
+        assert_eq!(last.parent().map(|node| node.id()), Some(outer.id()));
+    }
+
+    /// A parsed `PAGE` with its styling done and nothing pending.
+    fn settled() -> Document {
+        let mut document = parse_html(PAGE);
+        document.take_layout_dirty();
+        document.take_mutations();
+        document
+    }
+
+    fn id_of(document: &Document, id: &str) -> NodeId {
+        document.root().get_element_by_id(id).unwrap().id()
+    }
+
+    fn child_ids(document: &Document, parent: NodeId) -> Vec<NodeId> {
+        document
+            .node(parent)
+            .children()
+            .map(|node| node.id())
+            .collect()
+    }
+
+    #[test]
+    fn insert_before_links_and_records() {
+        let mut document = settled();
+        let (outer, second) = (id_of(&document, "outer"), id_of(&document, "second"));
+        let new = document.create_element(HtmlTag::P, HashMap::new());
+        assert!(document.take_mutations().is_empty());
+        assert!(document.insert_before(outer, new, Some(second)));
+        assert_eq!(
+            document.node(second).prev_sibling().map(|node| node.id()),
+            Some(new)
+        );
+        assert_eq!(document.get(new).parent, Some(outer));
+        let records = document.take_mutations();
+        assert!(matches!(
+            records.as_slice(),
+            [MutationRecord::ChildList { target, added, removed }]
+                if *target == outer && *added == [new] && removed.is_empty()
+        ));
+        // The new node is restyled, and so are its siblings, which selectors such as
+        // `p + p` or `:nth-child()` can now match differently
+        let dirty = document.take_style_dirty();
+        assert!(dirty.contains(&new) && dirty.contains(&second));
+        assert!(dirty.contains(&id_of(&document, "first")));
+        assert!(document.needs_layout());
+        assert!(document.take_layout_dirty().contains(&outer));
+        assert!(!document.needs_layout());
+    }
+
+    #[test]
+    fn moving_a_node_records_its_removal() {
+        let mut document = settled();
+        let (outer, first, item) = (
+            id_of(&document, "outer"),
+            id_of(&document, "first"),
+            id_of(&document, "item"),
+        );
+        let list = document.get(item).parent.unwrap();
+        assert!(document.append_child(list, first));
+        assert_eq!(child_ids(&document, list), [item, first]);
+        assert!(!child_ids(&document, outer).contains(&first));
+        let records = document.take_mutations();
+        assert!(matches!(
+            records.as_slice(),
+            [
+                MutationRecord::ChildList { target: from, removed, .. },
+                MutationRecord::ChildList { target: to, added, .. },
+            ] if *from == outer && *removed == [first] && *to == list && *added == [first]
+        ));
+    }
+
+    #[test]
+    fn insert_before_refuses_cycles_and_strangers() {
+        let mut document = settled();
+        let (outer, first, item) = (
+            id_of(&document, "outer"),
+            id_of(&document, "first"),
+            id_of(&document, "item"),
+        );
+        let before = child_ids(&document, outer);
+        // A node can't go inside itself or its own descendants
+        assert!(!document.append_child(item, outer));
+        assert!(!document.append_child(outer, outer));
+        // The reference must be a child of the parent
+        let new = document.create_text("x".to_string());
+        assert!(!document.insert_before(outer, new, Some(item)));
+        assert_eq!(child_ids(&document, outer), before);
+        assert!(!document.is_attached(new));
+        assert!(document.take_mutations().is_empty());
+        assert!(document.take_style_dirty().is_empty());
+        // In front of itself is where it already is
+        assert!(document.insert_before(outer, first, Some(first)));
+        assert_eq!(child_ids(&document, outer), before);
+    }
+
+    #[test]
+    fn remove_child() {
+        let mut document = settled();
+        let (outer, first, second) = (
+            id_of(&document, "outer"),
+            id_of(&document, "first"),
+            id_of(&document, "second"),
+        );
+        assert!(!document.remove_child(second, first));
+        assert!(document.take_mutations().is_empty());
+        assert!(document.remove_child(outer, first));
+        assert!(!document.is_attached(first));
+        assert!(document.root().get_element_by_id("first").is_none());
+        let records = document.take_mutations();
+        assert!(matches!(
+            records.as_slice(),
+            [MutationRecord::ChildList { target, added, removed }]
+                if *target == outer && added.is_empty() && *removed == [first]
+        ));
+        // Detached nodes aren't reported dirty
+        let dirty = document.take_style_dirty();
+        assert!(dirty.contains(&second) && !dirty.contains(&first));
+        let layout = document.take_layout_dirty();
+        assert!(layout.contains(&outer) && layout.contains(&document.root().id()));
+    }
+
+    #[test]
+    fn replace_subtree_is_one_change() {
+        let mut document = settled();
+        let (outer, second) = (id_of(&document, "outer"), id_of(&document, "second"));
+        let tree = HtmlNode::new_element(
+            HtmlTag::Div,
+            HashMap::from([("id".to_string(), "new".to_string())]),
+            vec![HtmlNode::new_text("New".to_string())],
+        );
+        let new = document.replace_subtree(second, tree).unwrap();
+        assert_eq!(
+            document
+                .root()
+                .get_element_by_id("new")
+                .map(|node| node.id()),
+            Some(new)
+        );
+        assert_eq!(document.node(new).text_content(), "New");
+        assert!(!document.is_attached(second));
+        let records = document.take_mutations();
+        assert!(matches!(
+            records.as_slice(),
+            [MutationRecord::ChildList { target, added, removed }]
+                if *target == outer && *added == [new] && *removed == [second]
+        ));
+        let root = document.root().id();
+        let tree = HtmlNode::new_text(String::new());
+        assert!(document.replace_subtree(root, tree).is_none());
+    }
+
+    #[test]
+    fn attribute_changes() {
+        let mut document = settled();
+        let (outer, first, second, link) = (
+            id_of(&document, "outer"),
+            id_of(&document, "first"),
+            id_of(&document, "second"),
+            id_of(&document, "link"),
+        );
+        document.set_attribute(second, "Title", "a");
+        document.set_attribute(second, "title", "a");
+        document.set_attribute(second, "title", "b");
+        document.remove_attribute(second, "title");
+        document.remove_attribute(second, "title");
+        let records = document.take_mutations();
+        let changes: Vec<(&str, Option<&str>)> = records
+            .iter()
+            .map(|record| match record {
+                MutationRecord::Attribute {
+                    target,
+                    name,
+                    old_value,
+                } => {
+                    assert_eq!(*target, second);
+                    (name.as_str(), old_value.as_deref())
+                }
+                _ => panic!("unexpected {record:?}"),
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [("title", None), ("title", Some("a")), ("title", Some("b"))]
+        );
+        // The element and what comes after it may match differently; what comes before can't
+        let dirty = document.take_style_dirty();
+        assert!(dirty.contains(&second) && dirty.contains(&link));
+        assert!(!dirty.contains(&first) && !dirty.contains(&outer));
+
+        document.set_attribute(first, "style", "white-space: pre");
+        assert!(
+            document
+                .get(first)
+                .inline_style
+                .properties
+                .contains_key("white-space")
+        );
+        document.remove_attribute(first, "style");
+        assert!(document.get(first).inline_style.properties.is_empty());
+    }
+
+    #[test]
+    fn set_text() {
+        let mut document = settled();
+        let (first, second) = (id_of(&document, "first"), id_of(&document, "second"));
+        let text = document.node(second).first_child().unwrap().id();
+        document.set_text(text, "Four".to_string());
+        document.set_text(text, "Four".to_string());
+        let records = document.take_mutations();
+        assert!(matches!(
+            records.as_slice(),
+            [MutationRecord::CharacterData { target, old_value }]
+                if *target == text && old_value == "Three"
+        ));
+        // Text doesn't change what selectors match, only the layout
+        assert!(document.take_style_dirty().is_empty());
+        assert!(document.take_layout_dirty().contains(&second));
+
+        // On an element it replaces the children
+        document.set_text(first, "Plain".to_string());
+        assert_eq!(document.node(first).text_content(), "Plain");
+        assert_eq!(document.node(first).children().count(), 1);
+        assert!(document.root().query_selector("em").is_none());
+    }
+}
@@ -0,0 +1,496 @@
+// generated.rs
+use crate::css_tokenizer::{Token, Tokenizer};
+use crate::dom::{Document, NodeId, NodeRef};
+use crate::layout::{HtmlTag, PseudoElement, StyleProperty};
+use std::collections::HashMap;
+
+/// The text of the boxes the `content` property generates: the `::before` and `::after` of
+/// elements, and the `::marker` of list items that set one. It is worked out in a single
+/// pass over the document, because counters and quotes depend on everything before a box.
+#[derive(Debug, Clone, Default)]
+pub struct GeneratedContent {
+    text: HashMap<(NodeId, PseudoElement), String>,
+    ordinals: HashMap<NodeId, i64>, // the `list-item` counter of each `<li>`
+}
+
+impl GeneratedContent {
+    /// The text of `pseudo` of the element `id`, or `None` if it generates no box.
+    pub fn get(&self, id: NodeId, pseudo: PseudoElement) -> Option<&str> {
+        self.text.get(&(id, pseudo)).map(String::as_str)
+    }
+
+    /// The number of the list item `id`, which its default marker shows.
+    pub fn ordinal(&self, id: NodeId) -> Option<i64> {
+        self.ordinals.get(&id).copied()
+    }
+}
+
+/// Generate the content of every `::before`, `::after` and `::marker` in `document`.
+pub fn generate(document: &Document) -> GeneratedContent {
+    let mut generator = Generator {
+        counters: Vec::new(),
+        quote_depth: 0,
+        content: GeneratedContent::default(),
+    };
+    generator.visit(document.root());
+    generator.content
+}
+
+/// One item of a `content` value.
+#[derive(Debug, Clone, PartialEq)]
+enum ContentItem {
+    Text(String),
+    Attr(String),                     // attr(name)
+    Counter(String, String),          // counter(name, style)
+    Counters(String, String, String), // counters(name, separator, style)
+    OpenQuote { shown: bool },        // open-quote, no-open-quote
+    CloseQuote { shown: bool },       // close-quote, no-close-quote
+}
+
+struct Generator {
+    // The counters in scope, innermost last. A name can be instantiated several times by
+    // nested elements; `counter()` reads the innermost, `counters()` all of them.
+    counters: Vec<(String, i64)>,
+    quote_depth: usize,
+    content: GeneratedContent,
+}
+
+impl Generator {
+    fn visit(&mut self, node: NodeRef) {
+        if !node.is_element() {
+            return;
+        }
+        let mut changes = CounterChanges::from_style(&node.style);
+        changes.add_list_item(node);
+        self.apply_counters(changes);
+        if matches!(node.tag(), Some(HtmlTag::Li)) {
+            let ordinal = self.counter("list-item").map_or(0, |(_, value)| *value);
+            self.content.ordinals.insert(node.id(), ordinal);
+        }
+        // A counter reset by the element lasts for its following siblings too; anything
+        // instantiated inside it ends with it
+        let scope = self.counters.len();
+
+        for pseudo in [PseudoElement::Marker, PseudoElement::Before] {
+            self.generate(node, pseudo);
+        }
+        for child in node.children() {
+            self.visit(child);
+        }
+        self.generate(node, PseudoElement::After);
+        self.counters.truncate(scope);
+    }
+
+    /// Work out the content of one pseudo-element of `node`, if its `content` gives it one.
+    fn generate(&mut self, node: NodeRef, pseudo: PseudoElement) {
+        let Some(style) = node.pseudo_style.get(&pseudo) else {
+            return;
+        };
+        let Some(StyleProperty::Keyword(value)) = style.get("content") else {
+            return;
+        };
+        let Some(items) = parse_content(value) else {
+            return;
+        };
+        // The pseudo-element is a child of `node`, and can use counters of its own
+        self.apply_counters(CounterChanges::from_style(style));
+        let text = items.iter().map(|item| self.evaluate(node, item)).collect();
+        self.content.text.insert((node.id(), pseudo), text);
+    }
+
+    fn evaluate(&mut self, node: NodeRef, item: &ContentItem) -> String {
+        match item {
+            ContentItem::Text(text) => text.clone(),
+            ContentItem::Attr(name) => node.get_attribute(name).unwrap_or_default().to_string(),
+            ContentItem::Counter(name, style) => {
+                let value = self.counter(name).map_or(0, |(_, value)| *value);
+                counter_text(value, style)
+            }
+            ContentItem::Counters(name, separator, style) => {
+                let values: Vec<String> = self
+                    .counters
+                    .iter()
+                    .filter(|(counter, _)| counter == name)
+                    .map(|(_, value)| counter_text(*value, style))
+                    .collect();
+                if values.is_empty() {
+                    counter_text(0, style)
+                } else {
+                    values.join(separator)
+                }
+            }
+            ContentItem::OpenQuote { shown } => {
+                let depth = self.quote_depth;
+                self.quote_depth += 1;
+                if !shown {
+                    return String::new();
+                }
+                quote(node, depth).map(|(open, _)| open).unwrap_or_default()
+            }
+            ContentItem::CloseQuote { shown } => {
+                // A close quote without an open one shows nothing
+                let Some(depth) = self.quote_depth.checked_sub(1) else {
+                    return String::new();
+                };
+                self.quote_depth = depth;
+                if !shown {
+                    return String::new();
+                }
+                quote(node, depth)
+                    .map(|(_, close)| close)
+                    .unwrap_or_default()
+            }
+        }
+    }
+
+    fn counter(&mut self, name: &str) -> Option<&mut (String, i64)> {
+        self.counters
+            .iter_mut()
+            .rev()
+            .find(|(counter, _)| counter == name)
+    }
+
+    /// Apply counter changes: resets first, then increments, then sets.
+    fn apply_counters(&mut self, changes: CounterChanges) {
+        for (name, value) in changes.resets {
+            self.counters.push((name, value));
+        }
+        // Using a counter that doesn't exist yet creates it at 0
+        for (name, value) in changes.increments {
+            match self.counter(&name) {
+                Some((_, counter)) => *counter = counter.saturating_add(value),
+                None => self.counters.push((name, value)),
+            }
+        }
+        for (name, value) in changes.sets {
+            match self.counter(&name) {
+                Some((_, counter)) => *counter = value,
+                None => self.counters.push((name, value)),
+            }
+        }
+    }
+}
+
+/// The `counter-reset`, `counter-increment` and `counter-set` of an element or
+/// pseudo-element.
+#[derive(Debug, Default)]
+struct CounterChanges {
+    resets: Vec<(String, i64)>,
+    increments: Vec<(String, i64)>,
+    sets: Vec<(String, i64)>,
+}
+
+impl CounterChanges {
+    fn from_style(style: &HashMap<String, StyleProperty>) -> Self {
+        let list = |name: &str, default: i64| match style.get(name) {
+            Some(StyleProperty::Keyword(value)) => parse_counter_list(value, default),
+            _ => Vec::new(),
+        };
+        CounterChanges {
+            resets: list("counter-reset", 0),
+            increments: list("counter-increment", 1),
+            sets: list("counter-set", 0),
+        }
+    }
+
+    /// Lists reset the `list-item` counter and their items count it, unless their style
+    /// does something else with it. `start`, `reversed` and `value` set it like in HTML.
+    fn add_list_item(&mut self, node: NodeRef) {
+        let name = "list-item".to_string();
+        let mentioned = [&self.resets, &self.increments, &self.sets]
+            .iter()
+            .any(|list| list.iter().any(|(counter, _)| *counter == name));
+        if mentioned {
+            return;
+        }
+        let number = |attribute: &str| {
+            node.get_attribute(attribute)
+                .and_then(|value| value.trim().parse::<i64>().ok())
+        };
+        match node.tag() {
+            Some(HtmlTag::Ol | HtmlTag::Ul) => {
+                // Reset to one step before the first item, which the first item's
+                // increment takes it to
+                let (first, step) = list_numbering(node);
+                self.resets.push((name, first.saturating_sub(step)));
+            }
+            Some(HtmlTag::Li) => match number("value") {
+                Some(value) => self.sets.push((name, value)),
+                None => {
+                    let step = node.parent().map_or(1, list_step);
+                    self.increments.push((name, step));
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// How a list numbers its items: the number of the first one, and what each item adds.
+/// `start` sets the first number; a `reversed` `<ol>` counts down, by default from the
+/// number of its items so that the last one is 1.
+fn list_numbering(list: NodeRef) -> (i64, i64) {
+    let step = list_step(list);
+    let first = list
+        .get_attribute("start")
+        .and_then(|start| start.trim().parse::<i64>().ok())
+        .unwrap_or_else(|| {
+            if step < 0 {
+                list.children()
+                    .filter(|child| matches!(child.tag(), Some(HtmlTag::Li)))
+                    .count() as i64
+            } else {
+                1
+            }
+        });
+    (first, step)
+}
+
+/// What each item of `list` adds to the `list-item` counter.
+fn list_step(list: NodeRef) -> i64 {
+    match list.tag() {
+        Some(HtmlTag::Ol) if list.has_attribute("reversed") => -1,
+        _ => 1,
+    }
+}
+
+/// The open and close quote for nesting level `depth`, from the `quotes` of `node`. The
+/// deepest pair is reused below the last level.
+fn quote(node: NodeRef, depth: usize) -> Option<(String, String)> {
+    let default =
+        [("“", "”"), ("‘", "’")].map(|(open, close)| (open.to_string(), close.to_string()));
+    let quotes = node.computed.quotes.as_deref().unwrap_or(&default);
+    let last = quotes.len().checked_sub(1)?;
+    quotes.get(depth.min(last)).cloned()
+}
+
+/// Parse a `content` value. `None` for `normal` and `none`, which generate no box, and for
+/// values we can't read, which are invalid.
+fn parse_content(value: &str) -> Option<Vec<ContentItem>> {
+    let tokens: Vec<Token> = Tokenizer::new(value)
+        .map(|(token, _)| token)
+        .filter(|token| *token != Token::Whitespace)
+        .collect();
+    let mut items = Vec::new();
+    let mut i = 0;
+    while let Some(token) = tokens.get(i) {
+        let item = match token {
+            Token::String(text) => ContentItem::Text(text.clone()),
+            Token::Ident(name) => match name.to_ascii_lowercase().as_str() {
+                "open-quote" => ContentItem::OpenQuote { shown: true },
+                "close-quote" => ContentItem::CloseQuote { shown: true },
+                "no-open-quote" => ContentItem::OpenQuote { shown: false },
+                "no-close-quote" => ContentItem::CloseQuote { shown: false },
+                _ => return None,
+            },
+            Token::Function(name) => {
+                let end = tokens[i..]
+                    .iter()
+                    .position(|token| *token == Token::CloseParen)
+                    .map_or(tokens.len(), |end| i + end);
+                let arguments: Vec<&[Token]> = tokens[i + 1..end]
+                    .split(|token| *token == Token::Comma)
+                    .collect();
+                i = end;
+                let ident = |index: usize| match arguments.get(index)? {
+                    [Token::Ident(name)] => Some(name.clone()),
+                    _ => None,
+                };
+                let style = |index: usize| match arguments.get(index) {
+                    None => Some("decimal".to_string()),
+                    Some(_) => ident(index).map(|style| style.to_ascii_lowercase()),
+                };
+                match name.to_ascii_lowercase().as_str() {
+                    "attr" if arguments.len() == 1 => {
+                        ContentItem::Attr(ident(0)?.to_ascii_lowercase())
+                    }
+                    "counter" if arguments.len() <= 2 => ContentItem::Counter(ident(0)?, style(1)?),
+                    "counters" if (2..=3).contains(&arguments.len()) => {
+                        let separator = match arguments[1] {
+                            [Token::String(separator)] => separator.clone(),
+                            _ => return None,
+                        };
+                        ContentItem::Counters(ident(0)?, separator, style(2)?)
+                    }
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        items.push(item);
+        i += 1;
+    }
+    (!items.is_empty()).then_some(items)
+}
+
+/// Parse a `counter-reset`, `counter-increment` or `counter-set` value: names, each
+/// optionally followed by an integer (`chapter 2 section`). `none` is the empty list.
+fn parse_counter_list(value: &str, default: i64) -> Vec<(String, i64)> {
+    let mut counters: Vec<(String, i64)> = Vec::new();
+    for (token, _) in Tokenizer::new(value) {
+        match token {
+            Token::Ident(name) if name.eq_ignore_ascii_case("none") && counters.is_empty() => {}
+            Token::Ident(name) => counters.push((name, default)),
+            Token::Number(number) => match counters.last_mut() {
+                Some((_, value)) => *value = number as i64,
+                None => return Vec::new(),
+            },
+            Token::Whitespace => {}
+            _ => return Vec::new(),
+        }
+    }
+    counters
+}
+
+/// A counter value in a list style: `decimal`, `lower-roman`, `upper-alpha`, ... The bullet
+/// styles show their bullet whatever the value, and `none` shows nothing.
+pub fn counter_text(value: i64, style: &str) -> String {
+    match style {
+        "none" => String::new(),
+        "disc" => "•".to_string(),
+        "circle" => "◦".to_string(),
+        "square" => "▪".to_string(),
+        "decimal-leading-zero" if (0..10).contains(&value) => format!("0{}", value),
+        "lower-alpha" | "lower-latin" => to_alpha(value).unwrap_or(value.to_string()),
+        "upper-alpha" | "upper-latin" => to_alpha(value)
+            .map(|text| text.to_uppercase())
+            .unwrap_or(value.to_string()),
+        "lower-roman" => to_roman(value)
+            .map(|text| text.to_lowercase())
+            .unwrap_or(value.to_string()),
+        "upper-roman" => to_roman(value).unwrap_or(value.to_string()),
+        _ => value.to_string(),
+    }
+}
+
+/// 1 -> "a", 26 -> "z", 27 -> "aa". `None` below 1, where CSS falls back to decimal.
+fn to_alpha(mut n: i64) -> Option<String> {
+    if n < 1 {
+        return None;
+    }
+    let mut letters = Vec::new();
+    while n > 0 {
+        n -= 1;
+        letters.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    Some(letters.into_iter().rev().collect())
+}
+
+/// Upper-case roman numerals for 1..=3999, `None` outside that range.
+fn to_roman(mut n: i64) -> Option<String> {
+    if !(1..=3999).contains(&n) {
+        return None;
+    }
+    const NUMERALS: [(i64, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut out = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            out.push_str(numeral);
+            n -= value;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_parser::parse_html;
+
+    /// The number of each `<li>` of `html`, in document order.
+    fn ordinals(html: &str) -> Vec<i64> {
+        let document = parse_html(html);
+        let generated = document.generated_content();
+        document
+            .root()
+            .query_selector_all("li")
+            .into_iter()
+            .map(|item| generated.ordinal(item.id()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn start() {
+        assert_eq!(ordinals("<ol><li></li><li></li><li></li></ol>"), [1, 2, 3]);
+        assert_eq!(ordinals(r#"<ol start="5"><li></li><li></li></ol>"#), [5, 6]);
+        assert_eq!(
+            ordinals(r#"<ol start="-1"><li></li><li></li></ol>"#),
+            [-1, 0]
+        );
+        assert_eq!(ordinals(r#"<ol start="x"><li></li><li></li></ol>"#), [1, 2]);
+        assert_eq!(ordinals("<ul><li></li><li></li></ul>"), [1, 2]);
+    }
+
+    #[test]
+    fn reversed() {
+        let html = "<ol reversed><li></li><li></li><li></li></ol>";
+        assert_eq!(ordinals(html), [3, 2, 1]);
+        let html = r#"<ol reversed start="10"><li></li><li></li></ol>"#;
+        assert_eq!(ordinals(html), [10, 9]);
+        // Only `<ol>` can be reversed
+        assert_eq!(ordinals("<ul reversed><li></li><li></li></ul>"), [1, 2]);
+    }
+
+    #[test]
+    fn value() {
+        let html = r#"<ol><li></li><li value="10"></li><li></li></ol>"#;
+        assert_eq!(ordinals(html), [1, 10, 11]);
+        let html = r#"<ol reversed><li></li><li value="10"></li><li></li></ol>"#;
+        assert_eq!(ordinals(html), [3, 10, 9]);
+    }
+
+    #[test]
+    fn nested_lists_count_separately() {
+        let html = "<ol><li>a<ol reversed><li></li><li></li></ol></li><li></li></ol>";
+        assert_eq!(ordinals(html), [1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn numbers_saturate_at_the_limits() {
+        let html = r#"<ol start="9223372036854775807"><li></li><li></li></ol>"#;
+        assert_eq!(ordinals(html), [i64::MAX, i64::MAX]);
+        let html = r#"<ol reversed start="-9223372036854775808"><li></li><li></li></ol>"#;
+        assert_eq!(ordinals(html), [i64::MIN, i64::MIN]);
+        let html = r#"<ol><li value="9223372036854775807"></li><li></li></ol>"#;
+        assert_eq!(ordinals(html), [i64::MAX, i64::MAX]);
+        let html = r#"<ol reversed><li value="-9223372036854775808"></li><li></li></ol>"#;
+        assert_eq!(ordinals(html), [i64::MIN, i64::MIN]);
+        // A number that doesn't fit is ignored
+        let html = r#"<ol start="9223372036854775808"><li></li></ol>"#;
+        assert_eq!(ordinals(html), [1]);
+    }
+
+    #[test]
+    fn counter_list_item_matches_the_markers() {
+        let html = r#"<style>li::before { content: counter(list-item) ". " }</style>
+            <ol reversed start="3"><li>a</li><li value="7">b</li><li>c</li></ol>"#;
+        let document = parse_html(html);
+        let generated = document.generated_content();
+        let items = document.root().query_selector_all("li");
+        let before: Vec<&str> = items
+            .iter()
+            .map(|item| generated.get(item.id(), PseudoElement::Before).unwrap())
+            .collect();
+        assert_eq!(before, ["3. ", "7. ", "6. "]);
+        let ordinals: Vec<i64> = items
+            .iter()
+            .map(|item| generated.ordinal(item.id()).unwrap())
+            .collect();
+        assert_eq!(ordinals, [3, 7, 6]);
+    }
+}
@@ -0,0 +1,226 @@
+// head.rs
+use crate::dom::{Document, NodeRef};
+use crate::network;
+use std::time::Duration;
+
+/// What a page says about itself in the `<meta>` and `<link>` elements of its `<head>`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageInfo {
+    pub refresh: Option<Refresh>, // <meta http-equiv="refresh">
+    pub icon: Option<String>,     // <link rel="icon">, resolved
+    pub canonical: Option<String>,
+    pub feeds: Vec<Feed>, // <link rel="alternate"> to RSS and Atom feeds
+    pub viewport: Option<Viewport>, // <meta name="viewport">
+    pub color_schemes: Vec<String>, // <meta name="color-scheme">: `light`/`dark`, preferred first
+}
+
+/// A `<meta http-equiv="refresh">`: load `url` once `delay` has passed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Refresh {
+    pub delay: Duration,
+    pub url: String, // the page itself when the content names no URL
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feed {
+    pub title: Option<String>,
+    pub url: String,
+    pub atom: bool, // application/atom+xml rather than application/rss+xml
+}
+
+/// The properties of `<meta name="viewport">` a desktop window can tell the user about.
+/// Like other desktop browsers we lay out at the window's width whatever they say.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Viewport {
+    pub width: Option<String>, // `device-width` or a number of pixels
+    pub initial_scale: Option<f32>,
+    pub user_scalable: bool,
+}
+
+impl PageInfo {
+    /// Whether the page should be shown with dark colors: the scheme the user prefers if the
+    /// page supports it, otherwise the one the page lists first. Pages that name no scheme
+    /// follow the user.
+    pub fn dark(&self, prefer_dark: bool) -> bool {
+        let preferred = if prefer_dark { "dark" } else { "light" };
+        match self.color_schemes.first() {
+            Some(first) if !self.color_schemes.iter().any(|s| s == preferred) => first == "dark",
+            _ => prefer_dark,
+        }
+    }
+}
+
+/// Read the metadata of `document`, resolving URLs against `base_url`.
+pub fn page_info(document: &Document, base_url: &str) -> PageInfo {
+    let mut info = PageInfo::default();
+    let Some(head) = document.head() else {
+        return info;
+    };
+    let resolve = |href: &str| network::resolve_url(base_url, href.trim());
+
+    for meta in head.query_selector_all("meta") {
+        let content = meta.get_attribute("content").unwrap_or_default();
+        if let Some(equiv) = meta.get_attribute("http-equiv") {
+            // The first refresh wins
+            if equiv.eq_ignore_ascii_case("refresh") && info.refresh.is_none() {
+                info.refresh = parse_refresh(content).and_then(|(delay, url)| {
+                    let url = match url {
+                        Some(url) => resolve(url)?,
+                        None => base_url.to_string(),
+                    };
+                    Some(Refresh { delay, url })
+                });
+            }
+            continue;
+        }
+        let name = meta.get_attribute("name").unwrap_or_default();
+        if name.eq_ignore_ascii_case("viewport") && info.viewport.is_none() {
+            info.viewport = Some(parse_viewport(content));
+        } else if name.eq_ignore_ascii_case("color-scheme") && info.color_schemes.is_empty() {
+            info.color_schemes = content
+                .split_whitespace()
+                .map(str::to_ascii_lowercase)
+                .filter(|scheme| scheme == "light" || scheme == "dark")
+                .collect();
+        }
+    }
+
+    for link in head.query_selector_all("link") {
+        let Some(url) = link.get_attribute("href").and_then(resolve) else {
+            continue;
+        };
+        if has_rel(link, "icon") && info.icon.is_none() {
+            info.icon = Some(url);
+        } else if has_rel(link, "canonical") && info.canonical.is_none() {
+            info.canonical = Some(url);
+        } else if has_rel(link, "alternate") {
+            let mime = link.get_attribute("type").unwrap_or_default().trim();
+            let atom = mime.eq_ignore_ascii_case("application/atom+xml");
+            if atom || mime.eq_ignore_ascii_case("application/rss+xml") {
+                let title = link
+                    .get_attribute("title")
+                    .map(str::trim)
+                    .filter(|title| !title.is_empty())
+                    .map(str::to_string);
+                info.feeds.push(Feed { title, url, atom });
+            }
+        }
+    }
+    info
+}
+
+/// Whether a `<link>`'s space separated `rel` list contains `keyword`.
+fn has_rel(link: NodeRef, keyword: &str) -> bool {
+    link.get_attribute("rel")
+        .unwrap_or_default()
+        .split_ascii_whitespace()
+        .any(|rel| rel.eq_ignore_ascii_case(keyword))
+}
+
+/// Parse the content of a refresh `<meta>` (`5`, `0; url=/next` or `3, URL='page.html'`)
+/// into the delay and the URL, if one is given.
+fn parse_refresh(content: &str) -> Option<(Duration, Option<&str>)> {
+    let content = content.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    let digits = content
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(content.len());
+    // A fractional part is allowed but ignored
+    let rest = content[digits..].trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if digits == 0 && rest.len() == content.len() {
+        return None;
+    }
+    // A delay too long to represent is an error, not an immediate refresh
+    let seconds = match digits {
+        0 => 0,
+        _ => content[..digits].parse::<u64>().ok()?,
+    };
+    let delay = Duration::from_secs(seconds);
+
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    let rest = rest
+        .strip_prefix([';', ','])
+        .unwrap_or(rest)
+        .trim_start_matches(|c: char| c.is_ascii_whitespace());
+    if rest.is_empty() {
+        return Some((delay, None));
+    }
+    // `url=` is optional
+    let mut url = rest;
+    if rest
+        .get(..3)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("url"))
+    {
+        let after = rest[3..].trim_start_matches(|c: char| c.is_ascii_whitespace());
+        if let Some(after) = after.strip_prefix('=') {
+            url = after.trim_start_matches(|c: char| c.is_ascii_whitespace());
+        }
+    }
+    let url = match url.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let inner = &url[1..];
+            &inner[..inner.find(quote).unwrap_or(inner.len())]
+        }
+        _ => url.trim_end_matches(|c: char| c.is_ascii_whitespace()),
+    };
+    Some((delay, Some(url)))
+}
+
+/// Parse `width=device-width, initial-scale=1` style viewport content.
+fn parse_viewport(content: &str) -> Viewport {
+    let mut viewport = Viewport {
+        user_scalable: true,
+        ..Default::default()
+    };
+    for property in content.split([',', ';']) {
+        let Some((key, value)) = property.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "width" => viewport.width = Some(value.to_ascii_lowercase()),
+            "initial-scale" => viewport.initial_scale = value.parse().ok(),
+            "user-scalable" => {
+                viewport.user_scalable = !matches!(value.to_ascii_lowercase().as_str(), "no" | "0")
+            }
+            _ => {}
+        }
+    }
+    viewport
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refresh(content: &str) -> Option<(u64, Option<&str>)> {
+        parse_refresh(content).map(|(delay, url)| (delay.as_secs(), url))
+    }
+
+    #[test]
+    fn delay_and_url() {
+        assert_eq!(refresh("5"), Some((5, None)));
+        assert_eq!(refresh(" 2.5 "), Some((2, None)));
+        assert_eq!(refresh(".5; next.html"), Some((0, Some("next.html"))));
+        assert_eq!(refresh("0; url=/next"), Some((0, Some("/next"))));
+        assert_eq!(
+            refresh("3, URL = 'a b.html' x"),
+            Some((3, Some("a b.html")))
+        );
+        assert_eq!(refresh("1;URL=\"q.html"), Some((1, Some("q.html"))));
+        assert_eq!(refresh("x"), None);
+    }
+
+    #[test]
+    fn non_ascii_url() {
+        assert_eq!(refresh("0; ñé.html"), Some((0, Some("ñé.html"))));
+        assert_eq!(refresh("0; ñ"), Some((0, Some("ñ"))));
+        assert_eq!(refresh("0; url=ñé"), Some((0, Some("ñé"))));
+    }
+
+    #[test]
+    fn overflowing_delay_is_rejected() {
+        assert_eq!(refresh("99999999999999999999999; url=/next"), None);
+        assert_eq!(refresh("18446744073709551616"), None);
+        assert_eq!(refresh("18446744073709551615"), Some((u64::MAX, None)));
+    }
+}
@@ -1,44 +1,457 @@
 // parser.rs
-use crate::css_parser::parse_css;
-use crate::layout::{CssRule, HtmlNode, HtmlTag, NodeType, Selector};
+use crate::css_parser::parse_stylesheet;
+use crate::dom::{Document, NodeId, NodeRef};
+use crate::layout::{CssRule, HtmlNode, HtmlTag, NodeType};
+use crate::stylesheet::{self, PageSheet, SheetLoads};
 use std::collections::HashMap;
+use std::sync::Arc;
 
-/// Parse an HTML string into a tree of HtmlNode, discarding comments and doctype.
-pub fn parse_html(input: &str) -> HtmlNode {
-    let mut parser = Parser::new(input);
-    let nodes = parser.parse_nodes(None); // Start parsing top-level nodes
-
-    // Filter out any potential non-element nodes at the top level (e.g., whitespace text)
-    // before deciding if wrapping is needed.
-    let mut top_level_elements: Vec<HtmlNode> = nodes
-        .into_iter()
-        .filter(|n| matches!(n.node_type, NodeType::Element(_)))
-        .collect();
-
-    let first_element_is_html = top_level_elements
-        .first()
-        .map_or(false, |n| match &n.node_type {
-            NodeType::Element(HtmlTag::Html) => true,
-            NodeType::Element(HtmlTag::Custom(tag)) if tag.eq_ignore_ascii_case("html") => true,
+/// Parse an HTML string into a styled Document, discarding comments and doctype.
+pub fn parse_html(input: &str) -> Document {
+    let mut parser = StreamingParser::new();
+    parser.feed(input);
+    parser.finish()
+}
+
+/// A subresource referenced by markup, with the URL as written in the attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resource {
+    Stylesheet(String),
+    Image(String),
+}
+
+/// An empty document with the `<html>`, `<head>`, `<body>` skeleton every parse starts from.
+pub fn new_document() -> Document {
+    Document::from_tree(HtmlNode::new_element(
+        HtmlTag::Html,
+        HashMap::new(),
+        vec![
+            HtmlNode::new_element(HtmlTag::Head, HashMap::new(), vec![]),
+            HtmlNode::new_element(HtmlTag::Body, HashMap::new(), vec![]),
+        ],
+    ))
+}
+
+/// A change the parser made to its tree.
+#[derive(Debug, Clone)]
+enum TreeEdit {
+    Append {
+        parent: NodeId,
+        node: HtmlNode,
+    },
+    Attributes {
+        target: NodeId,
+        attributes: HashMap<String, String>,
+    },
+}
+
+/// What a `StreamingParser` added to its document since the previous update. Replaying the
+/// updates of a parse in order on `new_document()` builds the same tree, with the same node
+/// ids, so a page can be shown while it loads without copying the whole document each time.
+#[derive(Debug, Clone)]
+pub struct ParseUpdate {
+    edits: Vec<TreeEdit>,
+    sheets: Option<Vec<PageSheet>>, // all of the page's sheets, if there are new ones
+}
+
+impl ParseUpdate {
+    /// Replay the edits on `document`. New nodes are marked for `Document::restyle`. Returns
+    /// whether the page's sheets changed, in which case its rules must be collected again.
+    pub fn apply(self, document: &mut Document) -> bool {
+        for edit in self.edits {
+            match edit {
+                TreeEdit::Append { parent, node } => {
+                    document.append_parsed(parent, node);
+                }
+                TreeEdit::Attributes { target, attributes } => {
+                    document.merge_parsed_attributes(target, attributes)
+                }
+            }
+        }
+        match self.sheets {
+            Some(sheets) => {
+                document.set_page_sheets(sheets);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Incremental HTML parser: accepts the document in chunks as they arrive and builds the
+/// `Document` as it goes, so partial results can be shown before the load finishes.
+///
+/// The tree always has the `<html>`, `<head>`, `<body>` skeleton. Head-only elements seen
+/// before any body content go into `<head>`; everything else goes into `<body>`.
+/// The sheets of `<style>` elements and `<link rel="stylesheet">`s are also recorded in
+/// the document in order, and `finish` styles the document with the `<style>` rules.
+/// While a page loads, the browser replays `take_update`s on its own copy instead and
+/// styles that, adding the linked sheets as they load.
+pub struct StreamingParser {
+    buffer: String, // input that doesn't form a complete token yet
+    document: Document,
+    head: NodeId,
+    body: NodeId,
+    open_elements: Vec<NodeId>, // bottom is <head> or <body>; the top receives new nodes
+    in_body: bool,
+    sheets: Vec<PageSheet>,
+    resources: Vec<Resource>, // discovered since the last `take_resources`
+    edits: Vec<TreeEdit>,     // made since the last `take_update`
+    sheets_changed: bool,     // since the last `take_update`
+}
+
+impl Default for StreamingParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamingParser {
+    pub fn new() -> Self {
+        let document = new_document();
+        let head = document.head().map(|node| node.id()).unwrap();
+        let body = document.body().map(|node| node.id()).unwrap();
+        StreamingParser {
+            buffer: String::new(),
+            document,
+            head,
+            body,
+            open_elements: vec![head],
+            in_body: false,
+            sheets: Vec::new(),
+            resources: Vec::new(),
+            edits: Vec::new(),
+            sheets_changed: false,
+        }
+    }
+
+    /// Parse as much of the input received so far as possible. A token cut off at the end of
+    /// the chunk is kept until the next call. Returns whether the document changed.
+    pub fn feed(&mut self, chunk: &str) -> bool {
+        self.buffer.push_str(chunk);
+        self.pump(false)
+    }
+
+    /// Resources referenced by elements parsed since the last call.
+    pub fn take_resources(&mut self) -> Vec<Resource> {
+        std::mem::take(&mut self.resources)
+    }
+
+    /// The changes to the document since the previous call, to replay on another copy.
+    pub fn take_update(&mut self) -> ParseUpdate {
+        let sheets_changed = std::mem::take(&mut self.sheets_changed);
+        ParseUpdate {
+            edits: std::mem::take(&mut self.edits),
+            sheets: sheets_changed.then(|| self.sheets.clone()),
+        }
+    }
+
+    /// The unstyled document as parsed so far.
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// Parse whatever input is left and close all open elements.
+    pub fn close(&mut self) {
+        self.pump(true);
+        for &id in self.open_elements.iter().skip(1) {
+            if let Some(tag) = self.document.node(id).tag() {
+                eprintln!(
+                    "Warning: Missing closing tag for <{}>. Auto-closing.",
+                    tag.tag_name()
+                );
+            }
+        }
+        self.open_elements.truncate(1);
+    }
+
+    /// `close` the parser and style the document.
+    pub fn finish(mut self) -> Document {
+        self.close();
+        style_document(&mut self.document, &self.sheets);
+        print_rules(self.document.stylesheet());
+        self.document
+    }
+
+    fn pump(&mut self, at_eof: bool) -> bool {
+        let mut tokens = Vec::new();
+        let consumed = {
+            let mut tokenizer = Parser::new(&self.buffer);
+            while let Some(token) = tokenizer.next_token(at_eof) {
+                tokens.push(token);
+            }
+            tokenizer.pos
+        };
+        self.buffer.drain(..consumed);
+        let changed = !tokens.is_empty();
+        for token in tokens {
+            self.process(token);
+        }
+        changed
+    }
+
+    fn current(&self) -> NodeId {
+        *self.open_elements.last().unwrap()
+    }
+
+    fn switch_to_body(&mut self) {
+        if !self.in_body {
+            self.in_body = true;
+            self.open_elements = vec![self.body];
+        }
+    }
+
+    fn process(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => {
+                // Whitespace is kept; the renderer collapses it according to `white-space`.
+                // Only a newline right after <pre> is not part of the content.
+                let parent = self.document.node(self.current());
+                let text = match text.strip_prefix('\n') {
+                    Some(rest)
+                        if matches!(parent.node_type, NodeType::Element(HtmlTag::Pre))
+                            && parent.first_child().is_none() =>
+                    {
+                        rest.to_string()
+                    }
+                    _ => text,
+                };
+                if text.is_empty() {
+                    return;
+                }
+                // Text can't be a direct child of <head>; whitespace there is just formatting
+                if !self.in_body && self.current() == self.head {
+                    if is_blank(&text) {
+                        return;
+                    }
+                    self.switch_to_body();
+                }
+                self.append(self.current(), HtmlNode::new_text(text));
+            }
+            Token::StartTag {
+                name,
+                attributes,
+                self_closing,
+            } => {
+                let tag = Parser::match_tag(&name);
+                match tag {
+                    HtmlTag::Html => self.merge_attributes(self.document.root().id(), attributes),
+                    HtmlTag::Head => {}
+                    HtmlTag::Body => {
+                        self.merge_attributes(self.body, attributes);
+                        self.switch_to_body();
+                    }
+                    _ => {
+                        if !self.in_body && !is_head_element(&tag) {
+                            self.switch_to_body();
+                        }
+                        self.close_implied(&tag);
+                        self.discover_resources(&tag, &attributes);
+                        let is_void = self_closing || is_void_element(&tag);
+                        let id = self.append(
+                            self.current(),
+                            HtmlNode::new_element(tag, attributes, vec![]),
+                        );
+                        if !is_void {
+                            self.open_elements.push(id);
+                        }
+                    }
+                }
+            }
+            Token::RawTextElement {
+                name,
+                attributes,
+                text,
+            } => {
+                let tag = Parser::match_tag(&name);
+                if let HtmlTag::Style = tag {
+                    let media = attributes.get("media").cloned().unwrap_or_default();
+                    let sheet = Arc::new(parse_stylesheet(&text));
+                    self.sheets.push(PageSheet::Inline { sheet, media });
+                    self.sheets_changed = true;
+                }
+                if !self.in_body && !is_head_element(&tag) {
+                    self.switch_to_body();
+                }
+                let children = if text.is_empty() {
+                    vec![]
+                } else {
+                    vec![HtmlNode::new_text(text)]
+                };
+                self.append(
+                    self.current(),
+                    HtmlNode::new_element(tag, attributes, children),
+                );
+            }
+            Token::EndTag(name) => {
+                if ["html", "head", "body"]
+                    .iter()
+                    .any(|t| t.eq_ignore_ascii_case(&name))
+                {
+                    return;
+                }
+                // Close the nearest open element with this name and everything inside it
+                let position = self.open_elements.iter().skip(1).rposition(|&id| {
+                    self.document
+                        .node(id)
+                        .tag()
+                        .is_some_and(|tag| tag.tag_name().eq_ignore_ascii_case(&name))
+                });
+                match position {
+                    Some(index) => self.open_elements.truncate(index + 1),
+                    None => eprintln!("Warning: Unexpected closing tag </{}>. Skipping.", name),
+                }
+            }
+        }
+    }
+
+    /// List items and table parts may omit their end tag: a new `<li>` closes the open item
+    /// of the same list, a new cell the open cell of the same row, and so on.
+    fn close_implied(&mut self, tag: &HtmlTag) {
+        let closes = |open: &HtmlTag| match tag {
+            HtmlTag::Li => matches!(open, HtmlTag::Li),
+            HtmlTag::Dt | HtmlTag::Dd => matches!(open, HtmlTag::Dt | HtmlTag::Dd),
+            HtmlTag::Td | HtmlTag::Th => matches!(open, HtmlTag::Td | HtmlTag::Th),
+            HtmlTag::Tr => matches!(open, HtmlTag::Tr),
+            HtmlTag::Thead | HtmlTag::Tbody | HtmlTag::Tfoot => matches!(
+                open,
+                HtmlTag::Thead | HtmlTag::Tbody | HtmlTag::Tfoot | HtmlTag::Tr
+            ),
             _ => false,
+        };
+        let is_boundary = |open: &HtmlTag| match tag {
+            HtmlTag::Li => matches!(open, HtmlTag::Ul | HtmlTag::Ol),
+            HtmlTag::Dt | HtmlTag::Dd => matches!(open, HtmlTag::Dl),
+            HtmlTag::Td | HtmlTag::Th => matches!(open, HtmlTag::Tr | HtmlTag::Table),
+            HtmlTag::Tr => matches!(
+                open,
+                HtmlTag::Thead | HtmlTag::Tbody | HtmlTag::Tfoot | HtmlTag::Table
+            ),
+            _ => matches!(open, HtmlTag::Table),
+        };
+        // Close the outermost match, so a new row group also ends the row left open in it
+        let mut close_at = None;
+        for index in (1..self.open_elements.len()).rev() {
+            let Some(open) = self.document.node(self.open_elements[index]).tag() else {
+                continue;
+            };
+            if is_boundary(open) {
+                break;
+            }
+            if closes(open) {
+                close_at = Some(index);
+            }
+        }
+        if let Some(index) = close_at {
+            self.open_elements.truncate(index);
+        }
+    }
+
+    /// Add a node to the tree, recording the edit for the next update.
+    fn append(&mut self, parent: NodeId, node: HtmlNode) -> NodeId {
+        self.edits.push(TreeEdit::Append {
+            parent,
+            node: node.clone(),
+        });
+        self.document.append_parsed(parent, node)
+    }
+
+    fn merge_attributes(&mut self, id: NodeId, attributes: HashMap<String, String>) {
+        if attributes.is_empty() {
+            return;
+        }
+        self.edits.push(TreeEdit::Attributes {
+            target: id,
+            attributes: attributes.clone(),
         });
+        self.document.merge_parsed_attributes(id, attributes);
+    }
+
+    fn discover_resources(&mut self, tag: &HtmlTag, attributes: &HashMap<String, String>) {
+        match tag {
+            HtmlTag::Img => {
+                // Lazy images wait until they are scrolled to, and with a srcset the
+                // `src` may not be the file that is shown
+                let lazy = attributes
+                    .get("loading")
+                    .is_some_and(|loading| loading.eq_ignore_ascii_case("lazy"));
+                if lazy || attributes.contains_key("srcset") {
+                    return;
+                }
+                if let Some(src) = attributes.get("src").filter(|src| !src.is_empty()) {
+                    self.resources.push(Resource::Image(src.clone()));
+                }
+            }
+            HtmlTag::Custom(name) if name.eq_ignore_ascii_case("link") => {
+                // Alternate stylesheets are only used when the reader picks them
+                let rel = attributes
+                    .get("rel")
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                let has_rel = |keyword: &str| {
+                    rel.split_whitespace()
+                        .any(|r| r.eq_ignore_ascii_case(keyword))
+                };
+                if has_rel("stylesheet")
+                    && !has_rel("alternate")
+                    && let Some(href) = attributes.get("href").filter(|href| !href.is_empty())
+                {
+                    let media = attributes.get("media").cloned().unwrap_or_default();
+                    self.sheets.push(PageSheet::Link {
+                        href: href.clone(),
+                        media,
+                    });
+                    self.sheets_changed = true;
+                    self.resources.push(Resource::Stylesheet(href.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Record the page's stylesheets in `document` and style it with what is known so far: the
+/// `<style>` rules.
+fn style_document(document: &mut Document, sheets: &[PageSheet]) {
+    let rules = stylesheet::author_rules(sheets, None, &SheetLoads::default());
+    document.set_page_sheets(sheets.to_vec());
+    document.set_stylesheet(rules.rules);
+}
 
-    if top_level_elements.is_empty() {
-        // Handle empty input or input with only comments/doctype/whitespace
-        cleanup_tree(HtmlNode::new_element(HtmlTag::Html, HashMap::new(), vec![]))
-    } else if top_level_elements.len() == 1 && first_element_is_html {
-        // Single top-level element is <html>, clean it up directly
-        cleanup_tree(top_level_elements.remove(0))
-    } else {
-        // Multiple top-level elements, or the first one isn't <html>. Wrap them.
-        let html_node = HtmlNode::new_element(HtmlTag::Html, HashMap::new(), top_level_elements);
-        cleanup_tree(html_node)
+/// Whether text consists only of HTML whitespace (space, tab, newline, form feed, return).
+pub fn is_blank(text: &str) -> bool {
+    text.chars().all(|c| c.is_ascii_whitespace())
+}
+
+/// Elements that belong in `<head>` when they appear before any body content.
+fn is_head_element(tag: &HtmlTag) -> bool {
+    match tag {
+        HtmlTag::Title | HtmlTag::Style | HtmlTag::Script => true,
+        HtmlTag::Custom(name) => matches!(
+            name.to_ascii_lowercase().as_str(),
+            "meta" | "link" | "base" | "noscript"
+        ),
+        _ => false,
+    }
+}
+
+fn print_rules(rules: &[CssRule]) {
+    for rule in rules {
+        println!();
+        for selctor in &rule.selectors {
+            print!("{} ", selctor);
+        }
+        println!("( ");
+        for (name, property) in &rule.properties {
+            println!("{}: {:?}", name, property)
+        }
+        println!(")")
     }
 }
 
 /// Prints the HTML tree, including attributes and CSS style properties.
-pub fn print_tree(node: &HtmlNode) {
-    fn rec(node: &HtmlNode, indent: usize) {
+pub fn print_tree(node: NodeRef) {
+    fn rec(node: NodeRef, indent: usize) {
         let pad = "  ".repeat(indent);
         match &node.node_type {
             NodeType::Element(tag) => {
@@ -57,7 +470,7 @@ pub fn print_tree(node: &HtmlNode) {
                 }
 
                 // Recurse into children
-                for child in &node.children {
+                for child in node.children() {
                     rec(child, indent + 1);
                 }
 
@@ -75,172 +488,128 @@ pub fn print_tree(node: &HtmlNode) {
 }
 
 /// Check if a tag is a known void element
-fn is_void_element(tag: &HtmlTag) -> bool {
+pub fn is_void_element(tag: &HtmlTag) -> bool {
     match tag {
         // Check specific enum variants first for performance
-        HtmlTag::Br | HtmlTag::Hr | HtmlTag::Img => true,
+        HtmlTag::Br | HtmlTag::Hr | HtmlTag::Img | HtmlTag::Col | HtmlTag::Source => true,
+        // Void elements we don't have a dedicated variant for yet
+        HtmlTag::Custom(name) => matches!(
+            name.to_ascii_lowercase().as_str(),
+            "area" | "base" | "embed" | "input" | "link" | "meta" | "track" | "wbr"
+        ),
         _ => false,
     }
 }
 
-/// Ensure tree has html root with head and body
-pub fn cleanup_tree(mut root: HtmlNode) -> HtmlNode {
-    // Ensure the root node itself is HtmlTag::Html
-    let root_tag_name = match &root.node_type {
-        NodeType::Element(HtmlTag::Html) => Some("html"),
-        NodeType::Element(HtmlTag::Custom(name)) if name.eq_ignore_ascii_case("html") => {
-            Some("html")
-        }
-        _ => None,
-    };
-
-    if root_tag_name.is_none() {
-        let new_root = HtmlNode {
-            node_type: NodeType::Element(HtmlTag::Html),
-            style: HashMap::new(),
-            attributes: HashMap::new(),
-            children: vec![root],
-        };
-        return cleanup_tree(new_root);
+/// Decodes character references (`&amp;`, `&#39;`, `&#x27;`, ...) in text and attribute values.
+/// Unknown or malformed references are kept as written.
+pub fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
     }
-
-    root.node_type = NodeType::Element(HtmlTag::Html);
-
-    let mut head_node: Option<HtmlNode> = None;
-    let mut body_node: Option<HtmlNode> = None;
-    let mut other_children: Vec<HtmlNode> = Vec::new();
-    let mut style_contents: Vec<String> = Vec::new();
-    let mut head_found = false;
-    let mut body_found = false;
-
-    for child in root.children.drain(..) {
-        if let NodeType::Text(t) = &child.node_type {
-            if t.trim().is_empty() {
-                continue;
-            }
-        }
-
-        if let NodeType::Element(tag) = &child.node_type {
-            match tag {
-                HtmlTag::Head if !head_found => {
-                    head_node = Some(child);
-                    head_found = true;
-                }
-                HtmlTag::Body if !body_found => {
-                    body_node = Some(child);
-                    body_found = true;
-                }
-                HtmlTag::Custom(name) if !head_found && name.eq_ignore_ascii_case("head") => {
-                    head_node = Some(HtmlNode {
-                        node_type: NodeType::Element(HtmlTag::Head),
-                        ..child
-                    });
-                    head_found = true;
-                }
-                HtmlTag::Custom(name) if !body_found && name.eq_ignore_ascii_case("body") => {
-                    body_node = Some(HtmlNode {
-                        node_type: NodeType::Element(HtmlTag::Body),
-                        ..child
-                    });
-                    body_found = true;
-                }
-                HtmlTag::Head | HtmlTag::Body => {}
-                HtmlTag::Custom(name)
-                    if name.eq_ignore_ascii_case("head") || name.eq_ignore_ascii_case("body") => {}
-                HtmlTag::Style => {
-                    if let Some(NodeType::Text(style_text)) =
-                        child.children.get(0).map(|n| &n.node_type)
-                    {
-                        style_contents.push(style_text.clone());
-                    }
-                    // Don't push this style tag into children — it's being merged
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        // References are short; don't scan the whole document for a ';'
+        let end = rest
+            .char_indices()
+            .take(12)
+            .find(|(_, c)| *c == ';')
+            .map(|(i, _)| i);
+        let decoded = end.and_then(|end| {
+            let name = &rest[1..end];
+            let c = if let Some(num) = name.strip_prefix("#x").or(name.strip_prefix("#X")) {
+                u32::from_str_radix(num, 16).ok().and_then(char::from_u32)
+            } else if let Some(num) = name.strip_prefix('#') {
+                num.parse::<u32>().ok().and_then(char::from_u32)
+            } else {
+                match name {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some('\u{a0}'),
+                    "copy" => Some('©'),
+                    "reg" => Some('®'),
+                    "trade" => Some('™'),
+                    "hellip" => Some('…'),
+                    "mdash" => Some('—'),
+                    "ndash" => Some('–'),
+                    "laquo" => Some('«'),
+                    "raquo" => Some('»'),
+                    "ldquo" => Some('“'),
+                    "rdquo" => Some('”'),
+                    "lsquo" => Some('‘'),
+                    "rsquo" => Some('’'),
+                    "middot" => Some('·'),
+                    "bull" => Some('•'),
+                    "times" => Some('×'),
+                    "deg" => Some('°'),
+                    _ => None,
                 }
-                _ => other_children.push(child),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
             }
-        } else {
-            other_children.push(child);
         }
     }
+    out.push_str(rest);
+    out
+}
 
-    // Handle <style> tags inside head and body
-    let mut collect_styles = |node: &mut Option<HtmlNode>| {
-        if let Some(n) = node {
-            let mut retained_children = Vec::new();
-            for child in n.children.drain(..) {
-                if let NodeType::Element(HtmlTag::Style) = &child.node_type {
-                    if let Some(NodeType::Text(style_text)) =
-                        child.children.get(0).map(|n| &n.node_type)
-                    {
-                        style_contents.push(style_text.clone());
-                    }
-                } else {
-                    retained_children.push(child);
-                }
-            }
-            n.children = retained_children;
-        }
-    };
-
-    collect_styles(&mut head_node);
-    collect_styles(&mut body_node);
-
-    let final_head =
-        head_node.unwrap_or_else(|| HtmlNode::new_element(HtmlTag::Head, HashMap::new(), vec![]));
-    let mut final_body =
-        body_node.unwrap_or_else(|| HtmlNode::new_element(HtmlTag::Body, HashMap::new(), vec![]));
-
-    let mut body_children = other_children;
-    body_children.append(&mut final_body.children);
-    final_body.children = body_children;
-
-    // Create merged <style> tag if there are styles to insert
-    // let mut final_head = final_head;
-    // if !style_contents.is_empty() {
-    //     let mut childrens: Vec<HtmlNode> = vec![];
-    //     for style_text in style_contents {
-    //         childrens.push(HtmlNode {
-    //             node_type: NodeType::Text(style_text),
-    //             style: HashMap::new(),
-    //             attributes: HashMap::new(),
-    //             children: vec![],
-    //         });
-    //     }
-    // let style_node = HtmlNode {
-    //     node_type: NodeType::Element(HtmlTag::Style),
-    //     style: HashMap::new(),
-    //     attributes: HashMap::new(),
-    //     children: childrens,
-    // };
-    // final_head.children.insert(0, style_node);
-    // }
-    root.children = vec![final_head, final_body];
-    if !style_contents.is_empty() {
-        let mut rules: Vec<CssRule> = vec![];
-        for style_text in style_contents {
-            rules.extend(parse_css(style_text.as_str()));
-        }
-        root.stylize(&rules);
-        for rule in rules {
-            println!();
-            for selctor in rule.selectors {
-                match selctor {
-                    Selector::Universal => print!("* "),
-                    Selector::Class(s) => print!(".{} ", s),
-                    Selector::Id(s) => print!("#{} ", s),
-                    Selector::Type(s) => print!("{} ", s),
-                }
-            }
-            print!("( \n");
-            for (name, property) in rule.properties {
-                println!("{}: {:?}", name, property)
-            }
-            print!(")\n")
+// --- internal parser implementation ---
+/// What the tokenizer hands to the tree builder.
+#[derive(Debug)]
+enum Token {
+    StartTag {
+        name: String,
+        attributes: HashMap<String, String>,
+        self_closing: bool,
+    },
+    EndTag(String),
+    Text(String),
+    /// `<script>` or `<style>` with its unparsed content; only produced once the end tag is in
+    RawTextElement {
+        name: String,
+        attributes: HashMap<String, String>,
+        text: String,
+    },
+}
+
+/// Finds the `>` that ends the tag at the start of `input`, skipping quoted attribute values.
+fn tag_end(input: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    let mut after_equals = false;
+    for (i, c) in input.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '>' => return Some(i),
+                '"' | '\'' if after_equals => quote = Some(c),
+                '=' => after_equals = true,
+                c if c.is_whitespace() => {}
+                _ => after_equals = false,
+            },
+        }
+        if quote.is_some() {
+            after_equals = false;
         }
     }
-    root
+    None
 }
 
-// --- internal parser implementation ---
 struct Parser<'a> {
     input: &'a str,
     pos: usize,
@@ -251,82 +620,10 @@ impl<'a> Parser<'a> {
         Parser { input, pos: 0 }
     }
 
-    /// Parses nodes until it encounters a closing tag matching `end_tag` or EOF.
-    /// If `end_tag` is None, parses until EOF (for top-level).
-    /// Comments and Doctype are consumed but not added to the result vector.
-    fn parse_nodes(&mut self, end_tag: Option<&str>) -> Vec<HtmlNode> {
-        let mut nodes = Vec::new();
-        loop {
-            self.consume_whitespace(); // Consume leading whitespace before nodes
-            if self.eof() || (end_tag.is_some() && self.starts_with("</")) {
-                if let Some(tag_to_match) = end_tag {
-                    if self.check_closing_tag(tag_to_match) {
-                        break; // Found the correct closing tag
-                    }
-                    if self.eof() {
-                        break;
-                    }
-                } else if self.eof() {
-                    break;
-                }
-            }
-
-            let start_pos = self.pos; // For loop termination check
-
-            if self.starts_with("<!--") {
-                self.parse_comment(); // Consume comment, returns ()
-            } else if self.starts_with("<!DOCTYPE") || self.starts_with("<!doctype") {
-                self.parse_doctype(); // Consume doctype, returns ()
-            } else if self.starts_with("<") {
-                if self
-                    .input
-                    .get(self.pos + 1..)
-                    .map_or(false, |s| s.starts_with('/'))
-                {
-                    if end_tag.is_none() {
-                        let _ = self.parse_closing_tag(); // Consume and discard
-                        eprintln!("Warning: Found unexpected closing tag at top level. Skipping.");
-                    } else {
-                        break; // Let the parent element's parser handle unexpected closing tag
-                    }
-                } else {
-                    // It's an opening tag, parse it and add to nodes
-                    nodes.push(self.parse_element());
-                }
-            } else {
-                // Parse text node, only add if not empty after trimming whitespace
-                let text_node = self.parse_text();
-                if let NodeType::Text(t) = &text_node.node_type {
-                    if !t.trim().is_empty() {
-                        nodes.push(text_node);
-                    }
-                    // else: discard whitespace-only text nodes between elements
-                } else {
-                    // Should not happen based on parse_text logic, but safe guard
-                    nodes.push(text_node);
-                }
-            }
-
-            // Prevent infinite loop if no progress is made
-            if self.pos == start_pos && !self.eof() {
-                eprintln!(
-                    "Warning: Parser stuck at position {}. Attempting to advance.",
-                    self.pos
-                );
-                if !self.eof() {
-                    self.consume_char();
-                } else {
-                    break;
-                }
-            }
-        }
-        nodes
-    }
-
-    /// Parses and consumes `<!DOCTYPE ... >`. Returns nothing.
+    /// Parses and consumes `<!DOCTYPE ... >` (or any other `<! ... >`). Returns nothing.
     fn parse_doctype(&mut self) {
         // Changed return type to ()
-        assert!(self.starts_with("<!DOCTYPE") || self.starts_with("<!doctype"));
+        assert!(self.starts_with("<!"));
         // Consume until '>'
         while !self.eof() && self.current_char() != '>' {
             self.pos += 1;
@@ -353,15 +650,84 @@ impl<'a> Parser<'a> {
         // No node is created or returned
     }
 
-    /// Parses `<tag attr="value"> ... </tag>` or `<tag />`
-    fn parse_element(&mut self) -> HtmlNode {
+    /// Reads the next token. Comments and doctypes are consumed without producing one.
+    ///
+    /// Returns `None` at the end of the input, or when the rest of the input could be the
+    /// start of a longer token and more may still arrive (`at_eof == false`); `pos` is left
+    /// in front of the incomplete token then.
+    fn next_token(&mut self, at_eof: bool) -> Option<Token> {
+        loop {
+            let rest = self.input.get(self.pos..)?;
+            if rest.is_empty() {
+                return None;
+            }
+
+            if rest.starts_with("<!--") {
+                if !at_eof && !rest.contains("-->") {
+                    return None;
+                }
+                self.parse_comment();
+            } else if rest.starts_with("<!") {
+                if !at_eof && !rest.contains('>') {
+                    return None;
+                }
+                self.parse_doctype();
+            } else if rest.starts_with("</") {
+                if !at_eof && !rest.contains('>') {
+                    return None;
+                }
+                let name = self.parse_closing_tag();
+                if !name.is_empty() {
+                    return Some(Token::EndTag(name));
+                }
+            } else if rest.starts_with('<') && rest.len() == 1 && !at_eof {
+                return None;
+            } else if self.at_start_tag() {
+                if !at_eof && tag_end(rest).is_none() {
+                    return None;
+                }
+                let start = self.pos;
+                let token = self.parse_start_tag(at_eof);
+                if token.is_none() {
+                    self.pos = start; // Raw text element without its end tag yet
+                }
+                return token;
+            } else {
+                let start = self.pos;
+                let text = self.parse_text();
+                // Text runs until the next tag; wait for it so the run isn't split
+                if self.eof() && !at_eof {
+                    self.pos = start;
+                    return None;
+                }
+                if !text.is_empty() {
+                    return Some(Token::Text(text));
+                }
+            }
+        }
+    }
+
+    /// Whether the input is at `<` followed by a tag name. A `<` followed by anything else
+    /// (`a < b`) is text.
+    fn at_start_tag(&self) -> bool {
+        self.starts_with("<")
+            && self
+                .input
+                .get(self.pos + 1..)
+                .and_then(|s| s.chars().next())
+                .is_some_and(|c| c.is_ascii_alphabetic())
+    }
+
+    /// Parses `<tag attr="value">` or `<tag />`. For `<script>` and `<style>` the content and
+    /// end tag are consumed too; returns `None` if they haven't arrived yet.
+    fn parse_start_tag(&mut self, at_eof: bool) -> Option<Token> {
         assert!(self.consume_char() == '<');
         let tag_name = self.parse_tag_name();
         let attributes = self.parse_attributes();
         self.consume_whitespace();
 
-        let is_self_closing_slash = self.starts_with("/>");
-        if is_self_closing_slash {
+        let self_closing = self.starts_with("/>");
+        if self_closing {
             self.pos += 2; // Consume "/>"
         } else if self.starts_with(">") {
             self.pos += 1; // Consume ">"
@@ -376,78 +742,64 @@ impl<'a> Parser<'a> {
             }
         }
 
-        let tag = Self::match_tag(&tag_name);
-
-        if is_self_closing_slash || is_void_element(&tag) {
-            return HtmlNode::new_element(tag, attributes, vec![]);
+        let is_raw_text = matches!(Self::match_tag(&tag_name), HtmlTag::Script | HtmlTag::Style);
+        if !is_raw_text || self_closing {
+            return Some(Token::StartTag {
+                name: tag_name,
+                attributes,
+                self_closing,
+            });
         }
 
-        let children = if matches!(tag, HtmlTag::Script | HtmlTag::Style) {
-            self.parse_raw_text_content(&tag_name)
-        } else {
-            self.parse_nodes(Some(&tag_name))
-        };
-
-        if self.starts_with("</") {
-            if self.check_closing_tag(&tag_name) {
-                self.parse_closing_tag();
-            } else {
-                eprintln!(
-                    "Warning: Expected closing tag </{}> but found different closing tag or EOF. Auto-closing <{}>.",
-                    tag_name, tag_name
-                );
-            }
+        let end_tag = format!("</{}", tag_name.to_ascii_lowercase());
+        if !at_eof
+            && !self.input[self.pos..]
+                .to_ascii_lowercase()
+                .contains(&end_tag)
+        {
+            return None;
+        }
+        let text = self.parse_raw_text_content(&tag_name);
+        if self.check_closing_tag(&tag_name) {
+            self.parse_closing_tag();
         } else {
             eprintln!(
                 "Warning: Missing closing tag for <{}>. Auto-closing.",
                 tag_name
             );
         }
-
-        HtmlNode::new_element(tag, attributes, children)
+        Some(Token::RawTextElement {
+            name: tag_name,
+            attributes,
+            text,
+        })
     }
 
-    /// Parses the raw text content of elements like <script> or <style>
-    fn parse_raw_text_content(&mut self, tag_name: &str) -> Vec<HtmlNode> {
+    /// Parses the raw text content of elements like <script> or <style>, up to the end tag
+    fn parse_raw_text_content(&mut self, tag_name: &str) -> String {
         let start = self.pos;
-        let end_tag = format!("</{}>", tag_name);
-        let end_tag_lower = end_tag.to_lowercase();
-
-        while !self.eof() {
-            if self.input[self.pos..]
-                .to_lowercase()
-                .starts_with(&end_tag_lower)
-            {
-                break;
-            }
-            self.pos += 1;
-        }
-
-        let text = self.input[start..self.pos].to_string();
+        let end_tag = format!("</{}", tag_name.to_ascii_lowercase());
+        // ASCII lowercasing keeps byte offsets, so positions map back to `input`
+        self.pos = self.input[start..]
+            .to_ascii_lowercase()
+            .find(&end_tag)
+            .map_or(self.input.len(), |offset| start + offset);
         // Don't trim raw text content here
-        if text.is_empty() {
-            vec![]
-        } else {
-            vec![HtmlNode::new_text(text)]
-        }
+        self.input[start..self.pos].to_string()
     }
 
-    /// Parses plain text content between tags
-    fn parse_text(&mut self) -> HtmlNode {
+    /// Parses plain text content up to the next tag
+    fn parse_text(&mut self) -> String {
         let start = self.pos;
-        while !self.eof() && self.current_char() != '<' {
-            self.pos += 1;
+        while !(self.eof()
+            || self.starts_with("</")
+            || self.starts_with("<!")
+            || self.at_start_tag())
+        {
+            self.consume_char();
         }
-        let text = self.input[start..self.pos].trim().to_string();
-        // Simple HTML entity decoding
-        let decoded_text = text
-            .replace("<", "<")
-            .replace(">", ">")
-            .replace("&", "&")
-            .replace("\"", "\"")
-            .replace("'", "'");
-        // Return the node even if whitespace only, parse_nodes will filter if needed
-        HtmlNode::new_text(decoded_text)
+        // Whitespace is kept here; the tree builder decides what is significant
+        decode_entities(&self.input[start..self.pos])
     }
 
     /// Parses a tag name (alphanumeric characters)
@@ -547,12 +899,7 @@ impl<'a> Parser<'a> {
             }
         };
         // Decode HTML entities in attribute values
-        value
-            .replace("<", "<")
-            .replace(">", ">")
-            .replace("&", "&")
-            .replace("\"", "\"")
-            .replace("'", "'")
+        decode_entities(&value)
     }
 
     /// Consumes `</tag>`
@@ -569,9 +916,8 @@ impl<'a> Parser<'a> {
                 tag_name,
                 self.current_char()
             );
-            if !self.eof() {
-                self.pos += 1;
-            }
+            // Skip the rest of the tag
+            while !self.eof() && self.consume_char() != '>' {}
         }
         tag_name
     }
@@ -611,7 +957,7 @@ impl<'a> Parser<'a> {
     fn starts_with(&self, s: &str) -> bool {
         self.input
             .get(self.pos..)
-            .map_or(false, |slice| slice.starts_with(s))
+            .is_some_and(|slice| slice.starts_with(s))
     }
 
     /// Checks if the end of the input has been reached.
@@ -660,6 +1006,10 @@ impl<'a> Parser<'a> {
             "big" => HtmlTag::Big,
             "br" => HtmlTag::Br,
             "img" => HtmlTag::Img,
+            "picture" => HtmlTag::Picture,
+            "source" => HtmlTag::Source,
+            "svg" => HtmlTag::Svg,
+            "iframe" => HtmlTag::Iframe,
             "audio" => HtmlTag::Audio,
             "body" => HtmlTag::Body,
             "head" => HtmlTag::Head,
@@ -681,9 +1031,194 @@ impl<'a> Parser<'a> {
             "td" => HtmlTag::Td,
             "tr" => HtmlTag::Tr,
             "caption" => HtmlTag::Caption,
+            "colgroup" => HtmlTag::Colgroup,
+            "col" => HtmlTag::Col,
+            "ul" => HtmlTag::Ul,
+            "ol" => HtmlTag::Ol,
+            "li" => HtmlTag::Li,
+            "dl" => HtmlTag::Dl,
+            "dt" => HtmlTag::Dt,
+            "dd" => HtmlTag::Dd,
+            "pre" => HtmlTag::Pre,
+            "code" => HtmlTag::Code,
+            "kbd" => HtmlTag::Kbd,
+            "samp" => HtmlTag::Samp,
+            "tt" => HtmlTag::Tt,
+            "sup" => HtmlTag::Sup,
+            "sub" => HtmlTag::Sub,
+            "mark" => HtmlTag::Mark,
+            "q" => HtmlTag::Q,
+            "cite" => HtmlTag::Cite,
+            "del" => HtmlTag::Del,
+            "ins" => HtmlTag::Ins,
+            "time" => HtmlTag::Time,
+            "var" => HtmlTag::Var,
+            "dfn" => HtmlTag::Dfn,
+            "section" => HtmlTag::Section,
+            "article" => HtmlTag::Article,
+            "nav" => HtmlTag::Nav,
+            "header" => HtmlTag::Header,
+            "footer" => HtmlTag::Footer,
+            "main" => HtmlTag::Main,
+            "aside" => HtmlTag::Aside,
+            "figure" => HtmlTag::Figure,
+            "figcaption" => HtmlTag::Figcaption,
+            "blockquote" => HtmlTag::Blockquote,
+            "details" => HtmlTag::Details,
+            "summary" => HtmlTag::Summary,
             "script" => HtmlTag::Script,
             "style" => HtmlTag::Style,
             _ => HtmlTag::Custom(tag_name.to_string()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_serializer::{SerializeOptions, serialize_html};
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en"><head><title>Title</title><style media="screen">p { color: red }</style>
+<link rel="stylesheet" href="a.css"></head>
+<body class="main">
+<!-- a comment with <p>tags</p> in it -->
+<p id="one" title="a > b" data-x='c "d"'>Hello &amp; <em>wörld</em></p>
+<script>if (a </b) { x = "</p>"; }</script>
+<ul><li>one<li>two <img src="x.png" alt=""></ul><br/>
+</body></html>
+"#;
+
+    /// Everything a parse produced: its edits and resources in order, the sheets of its
+    /// last update, and the tree, both as built and as replayed from the updates.
+    #[derive(Debug, PartialEq)]
+    struct Parsed {
+        edits: Vec<String>,
+        sheets: Vec<String>,
+        resources: Vec<Resource>,
+        tree: String,
+        replayed: String,
+    }
+
+    fn parse_in_chunks<'a>(chunks: impl IntoIterator<Item = &'a str>) -> Parsed {
+        let mut parser = StreamingParser::new();
+        let mut replayed = new_document();
+        let mut edits = Vec::new();
+        let mut sheets = Vec::new();
+        let mut resources = Vec::new();
+        let mut take_update = |parser: &mut StreamingParser| {
+            resources.extend(parser.take_resources());
+            let update = parser.take_update();
+            edits.extend(update.edits.iter().map(describe_edit));
+            if let Some(new_sheets) = &update.sheets {
+                sheets = new_sheets.iter().map(describe_sheet).collect();
+            }
+            update.apply(&mut replayed);
+        };
+        for chunk in chunks {
+            parser.feed(chunk);
+            take_update(&mut parser);
+        }
+        parser.close();
+        take_update(&mut parser);
+
+        let options = SerializeOptions::default();
+        Parsed {
+            edits,
+            sheets,
+            resources,
+            tree: serialize_html(parser.document().root(), &options),
+            replayed: serialize_html(replayed.root(), &options),
+        }
+    }
+
+    fn describe_edit(edit: &TreeEdit) -> String {
+        match edit {
+            TreeEdit::Append { parent, node } => {
+                format!("append to {parent:?}: {}", describe(node))
+            }
+            TreeEdit::Attributes { target, attributes } => {
+                let mut attributes: Vec<_> = attributes.iter().collect();
+                attributes.sort();
+                format!("attributes of {target:?}: {attributes:?}")
+            }
+        }
+    }
+
+    /// A node and its subtree on one line, with the attributes sorted.
+    fn describe(node: &HtmlNode) -> String {
+        match &node.node_type {
+            NodeType::Text(text) => format!("{text:?}"),
+            NodeType::Element(tag) => {
+                let mut attributes: Vec<_> = node.attributes.iter().collect();
+                attributes.sort();
+                let children: Vec<String> = node.children.iter().map(describe).collect();
+                format!(
+                    "<{} {attributes:?}>[{}]",
+                    tag.tag_name(),
+                    children.join(", ")
+                )
+            }
+        }
+    }
+
+    fn describe_sheet(sheet: &PageSheet) -> String {
+        match sheet {
+            PageSheet::Inline { sheet, media } => {
+                format!("<style media={media:?}> {} rules", sheet.rules.len())
+            }
+            PageSheet::Link { href, media } => format!("<link href={href:?} media={media:?}>"),
+        }
+    }
+
+    #[test]
+    fn replayed_updates_build_the_same_tree() {
+        let parsed = parse_in_chunks([PAGE]);
+        assert_eq!(parsed.replayed, parsed.tree);
+        assert_eq!(
+            parsed.sheets,
+            [
+                "<style media=\"screen\"> 1 rules",
+                "<link href=\"a.css\" media=\"\">"
+            ]
+        );
+        assert_eq!(
+            parsed.resources,
+            [
+                Resource::Stylesheet("a.css".to_string()),
+                Resource::Image("x.png".to_string())
+            ]
+        );
+        assert!(
+            parsed
+                .tree
+                .contains(r#"<script>if (a </b) { x = "</p>"; }</script>"#)
+        );
+        assert!(!parsed.tree.contains("comment"));
+    }
+
+    #[test]
+    fn any_split_gives_the_same_result() {
+        let whole = parse_in_chunks([PAGE]);
+        // Every split point, which includes splits inside a tag name, an attribute value,
+        // a character reference, a comment, the doctype and `</script>`
+        for (split, _) in PAGE.char_indices().skip(1) {
+            let (first, second) = PAGE.split_at(split);
+            assert_eq!(
+                parse_in_chunks([first, second]),
+                whole,
+                "split before {:?}",
+                &PAGE[split..]
+            );
+        }
+    }
+
+    #[test]
+    fn one_character_at_a_time_gives_the_same_result() {
+        let chunks: Vec<&str> = PAGE
+            .char_indices()
+            .map(|(start, c)| &PAGE[start..start + c.len_utf8()])
+            .collect();
+        assert_eq!(parse_in_chunks(chunks), parse_in_chunks([PAGE]));
+    }
+}
@@ -0,0 +1,329 @@
+// html_serializer.rs
+use crate::dom::NodeRef;
+use crate::html_parser::{is_blank, is_void_element};
+use crate::layout::{HtmlTag, NodeType};
+
+/// Options controlling how a tree is written back out as HTML.
+#[derive(Debug, Clone)]
+pub struct SerializeOptions {
+    /// Put block-level children (by their computed `display`) on their own lines, indented
+    /// by `indent`. Text and inline content is never reflowed, so the rendered result stays
+    /// the same.
+    pub pretty: bool,
+    /// Indentation used per nesting level in pretty mode.
+    pub indent: String,
+    /// Emit `<!DOCTYPE html>` in front of an `<html>` root.
+    pub doctype: bool,
+}
+
+impl Default for SerializeOptions {
+    fn default() -> Self {
+        SerializeOptions {
+            pretty: false,
+            indent: "  ".to_string(),
+            doctype: true,
+        }
+    }
+}
+
+impl SerializeOptions {
+    /// Default options with pretty printing turned on.
+    pub fn pretty() -> Self {
+        SerializeOptions {
+            pretty: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Serialize a tree back to HTML. `SerializeOptions::default()` gives compact output.
+pub fn serialize_html(node: NodeRef, options: &SerializeOptions) -> String {
+    let mut out = String::new();
+    if options.doctype && matches!(node.node_type, NodeType::Element(HtmlTag::Html)) {
+        out.push_str("<!DOCTYPE html>");
+        if options.pretty {
+            out.push('\n');
+        }
+    }
+    Serializer {
+        options,
+        out: &mut out,
+    }
+    .write_node(node, 0, false);
+    if options.pretty && !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+/// Escape text content: `&`, `<` and `>`.
+pub fn escape_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\u{a0}' => out.push_str("&nbsp;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escape a double-quoted attribute value: `&` and `"`.
+pub fn escape_attribute(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\u{a0}' => out.push_str("&nbsp;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Elements whose text children are written verbatim (no escaping).
+fn is_raw_text_element(tag: &HtmlTag) -> bool {
+    matches!(tag, HtmlTag::Script | HtmlTag::Style)
+}
+
+/// Elements whose content must not be re-indented in pretty mode.
+fn preserves_whitespace(tag: &HtmlTag) -> bool {
+    is_raw_text_element(tag)
+        || matches!(tag, HtmlTag::Pre)
+        || matches!(tag, HtmlTag::Custom(name) if name.eq_ignore_ascii_case("textarea"))
+}
+
+/// Whether `name` can be written as an attribute name; anything else would break the markup.
+fn is_serializable_attribute(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '>' | '/' | '='))
+}
+
+struct Serializer<'a> {
+    options: &'a SerializeOptions,
+    out: &'a mut String,
+}
+
+impl Serializer<'_> {
+    fn newline(&mut self, depth: usize) {
+        self.out.push('\n');
+        for _ in 0..depth {
+            self.out.push_str(&self.options.indent);
+        }
+    }
+
+    fn write_node(&mut self, node: NodeRef, depth: usize, raw: bool) {
+        match &node.node_type {
+            NodeType::Text(text) => {
+                if raw {
+                    self.out.push_str(text);
+                } else {
+                    self.out.push_str(&escape_text(text));
+                }
+            }
+            NodeType::Element(tag) => self.write_element(node, tag, depth),
+        }
+    }
+
+    fn write_element(&mut self, node: NodeRef, tag: &HtmlTag, depth: usize) {
+        let name = tag.tag_name();
+        self.out.push('<');
+        self.out.push_str(name);
+
+        // HashMap order is random; sort so the same tree always serializes the same way
+        let mut attributes: Vec<_> = node
+            .attributes
+            .iter()
+            .filter(|(name, _)| is_serializable_attribute(name))
+            .collect();
+        attributes.sort_by(|a, b| a.0.cmp(b.0));
+        for (attr, value) in attributes {
+            self.out.push(' ');
+            self.out.push_str(attr);
+            if !value.is_empty() {
+                self.out.push_str("=\"");
+                self.out.push_str(&escape_attribute(value));
+                self.out.push('"');
+            }
+        }
+        self.out.push('>');
+
+        if is_void_element(tag) {
+            return;
+        }
+
+        // The parser drops a newline right after <pre>, so a leading one in the content
+        // needs another in front of it to survive a round trip
+        if matches!(tag, HtmlTag::Pre)
+            && node.first_child().is_some_and(
+                |child| matches!(&child.node_type, NodeType::Text(text) if text.starts_with('\n')),
+            )
+        {
+            self.out.push('\n');
+        }
+
+        let raw = is_raw_text_element(tag);
+        // Only break lines when no child is text or inline; otherwise whitespace we add
+        // would become visible content. Whitespace-only text between blocks is replaced
+        // by our own indentation.
+        let is_blank_text =
+            |child: &NodeRef| matches!(&child.node_type, NodeType::Text(text) if is_blank(text));
+        let block_layout = self.options.pretty
+            && !preserves_whitespace(tag)
+            && node.children().any(|child| !is_blank_text(&child))
+            && node.children().all(|child| match &child.node_type {
+                NodeType::Element(_) => !child.computed.is_inline(),
+                NodeType::Text(_) => is_blank_text(&child),
+            });
+
+        for child in node.children() {
+            if block_layout {
+                if is_blank_text(&child) {
+                    continue;
+                }
+                self.newline(depth + 1);
+            }
+            self.write_node(child, depth + 1, raw);
+        }
+        if block_layout {
+            self.newline(depth);
+        }
+
+        self.out.push_str("</");
+        self.out.push_str(name);
+        self.out.push('>');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_parser::parse_html;
+
+    /// The shape of a tree, one line per node: indented tag and sorted attributes, or the
+    /// text. With `blank_text` false, whitespace-only text is left out.
+    fn outline(node: NodeRef, blank_text: bool) -> Vec<String> {
+        fn rec(node: NodeRef, depth: usize, blank_text: bool, lines: &mut Vec<String>) {
+            let indent = "  ".repeat(depth);
+            match &node.node_type {
+                NodeType::Text(text) if !blank_text && is_blank(text) => return,
+                NodeType::Text(text) => lines.push(format!("{indent}{text:?}")),
+                NodeType::Element(tag) => {
+                    let mut attributes: Vec<_> = node.attributes.iter().collect();
+                    attributes.sort();
+                    lines.push(format!("{indent}<{}> {attributes:?}", tag.tag_name()));
+                }
+            }
+            for child in node.children() {
+                rec(child, depth + 1, blank_text, lines);
+            }
+        }
+        let mut lines = Vec::new();
+        rec(node, 0, blank_text, &mut lines);
+        lines
+    }
+
+    fn body(html: &str, options: &SerializeOptions) -> String {
+        let document = parse_html(html);
+        serialize_html(document.body().unwrap(), options)
+    }
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en"><head><title>A &amp; B</title>
+<style>p > a::after { content: "&amp;" }</style></head>
+<body class="main">
+  <h1 title='say "hi"'>Fish &amp; chips &lt;3</h1>
+  <ul><li>one<li>two <a href="/x?a=1&amp;b=2">link</a></ul>
+  <pre>
+
+  indented
+</pre>
+  <p>a<br>b<img src="x.png" alt="">&nbsp;c</p>
+  <table><tr><td>1<td>2</table>
+  <script>if (a < b && b > c) { document.write("</p>"); }</script>
+</body></html>"#;
+
+    #[test]
+    fn round_trip_is_stable() {
+        let document = parse_html(PAGE);
+        let html = serialize_html(document.root(), &SerializeOptions::default());
+        let reparsed = parse_html(&html);
+        assert_eq!(
+            outline(reparsed.root(), true),
+            outline(document.root(), true)
+        );
+        // ...and serializing again gives the same markup
+        assert_eq!(
+            serialize_html(reparsed.root(), &SerializeOptions::default()),
+            html
+        );
+    }
+
+    #[test]
+    fn escapes_text_and_attributes() {
+        assert_eq!(
+            body(
+                r#"<p title='say "hi" &amp; bye' data-x="a<b">1 &lt; 2 &amp;&amp; 3 &gt; 2&nbsp;!"#,
+                &SerializeOptions::default()
+            ),
+            "<body><p data-x=\"a<b\" title=\"say &quot;hi&quot; &amp; bye\">\
+             1 &lt; 2 &amp;&amp; 3 &gt; 2&nbsp;!</p></body>"
+        );
+        assert_eq!(escape_text("<a href=\"x\">&"), "&lt;a href=\"x\"&gt;&amp;");
+        assert_eq!(
+            escape_attribute("<a href=\"x\">&"),
+            "<a href=&quot;x&quot;>&amp;"
+        );
+    }
+
+    #[test]
+    fn void_elements_have_no_end_tag() {
+        assert_eq!(
+            body(
+                r#"<p>a<br/>b<img src="x.png" alt=""><input disabled></p><hr>"#,
+                &SerializeOptions::default()
+            ),
+            r#"<body><p>a<br>b<img alt src="x.png"><input disabled></p><hr></body>"#
+        );
+    }
+
+    #[test]
+    fn raw_text_is_written_verbatim() {
+        let style = r#"<style>p > a::after { content: "&amp;" }</style>"#;
+        let script = r#"<script>if (a < b && b > c) { x = "</p>"; }</script>"#;
+        let document = parse_html(&format!("{style}{script}"));
+        assert_eq!(
+            serialize_html(document.head().unwrap(), &SerializeOptions::default()),
+            format!("<head>{style}{script}</head>")
+        );
+    }
+
+    #[test]
+    fn pretty_mode_indents_blocks_only() {
+        let document = parse_html(
+            "<div><p>One <em>two</em></p><ul><li>a</li><li>b</li></ul><pre>  x\n y</pre></div>",
+        );
+        let div = document.body().unwrap().first_child().unwrap();
+        assert_eq!(
+            serialize_html(div, &SerializeOptions::pretty()),
+            "<div>\n  <p>One <em>two</em></p>\n  <ul>\n    <li>a</li>\n    <li>b</li>\n  </ul>\n  \
+             <pre>  x\n y</pre>\n</div>\n"
+        );
+
+        // The indentation is only whitespace between blocks, so the page reads the same
+        let pretty = serialize_html(document.root(), &SerializeOptions::pretty());
+        assert!(pretty.starts_with("<!DOCTYPE html>\n<html>\n  <head></head>\n  <body>\n"));
+        let document = parse_html(PAGE);
+        let pretty = serialize_html(document.root(), &SerializeOptions::pretty());
+        assert_eq!(
+            outline(parse_html(&pretty).root(), false),
+            outline(document.root(), false)
+        );
+    }
+}
@@ -0,0 +1,223 @@
+// image.rs
+use crate::css_parser::{MediaEnvironment, media_matches};
+use crate::dom::NodeRef;
+use crate::layout::{HtmlTag, NodeType};
+use eframe::egui::{Rect, Vec2, pos2};
+
+/// MIME types of the formats the image loaders can decode.
+const SUPPORTED_TYPES: [&str; 7] = [
+    "image/png",
+    "image/jpeg",
+    "image/jpg",
+    "image/gif",
+    "image/webp",
+    "image/bmp",
+    "image/svg+xml",
+];
+
+/// The image file picked for an `<img>`, and how many of its pixels make up one CSS pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageChoice {
+    pub url: String,
+    pub density: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Descriptor {
+    Density(f32), // `2x`
+    Width(f32),   // `800w`
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Candidate {
+    url: String,
+    descriptor: Descriptor,
+}
+
+/// Pick the file an `<img>` shows: from the first `<source>` of its `<picture>` whose `type`
+/// we can decode and whose `media` matches, otherwise from its own `srcset` and `src`.
+/// Among the candidates of a `srcset`, the smallest one that is sharp at `env.resolution` wins.
+pub fn choose_image(img: NodeRef, env: &MediaEnvironment) -> Option<ImageChoice> {
+    if let Some(picture) = img.parent()
+        && matches!(picture.node_type, NodeType::Element(HtmlTag::Picture))
+    {
+        // Only the sources before the <img> count
+        for source in picture.children().take_while(|child| *child != img) {
+            if !matches!(source.node_type, NodeType::Element(HtmlTag::Source)) {
+                continue;
+            }
+            if let Some(mime) = source.get_attribute("type")
+                && !is_supported_type(mime)
+            {
+                continue;
+            }
+            if let Some(media) = source.get_attribute("media")
+                && !media_matches(media, env)
+            {
+                continue;
+            }
+            let candidates = parse_srcset(source.get_attribute("srcset").unwrap_or_default());
+            if let Some(choice) = pick_candidate(candidates, source.get_attribute("sizes"), env) {
+                return Some(choice);
+            }
+        }
+    }
+
+    let mut candidates = parse_srcset(img.get_attribute("srcset").unwrap_or_default());
+    // `src` is the 1x candidate, unless the srcset already has one or uses widths
+    let src = img.get_attribute("src").map(str::trim).unwrap_or_default();
+    if !src.is_empty()
+        && !candidates.iter().any(|candidate| {
+            matches!(candidate.descriptor, Descriptor::Width(_))
+                || candidate.descriptor == Descriptor::Density(1.0)
+        })
+    {
+        candidates.push(Candidate {
+            url: src.to_string(),
+            descriptor: Descriptor::Density(1.0),
+        });
+    }
+    pick_candidate(candidates, img.get_attribute("sizes"), env)
+}
+
+fn is_supported_type(mime: &str) -> bool {
+    let mime = mime.split(';').next().unwrap_or_default().trim();
+    SUPPORTED_TYPES
+        .iter()
+        .any(|supported| supported.eq_ignore_ascii_case(mime))
+}
+
+fn pick_candidate(
+    candidates: Vec<Candidate>,
+    sizes: Option<&str>,
+    env: &MediaEnvironment,
+) -> Option<ImageChoice> {
+    // Width descriptors are turned into densities by the width the image will be shown at
+    let slot_width = sizes
+        .map_or(env.width, |sizes| source_size(sizes, env))
+        .max(1.0);
+    let mut choices: Vec<ImageChoice> = candidates
+        .into_iter()
+        .map(|candidate| ImageChoice {
+            url: candidate.url,
+            density: match candidate.descriptor {
+                Descriptor::Density(density) => density,
+                Descriptor::Width(width) => width / slot_width,
+            },
+        })
+        .filter(|choice| choice.density > 0.0)
+        .collect();
+    choices.sort_by(|a, b| a.density.total_cmp(&b.density));
+    let best = choices
+        .iter()
+        .position(|choice| choice.density >= env.resolution)
+        .or(choices.len().checked_sub(1))?;
+    Some(choices.swap_remove(best))
+}
+
+/// Parse a `srcset` list (`small.png 1x, big.png 2x` or `a.jpg 480w, b.jpg 800w`).
+/// Candidates with descriptors we don't understand are dropped.
+fn parse_srcset(srcset: &str) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            break;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let mut url = &rest[..end];
+        rest = &rest[end..];
+        // A URL followed directly by a comma has no descriptors
+        let mut descriptors = "";
+        if url.ends_with(',') {
+            url = url.trim_end_matches(',');
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            descriptors = &rest[..end];
+            rest = &rest[end..];
+        }
+        let mut descriptor = Some(Descriptor::Density(1.0));
+        for word in descriptors.split_whitespace() {
+            descriptor = if let Some(density) = word.strip_suffix('x') {
+                density.parse().ok().map(Descriptor::Density)
+            } else if let Some(width) = word.strip_suffix('w') {
+                width.parse().ok().map(Descriptor::Width)
+            } else if word.ends_with('h') {
+                continue; // height hints don't affect the choice
+            } else {
+                None
+            };
+        }
+        if let Some(descriptor) = descriptor
+            && !url.is_empty()
+        {
+            candidates.push(Candidate {
+                url: url.to_string(),
+                descriptor,
+            });
+        }
+    }
+    candidates
+}
+
+/// The width from a `sizes` list (`(max-width: 600px) 100vw, 50vw`): the size of the
+/// first entry whose media condition matches, or the full viewport width.
+fn source_size(sizes: &str, env: &MediaEnvironment) -> f32 {
+    for entry in sizes.split(',') {
+        let entry = entry.trim();
+        let (condition, size) = entry
+            .rsplit_once(char::is_whitespace)
+            .unwrap_or(("", entry));
+        if !media_matches(condition, env) {
+            continue;
+        }
+        let size = size.trim();
+        let width = if let Some(vw) = size.strip_suffix("vw") {
+            vw.parse::<f32>().ok().map(|vw| vw / 100.0 * env.width)
+        } else if let Some(px) = size.strip_suffix("px") {
+            px.parse().ok()
+        } else if let Some(em) = size.strip_suffix("rem").or(size.strip_suffix("em")) {
+            em.parse::<f32>().ok().map(|em| em * 16.0)
+        } else {
+            None
+        };
+        if let Some(width) = width {
+            return width;
+        }
+    }
+    env.width
+}
+
+/// Whether a URL names an SVG file, which is drawn by our own renderer rather than the
+/// image loaders.
+pub fn is_svg(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.to_ascii_lowercase().ends_with(".svg")
+}
+
+/// Place an image of `size` (in CSS pixels) in `frame` the way CSS `object-fit` does.
+/// Returns the part of the frame the image covers and the part of the image (as texture
+/// coordinates) that shows there.
+pub fn object_fit(fit: &str, frame: Rect, size: Vec2) -> (Rect, Rect) {
+    let full = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+    if size.x <= 0.0 || size.y <= 0.0 {
+        return (frame, full);
+    }
+    let contain = (frame.width() / size.x).min(frame.height() / size.y);
+    let scale = match fit.trim() {
+        "contain" => contain,
+        "cover" => (frame.width() / size.x).max(frame.height() / size.y),
+        "none" => 1.0,
+        "scale-down" => contain.min(1.0),
+        _ => return (frame, full), // fill: stretched to the frame
+    };
+    // Centre the scaled image and cut away what falls outside the frame
+    let drawn = Rect::from_center_size(frame.center(), size * scale);
+    let visible = drawn.intersect(frame);
+    let uv = Rect::from_min_max(
+        ((visible.min - drawn.min) / drawn.size()).to_pos2(),
+        ((visible.max - drawn.min) / drawn.size()).to_pos2(),
+    );
+    (visible, uv)
+}
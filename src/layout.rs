@@ -1,5 +1,10 @@
+use crate::css_parser::{MediaEnvironment, MediaQueryList, user_agent_stylesheet, user_stylesheet};
+use crate::dom::{Document, NodeId, NodeRef};
+use crate::style::{self, ComputedStyle};
 use eframe::egui::Color32;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 static COUNTER: AtomicUsize = AtomicUsize::new(1);
@@ -34,14 +39,53 @@ pub enum HtmlTag {
     Abbr, // Abbreviation tag
     Audio,
     Img,
-    Table, // Table tag
+    Picture, // Alternative sources for the <img> inside it
+    Source,  // One alternative of a <picture>
+    Svg,     // Inline vector graphics; its children describe the drawing
+    Iframe,  // A nested page
+    Table,   // Table tag
     Thead,
     Tbody,
     Tfoot,
-    Th,      // Table header cell tag
-    Td,      // Table data cell tag
-    Tr,      // Table row tag
-    Caption, // Table caption tag
+    Th,       // Table header cell tag
+    Td,       // Table data cell tag
+    Tr,       // Table row tag
+    Caption,  // Table caption tag
+    Colgroup, // Group of table columns
+    Col,      // Table column; sets the width of its columns
+    Ul,       // Unordered list
+    Ol,       // Ordered list
+    Li,       // List item
+    Dl,       // Description list
+    Dt,       // Description term
+    Dd,       // Description details
+    Pre,      // Preformatted text
+    Code,     // Inline code
+    Kbd,      // Keyboard input
+    Samp,     // Sample program output
+    Tt,       // Teletype (monospace) text
+    Sup,      // Superscript
+    Sub,      // Subscript
+    Mark,     // Highlighted text
+    Q,        // Inline quotation
+    Cite,     // Title of a cited work
+    Del,      // Deleted text
+    Ins,      // Inserted text
+    Time,     // Date or time, machine-readable in `datetime`
+    Var,      // Variable
+    Dfn,      // Defining instance of a term
+    Section,
+    Article,
+    Nav,
+    Header,
+    Footer,
+    Main,
+    Aside,
+    Figure,
+    Figcaption,
+    Blockquote,
+    Details, // Disclosure widget; shows its content when it has the `open` attribute
+    Summary, // The always visible label of a <details>
     Body,
     Head,
     Title,
@@ -51,13 +95,99 @@ pub enum HtmlTag {
     Custom(String), // for arbitrary tags
 }
 
+impl HtmlTag {
+    /// The tag name as it is written in markup (`HtmlTag::Div` -> `"div"`).
+    pub fn tag_name(&self) -> &str {
+        match self {
+            HtmlTag::Div => "div",
+            HtmlTag::Span => "span",
+            HtmlTag::P => "p",
+            HtmlTag::H1 => "h1",
+            HtmlTag::H2 => "h2",
+            HtmlTag::H3 => "h3",
+            HtmlTag::H4 => "h4",
+            HtmlTag::H5 => "h5",
+            HtmlTag::H6 => "h6",
+            HtmlTag::Strong => "strong",
+            HtmlTag::Em => "em",
+            HtmlTag::Small => "small",
+            HtmlTag::Big => "big",
+            HtmlTag::B => "b",
+            HtmlTag::W => "w",
+            HtmlTag::U => "u",
+            HtmlTag::I => "i",
+            HtmlTag::S => "s",
+            HtmlTag::Br => "br",
+            HtmlTag::Hr => "hr",
+            HtmlTag::A => "a",
+            HtmlTag::Abbr => "abbr",
+            HtmlTag::Audio => "audio",
+            HtmlTag::Img => "img",
+            HtmlTag::Picture => "picture",
+            HtmlTag::Source => "source",
+            HtmlTag::Svg => "svg",
+            HtmlTag::Iframe => "iframe",
+            HtmlTag::Table => "table",
+            HtmlTag::Thead => "thead",
+            HtmlTag::Tbody => "tbody",
+            HtmlTag::Tfoot => "tfoot",
+            HtmlTag::Th => "th",
+            HtmlTag::Td => "td",
+            HtmlTag::Tr => "tr",
+            HtmlTag::Caption => "caption",
+            HtmlTag::Colgroup => "colgroup",
+            HtmlTag::Col => "col",
+            HtmlTag::Ul => "ul",
+            HtmlTag::Ol => "ol",
+            HtmlTag::Li => "li",
+            HtmlTag::Dl => "dl",
+            HtmlTag::Dt => "dt",
+            HtmlTag::Dd => "dd",
+            HtmlTag::Pre => "pre",
+            HtmlTag::Code => "code",
+            HtmlTag::Kbd => "kbd",
+            HtmlTag::Samp => "samp",
+            HtmlTag::Tt => "tt",
+            HtmlTag::Sup => "sup",
+            HtmlTag::Sub => "sub",
+            HtmlTag::Mark => "mark",
+            HtmlTag::Q => "q",
+            HtmlTag::Cite => "cite",
+            HtmlTag::Del => "del",
+            HtmlTag::Ins => "ins",
+            HtmlTag::Time => "time",
+            HtmlTag::Var => "var",
+            HtmlTag::Dfn => "dfn",
+            HtmlTag::Section => "section",
+            HtmlTag::Article => "article",
+            HtmlTag::Nav => "nav",
+            HtmlTag::Header => "header",
+            HtmlTag::Footer => "footer",
+            HtmlTag::Main => "main",
+            HtmlTag::Aside => "aside",
+            HtmlTag::Figure => "figure",
+            HtmlTag::Figcaption => "figcaption",
+            HtmlTag::Blockquote => "blockquote",
+            HtmlTag::Details => "details",
+            HtmlTag::Summary => "summary",
+            HtmlTag::Body => "body",
+            HtmlTag::Head => "head",
+            HtmlTag::Title => "title",
+            HtmlTag::Html => "html",
+            HtmlTag::Script => "script",
+            HtmlTag::Style => "style",
+            HtmlTag::Custom(name) => name,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum NodeType {
     Element(HtmlTag),
     Text(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Length {
     Px(f32),
     Em(f32),
@@ -78,7 +208,21 @@ impl Length {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Parse a legacy dimension attribute: `120` is pixels, `50%` a percentage.
+pub fn parse_dimension(value: &str) -> Option<Length> {
+    let value = value.trim();
+    if let Some(percent) = value.strip_suffix('%') {
+        return percent.trim().parse().ok().map(Length::Percent);
+    }
+    let pixels = value.strip_suffix("px").unwrap_or(value);
+    pixels
+        .parse()
+        .ok()
+        .filter(|px: &f32| *px >= 0.0)
+        .map(Length::Px)
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Color {
     Rgb(u8, u8, u8),
     Rgba(u8, u8, u8, f32),
@@ -88,8 +232,8 @@ pub enum Color {
 }
 
 impl Color {
-    pub fn to_ecolor(self) -> Color32 {
-        match self {
+    pub fn to_ecolor(&self) -> Color32 {
+        match *self {
             Color::Rgb(r, g, b) => Color32::from_rgb(r, g, b),
             Color::Rgba(r, g, b, a) => {
                 let alpha = (a.clamp(0.0, 1.0) * 255.0).round() as u8;
@@ -104,7 +248,7 @@ impl Color {
                 let alpha = (a.clamp(0.0, 1.0) * 255.0).round() as u8;
                 Color32::from_rgba_premultiplied(r, g, b, alpha)
             }
-            Color::Hex(s) => parse_hex_color(&s),
+            Color::Hex(ref s) => parse_hex_color(s),
         }
     }
 }
@@ -151,7 +295,7 @@ fn parse_hex_color(hex: &str) -> Color32 {
             (v, v, v, 255)
         }
         2 => {
-            let v = parse_component(&hex);
+            let v = parse_component(hex);
             (v, v, v, 255)
         }
         3 => (
@@ -202,32 +346,341 @@ fn parse_hex_color(hex: &str) -> Color32 {
     Color32::from_rgba_premultiplied(r, g, b, a)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum StyleProperty {
     Keyword(String),
     Length(Length),
     Color(Color),
+    /// A value that uses `var()`, as written. It is parsed again once the node's custom
+    /// properties are substituted, so no node's cascaded style keeps one.
+    Unresolved(String),
 }
 
-#[derive(Debug, Clone)]
-pub enum Selector {
+/// One condition of a compound selector.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimpleSelector {
     Universal,     // * // All
-    Class(String), // #message, ...
+    Class(String), // .message, ...
     Id(String),    // #message-box, ...
     Type(String),  // H1, P, ...
+    Attribute {
+        name: String, // lowercase, like attribute names in the document
+        value: Option<(AttributeOperator, String)>, // `None` for `[name]`
+        case_insensitive: bool, // the `i` flag: `[type="TEXT" i]`
+    },
+    PseudoClass(PseudoClass),
+}
+
+/// A pseudo-class: a condition on where an element is in the tree or on its state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PseudoClass {
+    Root,
+    Empty,
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    FirstOfType,
+    LastOfType,
+    OnlyOfType,
+    NthChild(i32, i32), // an+b
+    NthLastChild(i32, i32),
+    NthOfType(i32, i32),
+    NthLastOfType(i32, i32),
+    Not(Vec<Selector>),
+    Is(Vec<Selector>),
+    Where(Vec<Selector>), // like `Is`, but adds nothing to the specificity
+    AnyLink,
+    Link,
+    Visited,
+    Checked,
+    Disabled,
+    Enabled,
+    Hover,
+    Active,
+    Focus,
+    FocusWithin,
+}
+
+/// A pseudo-element: a part of an element, or a box it generates, that rules can style.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PseudoElement {
+    Before, // a box before the element's content, holding its `content`
+    After,
+    Marker, // the bullet or number of a list item
+    FirstLine,
+    FirstLetter,
+}
+
+impl PseudoElement {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "before" => Some(PseudoElement::Before),
+            "after" => Some(PseudoElement::After),
+            "marker" => Some(PseudoElement::Marker),
+            "first-line" => Some(PseudoElement::FirstLine),
+            "first-letter" => Some(PseudoElement::FirstLetter),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PseudoElement::Before => "before",
+            PseudoElement::After => "after",
+            PseudoElement::Marker => "marker",
+            PseudoElement::FirstLine => "first-line",
+            PseudoElement::FirstLetter => "first-letter",
+        }
+    }
+}
+
+/// How an attribute selector compares the attribute's value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttributeOperator {
+    Equals,    // [a=v]
+    Includes,  // [a~=v]: one of its whitespace separated words
+    DashMatch, // [a|=v]: `v` or starts with `v-`
+    Prefix,    // [a^=v]
+    Suffix,    // [a$=v]
+    Substring, // [a*=v]
+}
+
+/// How two compound selectors of a complex selector relate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Combinator {
+    Descendant,        // `a b`
+    Child,             // `a > b`
+    NextSibling,       // `a + b`
+    SubsequentSibling, // `a ~ b`
+}
+
+/// A complex selector such as `ul > li.item a[href]`, kept in the right-to-left order it
+/// is matched in: `subject` is the compound the element itself must match, and each entry
+/// of `context` says how the next compound to the left relates to the previous match.
+/// A selector ending in a pseudo-element (`p::first-line`) styles that part of the subject
+/// rather than the subject itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    pub subject: Vec<SimpleSelector>,
+    pub context: Vec<(Combinator, Vec<SimpleSelector>)>,
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+/// (ids, classes and attributes, types); compared in that order.
+pub type Specificity = (u32, u32, u32);
+
+impl Selector {
+    fn simple_selectors(&self) -> impl Iterator<Item = &SimpleSelector> {
+        std::iter::once(&self.subject)
+            .chain(self.context.iter().map(|(_, compound)| compound))
+            .flatten()
+    }
+
+    pub fn specificity(&self) -> Specificity {
+        let mut specificity = (0, 0, 0);
+        for simple in self.simple_selectors() {
+            let (ids, classes, types) = match simple {
+                SimpleSelector::Universal => (0, 0, 0),
+                SimpleSelector::Id(_) => (1, 0, 0),
+                SimpleSelector::Class(_) | SimpleSelector::Attribute { .. } => (0, 1, 0),
+                SimpleSelector::Type(_) => (0, 0, 1),
+                // These count as their most specific argument
+                SimpleSelector::PseudoClass(PseudoClass::Not(list) | PseudoClass::Is(list)) => list
+                    .iter()
+                    .map(Selector::specificity)
+                    .max()
+                    .unwrap_or_default(),
+                SimpleSelector::PseudoClass(PseudoClass::Where(_)) => (0, 0, 0),
+                SimpleSelector::PseudoClass(_) => (0, 1, 0),
+            };
+            specificity.0 += ids;
+            specificity.1 += classes;
+            specificity.2 += types;
+        }
+        // A pseudo-element counts like a type
+        if self.pseudo_element.is_some() {
+            specificity.2 += 1;
+        }
+        specificity
+    }
+}
+
+impl fmt::Display for PseudoClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list(f: &mut fmt::Formatter<'_>, name: &str, selectors: &[Selector]) -> fmt::Result {
+            write!(f, ":{}(", name)?;
+            for (i, selector) in selectors.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", selector)?;
+            }
+            write!(f, ")")
+        }
+        match self {
+            PseudoClass::Root => write!(f, ":root"),
+            PseudoClass::Empty => write!(f, ":empty"),
+            PseudoClass::FirstChild => write!(f, ":first-child"),
+            PseudoClass::LastChild => write!(f, ":last-child"),
+            PseudoClass::OnlyChild => write!(f, ":only-child"),
+            PseudoClass::FirstOfType => write!(f, ":first-of-type"),
+            PseudoClass::LastOfType => write!(f, ":last-of-type"),
+            PseudoClass::OnlyOfType => write!(f, ":only-of-type"),
+            PseudoClass::NthChild(a, b) => write!(f, ":nth-child({}n{:+})", a, b),
+            PseudoClass::NthLastChild(a, b) => write!(f, ":nth-last-child({}n{:+})", a, b),
+            PseudoClass::NthOfType(a, b) => write!(f, ":nth-of-type({}n{:+})", a, b),
+            PseudoClass::NthLastOfType(a, b) => write!(f, ":nth-last-of-type({}n{:+})", a, b),
+            PseudoClass::Not(selectors) => list(f, "not", selectors),
+            PseudoClass::Is(selectors) => list(f, "is", selectors),
+            PseudoClass::Where(selectors) => list(f, "where", selectors),
+            PseudoClass::AnyLink => write!(f, ":any-link"),
+            PseudoClass::Link => write!(f, ":link"),
+            PseudoClass::Visited => write!(f, ":visited"),
+            PseudoClass::Checked => write!(f, ":checked"),
+            PseudoClass::Disabled => write!(f, ":disabled"),
+            PseudoClass::Enabled => write!(f, ":enabled"),
+            PseudoClass::Hover => write!(f, ":hover"),
+            PseudoClass::Active => write!(f, ":active"),
+            PseudoClass::Focus => write!(f, ":focus"),
+            PseudoClass::FocusWithin => write!(f, ":focus-within"),
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn compound(f: &mut fmt::Formatter<'_>, simples: &[SimpleSelector]) -> fmt::Result {
+            for simple in simples {
+                match simple {
+                    SimpleSelector::Universal => write!(f, "*")?,
+                    SimpleSelector::Class(name) => write!(f, ".{}", name)?,
+                    SimpleSelector::Id(name) => write!(f, "#{}", name)?,
+                    SimpleSelector::Type(name) => write!(f, "{}", name)?,
+                    SimpleSelector::Attribute {
+                        name,
+                        value,
+                        case_insensitive,
+                    } => {
+                        write!(f, "[{}", name)?;
+                        if let Some((operator, value)) = value {
+                            let operator = match operator {
+                                AttributeOperator::Equals => "=",
+                                AttributeOperator::Includes => "~=",
+                                AttributeOperator::DashMatch => "|=",
+                                AttributeOperator::Prefix => "^=",
+                                AttributeOperator::Suffix => "$=",
+                                AttributeOperator::Substring => "*=",
+                            };
+                            write!(f, "{}{:?}", operator, value)?;
+                        }
+                        if *case_insensitive {
+                            write!(f, " i")?;
+                        }
+                        write!(f, "]")?;
+                    }
+                    SimpleSelector::PseudoClass(pseudo) => write!(f, "{}", pseudo)?,
+                }
+            }
+            Ok(())
+        }
+        for (combinator, simples) in self.context.iter().rev() {
+            compound(f, simples)?;
+            let combinator = match combinator {
+                Combinator::Descendant => " ",
+                Combinator::Child => " > ",
+                Combinator::NextSibling => " + ",
+                Combinator::SubsequentSibling => " ~ ",
+            };
+            write!(f, "{}", combinator)?;
+        }
+        compound(f, &self.subject)?;
+        if let Some(pseudo) = self.pseudo_element {
+            write!(f, "::{}", pseudo.name())?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct CssRule {
     pub selectors: Vec<Selector>,
     pub properties: HashMap<String, StyleProperty>,
+    pub important: HashSet<String>, // the properties declared `!important`
+    /// The media query lists of the `@media` blocks and sheets the rule is in; it only
+    /// applies while they all match
+    pub media: Vec<Arc<MediaQueryList>>,
+}
+
+impl CssRule {
+    pub fn applies(&self, env: &MediaEnvironment) -> bool {
+        self.media.iter().all(|media| media.matches(env))
+    }
+}
+
+/// A parsed stylesheet. The rules of the sheets it imports come before its own.
+#[derive(Debug, Clone, Default)]
+pub struct StyleSheet {
+    pub imports: Vec<Import>,
+    pub rules: Vec<CssRule>,
+}
+
+/// An `@import` at the start of a stylesheet.
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub href: String,  // as written; relative to the importing sheet
+    pub media: String, // the media query list after the URL; empty for all media
+}
+
+/// The declarations of an element's `style` attribute.
+#[derive(Debug, Clone, Default)]
+pub struct InlineStyle {
+    pub properties: HashMap<String, StyleProperty>,
+    pub important: HashSet<String>,
+}
+
+/// Where a stylesheet comes from. Normal declarations of a later origin win over an
+/// earlier one; for `!important` declarations the order is reversed, so the browser's
+/// and the reader's important styles can't be overridden by the page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    UserAgent,
+    User,
+    Author,
+    Inline, // the page's `style` attributes, which beat its rules whatever their specificity
+}
+
+impl Origin {
+    /// Where the origin comes in the order user agent, user, author; `revert` rolls back
+    /// to the origins before the one it is declared in.
+    fn level(self) -> u8 {
+        match self {
+            Origin::UserAgent => 0,
+            Origin::User => 1,
+            Origin::Author | Origin::Inline => 2,
+        }
+    }
+
+    /// The rank of a declaration from this origin in the cascade; higher wins.
+    fn precedence(self, important: bool) -> u8 {
+        match (important, self) {
+            (false, Origin::UserAgent) => 0,
+            (false, Origin::User) => 1,
+            (false, Origin::Author) => 2,
+            (false, Origin::Inline) => 3,
+            (true, Origin::Author) => 4,
+            (true, Origin::Inline) => 5,
+            (true, Origin::User) => 6,
+            (true, Origin::UserAgent) => 7,
+        }
+    }
 }
 
+/// Owned node tree, as produced by the HTML parser. The browser converts it into a
+/// `dom::Document` before styling and rendering.
 #[derive(Debug, Clone)]
 pub struct HtmlNode {
     pub node_type: NodeType,
     pub attributes: HashMap<String, String>,
-    pub style: HashMap<String, StyleProperty>, // property_name: value // curently not suported
     pub children: Vec<HtmlNode>,
 }
 
@@ -240,7 +693,6 @@ impl HtmlNode {
     ) -> Self {
         HtmlNode {
             node_type: NodeType::Element(tag),
-            style: HashMap::new(),
             attributes,
             children,
         }
@@ -250,120 +702,745 @@ impl HtmlNode {
     pub fn new_text(text: String) -> Self {
         HtmlNode {
             node_type: NodeType::Text(text),
-            style: HashMap::new(),
             attributes: HashMap::new(),
             children: Vec::new(),
         }
     }
+}
+
+impl Document {
+    /// Store the author rules and restyle the whole document with them
+    pub fn set_stylesheet(&mut self, rules: Vec<CssRule>) {
+        self.replace_stylesheet(rules);
+        self.stylize();
+    }
+
+    /// Evaluate media queries against `env` from now on. Restyles the document if that
+    /// changes whether any rule applies, and returns whether it did.
+    pub fn set_media(&mut self, env: MediaEnvironment) -> bool {
+        let old = self.replace_media(env);
+        let flipped = user_agent_stylesheet()
+            .iter()
+            .chain(user_stylesheet())
+            .chain(self.stylesheet())
+            .any(|rule| !rule.media.is_empty() && rule.applies(&old) != rule.applies(&env));
+        if flipped {
+            self.stylize();
+        }
+        flipped
+    }
 
     /// apply rules and inheritance
-    pub fn stylize(&mut self, rules: &Vec<CssRule>) {
-        self.stylize_recursive(rules);
+    pub fn stylize(&mut self) {
+        self.take_style_dirty();
+        let ids = self.node_ids();
+        self.stylize_nodes(ids);
     }
 
-    /// Recursive worker: apply rules with specificity and inherit from parent
-    fn stylize_recursive(&mut self, rules: &Vec<CssRule>) {
+    /// Recompute style only for the nodes mutations have marked dirty, and the children of
+    /// those whose style changed. Returns the number of nodes that were restyled.
+    pub fn restyle(&mut self) -> usize {
+        let dirty = self.take_style_dirty();
+        self.stylize_nodes(dirty)
+    }
+
+    /// Restyle some nodes, parents before their children so they inherit the new values.
+    /// The children of a node whose computed style changed are restyled too, whether they
+    /// were asked for or not. Returns the number of nodes restyled.
+    fn stylize_nodes(&mut self, ids: Vec<NodeId>) -> usize {
+        // (depth, node), so the shallowest node comes first
+        let mut pending: BTreeSet<(usize, NodeId)> = ids
+            .into_iter()
+            .map(|id| (self.node(id).ancestors().count(), id))
+            .collect();
+        let mut count = 0;
+        while let Some((depth, id)) = pending.pop_first() {
+            count += 1;
+            if self.stylize_node(id) {
+                pending.extend(
+                    self.node(id)
+                        .children()
+                        .map(|child| (depth + 1, child.id())),
+                );
+            }
+        }
+        count
+    }
+
+    /// Returns whether the computed style of the node changed.
+    fn stylize_node(&mut self, id: NodeId) -> bool {
+        let mut styles = self.cascade(
+            self.node(id),
+            &[
+                (Origin::UserAgent, user_agent_stylesheet()),
+                (Origin::User, user_stylesheet()),
+                (Origin::Author, self.stylesheet()),
+            ],
+        );
+        let mut style = styles.remove(&None).unwrap_or_default();
+        let parent = self.node(id).parent().map(|parent| parent.computed.clone());
+        let inherited = parent
+            .as_ref()
+            .map(|parent| Arc::clone(&parent.custom))
+            .unwrap_or_default();
+        let custom = style::resolve_variables(&mut style, &inherited);
+        let mut computed = ComputedStyle::compute(&style, parent.as_deref());
+        computed.custom = Arc::clone(&custom);
+        let node = self.get_mut(id);
+        // `content`, the counters and SVG properties are only kept in `style`, so a change
+        // there counts too
+        let changed = *node.computed != computed || node.style != style;
+        node.computed = Arc::new(computed);
+        node.style = style;
+        // Pseudo-elements inherit the element's custom properties
+        node.pseudo_style = styles
+            .into_iter()
+            .filter_map(|(pseudo, mut style)| {
+                style::resolve_variables(&mut style, &custom);
+                Some((pseudo?, style))
+            })
+            .collect();
+        changed
+    }
+
+    /// Resolve the properties of one node, and of each of its pseudo-elements (the `Some`
+    /// keys), from the rules that match it and its `style` attribute. For each property
+    /// the declaration that wins is the one with the highest origin and importance, then
+    /// the most specific selector, then the one that comes last.
+    fn cascade(
+        &self,
+        node: NodeRef,
+        sheets: &[(Origin, &[CssRule])],
+    ) -> HashMap<Option<PseudoElement>, HashMap<String, StyleProperty>> {
         // Only element nodes get rules
-        if let NodeType::Element(_) = self.node_type {
-            // temp map: property -> (specificity, value)
-            let mut computed: HashMap<String, (u8, StyleProperty)> = HashMap::new();
-
-            // apply each rule in order
-            for rule in rules {
-                // find highest specificity among selectors that match
-                let mut rule_spec: Option<u8> = None;
-                for sel in &rule.selectors {
-                    if self.matches_selector(sel) {
-                        let spec = match sel {
-                            Selector::Universal => 0,
-                            Selector::Type(_) => 1,
-                            Selector::Class(_) => 2,
-                            Selector::Id(_) => 3,
-                        };
-                        rule_spec = Some(rule_spec.map_or(spec, |old| old.max(spec)));
-                    }
+        if !node.is_element() {
+            return HashMap::new();
+        }
+        // property -> every declaration of it that applies, since `revert` can fall back
+        // to a losing one
+        let mut declared: HashMap<Option<PseudoElement>, HashMap<String, Vec<Declaration>>> =
+            HashMap::new();
+
+        let rules = sheets
+            .iter()
+            .flat_map(|(origin, rules)| rules.iter().map(move |rule| (*origin, rule)));
+        for (order, (origin, rule)) in rules.enumerate() {
+            if !rule.applies(self.media()) {
+                continue;
+            }
+            // find highest specificity among selectors that match, for the element and for
+            // each of its pseudo-elements
+            let mut matched: Vec<(Option<PseudoElement>, Specificity)> = Vec::new();
+            for selector in rule.selectors.iter().filter(|sel| node.originates(sel)) {
+                let spec = selector.specificity();
+                match matched
+                    .iter_mut()
+                    .find(|(pseudo, _)| *pseudo == selector.pseudo_element)
+                {
+                    Some((_, best)) => *best = spec.max(*best),
+                    None => matched.push((selector.pseudo_element, spec)),
                 }
-                if let Some(spec) = rule_spec {
-                    // rule applies: integrate its properties
-                    for (key, value) in &rule.properties {
-                        // override if higher or equal specificity (later wins)
-                        if computed
-                            .get(key)
-                            .map_or(true, |(old_spec, _)| spec >= *old_spec)
-                        {
-                            computed.insert(key.clone(), (spec, value.clone()));
-                        }
-                    }
+            }
+            for (pseudo, spec) in matched {
+                let declared = declared.entry(pseudo).or_default();
+                for (key, value) in &rule.properties {
+                    let precedence = origin.precedence(rule.important.contains(key));
+                    declared.entry(key.clone()).or_default().push(Declaration {
+                        rank: (precedence, spec, order),
+                        origin,
+                        value: value.clone(),
+                    });
                 }
             }
+        }
+
+        // The style attribute applies to the element itself, after every rule
+        let declared_element = declared.entry(None).or_default();
+        for (key, value) in &node.inline_style.properties {
+            let important = node.inline_style.important.contains(key);
+            declared_element
+                .entry(key.clone())
+                .or_default()
+                .push(Declaration {
+                    rank: (Origin::Inline.precedence(important), (0, 0, 0), usize::MAX),
+                    origin: Origin::Inline,
+                    value: value.clone(),
+                });
+        }
 
-            // write computed values into node.style
-            self.style = computed.into_iter().map(|(k, (_spec, v))| (k, v)).collect();
+        declared
+            .into_iter()
+            .map(|(pseudo, style)| {
+                let style = style
+                    .into_iter()
+                    .filter_map(|(key, declarations)| Some((key, cascaded_value(declarations)?)))
+                    .collect();
+                (pseudo, style)
+            })
+            .collect()
+    }
+}
+
+/// A declaration that applies to a node, with what decides whether it wins.
+struct Declaration {
+    rank: (u8, Specificity, usize), // precedence of origin and importance, specificity, order
+    origin: Origin,
+    value: StyleProperty,
+}
 
-            // recurse for all children, passing this node's style as parent
-            for child in &mut self.children {
-                child.stylize_recursive(rules);
+/// The value the cascade picks from the declarations of one property: the highest ranked,
+/// except that `revert` rolls back to what the earlier origins alone would give. `None`
+/// if nothing is left, so the property is inherited or initial as if unset.
+fn cascaded_value(mut declarations: Vec<Declaration>) -> Option<StyleProperty> {
+    declarations.sort_by_key(|declaration| std::cmp::Reverse(declaration.rank));
+    let mut below = None; // only origins before this one count after a `revert`
+    for declaration in declarations {
+        let level = declaration.origin.level();
+        if below.is_some_and(|below| level >= below) {
+            continue;
+        }
+        match &declaration.value {
+            StyleProperty::Keyword(keyword) if keyword.trim().eq_ignore_ascii_case("revert") => {
+                below = Some(level);
             }
+            _ => return Some(declaration.value),
         }
     }
+    None
+}
 
-    fn matches_selector(&self, selector: &Selector) -> bool {
-        match selector {
-            Selector::Universal => true,
-            Selector::Class(name) => self
-                .attributes
-                .get("class")
-                .map_or(false, |cls| cls.split_whitespace().any(|c| c == name)),
-            Selector::Id(id) => self.attributes.get("id").map_or(false, |v| v == id),
-            Selector::Type(s) => {
-                let s_lower = s.to_lowercase();
-                // Borrow node_type to avoid moving out
-                if let NodeType::Element(ref html_tag) = self.node_type {
-                    match html_tag {
-                        HtmlTag::Div => s_lower == "div",
-                        HtmlTag::Span => s_lower == "span",
-                        HtmlTag::P => s_lower == "p",
-                        HtmlTag::H1 => s_lower == "h1",
-                        HtmlTag::H2 => s_lower == "h2",
-                        HtmlTag::H3 => s_lower == "h3",
-                        HtmlTag::H4 => s_lower == "h4",
-                        HtmlTag::H5 => s_lower == "h5",
-                        HtmlTag::H6 => s_lower == "h6",
-                        HtmlTag::Strong => s_lower == "strong",
-                        HtmlTag::Small => s_lower == "small",
-                        HtmlTag::Big => s_lower == "big",
-                        HtmlTag::B => s_lower == "b",
-                        HtmlTag::W => s_lower == "w",
-                        HtmlTag::I => s_lower == "i",
-                        HtmlTag::U => s_lower == "u",
-                        HtmlTag::S => s_lower == "s",
-                        HtmlTag::Br => s_lower == "br",
-                        HtmlTag::Hr => s_lower == "hr",
-                        HtmlTag::A => s_lower == "a",
-                        HtmlTag::Em => s_lower == "em",
-                        HtmlTag::Abbr => s_lower == "abbr",
-                        HtmlTag::Img => s_lower == "img",
-                        HtmlTag::Audio => s_lower == "audio",
-                        HtmlTag::Table => s_lower == "table",
-                        HtmlTag::Thead => s_lower == "thead",
-                        HtmlTag::Tbody => s_lower == "tbody",
-                        HtmlTag::Tfoot => s_lower == "tfoot",
-                        HtmlTag::Th => s_lower == "th",
-                        HtmlTag::Td => s_lower == "td",
-                        HtmlTag::Tr => s_lower == "tr",
-                        HtmlTag::Caption => s_lower == "caption",
-                        HtmlTag::Body => s_lower == "body",
-                        HtmlTag::Head => s_lower == "head",
-                        HtmlTag::Title => s_lower == "title",
-                        HtmlTag::Html => s_lower == "html",
-                        HtmlTag::Script => s_lower == "script",
-                        HtmlTag::Style => s_lower == "style",
-                        HtmlTag::Custom(t) => t.to_lowercase() == s_lower,
-                    }
+/// Whether position `index` (counting from 1) is `a*n + b` for some `n >= 0`.
+fn nth(a: i32, b: i32, index: usize) -> bool {
+    // In i64, where `a` and `b` at the ends of their range can't overflow
+    let Ok(index) = i64::try_from(index) else {
+        return false;
+    };
+    let (a, steps) = (i64::from(a), index - i64::from(b));
+    if a == 0 {
+        return steps == 0;
+    }
+    steps % a == 0 && steps / a >= 0
+}
+
+impl NodeRef<'_> {
+    /// The `width` or `height` an element asks for, from its CSS or else its HTML attribute.
+    pub fn dimension(&self, name: &str) -> Option<Length> {
+        if let Some(StyleProperty::Length(length)) = self.style.get(name) {
+            return Some(length.clone());
+        }
+        self.get_attribute(name).and_then(parse_dimension)
+    }
+
+    /// Whether this element matches a complex selector. Matching goes right to left: the
+    /// element must match the rightmost compound, and then an element related to it by the
+    /// combinator must match the next one, and so on.
+    /// A selector with a pseudo-element matches no element.
+    pub fn matches_selector(&self, selector: &Selector) -> bool {
+        selector.pseudo_element.is_none() && self.originates(selector)
+    }
+
+    /// Whether this element matches `selector` apart from its pseudo-element, so the
+    /// selector applies to the element or to that pseudo-element of it.
+    fn originates(&self, selector: &Selector) -> bool {
+        self.matches_compound(&selector.subject) && self.matches_context(&selector.context)
+    }
+
+    fn matches_context(&self, context: &[(Combinator, Vec<SimpleSelector>)]) -> bool {
+        let Some(((combinator, compound), rest)) = context.split_first() else {
+            return true;
+        };
+        let candidate =
+            |node: &NodeRef| node.matches_compound(compound) && node.matches_context(rest);
+        match combinator {
+            Combinator::Child => self.parent().is_some_and(|parent| candidate(&parent)),
+            Combinator::Descendant => self.ancestors().any(|ancestor| candidate(&ancestor)),
+            Combinator::NextSibling => self
+                .preceding_elements()
+                .next()
+                .is_some_and(|sibling| candidate(&sibling)),
+            Combinator::SubsequentSibling => {
+                self.preceding_elements().any(|sibling| candidate(&sibling))
+            }
+        }
+    }
+
+    /// The element siblings before this node, nearest first.
+    fn preceding_elements(&self) -> impl Iterator<Item = NodeRef<'_>> + use<'_> {
+        std::iter::successors(self.prev_sibling(), NodeRef::prev_sibling)
+            .filter(NodeRef::is_element)
+    }
+
+    fn matches_compound(&self, compound: &[SimpleSelector]) -> bool {
+        self.is_element() && compound.iter().all(|simple| self.matches_simple(simple))
+    }
+
+    fn matches_simple(&self, simple: &SimpleSelector) -> bool {
+        match simple {
+            SimpleSelector::Universal => true,
+            SimpleSelector::Class(name) => self.has_class(name),
+            SimpleSelector::Id(id) => self.get_attribute("id") == Some(id.as_str()),
+            SimpleSelector::Type(s) => self
+                .tag()
+                .is_some_and(|html_tag| html_tag.tag_name().eq_ignore_ascii_case(s)),
+            SimpleSelector::Attribute {
+                name,
+                value,
+                case_insensitive,
+            } => {
+                let Some(actual) = self.get_attribute(name) else {
+                    return false;
+                };
+                let Some((operator, expected)) = value else {
+                    return true;
+                };
+                let (actual, expected) = if *case_insensitive {
+                    (actual.to_lowercase(), expected.to_lowercase())
                 } else {
-                    false
+                    (actual.to_string(), expected.clone())
+                };
+                // An empty value never matches the substring operators
+                match operator {
+                    AttributeOperator::Equals => actual == expected,
+                    AttributeOperator::Includes => {
+                        actual.split_ascii_whitespace().any(|word| word == expected)
+                    }
+                    AttributeOperator::DashMatch => {
+                        actual == expected
+                            || actual
+                                .strip_prefix(&expected)
+                                .is_some_and(|rest| rest.starts_with('-'))
+                    }
+                    AttributeOperator::Prefix => {
+                        !expected.is_empty() && actual.starts_with(&expected)
+                    }
+                    AttributeOperator::Suffix => {
+                        !expected.is_empty() && actual.ends_with(&expected)
+                    }
+                    AttributeOperator::Substring => {
+                        !expected.is_empty() && actual.contains(&expected)
+                    }
                 }
             }
+            SimpleSelector::PseudoClass(pseudo) => self.matches_pseudo_class(pseudo),
         }
     }
+
+    fn matches_pseudo_class(&self, pseudo: &PseudoClass) -> bool {
+        let name = self.tag().map(HtmlTag::tag_name).unwrap_or_default();
+        let same_type = |node: &NodeRef| {
+            node.tag()
+                .is_some_and(|tag| tag.tag_name().eq_ignore_ascii_case(name))
+        };
+        match pseudo {
+            PseudoClass::Root => self.parent().is_none(),
+            PseudoClass::Empty => self.children().next().is_none(),
+            PseudoClass::FirstChild => self.preceding_elements().next().is_none(),
+            PseudoClass::LastChild => self.following_elements().next().is_none(),
+            PseudoClass::OnlyChild => {
+                self.preceding_elements().next().is_none()
+                    && self.following_elements().next().is_none()
+            }
+            PseudoClass::FirstOfType => !self.preceding_elements().any(|node| same_type(&node)),
+            PseudoClass::LastOfType => !self.following_elements().any(|node| same_type(&node)),
+            PseudoClass::OnlyOfType => {
+                !self.preceding_elements().any(|node| same_type(&node))
+                    && !self.following_elements().any(|node| same_type(&node))
+            }
+            // Positions count from 1
+            PseudoClass::NthChild(a, b) => nth(*a, *b, self.preceding_elements().count() + 1),
+            PseudoClass::NthLastChild(a, b) => nth(*a, *b, self.following_elements().count() + 1),
+            PseudoClass::NthOfType(a, b) => nth(
+                *a,
+                *b,
+                self.preceding_elements().filter(same_type).count() + 1,
+            ),
+            PseudoClass::NthLastOfType(a, b) => nth(
+                *a,
+                *b,
+                self.following_elements().filter(same_type).count() + 1,
+            ),
+            PseudoClass::Not(selectors) => !selectors
+                .iter()
+                .any(|selector| self.matches_selector(selector)),
+            PseudoClass::Is(selectors) | PseudoClass::Where(selectors) => selectors
+                .iter()
+                .any(|selector| self.matches_selector(selector)),
+            PseudoClass::AnyLink => self.is_link(),
+            PseudoClass::Link => self.is_link() && !self.document().is_visited(self.id()),
+            PseudoClass::Visited => self.is_link() && self.document().is_visited(self.id()),
+            PseudoClass::Checked => match self.tag() {
+                Some(HtmlTag::Custom(name)) if name.eq_ignore_ascii_case("input") => {
+                    self.has_attribute("checked")
+                        && self.get_attribute("type").is_some_and(|kind| {
+                            kind.eq_ignore_ascii_case("checkbox")
+                                || kind.eq_ignore_ascii_case("radio")
+                        })
+                }
+                Some(HtmlTag::Custom(name)) if name.eq_ignore_ascii_case("option") => {
+                    self.has_attribute("selected")
+                }
+                _ => false,
+            },
+            PseudoClass::Disabled => self.is_form_control() && self.has_attribute("disabled"),
+            PseudoClass::Enabled => self.is_form_control() && !self.has_attribute("disabled"),
+            // The ancestors of the hovered and pressed elements are hovered and pressed too
+            PseudoClass::Hover => self.is_inclusive_ancestor_of(self.document().hovered()),
+            PseudoClass::Active => self.is_inclusive_ancestor_of(self.document().active()),
+            PseudoClass::Focus => self.document().focused() == Some(self.id()),
+            PseudoClass::FocusWithin => self.is_inclusive_ancestor_of(self.document().focused()),
+        }
+    }
+
+    /// The element siblings after this node, nearest first.
+    fn following_elements(&self) -> impl Iterator<Item = NodeRef<'_>> + use<'_> {
+        std::iter::successors(self.next_sibling(), NodeRef::next_sibling)
+            .filter(NodeRef::is_element)
+    }
+
+    fn is_inclusive_ancestor_of(&self, id: Option<NodeId>) -> bool {
+        id.is_some_and(|id| {
+            let node = self.document().node(id);
+            node == *self || node.ancestors().any(|ancestor| ancestor == *self)
+        })
+    }
+
+    /// `<a>` and `<area>` elements with an `href`.
+    fn is_link(&self) -> bool {
+        let link = match self.tag() {
+            Some(HtmlTag::A) => true,
+            Some(HtmlTag::Custom(name)) => name.eq_ignore_ascii_case("area"),
+            _ => false,
+        };
+        link && self.has_attribute("href")
+    }
+
+    fn is_form_control(&self) -> bool {
+        matches!(self.tag(), Some(HtmlTag::Custom(name)) if matches!(
+            name.to_ascii_lowercase().as_str(),
+            "button" | "input" | "select" | "textarea" | "option" | "optgroup" | "fieldset"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css_parser::{parse_css, parse_selectors};
+    use crate::html_parser::parse_html;
+
+    /// The cascaded `white-space` of the page's `<p>` under the given author, user and user
+    /// agent rules (in that order; empty strings for none).
+    fn white_space(html: &str, author: &str, user: &str, user_agent: &str) -> Option<String> {
+        let document = parse_html(html);
+        let p = document.root().query_selector("p").unwrap();
+        let (author, user, user_agent) =
+            (parse_css(author), parse_css(user), parse_css(user_agent));
+        let mut styles = document.cascade(
+            p,
+            &[
+                (Origin::UserAgent, &user_agent[..]),
+                (Origin::User, &user[..]),
+                (Origin::Author, &author[..]),
+            ],
+        );
+        match styles.remove(&None)?.remove("white-space")? {
+            StyleProperty::Keyword(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn author(html: &str, css: &str) -> Option<String> {
+        white_space(html, css, "", "")
+    }
+
+    #[test]
+    fn more_specific_selector_wins() {
+        let html = r#"<p id="x" class="c">text</p>"#;
+        let css = "#x { white-space: pre } p.c { white-space: nowrap } p { white-space: normal }";
+        assert_eq!(author(html, css).as_deref(), Some("pre"));
+        let css = ".c.c { white-space: pre } p.c { white-space: nowrap }";
+        assert_eq!(author(html, css).as_deref(), Some("pre"));
+        // Attributes count like classes, and a class beats any number of types
+        let css = "[id] { white-space: pre } html body p { white-space: nowrap }";
+        assert_eq!(author(html, css).as_deref(), Some("pre"));
+    }
+
+    #[test]
+    fn later_rule_wins_a_specificity_tie() {
+        let html = r#"<p class="a b">text</p>"#;
+        assert_eq!(
+            author(html, ".a { white-space: pre } .b { white-space: nowrap }").as_deref(),
+            Some("nowrap")
+        );
+        assert_eq!(
+            author(html, ".b { white-space: nowrap } .a { white-space: pre }").as_deref(),
+            Some("pre")
+        );
+    }
+
+    #[test]
+    fn selector_list_counts_its_most_specific_match() {
+        let html = r#"<p id="x">text</p>"#;
+        let css =
+            "p, #x { white-space: pre } p#x { white-space: nowrap } #x { white-space: normal }";
+        // `#x` in the list ties with the last rule, which comes later; `p#x` beats both
+        assert_eq!(author(html, css).as_deref(), Some("nowrap"));
+    }
+
+    #[test]
+    fn important_beats_specificity_and_order() {
+        let html = r#"<p id="x">text</p>"#;
+        let css = "p { white-space: pre !important } #x { white-space: nowrap }";
+        assert_eq!(author(html, css).as_deref(), Some("pre"));
+    }
+
+    #[test]
+    fn normal_declarations_go_by_origin() {
+        let html = "<p>text</p>";
+        let (ua, user, page) = (
+            "p { white-space: pre }",
+            "p { white-space: nowrap }",
+            "p { white-space: normal }",
+        );
+        assert_eq!(white_space(html, page, user, ua).as_deref(), Some("normal"));
+        assert_eq!(white_space(html, "", user, ua).as_deref(), Some("nowrap"));
+        // The page's more specific selector doesn't matter across origins
+        let html = r#"<p id="x">text</p>"#;
+        assert_eq!(
+            white_space(
+                html,
+                "p { white-space: normal }",
+                "#x { white-space: pre }",
+                ""
+            )
+            .as_deref(),
+            Some("normal")
+        );
+    }
+
+    #[test]
+    fn important_declarations_reverse_the_origins() {
+        let html = "<p>text</p>";
+        let (ua, user, page) = (
+            "p { white-space: pre !important }",
+            "p { white-space: nowrap !important }",
+            "p { white-space: normal !important }",
+        );
+        assert_eq!(white_space(html, page, user, ua).as_deref(), Some("pre"));
+        assert_eq!(white_space(html, page, user, "").as_deref(), Some("nowrap"));
+        // A normal user agent declaration still loses to the page
+        assert_eq!(
+            white_space(html, page, "", "p { white-space: pre }").as_deref(),
+            Some("normal")
+        );
+    }
+
+    #[test]
+    fn style_attribute_beats_rules_unless_they_are_important() {
+        let html = r#"<p id="x" style="white-space: pre-wrap">text</p>"#;
+        assert_eq!(
+            author(html, "#x { white-space: pre }").as_deref(),
+            Some("pre-wrap")
+        );
+        assert_eq!(
+            author(html, "p { white-space: pre !important }").as_deref(),
+            Some("pre")
+        );
+        let html = r#"<p style="white-space: pre-wrap !important">text</p>"#;
+        assert_eq!(
+            author(html, "p { white-space: pre !important }").as_deref(),
+            Some("pre-wrap")
+        );
+        // ...but an important user declaration beats an important style attribute
+        assert_eq!(
+            white_space(html, "", "p { white-space: nowrap !important }", "").as_deref(),
+            Some("nowrap")
+        );
+    }
+
+    /// The `id`s of the elements of `html` that match `selectors`, in document order.
+    fn matching(html: &str, selectors: &str) -> Vec<String> {
+        let document = parse_html(html);
+        document
+            .root()
+            .query_selector_all(selectors)
+            .into_iter()
+            .map(|node| node.get_attribute("id").unwrap_or("?").to_string())
+            .collect()
+    }
+
+    const LIST: &str = r#"<ul> <li id="a"></li> <li id="b"></li> <li id="c"></li>
+        <li id="d"></li> <li id="e"></li> </ul>"#;
+
+    const MIXED: &str = r#"<div><p id="p1"></p><span id="s1"></span><p id="p2"></p>
+        <span id="s2"></span><p id="p3"></p></div>"#;
+
+    #[test]
+    fn nth_child() {
+        let list = |selectors: &str| matching(LIST, selectors);
+        assert_eq!(list("li:nth-child(2n+1)"), ["a", "c", "e"]);
+        assert_eq!(list("li:nth-child(odd)"), ["a", "c", "e"]);
+        assert_eq!(list("li:nth-child(even)"), ["b", "d"]);
+        assert_eq!(list("li:nth-child(3)"), ["c"]);
+        assert_eq!(list("li:nth-child(n)"), ["a", "b", "c", "d", "e"]);
+        assert_eq!(list("li:nth-child(n+4)"), ["d", "e"]);
+        assert_eq!(list("li:nth-last-child(1)"), ["e"]);
+        assert_eq!(list("li:nth-last-child(-n+2)"), ["d", "e"]);
+        assert!(list("li:nth-child(0n+0)").is_empty());
+    }
+
+    #[test]
+    fn nth_child_with_negative_a() {
+        let list = |selectors: &str| matching(LIST, selectors);
+        assert_eq!(list("li:nth-child(-n+3)"), ["a", "b", "c"]);
+        assert_eq!(list("li:nth-child(-2n+5)"), ["a", "c", "e"]);
+        assert!(list("li:nth-child(-n)").is_empty());
+        assert!(list("li:nth-child(-3n-1)").is_empty());
+    }
+
+    #[test]
+    fn nth_child_at_the_ends_of_the_range() {
+        let list = |selectors: &str| matching(LIST, selectors);
+        assert!(list("li:nth-child(-2147483648)").is_empty());
+        assert!(list("li:nth-child(2147483647)").is_empty());
+        assert!(list("li:nth-child(2147483647n-2147483648)").is_empty());
+        assert!(list("li:nth-child(-2147483648n+2147483647)").is_empty());
+        assert_eq!(
+            list("li:nth-child(-n+2147483647)"),
+            ["a", "b", "c", "d", "e"]
+        );
+        // A `b` that doesn't fit makes the selector, and the whole list, invalid
+        assert!(list("li:nth-child(99999999999)").is_empty());
+        assert!(list("li:nth-child(99999999999), #a").is_empty());
+    }
+
+    #[test]
+    fn nth_of_type() {
+        let mixed = |selectors: &str| matching(MIXED, selectors);
+        assert_eq!(mixed("p:nth-of-type(2)"), ["p2"]);
+        assert_eq!(mixed("div > :nth-of-type(1)"), ["p1", "s1"]);
+        assert_eq!(mixed("div > :nth-last-of-type(1)"), ["s2", "p3"]);
+        assert_eq!(mixed("p:nth-of-type(-n+2)"), ["p1", "p2"]);
+        // Counting by type, not by position among all children
+        assert_eq!(mixed("p:nth-child(3)"), ["p2"]);
+        assert!(mixed("span:nth-of-type(3)").is_empty());
+    }
+
+    #[test]
+    fn not() {
+        let list = |selectors: &str| matching(LIST, selectors);
+        assert_eq!(list("li:not(#a, :nth-child(even))"), ["c", "e"]);
+        assert_eq!(list("li:not(:not(#b))"), ["b"]);
+        assert_eq!(list("li:not(ul > li:first-child)"), ["b", "c", "d", "e"]);
+        // Unlike `:is()`, an invalid selector invalidates the whole `:not()`
+        assert!(list("li:not(#b, !!bad)").is_empty());
+    }
+
+    #[test]
+    fn is_and_where() {
+        let mixed = |selectors: &str| matching(MIXED, selectors);
+        assert_eq!(
+            mixed(":is(ul, div) > :is(#s2, p:first-child, #nope)"),
+            ["p1", "s2"]
+        );
+        assert_eq!(mixed(":where(#p1, span)"), ["p1", "s1", "s2"]);
+        // Both are forgiving
+        assert_eq!(mixed("p:is(#p3, !!bad)"), ["p3"]);
+        assert_eq!(mixed("p:where(!!bad, #p2)"), ["p2"]);
+        assert!(mixed("p:is(!!bad)").is_empty());
+    }
+
+    #[test]
+    fn pseudo_class_specificity() {
+        let specificity = |selector: &str| parse_selectors(selector)[0].specificity();
+        assert_eq!(specificity(":is(#a, p)"), (1, 0, 0));
+        assert_eq!(specificity(":not(.x, #y) p"), (1, 0, 1));
+        assert_eq!(specificity(":where(#a) p"), (0, 0, 1));
+        assert_eq!(specificity("li:nth-child(2n+1)"), (0, 1, 1));
+    }
+
+    const NESTED: &str = r#"<div id="root" class="top">
+        <section id="sec">
+            <p id="p1"><span id="s1"><em id="e1"></em></span></p>
+            <p id="p2"><em id="e2"></em></p>
+            <h2 id="h"></h2>
+            <p id="p3"></p>
+            some text
+            <p id="p4"></p>
+        </section>
+        <p id="p5"></p>
+    </div>"#;
+
+    #[test]
+    fn descendant_and_child_combinators() {
+        let nested = |selectors: &str| matching(NESTED, selectors);
+        assert_eq!(nested("section em"), ["e1", "e2"]);
+        assert_eq!(nested("p em"), ["e1", "e2"]);
+        assert_eq!(nested("p > em"), ["e2"]);
+        assert_eq!(nested("section > p"), ["p1", "p2", "p3", "p4"]);
+        assert_eq!(nested("div>p"), ["p5"]);
+        assert_eq!(nested(".top > * em"), ["e1", "e2"]);
+        // The nearest ancestor that matches a compound isn't always the one that matches
+        // the rest of the selector
+        assert_eq!(nested("section > p em"), ["e1", "e2"]);
+        assert_eq!(nested("div > p em"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn sibling_combinators() {
+        let nested = |selectors: &str| matching(NESTED, selectors);
+        assert_eq!(nested("h2 + p"), ["p3"]);
+        // Text between elements doesn't separate them
+        assert_eq!(nested("p + p"), ["p2", "p4"]);
+        assert_eq!(nested("h2 ~ p"), ["p3", "p4"]);
+        assert_eq!(nested("p~h2"), ["h"]);
+        assert_eq!(nested("em + em"), Vec::<String>::new());
+        assert_eq!(nested("section ~ p"), ["p5"]);
+        // Only the element's own siblings count, not its ancestors'
+        assert_eq!(nested("p ~ em"), Vec::<String>::new());
+        assert_eq!(nested("div > section p + p > em"), ["e2"]);
+        assert_eq!(nested("#p1 ~ p:not(h2 + p)"), ["p2", "p4"]);
+    }
+
+    const LINKS: &str = r#"
+        <a id="a1" href="https://example.com/page.html" lang="en-US" class="nav main"
+            type="TEXT"></a>
+        <a id="a2" href="http://example.org/" lang="en" class="main-nav" data-empty=""></a>
+        <a id="a3" href="/local.PDF" lang="english" class="nav"></a>"#;
+
+    #[test]
+    fn attribute_operators() {
+        let links = |selectors: &str| matching(LINKS, selectors);
+        let none = Vec::<String>::new();
+        assert_eq!(links("[href]"), ["a1", "a2", "a3"]);
+        assert_eq!(links("[data-empty]"), ["a2"]);
+        assert_eq!(links("[data-empty='']"), ["a2"]);
+        assert_eq!(links("[lang=en]"), ["a2"]);
+        assert_eq!(links("[href^='https:']"), ["a1"]);
+        assert_eq!(links("[href$='.html']"), ["a1"]);
+        assert_eq!(links("[href*=example]"), ["a1", "a2"]);
+        assert_eq!(links("[class~=nav]"), ["a1", "a3"]);
+        assert_eq!(links("[lang|=en]"), ["a1", "a2"]);
+        // An empty value never matches a prefix, suffix or substring, and a word can't
+        // contain whitespace
+        assert_eq!(links("[href^=''], [href$=''], [href*='']"), none);
+        assert_eq!(links("[class~='nav main']"), none);
+        // Attribute names aren't case-sensitive in HTML
+        assert_eq!(links("[HREF^=http]"), ["a1", "a2"]);
+    }
+
+    #[test]
+    fn attribute_case_flag() {
+        let links = |selectors: &str| matching(LINKS, selectors);
+        assert_eq!(links("[type=text]"), Vec::<String>::new());
+        assert_eq!(links("[type=text i]"), ["a1"]);
+        assert_eq!(links("[type='text' I]"), ["a1"]);
+        assert_eq!(links("[type=TEXT s]"), ["a1"]);
+        assert_eq!(links("[href$='.pdf']"), Vec::<String>::new());
+        assert_eq!(links("[href$='.pdf' i]"), ["a3"]);
+        assert_eq!(links("[lang|='EN' i]"), ["a1", "a2"]);
+        assert_eq!(links("[class~=NAV i]"), ["a1", "a3"]);
+        // Any other flag makes the selector invalid
+        assert_eq!(links("[type=text x]"), Vec::<String>::new());
+    }
 }
@@ -1,10 +1,20 @@
 // main.rs
+mod audio_player;
 mod browser;
 mod css_parser;
+mod css_tokenizer;
+mod dom;
+mod generated;
+mod head;
 mod html_parser;
+mod html_serializer;
+mod image;
 mod layout;
 mod network;
-mod audio_player;
+mod style;
+mod stylesheet;
+mod svg;
+mod table;
 
 use browser::BrowserApp;
 use eframe::egui;
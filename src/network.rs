@@ -1,9 +1,10 @@
 // netwoek.rs
 use anyhow::Result;
-// use std::collections::HashMap;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
-// use std::sync::{Arc, Mutex};
+use std::io::Read;
+use std::sync::{Arc, Mutex, MutexGuard};
 use url::Url;
 
 #[derive(Debug)]
@@ -17,7 +18,9 @@ impl fmt::Display for BrowserError {
 
 impl Error for BrowserError {}
 
-pub fn load_url(url_str: &str) -> Result<String> {
+/// Fetch a page and hand its body to `on_chunk` piece by piece as it arrives.
+/// Chunks always end on a character boundary; invalid UTF-8 is replaced with U+FFFD.
+pub fn load_url_streaming(url_str: &str, mut on_chunk: impl FnMut(&str)) -> Result<()> {
     println!("URL: {}", url_str);
 
     let parsed_url = Url::parse(url_str)?;
@@ -28,64 +31,130 @@ pub fn load_url(url_str: &str) -> Result<String> {
         return Err(BrowserError(format!("Unsupported scheme: {}", scheme)).into());
     }
 
-    let body = reqwest::blocking::get(url_str)?.text()?;
+    let mut response = reqwest::blocking::get(url_str)?;
+    let mut buffer = [0u8; 16 * 1024];
+    let mut pending: Vec<u8> = Vec::new(); // bytes of a character split across reads
+    loop {
+        let read = response.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        pending.extend_from_slice(&buffer[..read]);
+        loop {
+            match std::str::from_utf8(&pending) {
+                Ok(text) => {
+                    if !text.is_empty() {
+                        on_chunk(text);
+                    }
+                    pending.clear();
+                    break;
+                }
+                Err(error) => {
+                    let valid = error.valid_up_to();
+                    if valid > 0 {
+                        on_chunk(std::str::from_utf8(&pending[..valid])?);
+                    }
+                    match error.error_len() {
+                        Some(len) => {
+                            on_chunk("\u{FFFD}");
+                            pending.drain(..valid + len);
+                        }
+                        None => {
+                            // Incomplete character at the end; wait for the next read
+                            pending.drain(..valid);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if !pending.is_empty() {
+        on_chunk(&String::from_utf8_lossy(&pending));
+    }
 
-    Ok(body)
+    Ok(())
+}
+
+/// Fetch a text resource, with the MIME type the server gave it (without parameters such as
+/// the charset). An HTTP error status is an error.
+pub fn load_text(url_str: &str) -> Result<(String, Option<String>)> {
+    let parsed_url = Url::parse(url_str)?;
+    let scheme = parsed_url.scheme();
+    if scheme != "http" && scheme != "https" {
+        return Err(BrowserError(format!("Unsupported scheme: {}", scheme)).into());
+    }
+
+    let response = reqwest::blocking::get(url_str)?.error_for_status()?;
+    let mime = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|mime| mime.trim().to_ascii_lowercase())
+        .filter(|mime| !mime.is_empty());
+    let bytes = response.bytes()?;
+    Ok((String::from_utf8_lossy(&bytes).into_owned(), mime))
+}
+
+/// Resolve `href` against the URL of the page it appears on.
+pub fn resolve_url(base: &str, href: &str) -> Option<String> {
+    let base = Url::parse(base).ok()?;
+    base.join(href).ok().map(String::from)
 }
 
 // Manages network request's for browsers & caches files
-// pub struct NetworkManager {
-//     cache_audio: Mutex<HashMap<String, Arc<Vec<u8>>>>,
-// }
-
-// impl NetworkManager {
-//     pub fn new() -> Self {
-//         Self {
-//             cache_audio: Mutex::new(HashMap::new()),
-//         }
-//     }
-
-//     // Gets audio data from cache or network. Returns None if error occurs.
-//     pub fn get_audio_data(&self, url: &String) -> Option<Arc<Vec<u8>>> {
-//         // Acquire the mutex lock to check the cache.
-//         // Return None if locking fails (poisoned mutex).
-//         let cache = self.cache_audio.lock().ok()?;
-
-//         // Check if the data is already in the cache.
-//         if let Some(aud) = cache.get(url) {
-//             // Found in cache, return clone and release the lock.
-//             return Some(aud.clone());
-//         }
-
-//         // Data not in cache. Release the lock before making the network request.
-//         // This prevents blocking other threads waiting for the cache lock
-//         // while we perform blocking I/O.
-//         drop(cache);
-
-//         // Fetch data from the network. Return None if the request fails.
-//         let response = reqwest::blocking::get(url).ok()?;
-
-//         // Read the response body as bytes. Return None if reading fails.
-//         let bytes = response.bytes().ok()?.to_vec();
-
-//         // Re-acquire the mutex lock to insert into the cache.
-//         let mut cache = self.cache_audio.lock().ok()?;
-
-//         // IMPORTANT: Re-check the cache *after* acquiring the lock again.
-//         // Another thread might have fetched and inserted the data while we were
-//         // performing the network request for the same URL.
-//         if let Some(aud) = cache.get(url) {
-//             // Another thread inserted it while we were busy. Return their data.
-//             // The bytes we fetched will be dropped.
-//             return Some(aud.clone());
-//         }
-
-//         // Data is still not in cache (or was removed/replaced, which is unlikely here).
-//         // Insert the data we just fetched.
-//         let audio_data_arc = Arc::new(bytes);
-//         cache.insert(url.clone(), audio_data_arc.clone());
-
-//         // Return the data we just inserted.
-//         Some(audio_data_arc)
-//     }
-// }
+pub struct NetworkManager {
+    cache: Mutex<HashMap<String, Arc<TextResource>>>,
+}
+
+/// A text file fetched through the `NetworkManager`, such as a stylesheet.
+#[derive(Debug)]
+pub struct TextResource {
+    pub text: String,
+    pub mime: Option<String>, // as the server gave it (see `load_text`)
+}
+
+impl Default for NetworkManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkManager {
+    pub fn new() -> Self {
+        Self {
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Gets a text file from cache or network. Failed requests aren't cached, so they are
+    // tried again next time.
+    pub fn get_text(&self, url: &str) -> Result<Arc<TextResource>> {
+        // Check if the data is already in the cache, releasing the lock before making the
+        // network request. This prevents blocking other threads waiting for the cache lock
+        // while we perform blocking I/O.
+        if let Some(data) = self.lock_cache()?.get(url) {
+            return Ok(data.clone());
+        }
+
+        let (text, mime) = load_text(url)?;
+
+        let mut cache = self.lock_cache()?;
+        // IMPORTANT: Re-check the cache *after* acquiring the lock again.
+        // Another thread might have fetched and inserted the data while we were
+        // performing the network request for the same URL.
+        if let Some(data) = cache.get(url) {
+            return Ok(data.clone());
+        }
+        let data = Arc::new(TextResource { text, mime });
+        cache.insert(url.to_string(), data.clone());
+        Ok(data)
+    }
+
+    fn lock_cache(&self) -> Result<MutexGuard<'_, HashMap<String, Arc<TextResource>>>> {
+        self.cache
+            .lock()
+            .map_err(|_| BrowserError("Network cache is poisoned".to_string()).into())
+    }
+}
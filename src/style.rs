@@ -0,0 +1,957 @@
+// style.rs
+use crate::css_parser;
+use crate::css_tokenizer::{Token, Tokenizer};
+use crate::layout::{Color, Length, StyleProperty};
+use eframe::egui;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// The default font size, in points.
+pub const BASE_SIZE: f32 = 16.0;
+
+/// The resolved values of the properties the renderer uses, for one node or pseudo-element.
+///
+/// Inherited properties start from the parent's computed values and the others from their
+/// initial values; then the declarations the cascade picked for the node are applied on top.
+/// The CSS-wide keywords `inherit`, `initial` and `unset` are resolved here, `revert` by the
+/// cascade, and `var()` just before (see `resolve_variables`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedStyle {
+    // Inherited
+    pub color: Option<Color>, // `None`: the theme's text color
+    pub font_size: f32,
+    pub font_family: egui::FontFamily,
+    pub font_weight: u16, // 100 to 900; 400 is normal, 700 bold
+    pub italic: bool,
+    pub line_height: LineHeight,
+    pub text_align: TextAlign,
+    pub visibility: Visibility,
+    pub white_space: WhiteSpace,
+    // Text decorations aren't inherited in CSS, but they are drawn across all the text
+    // inside the element, so they are carried down the same way
+    pub underline: bool,
+    pub line_through: bool,
+    pub list_style_type: Option<String>, // `None`: not set, so the kind of list picks
+    pub list_style_position: ListStylePosition,
+    pub quotes: Option<Vec<(String, String)>>, // (open, close) pairs; `None`: `auto`
+    pub border_collapse: bool,
+    pub caption_side: CaptionSide,
+    pub custom: Arc<HashMap<String, String>>, // custom properties (`--name`), substituted
+
+    // Not inherited
+    pub display: Option<Display>, // `None`: not set, which is `inline`
+    pub margin: Edges,
+    pub padding: Edges,
+    pub border_width: Length,
+    pub border_color: Option<Color>, // `None`: transparent
+    pub border_radius: Corners,
+    pub background_color: Option<Color>,
+    pub vertical_align: VerticalAlign,
+}
+
+impl Default for ComputedStyle {
+    /// The initial value of every property.
+    fn default() -> Self {
+        ComputedStyle {
+            color: None,
+            font_size: BASE_SIZE,
+            font_family: egui::FontFamily::Proportional,
+            font_weight: 400,
+            italic: false,
+            line_height: LineHeight::Normal,
+            text_align: TextAlign::Left,
+            visibility: Visibility::Visible,
+            white_space: WhiteSpace::Normal,
+            underline: false,
+            line_through: false,
+            list_style_type: None,
+            list_style_position: ListStylePosition::Outside,
+            quotes: None,
+            border_collapse: false,
+            caption_side: CaptionSide::Top,
+            custom: Arc::default(),
+            display: None,
+            margin: Edges::default(),
+            padding: Edges::default(),
+            border_width: Length::Px(0.0),
+            border_color: None,
+            border_radius: Corners::default(),
+            background_color: None,
+            vertical_align: VerticalAlign::Baseline,
+        }
+    }
+}
+
+/// `line-height`. A number is inherited as a number, so it scales with each element's font.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineHeight {
+    Normal,
+    Number(f32),
+    Px(f32),
+}
+
+impl LineHeight {
+    /// The height of a line in points for text of `font_size`, or `None` for the font's own.
+    pub fn resolve(self, font_size: f32) -> Option<f32> {
+        match self {
+            LineHeight::Normal => None,
+            LineHeight::Number(factor) => Some(factor * font_size),
+            LineHeight::Px(px) => Some(px),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Right,
+    Center,
+    Justify, // drawn like `left`
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Visibility {
+    Visible,
+    Hidden,
+    Collapse, // like `hidden` outside tables
+}
+
+/// The CSS `white-space` property: which whitespace is kept and whether lines wrap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WhiteSpace {
+    Normal,  // collapse whitespace, wrap
+    Nowrap,  // collapse whitespace, don't wrap
+    Pre,     // keep everything, don't wrap
+    PreWrap, // keep everything, wrap
+    PreLine, // collapse spaces but keep line breaks, wrap
+}
+
+impl WhiteSpace {
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword.trim().to_ascii_lowercase().as_str() {
+            "normal" => Some(WhiteSpace::Normal),
+            "nowrap" => Some(WhiteSpace::Nowrap),
+            "pre" => Some(WhiteSpace::Pre),
+            "pre-wrap" => Some(WhiteSpace::PreWrap),
+            "pre-line" => Some(WhiteSpace::PreLine),
+            _ => None,
+        }
+    }
+
+    pub fn wraps(self) -> bool {
+        !matches!(self, WhiteSpace::Nowrap | WhiteSpace::Pre)
+    }
+
+    pub fn keeps_line_breaks(self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Pre | WhiteSpace::PreWrap | WhiteSpace::PreLine
+        )
+    }
+}
+
+/// Marker types accepted by `list-style-type` (and the `list-style` shorthand).
+pub const LIST_STYLE_TYPES: [&str; 12] = [
+    "none",
+    "disc",
+    "circle",
+    "square",
+    "decimal",
+    "lower-alpha",
+    "upper-alpha",
+    "lower-latin",
+    "upper-latin",
+    "lower-roman",
+    "upper-roman",
+    "decimal-leading-zero",
+];
+
+/// `list-style-position`: whether the marker is drawn in the margin or as part of the text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListStylePosition {
+    Outside,
+    Inside,
+}
+
+/// `caption-side`: whether a table's caption goes above or below it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptionSide {
+    Top,
+    Bottom,
+}
+
+/// The `display` values the renderer distinguishes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Display {
+    Block,
+    Inline,
+    None, // no box at all, for the element or anything inside it
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerticalAlign {
+    Baseline,
+    Super,
+    Sub,
+    Length(Length), // raised by this much
+}
+
+/// A length for each side of a box, for `margin` and `padding`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edges {
+    pub top: Length,
+    pub right: Length,
+    pub bottom: Length,
+    pub left: Length,
+}
+
+impl Default for Edges {
+    fn default() -> Self {
+        Edges {
+            top: Length::Px(0.0),
+            right: Length::Px(0.0),
+            bottom: Length::Px(0.0),
+            left: Length::Px(0.0),
+        }
+    }
+}
+
+/// A radius for each corner of a box, named by compass direction like egui's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Corners {
+    pub nw: Length,
+    pub ne: Length,
+    pub sw: Length,
+    pub se: Length,
+}
+
+impl Default for Corners {
+    fn default() -> Self {
+        Corners {
+            nw: Length::Px(0.0),
+            ne: Length::Px(0.0),
+            sw: Length::Px(0.0),
+            se: Length::Px(0.0),
+        }
+    }
+}
+
+impl ComputedStyle {
+    /// Whether the box is part of a line of text. Text, and elements no rule gives a
+    /// `display`, are inline, which is the initial value.
+    pub fn is_inline(&self) -> bool {
+        self.display
+            .is_none_or(|display| display == Display::Inline)
+    }
+
+    /// Compute the style of a node from the declarations the cascade picked for it, and the
+    /// computed style of its parent (`None` for the root).
+    pub fn compute(
+        declarations: &HashMap<String, StyleProperty>,
+        parent: Option<&ComputedStyle>,
+    ) -> ComputedStyle {
+        let initial = ComputedStyle::default();
+        let parent = parent.unwrap_or(&initial);
+        let mut style = parent.inherited();
+
+        // `font-size` first, since `em` lengths depend on it; then shorthands, so the
+        // longhands of the same rule refine them
+        let mut declarations: Vec<(&String, &StyleProperty)> = declarations.iter().collect();
+        declarations.sort_by_key(|(name, _)| match name.as_str() {
+            "font-size" => 0,
+            "margin" | "padding" | "border-radius" | "list-style" => 1,
+            _ => 2,
+        });
+        for (name, value) in declarations {
+            let keyword = match value {
+                StyleProperty::Keyword(keyword) => keyword.trim().to_ascii_lowercase(),
+                _ => String::new(),
+            };
+            match keyword.as_str() {
+                "inherit" => style.copy_property(name, parent),
+                "initial" => style.copy_property(name, &initial),
+                "unset" if is_inherited(name) => style.copy_property(name, parent),
+                "unset" => style.copy_property(name, &initial),
+                _ => style.set_property(name, value, parent),
+            }
+        }
+        style
+    }
+
+    /// A child's starting point: the inherited properties of `self`, initial values for
+    /// the rest.
+    fn inherited(&self) -> ComputedStyle {
+        ComputedStyle {
+            color: self.color.clone(),
+            font_size: self.font_size,
+            font_family: self.font_family.clone(),
+            font_weight: self.font_weight,
+            italic: self.italic,
+            line_height: self.line_height,
+            text_align: self.text_align,
+            visibility: self.visibility,
+            white_space: self.white_space,
+            underline: self.underline,
+            line_through: self.line_through,
+            list_style_type: self.list_style_type.clone(),
+            list_style_position: self.list_style_position,
+            quotes: self.quotes.clone(),
+            border_collapse: self.border_collapse,
+            caption_side: self.caption_side,
+            custom: Arc::clone(&self.custom),
+            ..ComputedStyle::default()
+        }
+    }
+
+    /// Whether text and boxes are drawn (they take their space either way).
+    pub fn is_visible(&self) -> bool {
+        self.visibility == Visibility::Visible
+    }
+
+    /// Whether the text is drawn in a bold face.
+    pub fn is_bold(&self) -> bool {
+        self.font_weight >= 600
+    }
+
+    /// Take the value of the property `name` from `from` (the parent's style or the
+    /// initial values).
+    fn copy_property(&mut self, name: &str, from: &ComputedStyle) {
+        match name {
+            "color" | "text-color" => self.color = from.color.clone(),
+            "font-size" => self.font_size = from.font_size,
+            "font-family" => self.font_family = from.font_family.clone(),
+            "font-weight" => self.font_weight = from.font_weight,
+            "font-style" => self.italic = from.italic,
+            "line-height" => self.line_height = from.line_height,
+            "text-align" => self.text_align = from.text_align,
+            "visibility" => self.visibility = from.visibility,
+            "white-space" => self.white_space = from.white_space,
+            "text-decoration" => {
+                self.underline = from.underline;
+                self.line_through = from.line_through;
+            }
+            "list-style" => {
+                self.list_style_type = from.list_style_type.clone();
+                self.list_style_position = from.list_style_position;
+            }
+            "list-style-type" => self.list_style_type = from.list_style_type.clone(),
+            "list-style-position" => self.list_style_position = from.list_style_position,
+            "quotes" => self.quotes = from.quotes.clone(),
+            "border-collapse" => self.border_collapse = from.border_collapse,
+            "caption-side" => self.caption_side = from.caption_side,
+            "display" => self.display = from.display,
+            "margin" => self.margin = from.margin.clone(),
+            "padding" => self.padding = from.padding.clone(),
+            "border-color" => self.border_color = from.border_color.clone(),
+            "border-radius" => self.border_radius = from.border_radius.clone(),
+            "background-color" => self.background_color = from.background_color.clone(),
+            "vertical-align" => self.vertical_align = from.vertical_align.clone(),
+            _ => {
+                // The single lengths; `length_mut` needs a copy of `from` to borrow
+                if let Some(value) = from.clone().length_mut(name).cloned()
+                    && let Some(slot) = self.length_mut(name)
+                {
+                    *slot = value;
+                }
+            }
+        }
+    }
+
+    /// Apply one declaration. Values the property doesn't accept are ignored, leaving the
+    /// inherited or initial value.
+    fn set_property(&mut self, name: &str, value: &StyleProperty, parent: &ComputedStyle) {
+        let keyword = match value {
+            StyleProperty::Keyword(keyword) => keyword.trim().to_ascii_lowercase(),
+            _ => String::new(),
+        };
+        match name {
+            "color" | "text-color" => match value {
+                StyleProperty::Color(color) => self.color = Some(color.clone()),
+                _ if keyword == "currentcolor" => self.color = parent.color.clone(),
+                _ => {}
+            },
+            "font-size" => {
+                if let Some(size) = font_size(value, parent.font_size) {
+                    self.font_size = size;
+                }
+            }
+            "font-family" => {
+                if let StyleProperty::Keyword(families) = value {
+                    self.font_family = font_family(families);
+                }
+            }
+            "font-weight" => {
+                if let Some(weight) = font_weight(&keyword, parent.font_weight) {
+                    self.font_weight = weight;
+                }
+            }
+            "font-style" => match keyword.as_str() {
+                "italic" | "oblique" => self.italic = true,
+                // `normal` also resets the other styles the legacy keywords below set
+                "normal" => {
+                    self.italic = false;
+                    self.font_weight = 400;
+                    self.underline = false;
+                    self.line_through = false;
+                }
+                "bold" => self.font_weight = 700,
+                "underline" => self.underline = true,
+                "strikethrough" => self.line_through = true,
+                _ => {}
+            },
+            "line-height" => match value {
+                StyleProperty::Length(length) => {
+                    self.line_height =
+                        LineHeight::Px(length.to_egui_value(self.font_size, self.font_size));
+                }
+                _ if keyword == "normal" => self.line_height = LineHeight::Normal,
+                _ => {
+                    if let Ok(factor) = keyword.parse::<f32>()
+                        && factor >= 0.0
+                    {
+                        self.line_height = LineHeight::Number(factor);
+                    }
+                }
+            },
+            "text-align" => match keyword.as_str() {
+                "left" | "start" => self.text_align = TextAlign::Left,
+                "right" | "end" => self.text_align = TextAlign::Right,
+                "center" => self.text_align = TextAlign::Center,
+                "justify" => self.text_align = TextAlign::Justify,
+                _ => {}
+            },
+            "visibility" => match keyword.as_str() {
+                "visible" => self.visibility = Visibility::Visible,
+                "hidden" => self.visibility = Visibility::Hidden,
+                "collapse" => self.visibility = Visibility::Collapse,
+                _ => {}
+            },
+            "white-space" => {
+                if let Some(white_space) = WhiteSpace::from_keyword(&keyword) {
+                    self.white_space = white_space;
+                }
+            }
+            "text-decoration" => {
+                for keyword in keyword.split_whitespace() {
+                    match keyword {
+                        "underline" => self.underline = true,
+                        "nounderline" => self.underline = false,
+                        "line-through" | "strikethrough" => self.line_through = true,
+                        "nostrikethrough" => self.line_through = false,
+                        "none" => {
+                            self.underline = false;
+                            self.line_through = false;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            // The shorthand sets whichever longhands its keywords belong to
+            "list-style" => {
+                for keyword in keyword.split_whitespace() {
+                    self.set_list_style(keyword);
+                }
+            }
+            "list-style-type" if LIST_STYLE_TYPES.contains(&keyword.as_str()) => {
+                self.list_style_type = Some(keyword);
+            }
+            "list-style-position" if matches!(keyword.as_str(), "inside" | "outside") => {
+                self.set_list_style(&keyword);
+            }
+            "quotes" => match value {
+                _ if keyword == "auto" => self.quotes = None,
+                StyleProperty::Keyword(value) => self.quotes = Some(parse_quotes(value)),
+                _ => {}
+            },
+            "border-collapse" => match keyword.as_str() {
+                "collapse" => self.border_collapse = true,
+                "separate" => self.border_collapse = false,
+                _ => {}
+            },
+            "caption-side" => match keyword.as_str() {
+                "top" => self.caption_side = CaptionSide::Top,
+                "bottom" => self.caption_side = CaptionSide::Bottom,
+                _ => {}
+            },
+            "display" => match keyword.as_str() {
+                "block" => self.display = Some(Display::Block),
+                "inline" => self.display = Some(Display::Inline),
+                "none" => self.display = Some(Display::None),
+                _ => {}
+            },
+            "margin" | "padding" => {
+                if let Some(edges) = edges(value) {
+                    match name {
+                        "margin" => self.margin = edges,
+                        _ => self.padding = edges,
+                    }
+                }
+            }
+            "border-radius" => {
+                if let Some(length) = length(value) {
+                    self.border_radius = Corners {
+                        nw: length.clone(),
+                        ne: length.clone(),
+                        sw: length.clone(),
+                        se: length,
+                    };
+                }
+            }
+            "border-color" => {
+                if let StyleProperty::Color(color) = value {
+                    self.border_color = Some(color.clone());
+                }
+            }
+            "background-color" => match value {
+                StyleProperty::Color(color) => self.background_color = Some(color.clone()),
+                _ if keyword == "transparent" => self.background_color = None,
+                _ => {}
+            },
+            "vertical-align" => match value {
+                StyleProperty::Length(length) => {
+                    self.vertical_align = VerticalAlign::Length(length.clone());
+                }
+                _ => match keyword.as_str() {
+                    "baseline" => self.vertical_align = VerticalAlign::Baseline,
+                    "super" => self.vertical_align = VerticalAlign::Super,
+                    "sub" => self.vertical_align = VerticalAlign::Sub,
+                    _ => {}
+                },
+            },
+            _ => {
+                if let Some(length) = length(value)
+                    && let Some(slot) = self.length_mut(name)
+                {
+                    *slot = length;
+                }
+            }
+        }
+    }
+
+    /// Apply one keyword of `list-style` or `list-style-position`.
+    fn set_list_style(&mut self, keyword: &str) {
+        match keyword {
+            "inside" => self.list_style_position = ListStylePosition::Inside,
+            "outside" => self.list_style_position = ListStylePosition::Outside,
+            _ if LIST_STYLE_TYPES.contains(&keyword) => {
+                self.list_style_type = Some(keyword.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    /// The value of a property that takes a single length: a side of the margin or
+    /// padding, the border width or a corner radius.
+    fn length_mut(&mut self, name: &str) -> Option<&mut Length> {
+        Some(match name {
+            "margin-top" => &mut self.margin.top,
+            "margin-right" => &mut self.margin.right,
+            "margin-bottom" => &mut self.margin.bottom,
+            "margin-left" => &mut self.margin.left,
+            "padding-top" => &mut self.padding.top,
+            "padding-right" => &mut self.padding.right,
+            "padding-bottom" => &mut self.padding.bottom,
+            "padding-left" => &mut self.padding.left,
+            "border-width" => &mut self.border_width,
+            "border-radius-nw" => &mut self.border_radius.nw,
+            "border-radius-ne" => &mut self.border_radius.ne,
+            "border-radius-sw" => &mut self.border_radius.sw,
+            "border-radius-se" => &mut self.border_radius.se,
+            _ => return None,
+        })
+    }
+}
+
+/// Work out a node's custom properties from the ones it `inherited` and the ones it
+/// declares, and substitute them for the `var()`s in its other declarations. A declaration
+/// that is invalid once substituted, or too long (see `css_parser::substitute_vars`), is
+/// dropped, leaving its property unset. A custom property that is, or that is in a cycle of
+/// references, is left out, so `var()`s of it fall back.
+pub fn resolve_variables(
+    declarations: &mut HashMap<String, StyleProperty>,
+    inherited: &Arc<HashMap<String, String>>,
+) -> Arc<HashMap<String, String>> {
+    let custom = {
+        let declared: HashMap<&str, &str> = declarations
+            .iter()
+            .filter_map(|(name, value)| match value {
+                StyleProperty::Keyword(value) if name.starts_with("--") => {
+                    Some((name.as_str(), value.as_str()))
+                }
+                _ => None,
+            })
+            .collect();
+        if declared.is_empty() {
+            Arc::clone(inherited)
+        } else {
+            let names: Vec<&str> = declared.keys().copied().collect();
+            let mut resolver = CustomProperties {
+                declared,
+                inherited,
+                resolved: HashMap::new(),
+                resolving: Vec::new(),
+                cyclic: HashSet::new(),
+            };
+            let mut custom = HashMap::clone(inherited);
+            for name in names {
+                match resolver.resolve(name) {
+                    Some(value) => custom.insert(name.to_string(), value),
+                    None => custom.remove(name),
+                };
+            }
+            Arc::new(custom)
+        }
+    };
+    declarations.retain(|_, value| {
+        let StyleProperty::Unresolved(text) = value else {
+            return true;
+        };
+        let substituted =
+            css_parser::substitute_vars(text, &mut |name: &str| custom.get(name).cloned());
+        match substituted.and_then(|text| css_parser::parse_property_value(&text)) {
+            Some(parsed) => {
+                *value = parsed;
+                true
+            }
+            None => false,
+        }
+    });
+    custom
+}
+
+/// The custom properties a node declares, resolved in whatever order they refer to each
+/// other.
+struct CustomProperties<'a> {
+    declared: HashMap<&'a str, &'a str>, // name -> value as written
+    inherited: &'a HashMap<String, String>,
+    resolved: HashMap<&'a str, Option<String>>,
+    resolving: Vec<&'a str>, // the properties whose references are being substituted
+    cyclic: HashSet<&'a str>,
+}
+
+impl<'a> CustomProperties<'a> {
+    /// The value of the custom property `name` for the node, or `None` if it has none.
+    fn resolve(&mut self, name: &str) -> Option<String> {
+        let Some((&name, &value)) = self.declared.get_key_value(name) else {
+            return self.inherited.get(name).cloned();
+        };
+        if let Some(resolved) = self.resolved.get(name) {
+            return resolved.clone();
+        }
+        if let Some(start) = self.resolving.iter().position(|&other| other == name) {
+            // Every property in the cycle is invalid, whatever fallbacks it has
+            self.cyclic.extend(&self.resolving[start..]);
+            return None;
+        }
+        self.resolving.push(name);
+        let result = match value.trim().to_ascii_lowercase().as_str() {
+            "initial" => None,
+            "inherit" | "unset" => self.inherited.get(name).cloned(),
+            _ => css_parser::substitute_vars(value, &mut |other: &str| self.resolve(other)),
+        };
+        self.resolving.pop();
+        let result = result.filter(|_| !self.cyclic.contains(name));
+        self.resolved.insert(name, result.clone());
+        result
+    }
+}
+
+/// Whether a property takes its parent's value when the element doesn't set it.
+fn is_inherited(name: &str) -> bool {
+    matches!(
+        name,
+        "color"
+            | "text-color"
+            | "font-size"
+            | "font-family"
+            | "font-weight"
+            | "font-style"
+            | "line-height"
+            | "text-align"
+            | "visibility"
+            | "white-space"
+            | "text-decoration"
+            | "list-style"
+            | "list-style-type"
+            | "list-style-position"
+            | "quotes"
+            | "border-collapse"
+            | "caption-side"
+    )
+}
+
+/// A length value; a plain `0` needs no unit.
+fn length(value: &StyleProperty) -> Option<Length> {
+    match value {
+        StyleProperty::Length(length) => Some(length.clone()),
+        StyleProperty::Keyword(keyword) if keyword.trim() == "0" => Some(Length::Px(0.0)),
+        _ => None,
+    }
+}
+
+/// The sides of a `margin` or `padding` shorthand: one to four lengths, clockwise from the
+/// top, with a missing side taking the value of the opposite one.
+fn edges(value: &StyleProperty) -> Option<Edges> {
+    let lengths = match value {
+        StyleProperty::Keyword(keyword) => keyword
+            .split_whitespace()
+            .map(|part| {
+                css_parser::parse_property_value(part)
+                    .as_ref()
+                    .and_then(length)
+            })
+            .collect::<Option<Vec<Length>>>()?,
+        _ => vec![length(value)?],
+    };
+    let [top, right, bottom, left] = match lengths.as_slice() {
+        [all] => [all, all, all, all],
+        [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+        [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+        [top, right, bottom, left] => [top, right, bottom, left],
+        _ => return None,
+    };
+    Some(Edges {
+        top: top.clone(),
+        right: right.clone(),
+        bottom: bottom.clone(),
+        left: left.clone(),
+    })
+}
+
+/// Parse a `quotes` value (`"“" "”" "‘" "’"`) into (open, close) pairs, outermost first.
+/// `none` gives no pairs.
+fn parse_quotes(value: &str) -> Vec<(String, String)> {
+    let strings: Vec<String> = Tokenizer::new(value)
+        .filter_map(|(token, _)| match token {
+            Token::String(text) => Some(text),
+            _ => None,
+        })
+        .collect();
+    strings
+        .chunks_exact(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect()
+}
+
+/// Resolve a `font-size` value against the parent's font size.
+fn font_size(value: &StyleProperty, parent: f32) -> Option<f32> {
+    match value {
+        StyleProperty::Length(Length::Px(px)) => Some(*px),
+        StyleProperty::Length(Length::Em(em)) => Some(em * parent),
+        StyleProperty::Length(Length::Rem(rem)) => Some(rem * BASE_SIZE),
+        StyleProperty::Length(Length::Percent(percent)) => Some(percent / 100.0 * parent),
+        StyleProperty::Keyword(keyword) => {
+            let scale = match keyword.trim() {
+                "smaller" => return Some(parent / 1.2),
+                "larger" => return Some(parent * 1.2),
+                "xx-small" => 0.6,
+                "x-small" => 0.75,
+                "small" => 0.89,
+                "medium" => 1.0,
+                "large" => 1.2,
+                "x-large" => 1.5,
+                "xx-large" => 2.0,
+                "xxx-large" => 3.0,
+                _ => return None,
+            };
+            Some(BASE_SIZE * scale)
+        }
+        _ => None,
+    }
+}
+
+/// Resolve a `font-weight` value; `bolder` and `lighter` are relative to the parent's.
+fn font_weight(keyword: &str, parent: u16) -> Option<u16> {
+    match keyword {
+        "normal" => Some(400),
+        "bold" => Some(700),
+        "bolder" => Some(match parent {
+            ..350 => 400,
+            350..550 => 700,
+            _ => 900,
+        }),
+        "lighter" => Some(match parent {
+            ..550 => 100,
+            550..750 => 400,
+            _ => 700,
+        }),
+        _ => keyword
+            .parse()
+            .ok()
+            .filter(|weight| (1..=1000).contains(weight)),
+    }
+}
+
+/// Pick the font for a `font-family` list: monospace if it names the generic family.
+fn font_family(families: &str) -> egui::FontFamily {
+    let monospace = families
+        .split(',')
+        .map(|family| family.trim().trim_matches(['"', '\'']))
+        .any(|family| family.eq_ignore_ascii_case("monospace"));
+    if monospace {
+        egui::FontFamily::Monospace
+    } else {
+        egui::FontFamily::Proportional
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css_parser::parse_css;
+
+    /// The declarations of `p { ... }` after substituting their `var()`s, with the custom
+    /// properties of the node.
+    fn resolve(
+        declarations: &str,
+        inherited: &[(&str, &str)],
+    ) -> (HashMap<String, StyleProperty>, Arc<HashMap<String, String>>) {
+        let mut properties = parse_css(&format!("p {{ {declarations} }}"))
+            .remove(0)
+            .properties;
+        let inherited = inherited
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let custom = resolve_variables(&mut properties, &Arc::new(inherited));
+        (properties, custom)
+    }
+
+    fn keyword<'p>(properties: &'p HashMap<String, StyleProperty>, name: &str) -> Option<&'p str> {
+        match properties.get(name)? {
+            StyleProperty::Keyword(value) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn substitutes_declared_and_inherited_properties() {
+        let (properties, custom) = resolve(
+            "--space: pre; --gap: var(--inherited-gap); white-space: var(--space); \
+             margin-top: var(--gap)",
+            &[("--inherited-gap", "4px")],
+        );
+        assert_eq!(keyword(&properties, "white-space"), Some("pre"));
+        assert_eq!(
+            properties.get("margin-top"),
+            Some(&StyleProperty::Length(Length::Px(4.0)))
+        );
+        assert_eq!(custom.get("--gap").map(String::as_str), Some("4px"));
+        assert_eq!(
+            custom.get("--inherited-gap").map(String::as_str),
+            Some("4px")
+        );
+    }
+
+    #[test]
+    fn fallbacks() {
+        let (properties, _) = resolve(
+            "white-space: var(--missing, nowrap); \
+             text-align: var(--missing, var(--also-missing, center)); \
+             font-family: var(--missing, Arial, sans-serif)",
+            &[],
+        );
+        assert_eq!(keyword(&properties, "white-space"), Some("nowrap"));
+        assert_eq!(keyword(&properties, "text-align"), Some("center"));
+        // Everything after the first comma is the fallback
+        assert_eq!(
+            keyword(&properties, "font-family"),
+            Some("Arial, sans-serif")
+        );
+    }
+
+    #[test]
+    fn cycles_make_every_property_in_them_invalid() {
+        let (properties, custom) = resolve(
+            "--a: var(--b, x); --b: var(--a, y); --self: var(--self); --c: var(--a, z); \
+             white-space: var(--a, pre); text-align: var(--self, right)",
+            &[("--a", "inherited")],
+        );
+        // The fallbacks of the properties in the cycle don't help them, and the inherited
+        // value is gone too
+        assert!(!custom.contains_key("--a"));
+        assert!(!custom.contains_key("--b"));
+        assert!(!custom.contains_key("--self"));
+        // ...but a reference to one from outside the cycle falls back
+        assert_eq!(custom.get("--c").map(|value| value.trim()), Some("z"));
+        assert_eq!(keyword(&properties, "white-space"), Some("pre"));
+        assert_eq!(keyword(&properties, "text-align"), Some("right"));
+    }
+
+    #[test]
+    fn invalid_at_computed_value_time() {
+        let (properties, custom) = resolve(
+            "--none: initial; white-space: var(--missing); text-align: var(--none); \
+             font-style: var(not-custom, italic); display: block",
+            &[("--none", "center")],
+        );
+        assert!(!custom.contains_key("--none"));
+        // The declarations are dropped, leaving the properties unset, while the rest stay
+        assert!(!properties.contains_key("white-space"));
+        assert!(!properties.contains_key("text-align"));
+        assert!(!properties.contains_key("font-style"));
+        assert_eq!(keyword(&properties, "display"), Some("block"));
+
+        // So an inherited property takes the parent's value, even though the rule set one
+        let parent = ComputedStyle {
+            white_space: WhiteSpace::Pre,
+            ..ComputedStyle::default()
+        };
+        let computed = ComputedStyle::compute(&properties, Some(&parent));
+        assert_eq!(computed.white_space, WhiteSpace::Pre);
+    }
+
+    #[test]
+    fn oversized_substitutions_are_invalid() {
+        // Each level doubles the value, so the last ones would have billions of tokens
+        let mut declarations = "--l0: x".to_string();
+        for level in 1..=40 {
+            let previous = level - 1;
+            declarations += &format!("; --l{level}: var(--l{previous}) var(--l{previous})");
+        }
+        declarations += "; white-space: var(--l40, pre); display: block; \
+             font-family: var(--l14) var(--l14) var(--l14) var(--l14)";
+        let (properties, custom) = resolve(&declarations, &[]);
+
+        // The custom properties over the limit are invalid, so references to them fall back
+        assert!(custom.contains_key("--l14"));
+        assert!(!custom.contains_key("--l16"));
+        assert!(!custom.contains_key("--l40"));
+        assert_eq!(keyword(&properties, "white-space"), Some("pre"));
+        // ...and a declaration that is over it once substituted is dropped
+        assert!(!properties.contains_key("font-family"));
+        assert_eq!(keyword(&properties, "display"), Some("block"));
+    }
+
+    #[test]
+    fn list_quotes_and_table_properties_inherit() {
+        let (list, _) = resolve(
+            "list-style: inside square; quotes: '<' '>'; border-collapse: collapse; \
+             caption-side: bottom",
+            &[],
+        );
+        let list = ComputedStyle::compute(&list, None);
+        assert_eq!(list.list_style_type.as_deref(), Some("square"));
+        assert_eq!(list.list_style_position, ListStylePosition::Inside);
+
+        // A child that sets nothing takes all of them from its parent
+        let item = ComputedStyle::compute(&HashMap::new(), Some(&list));
+        assert_eq!(item.list_style_type.as_deref(), Some("square"));
+        assert_eq!(item.list_style_position, ListStylePosition::Inside);
+        assert_eq!(item.quotes, Some(vec![("<".to_string(), ">".to_string())]));
+        assert!(item.border_collapse);
+        assert_eq!(item.caption_side, CaptionSide::Bottom);
+
+        // The longhands refine the shorthand, and values they don't accept are ignored
+        let (declarations, _) = resolve(
+            "list-style: none; list-style-position: outside; list-style-type: bogus; \
+             quotes: auto; caption-side: sideways",
+            &[],
+        );
+        let item = ComputedStyle::compute(&declarations, Some(&list));
+        assert_eq!(item.list_style_type.as_deref(), Some("none"));
+        assert_eq!(item.list_style_position, ListStylePosition::Outside);
+        assert_eq!(item.quotes, None);
+        assert_eq!(item.caption_side, CaptionSide::Bottom);
+    }
+}
@@ -0,0 +1,188 @@
+// stylesheet.rs
+use crate::css_parser::{self, MediaQueryList};
+use crate::layout::{CssRule, StyleSheet};
+use crate::network::{self, NetworkManager};
+use std::sync::Arc;
+use url::Url;
+
+/// One of a page's stylesheets, in the order it appears in the document.
+#[derive(Debug, Clone)]
+pub enum PageSheet {
+    /// A `<style>` element
+    Inline {
+        sheet: Arc<StyleSheet>,
+        media: String,
+    },
+    /// A `<link rel="stylesheet">`, with `href` as written
+    Link { href: String, media: String },
+}
+
+/// Where loading an external stylesheet has got to.
+#[derive(Debug, Clone)]
+pub enum SheetStatus {
+    Loading,
+    Loaded(Arc<StyleSheet>),
+    Failed(String),  // the network error
+    Blocked(String), // why the sheet wasn't requested, or was refused once it arrived
+}
+
+/// The external stylesheets (linked and imported) requested for a page, by URL, in the
+/// order they were first needed.
+#[derive(Debug, Clone, Default)]
+pub struct SheetLoads(Vec<(String, SheetStatus)>);
+
+impl SheetLoads {
+    pub fn get(&self, url: &str) -> Option<&SheetStatus> {
+        self.0
+            .iter()
+            .find(|(sheet_url, _)| sheet_url == url)
+            .map(|(_, status)| status)
+    }
+
+    /// Record the status of the sheet at `url`, replacing any earlier one.
+    pub fn set(&mut self, url: String, status: SheetStatus) {
+        match self.0.iter_mut().find(|(sheet_url, _)| *sheet_url == url) {
+            Some((_, old)) => *old = status,
+            None => self.0.push((url, status)),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &SheetStatus)> {
+        self.0.iter().map(|(url, status)| (url.as_str(), status))
+    }
+}
+
+/// The author rules of a page, and the external stylesheets they come from.
+pub struct AuthorRules {
+    pub rules: Vec<CssRule>,
+    pub urls: Vec<String>, // every linked or imported sheet, resolved, whatever its media
+}
+
+/// Whether the rules of `sheets` are known without loading anything: there are only
+/// `<style>`s, without `@import`s.
+pub fn is_self_contained(sheets: &[PageSheet]) -> bool {
+    sheets
+        .iter()
+        .all(|sheet| matches!(sheet, PageSheet::Inline { sheet, .. } if sheet.imports.is_empty()))
+}
+
+/// Collect the rules of a page's stylesheets in cascade order: document order, with each
+/// sheet's imports in place of its `@import`s. The `media` of a sheet or import becomes a
+/// condition of each of its rules. External sheets that haven't loaded are left out, but
+/// their URLs are still listed so they can be requested. Relative URLs are resolved
+/// against `base_url`, or external sheets are left out entirely without one.
+pub fn author_rules(
+    sheets: &[PageSheet],
+    base_url: Option<&str>,
+    loads: &SheetLoads,
+) -> AuthorRules {
+    let mut collected = AuthorRules {
+        rules: Vec::new(),
+        urls: Vec::new(),
+    };
+    for sheet in sheets {
+        match sheet {
+            PageSheet::Inline { sheet, media } => {
+                let media = media_condition(media);
+                collect(
+                    sheet,
+                    base_url,
+                    loads,
+                    &media,
+                    &mut Vec::new(),
+                    &mut collected,
+                );
+            }
+            PageSheet::Link { href, media } => {
+                if let Some(url) = base_url.and_then(|base| network::resolve_url(base, href.trim()))
+                {
+                    let media = media_condition(media);
+                    collect_external(url, loads, &media, &mut Vec::new(), &mut collected);
+                }
+            }
+        }
+    }
+    collected
+}
+
+/// The condition a `media` attribute or an `@import`'s media list puts on the rules of its
+/// sheet: none if it is empty.
+fn media_condition(media: &str) -> Vec<Arc<MediaQueryList>> {
+    if media.trim().is_empty() {
+        Vec::new()
+    } else {
+        vec![Arc::new(MediaQueryList::parse(media))]
+    }
+}
+
+/// Add the rules of `sheet`, after those of its imports, under the conditions `media`.
+/// `base_url` is what the imports are relative to; `importers` are the URLs of the sheets
+/// that imported this one, so an import cycle is cut off.
+fn collect(
+    sheet: &StyleSheet,
+    base_url: Option<&str>,
+    loads: &SheetLoads,
+    media: &[Arc<MediaQueryList>],
+    importers: &mut Vec<String>,
+    collected: &mut AuthorRules,
+) {
+    for import in &sheet.imports {
+        let Some(url) = base_url.and_then(|base| network::resolve_url(base, import.href.trim()))
+        else {
+            continue;
+        };
+        if !importers.contains(&url) {
+            let mut media = media.to_vec();
+            media.extend(media_condition(&import.media));
+            collect_external(url, loads, &media, importers, collected);
+        }
+    }
+    collected.rules.extend(sheet.rules.iter().map(|rule| {
+        let mut rule = rule.clone();
+        rule.media = media.iter().chain(&rule.media).cloned().collect();
+        rule
+    }));
+}
+
+fn collect_external(
+    url: String,
+    loads: &SheetLoads,
+    media: &[Arc<MediaQueryList>],
+    importers: &mut Vec<String>,
+    collected: &mut AuthorRules,
+) {
+    if !collected.urls.contains(&url) {
+        collected.urls.push(url.clone());
+    }
+    if let Some(SheetStatus::Loaded(sheet)) = loads.get(&url) {
+        importers.push(url.clone());
+        collect(sheet, Some(&url), loads, media, importers, collected);
+        importers.pop();
+    }
+}
+
+/// Why the page at `page_url` may not load the stylesheet at `url`, if it may not: only
+/// `http` and `https` sheets are fetched, and a secure page doesn't use insecure ones.
+pub fn blocked_reason(page_url: &str, url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    match url.scheme() {
+        "https" => None,
+        "http" if page_url.starts_with("https:") => {
+            Some("insecure stylesheet on a secure page".to_string())
+        }
+        "http" => None,
+        scheme => Some(format!("unsupported scheme {}", scheme)),
+    }
+}
+
+/// Fetch and parse the stylesheet at `url`. Blocks; run it on a background thread.
+pub fn fetch(network: &NetworkManager, url: &str) -> SheetStatus {
+    match network.get_text(url) {
+        // Like other browsers, refuse a sheet the server says is something else
+        Ok(file) => match &file.mime {
+            Some(mime) if mime != "text/css" => SheetStatus::Blocked(format!("served as {}", mime)),
+            _ => SheetStatus::Loaded(Arc::new(css_parser::parse_stylesheet(&file.text))),
+        },
+        Err(error) => SheetStatus::Failed(error.to_string()),
+    }
+}
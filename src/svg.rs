@@ -0,0 +1,991 @@
+// svg.rs
+use crate::css_parser;
+use crate::dom::NodeRef;
+use crate::layout::{HtmlTag, Length, NodeType, StyleProperty};
+use eframe::egui::{Align, Color32, ColorImage, Pos2, Vec2, pos2, vec2};
+use std::collections::hash_map::DefaultHasher;
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::hash::{Hash, Hasher};
+use tiny_skia::{FillRule, LineCap, LineJoin, Paint, Path, PathBuilder, Pixmap, Rect, Transform};
+
+/// Size of an `<svg>` that gives neither `width`, `height` nor `viewBox`, as for other
+/// replaced elements.
+const DEFAULT_SIZE: Vec2 = vec2(300.0, 150.0);
+const DEFAULT_FONT_SIZE: f32 = 16.0;
+/// Bounds the texture a scene is rasterized into; larger boxes are drawn stretched.
+const MAX_TEXTURE_SIDE: f32 = 4096.0;
+/// Control point distance of a cubic Bézier approximating a quarter circle of radius 1.
+const KAPPA: f32 = 0.552_284_8;
+
+/// How `preserveAspectRatio` places the `viewBox` in the viewport.
+#[derive(Debug, Clone, Copy)]
+enum AspectRatio {
+    Stretch, // `none`
+    Align { x: f32, y: f32, slice: bool },
+}
+
+#[derive(Debug, Clone)]
+struct Shape {
+    path: Path,
+    transform: Transform,
+    fill: Option<(Color32, FillRule)>,
+    stroke: Option<(Color32, tiny_skia::Stroke)>,
+}
+
+/// A `<text>` element. Text is laid out by egui on top of the rasterized shapes, so it stays
+/// sharp; rotations and skews of its transform are ignored.
+#[derive(Debug, Clone)]
+pub struct Text {
+    pub content: String,
+    /// Start of the baseline, in the coordinates of the outermost `<svg>`.
+    pub position: Pos2,
+    pub font_size: f32,
+    pub color: Color32,
+    /// `text-anchor`: which part of the text sits at `position`.
+    pub anchor: Align,
+}
+
+/// The drawing described by an `<svg>` element and its children: shapes in document order,
+/// each with the fill, stroke and transform it ends up with after inheritance.
+#[derive(Debug, Clone)]
+pub struct Scene {
+    view_box: Option<Rect>,
+    aspect_ratio: AspectRatio,
+    width: Option<f32>,
+    height: Option<f32>,
+    shapes: Vec<Shape>,
+    pub texts: Vec<Text>,
+}
+
+/// The properties a shape inherits from the elements around it.
+#[derive(Debug, Clone)]
+struct Inherited {
+    transform: Transform,
+    color: Color32, // `currentColor`
+    fill: Option<Color32>,
+    fill_opacity: f32,
+    fill_rule: FillRule,
+    stroke: Option<Color32>,
+    stroke_opacity: f32,
+    stroke_width: f32,
+    line_cap: LineCap,
+    line_join: LineJoin,
+    miter_limit: f32,
+    opacity: f32, // product of the `opacity` of all enclosing groups
+    font_size: f32,
+    anchor: Align,
+    viewport: Vec2, // what percentages refer to
+}
+
+impl Scene {
+    /// Build the scene of `svg`. `color` is what `currentColor` stands for, normally the text
+    /// color around the element.
+    pub fn new(svg: NodeRef, color: Color32) -> Self {
+        let view_box = svg.get_attribute("viewBox").and_then(parse_view_box);
+        // Percentages depend on the box the element ends up in, so they don't count here
+        let size = |name: &str| {
+            match svg.style.get(name) {
+                Some(StyleProperty::Length(Length::Percent(_))) => None,
+                Some(StyleProperty::Length(length)) => {
+                    Some(length.to_egui_value(DEFAULT_FONT_SIZE, 0.0))
+                }
+                _ => svg
+                    .get_attribute(name)
+                    .filter(|value| !value.trim().ends_with('%'))
+                    .and_then(|value| parse_length(value, 0.0, DEFAULT_FONT_SIZE)),
+            }
+            .filter(|size| *size > 0.0)
+        };
+        let mut scene = Scene {
+            view_box,
+            aspect_ratio: parse_aspect_ratio(svg.get_attribute("preserveAspectRatio")),
+            width: size("width"),
+            height: size("height"),
+            shapes: Vec::new(),
+            texts: Vec::new(),
+        };
+        let viewport = match view_box {
+            Some(view_box) => vec2(view_box.width(), view_box.height()),
+            None => scene.intrinsic_size(),
+        };
+        let root = Inherited {
+            transform: Transform::identity(),
+            color,
+            fill: Some(Color32::BLACK),
+            fill_opacity: 1.0,
+            fill_rule: FillRule::Winding,
+            stroke: None,
+            stroke_opacity: 1.0,
+            stroke_width: 1.0,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            miter_limit: 4.0,
+            opacity: 1.0,
+            font_size: DEFAULT_FONT_SIZE,
+            anchor: Align::Min,
+            viewport,
+        };
+        let root = inherit(svg, &root);
+        scene.add_children(svg, &root);
+        scene
+    }
+
+    /// The size the drawing asks for: its `width` and `height`, with a missing one following
+    /// the aspect ratio of the `viewBox`.
+    pub fn intrinsic_size(&self) -> Vec2 {
+        let ratio = self
+            .view_box
+            .map(|view_box| view_box.width() / view_box.height());
+        match (self.width, self.height, ratio) {
+            (Some(w), Some(h), _) => vec2(w, h),
+            (Some(w), None, Some(ratio)) => vec2(w, w / ratio),
+            (None, Some(h), Some(ratio)) => vec2(h * ratio, h),
+            (None, None, Some(ratio)) => vec2(DEFAULT_SIZE.x, DEFAULT_SIZE.x / ratio),
+            (w, h, None) => vec2(w.unwrap_or(DEFAULT_SIZE.x), h.unwrap_or(DEFAULT_SIZE.y)),
+        }
+    }
+
+    /// Maps the coordinates of the drawing to a box of `size` points with its corner at 0,0.
+    pub fn view_transform(&self, size: Vec2) -> Transform {
+        match self.view_box {
+            Some(view_box) => fit_view_box(view_box, self.aspect_ratio, size),
+            None => Transform::identity(),
+        }
+    }
+
+    /// Where the baseline of `text` starts in a box of `size` points, and its font size there.
+    pub fn place_text(&self, text: &Text, size: Vec2) -> (Pos2, f32) {
+        let view = self.view_transform(size);
+        let mut point = tiny_skia::Point::from_xy(text.position.x, text.position.y);
+        view.map_point(&mut point);
+        (pos2(point.x, point.y), text.font_size * scale_of(view))
+    }
+
+    /// Draw the shapes into an image `size` points large, at `pixels_per_point`.
+    pub fn rasterize(&self, size: Vec2, pixels_per_point: f32) -> Option<ColorImage> {
+        let pixels = (size * pixels_per_point)
+            .round()
+            .clamp(Vec2::splat(1.0), Vec2::splat(MAX_TEXTURE_SIDE));
+        let mut pixmap = Pixmap::new(pixels.x as u32, pixels.y as u32)?;
+        let view = self
+            .view_transform(size)
+            .post_scale(pixels.x / size.x, pixels.y / size.y);
+        let mut paint = Paint {
+            anti_alias: true,
+            ..Paint::default()
+        };
+        for shape in &self.shapes {
+            let transform = shape.transform.post_concat(view);
+            if let Some((color, rule)) = shape.fill {
+                paint.set_color(skia_color(color));
+                pixmap.fill_path(&shape.path, &paint, rule, transform, None);
+            }
+            if let Some((color, stroke)) = &shape.stroke {
+                paint.set_color(skia_color(*color));
+                pixmap.stroke_path(&shape.path, &paint, stroke, transform, None);
+            }
+        }
+        Some(ColorImage::from_rgba_premultiplied(
+            [pixmap.width() as usize, pixmap.height() as usize],
+            pixmap.data(),
+        ))
+    }
+
+    /// Changes whenever the rasterized image would, so a cached texture can be reused.
+    pub fn fingerprint(&self) -> u64 {
+        fn floats(values: &[f32], hasher: &mut DefaultHasher) {
+            for value in values {
+                value.to_bits().hash(hasher);
+            }
+        }
+        let mut hasher = DefaultHasher::new();
+        format!("{:?}", self.aspect_ratio).hash(&mut hasher);
+        if let Some(view_box) = self.view_box {
+            floats(
+                &[
+                    view_box.x(),
+                    view_box.y(),
+                    view_box.width(),
+                    view_box.height(),
+                ],
+                &mut hasher,
+            );
+        }
+        for shape in &self.shapes {
+            for verb in shape.path.verbs() {
+                (*verb as u8).hash(&mut hasher);
+            }
+            for point in shape.path.points() {
+                floats(&[point.x, point.y], &mut hasher);
+            }
+            let t = shape.transform;
+            floats(&[t.sx, t.ky, t.kx, t.sy, t.tx, t.ty], &mut hasher);
+            if let Some((color, rule)) = shape.fill {
+                (color, rule as u8).hash(&mut hasher);
+            }
+            if let Some((color, stroke)) = &shape.stroke {
+                (color, stroke.line_cap as u8, stroke.line_join as u8).hash(&mut hasher);
+                floats(&[stroke.width, stroke.miter_limit], &mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    fn add_children(&mut self, node: NodeRef, state: &Inherited) {
+        for child in node.children() {
+            if child.is_element() {
+                self.add_element(child, state);
+            }
+        }
+    }
+
+    fn add_element(&mut self, node: NodeRef, parent: &Inherited) {
+        let Some(tag) = node.tag() else {
+            return;
+        };
+        let hidden = |name: &str, hidden: &str| {
+            keyword(node, name).is_some_and(|value| value.eq_ignore_ascii_case(hidden))
+        };
+        if hidden("display", "none") {
+            return;
+        }
+        let state = inherit(node, parent);
+        let name = tag.tag_name().to_ascii_lowercase();
+        let shape = match (tag, name.as_str()) {
+            (HtmlTag::A, _) | (_, "g" | "switch") => {
+                self.add_children(node, &state);
+                return;
+            }
+            (_, "svg") => {
+                self.add_nested_svg(node, state);
+                return;
+            }
+            (_, "text") => {
+                if !hidden("visibility", "hidden") {
+                    self.add_text(node, &state);
+                }
+                return;
+            }
+            (HtmlTag::Custom(_), _) => basic_shape(node, &name, &state),
+            // Definitions, metadata, and everything else we can't draw
+            _ => None,
+        };
+        if let Some(path) = shape
+            && !hidden("visibility", "hidden")
+        {
+            let opacity = |alpha: f32| alpha * state.opacity;
+            let fill = state.fill.map(|color| {
+                let color = color.gamma_multiply(opacity(state.fill_opacity));
+                (color, state.fill_rule)
+            });
+            let stroke = state
+                .stroke
+                .filter(|_| state.stroke_width > 0.0)
+                .map(|color| {
+                    let color = color.gamma_multiply(opacity(state.stroke_opacity));
+                    let stroke = tiny_skia::Stroke {
+                        width: state.stroke_width,
+                        miter_limit: state.miter_limit,
+                        line_cap: state.line_cap,
+                        line_join: state.line_join,
+                        dash: None,
+                    };
+                    (color, stroke)
+                });
+            if fill.is_some() || stroke.is_some() {
+                self.shapes.push(Shape {
+                    path,
+                    transform: state.transform,
+                    fill,
+                    stroke,
+                });
+            }
+        }
+    }
+
+    /// A nested `<svg>` establishes a new viewport at `x`, `y` with its own `viewBox`.
+    fn add_nested_svg(&mut self, node: NodeRef, mut state: Inherited) {
+        let viewport = state.viewport;
+        let x = length(node, "x", viewport.x, state.font_size).unwrap_or(0.0);
+        let y = length(node, "y", viewport.y, state.font_size).unwrap_or(0.0);
+        let size = vec2(
+            length(node, "width", viewport.x, state.font_size).unwrap_or(viewport.x),
+            length(node, "height", viewport.y, state.font_size).unwrap_or(viewport.y),
+        );
+        state.transform = state.transform.pre_translate(x, y);
+        if let Some(view_box) = node.get_attribute("viewBox").and_then(parse_view_box) {
+            let aspect_ratio = parse_aspect_ratio(node.get_attribute("preserveAspectRatio"));
+            state.transform =
+                state
+                    .transform
+                    .pre_concat(fit_view_box(view_box, aspect_ratio, size));
+            state.viewport = vec2(view_box.width(), view_box.height());
+        } else {
+            state.viewport = size;
+        }
+        self.add_children(node, &state);
+    }
+
+    fn add_text(&mut self, node: NodeRef, state: &Inherited) {
+        let Some(color) = state.fill else {
+            return;
+        };
+        let content = node
+            .text_content()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if content.is_empty() {
+            return;
+        }
+        // Only the first of a list of positions is used; the text is laid out as one run
+        let first = |name: &str, reference: f32| {
+            let value = node
+                .get_attribute(name)?
+                .split([' ', ','])
+                .find(|v| !v.is_empty())?;
+            parse_length(value, reference, state.font_size)
+        };
+        let x = first("x", state.viewport.x).unwrap_or(0.0)
+            + first("dx", state.viewport.x).unwrap_or(0.0);
+        let y = first("y", state.viewport.y).unwrap_or(0.0)
+            + first("dy", state.viewport.y).unwrap_or(0.0);
+        let mut point = tiny_skia::Point::from_xy(x, y);
+        state.transform.map_point(&mut point);
+        self.texts.push(Text {
+            content,
+            position: pos2(point.x, point.y),
+            font_size: state.font_size * scale_of(state.transform),
+            color: color.gamma_multiply(state.fill_opacity * state.opacity),
+            anchor: state.anchor,
+        });
+    }
+}
+
+/// The properties `node` passes on to its children, starting from its parent's.
+fn inherit(node: NodeRef, parent: &Inherited) -> Inherited {
+    let mut state = parent.clone();
+    if let Some(transform) = node.get_attribute("transform").and_then(parse_transform) {
+        state.transform = state.transform.pre_concat(transform);
+    }
+    if let Some(Some(color)) = paint(node, "color", parent.color) {
+        state.color = color;
+    }
+    if let Some(fill) = paint(node, "fill", state.color) {
+        state.fill = fill;
+    }
+    if let Some(stroke) = paint(node, "stroke", state.color) {
+        state.stroke = stroke;
+    }
+    if let Some(opacity) = number(node, "fill-opacity") {
+        state.fill_opacity = opacity.clamp(0.0, 1.0);
+    }
+    if let Some(opacity) = number(node, "stroke-opacity") {
+        state.stroke_opacity = opacity.clamp(0.0, 1.0);
+    }
+    if let Some(opacity) = number(node, "opacity") {
+        state.opacity *= opacity.clamp(0.0, 1.0);
+    }
+    let diagonal = (parent.viewport.length_sq() / 2.0).sqrt();
+    if let Some(width) = length(node, "stroke-width", diagonal, parent.font_size) {
+        state.stroke_width = width.max(0.0);
+    }
+    if let Some(limit) = number(node, "stroke-miterlimit") {
+        state.miter_limit = limit.max(1.0);
+    }
+    if let Some(size) = length(node, "font-size", parent.font_size, parent.font_size) {
+        state.font_size = size.max(0.0);
+    }
+    match keyword(node, "fill-rule").as_deref() {
+        Some("evenodd") => state.fill_rule = FillRule::EvenOdd,
+        Some("nonzero") => state.fill_rule = FillRule::Winding,
+        _ => {}
+    }
+    match keyword(node, "stroke-linecap").as_deref() {
+        Some("butt") => state.line_cap = LineCap::Butt,
+        Some("round") => state.line_cap = LineCap::Round,
+        Some("square") => state.line_cap = LineCap::Square,
+        _ => {}
+    }
+    match keyword(node, "stroke-linejoin").as_deref() {
+        Some("miter") => state.line_join = LineJoin::Miter,
+        Some("round") => state.line_join = LineJoin::Round,
+        Some("bevel") => state.line_join = LineJoin::Bevel,
+        _ => {}
+    }
+    match keyword(node, "text-anchor").as_deref() {
+        Some("start") => state.anchor = Align::Min,
+        Some("middle") => state.anchor = Align::Center,
+        Some("end") => state.anchor = Align::Max,
+        _ => {}
+    }
+    state
+}
+
+/// The outline of `<path>`, `<rect>`, `<circle>`, `<ellipse>`, `<line>`, `<polyline>` or
+/// `<polygon>`. `None` for other elements and for shapes with nothing to draw.
+fn basic_shape(node: NodeRef, name: &str, state: &Inherited) -> Option<Path> {
+    let viewport = state.viewport;
+    let font_size = state.font_size;
+    let x = |name: &str| length(node, name, viewport.x, font_size).unwrap_or(0.0);
+    let y = |name: &str| length(node, name, viewport.y, font_size).unwrap_or(0.0);
+    let diagonal = (viewport.length_sq() / 2.0).sqrt();
+    match name {
+        "path" => parse_path(node.get_attribute("d")?),
+        "rect" => {
+            let (width, height) = (x("width"), y("height"));
+            if width <= 0.0 || height <= 0.0 {
+                return None;
+            }
+            // A missing corner radius takes the value of the other one
+            let rx = length(node, "rx", viewport.x, font_size);
+            let ry = length(node, "ry", viewport.y, font_size);
+            let (rx, ry) = match (rx, ry) {
+                (Some(rx), Some(ry)) => (rx, ry),
+                (Some(r), None) | (None, Some(r)) => (r, r),
+                (None, None) => (0.0, 0.0),
+            };
+            let rect = Rect::from_xywh(x("x"), y("y"), width, height)?;
+            rounded_rect(
+                rect,
+                rx.clamp(0.0, width / 2.0),
+                ry.clamp(0.0, height / 2.0),
+            )
+        }
+        "circle" => {
+            let r = length(node, "r", diagonal, font_size)?;
+            (r > 0.0).then(|| PathBuilder::from_circle(x("cx"), y("cy"), r))?
+        }
+        "ellipse" => {
+            let (rx, ry) = (x("rx"), y("ry"));
+            PathBuilder::from_oval(Rect::from_xywh(
+                x("cx") - rx,
+                y("cy") - ry,
+                2.0 * rx,
+                2.0 * ry,
+            )?)
+        }
+        "line" => {
+            let mut builder = PathBuilder::new();
+            builder.move_to(x("x1"), y("y1"));
+            builder.line_to(x("x2"), y("y2"));
+            builder.finish()
+        }
+        "polyline" | "polygon" => {
+            let mut numbers = Scanner::new(node.get_attribute("points")?);
+            let mut builder = PathBuilder::new();
+            // A trailing odd coordinate is ignored
+            while let (Some(x), Some(y)) = (numbers.number(), numbers.number()) {
+                if builder.is_empty() {
+                    builder.move_to(x, y);
+                } else {
+                    builder.line_to(x, y);
+                }
+            }
+            if name == "polygon" {
+                builder.close();
+            }
+            builder.finish()
+        }
+        _ => None,
+    }
+}
+
+fn rounded_rect(rect: Rect, rx: f32, ry: f32) -> Option<Path> {
+    if rx <= 0.0 || ry <= 0.0 {
+        return Some(PathBuilder::from_rect(rect));
+    }
+    let (left, top, right, bottom) = (rect.left(), rect.top(), rect.right(), rect.bottom());
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    let mut builder = PathBuilder::new();
+    builder.move_to(left + rx, top);
+    builder.line_to(right - rx, top);
+    builder.cubic_to(right - rx + kx, top, right, top + ry - ky, right, top + ry);
+    builder.line_to(right, bottom - ry);
+    builder.cubic_to(
+        right,
+        bottom - ry + ky,
+        right - rx + kx,
+        bottom,
+        right - rx,
+        bottom,
+    );
+    builder.line_to(left + rx, bottom);
+    builder.cubic_to(
+        left + rx - kx,
+        bottom,
+        left,
+        bottom - ry + ky,
+        left,
+        bottom - ry,
+    );
+    builder.line_to(left, top + ry);
+    builder.cubic_to(left, top + ry - ky, left + rx - kx, top, left + rx, top);
+    builder.close();
+    builder.finish()
+}
+
+/// A presentation property: from the page's CSS if it sets it, else from the attribute of
+/// the same name. Keywords and attributes come back as text.
+fn keyword(node: NodeRef, name: &str) -> Option<String> {
+    match node.style.get(name) {
+        Some(StyleProperty::Keyword(value)) => Some(value.trim().to_ascii_lowercase()),
+        Some(_) => None,
+        None => node
+            .get_attribute(name)
+            .map(|value| value.trim().to_ascii_lowercase()),
+    }
+}
+
+fn number(node: NodeRef, name: &str) -> Option<f32> {
+    match node.style.get(name) {
+        Some(StyleProperty::Length(Length::Percent(percent))) => Some(percent / 100.0),
+        _ => {
+            let value = keyword(node, name)?;
+            match value.strip_suffix('%') {
+                Some(percent) => percent.trim().parse::<f32>().ok().map(|p| p / 100.0),
+                None => value.parse().ok(),
+            }
+        }
+    }
+}
+
+/// A length property; percentages are of `reference`.
+fn length(node: NodeRef, name: &str, reference: f32, font_size: f32) -> Option<f32> {
+    match node.style.get(name) {
+        Some(StyleProperty::Length(length)) => Some(length.to_egui_value(font_size, reference)),
+        _ => parse_length(&keyword(node, name)?, reference, font_size),
+    }
+}
+
+/// A `fill`, `stroke` or `color`. `Some(None)` is `none`; `None` means the property isn't set
+/// (or can't be drawn, such as a gradient without a fallback color) and is inherited.
+fn paint(node: NodeRef, name: &str, current_color: Color32) -> Option<Option<Color32>> {
+    if let Some(StyleProperty::Color(color)) = node.style.get(name) {
+        return Some(Some(color.to_ecolor()));
+    }
+    let value = keyword(node, name)?;
+    // `url(#gradient) red` falls back to the color after the reference
+    let value = match value.strip_prefix("url(") {
+        Some(rest) => rest.split_once(')')?.1.trim().to_string(),
+        None => value,
+    };
+    match value.as_str() {
+        "none" => Some(None),
+        "currentcolor" => Some(Some(current_color)),
+        _ => css_parser::parse_color(&value).map(|color| Some(color.to_ecolor())),
+    }
+}
+
+/// A length attribute: a plain number is in user units (pixels).
+fn parse_length(value: &str, reference: f32, font_size: f32) -> Option<f32> {
+    let value = value.trim();
+    const UNITS: [(&str, f32); 8] = [
+        ("px", 1.0),
+        ("rem", DEFAULT_FONT_SIZE),
+        ("pt", 4.0 / 3.0),
+        ("pc", 16.0),
+        ("mm", 96.0 / 25.4),
+        ("cm", 96.0 / 2.54),
+        ("in", 96.0),
+        ("%", 0.0),
+    ];
+    for (unit, factor) in UNITS {
+        if let Some(number) = value.strip_suffix(unit) {
+            let number: f32 = number.trim().parse().ok()?;
+            return Some(if unit == "%" {
+                number / 100.0 * reference
+            } else {
+                number * factor
+            });
+        }
+    }
+    if let Some(em) = value.strip_suffix("em") {
+        return em.trim().parse::<f32>().ok().map(|em| em * font_size);
+    }
+    value.parse().ok()
+}
+
+fn parse_view_box(value: &str) -> Option<Rect> {
+    let mut numbers = Scanner::new(value);
+    let (x, y) = (numbers.number()?, numbers.number()?);
+    let (width, height) = (numbers.number()?, numbers.number()?);
+    (width > 0.0 && height > 0.0)
+        .then(|| Rect::from_xywh(x, y, width, height))
+        .flatten()
+}
+
+/// `preserveAspectRatio`, such as `xMidYMid meet` (the default) or `none`.
+fn parse_aspect_ratio(value: Option<&str>) -> AspectRatio {
+    let mut words = value.unwrap_or("").split_whitespace();
+    let align = words.next().unwrap_or("xMidYMid");
+    let slice = words.next() == Some("slice");
+    let position = |name: &str| {
+        if align.contains(&format!("{name}Min")) {
+            0.0
+        } else if align.contains(&format!("{name}Max")) {
+            1.0
+        } else {
+            0.5
+        }
+    };
+    if align == "none" {
+        AspectRatio::Stretch
+    } else {
+        AspectRatio::Align {
+            x: position("x"),
+            y: position("Y"),
+            slice,
+        }
+    }
+}
+
+/// Scale and move `view_box` onto a viewport of `size` starting at 0,0.
+fn fit_view_box(view_box: Rect, aspect_ratio: AspectRatio, size: Vec2) -> Transform {
+    let sx = size.x / view_box.width();
+    let sy = size.y / view_box.height();
+    let (sx, sy, x, y) = match aspect_ratio {
+        AspectRatio::Stretch => (sx, sy, 0.0, 0.0),
+        AspectRatio::Align { x, y, slice } => {
+            let scale = if slice { sx.max(sy) } else { sx.min(sy) };
+            let free = size - vec2(view_box.width(), view_box.height()) * scale;
+            (scale, scale, free.x * x, free.y * y)
+        }
+    };
+    Transform::from_row(
+        sx,
+        0.0,
+        0.0,
+        sy,
+        x - view_box.x() * sx,
+        y - view_box.y() * sy,
+    )
+}
+
+/// `translate(10 20) rotate(45)` and the other transform functions, applied left to right.
+fn parse_transform(value: &str) -> Option<Transform> {
+    let mut transform = Transform::identity();
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let (name, after) = rest.split_once('(')?;
+        let (arguments, after) = after.split_once(')')?;
+        let mut numbers = Scanner::new(arguments);
+        let args: Vec<f32> = std::iter::from_fn(|| numbers.number()).collect();
+        let step = match (name.trim(), args.as_slice()) {
+            ("matrix", &[a, b, c, d, e, f]) => Transform::from_row(a, b, c, d, e, f),
+            ("translate", &[x]) => Transform::from_translate(x, 0.0),
+            ("translate", &[x, y]) => Transform::from_translate(x, y),
+            ("scale", &[s]) => Transform::from_scale(s, s),
+            ("scale", &[x, y]) => Transform::from_scale(x, y),
+            ("rotate", &[angle]) => Transform::from_rotate(angle),
+            ("rotate", &[angle, x, y]) => Transform::from_rotate_at(angle, x, y),
+            ("skewX", &[angle]) => Transform::from_skew(angle.to_radians().tan(), 0.0),
+            ("skewY", &[angle]) => Transform::from_skew(0.0, angle.to_radians().tan()),
+            _ => return None,
+        };
+        transform = transform.pre_concat(step);
+        rest = after.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+    }
+    Some(transform)
+}
+
+/// Parse path data (`M 10 10 L 20 20 Z`). As in browsers, the path is drawn up to the first
+/// error.
+fn parse_path(data: &str) -> Option<Path> {
+    let mut scanner = Scanner::new(data);
+    let mut builder = PathBuilder::new();
+    let mut current = (0.0, 0.0);
+    let mut start = (0.0, 0.0);
+    // Second control point of the previous cubic or quadratic segment, for `S` and `T`
+    let mut last_cubic: Option<(f32, f32)> = None;
+    let mut last_quad: Option<(f32, f32)> = None;
+    let mut command = None;
+
+    while let Some(next) = scanner.peek() {
+        if next.is_ascii_alphabetic() {
+            scanner.pos += 1;
+            command = Some(next);
+        } else if command.is_none() {
+            break;
+        }
+        let Some(letter) = command else {
+            break;
+        };
+        let relative = letter.is_ascii_lowercase();
+        let offset = if relative { current } else { (0.0, 0.0) };
+        let point = |scanner: &mut Scanner| -> Option<(f32, f32)> {
+            Some((scanner.number()? + offset.0, scanner.number()? + offset.1))
+        };
+        let (cubic, quad) = match letter.to_ascii_uppercase() {
+            b'M' => {
+                let Some(to) = point(&mut scanner) else { break };
+                builder.move_to(to.0, to.1);
+                current = to;
+                start = to;
+                // Further coordinate pairs are lines
+                command = Some(if relative { b'l' } else { b'L' });
+                (None, None)
+            }
+            b'L' => {
+                let Some(to) = point(&mut scanner) else { break };
+                builder.line_to(to.0, to.1);
+                current = to;
+                (None, None)
+            }
+            b'H' => {
+                let Some(x) = scanner.number() else { break };
+                current.0 = x + offset.0;
+                builder.line_to(current.0, current.1);
+                (None, None)
+            }
+            b'V' => {
+                let Some(y) = scanner.number() else { break };
+                current.1 = y + offset.1;
+                builder.line_to(current.0, current.1);
+                (None, None)
+            }
+            b'C' | b'S' => {
+                let first = if letter.eq_ignore_ascii_case(&b'C') {
+                    let Some(first) = point(&mut scanner) else {
+                        break;
+                    };
+                    first
+                } else {
+                    // The reflection of the previous control point
+                    last_cubic.map_or(current, |(x, y)| (2.0 * current.0 - x, 2.0 * current.1 - y))
+                };
+                let (Some(second), Some(to)) = (point(&mut scanner), point(&mut scanner)) else {
+                    break;
+                };
+                builder.cubic_to(first.0, first.1, second.0, second.1, to.0, to.1);
+                current = to;
+                (Some(second), None)
+            }
+            b'Q' | b'T' => {
+                let control = if letter.eq_ignore_ascii_case(&b'Q') {
+                    let Some(control) = point(&mut scanner) else {
+                        break;
+                    };
+                    control
+                } else {
+                    last_quad.map_or(current, |(x, y)| (2.0 * current.0 - x, 2.0 * current.1 - y))
+                };
+                let Some(to) = point(&mut scanner) else { break };
+                builder.quad_to(control.0, control.1, to.0, to.1);
+                current = to;
+                (None, Some(control))
+            }
+            b'A' => {
+                let (Some(rx), Some(ry), Some(angle)) =
+                    (scanner.number(), scanner.number(), scanner.number())
+                else {
+                    break;
+                };
+                let (Some(large), Some(sweep)) = (scanner.flag(), scanner.flag()) else {
+                    break;
+                };
+                let Some(to) = point(&mut scanner) else { break };
+                arc_to(&mut builder, current, (rx, ry), angle, large, sweep, to);
+                current = to;
+                (None, None)
+            }
+            b'Z' => {
+                builder.close();
+                current = start;
+                // `Z` takes no numbers; any that follow are an error
+                command = None;
+                (None, None)
+            }
+            _ => break,
+        };
+        last_cubic = cubic;
+        last_quad = quad;
+    }
+    builder.finish()
+}
+
+/// Append an elliptical arc from `from` to `to` as cubic Béziers, converting the endpoint
+/// parameters of the path data to a center and angles (SVG 2, appendix B.2.4).
+fn arc_to(
+    builder: &mut PathBuilder,
+    from: (f32, f32),
+    radii: (f32, f32),
+    angle: f32,
+    large_arc: bool,
+    sweep: bool,
+    to: (f32, f32),
+) {
+    if from == to {
+        return;
+    }
+    let (mut rx, mut ry) = (radii.0.abs(), radii.1.abs());
+    if rx == 0.0 || ry == 0.0 {
+        builder.line_to(to.0, to.1);
+        return;
+    }
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let half = ((from.0 - to.0) / 2.0, (from.1 - to.1) / 2.0);
+    let x1 = cos * half.0 + sin * half.1;
+    let y1 = -sin * half.0 + cos * half.1;
+    // Radii too small to reach the end point are scaled up
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+    let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let mut coefficient = (numerator / denominator).max(0.0).sqrt();
+    if large_arc == sweep {
+        coefficient = -coefficient;
+    }
+    let (cx1, cy1) = (coefficient * rx * y1 / ry, -coefficient * ry * x1 / rx);
+    let center = (
+        cos * cx1 - sin * cy1 + (from.0 + to.0) / 2.0,
+        sin * cx1 + cos * cy1 + (from.1 + to.1) / 2.0,
+    );
+    let vector_angle =
+        |ux: f32, uy: f32, vx: f32, vy: f32| (ux * vy - uy * vx).atan2(ux * vx + uy * vy);
+    let start_vector = ((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let end_vector = ((-x1 - cx1) / rx, (-y1 - cy1) / ry);
+    let start_angle = vector_angle(1.0, 0.0, start_vector.0, start_vector.1);
+    let mut sweep_angle = vector_angle(start_vector.0, start_vector.1, end_vector.0, end_vector.1);
+    if !sweep && sweep_angle > 0.0 {
+        sweep_angle -= TAU;
+    } else if sweep && sweep_angle < 0.0 {
+        sweep_angle += TAU;
+    }
+
+    // One cubic per quarter turn at most
+    let segments = (sweep_angle.abs() / FRAC_PI_2).ceil().max(1.0) as usize;
+    let step = sweep_angle / segments as f32;
+    let handle = 4.0 / 3.0 * (step / 4.0).tan();
+    let point_at = |a: f32| {
+        let (sin_a, cos_a) = a.sin_cos();
+        (
+            center.0 + rx * cos_a * cos - ry * sin_a * sin,
+            center.1 + rx * cos_a * sin + ry * sin_a * cos,
+        )
+    };
+    let tangent_at = |a: f32| {
+        let (sin_a, cos_a) = a.sin_cos();
+        (
+            -rx * sin_a * cos - ry * cos_a * sin,
+            -rx * sin_a * sin + ry * cos_a * cos,
+        )
+    };
+    for i in 0..segments {
+        let a1 = start_angle + step * i as f32;
+        let a2 = a1 + step;
+        let (p1, t1) = (point_at(a1), tangent_at(a1));
+        let (t2, p2) = (
+            tangent_at(a2),
+            if i + 1 == segments { to } else { point_at(a2) },
+        );
+        builder.cubic_to(
+            p1.0 + handle * t1.0,
+            p1.1 + handle * t1.1,
+            p2.0 - handle * t2.0,
+            p2.1 - handle * t2.1,
+            p2.0,
+            p2.1,
+        );
+    }
+}
+
+/// Reads the numbers of path data, point lists and transforms, which may be separated by
+/// whitespace, a comma, or nothing at all when the sign or dot makes it clear (`10-5.5.5`).
+struct Scanner<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(input: &'a str) -> Self {
+        Scanner {
+            input: input.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    /// The next character after any separators.
+    fn peek(&mut self) -> Option<u8> {
+        while let Some(&c) = self.input.get(self.pos) {
+            if !(c.is_ascii_whitespace() || c == b',') {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.input.get(self.pos).copied()
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        self.peek()?;
+        let start = self.pos;
+        let digits = |scanner: &mut Self| {
+            let from = scanner.pos;
+            while scanner
+                .input
+                .get(scanner.pos)
+                .is_some_and(u8::is_ascii_digit)
+            {
+                scanner.pos += 1;
+            }
+            scanner.pos > from
+        };
+        if matches!(self.input.get(self.pos), Some(b'+' | b'-')) {
+            self.pos += 1;
+        }
+        let mut any = digits(self);
+        if self.input.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            any |= digits(self);
+        }
+        if !any {
+            self.pos = start;
+            return None;
+        }
+        if matches!(self.input.get(self.pos), Some(b'e' | b'E')) {
+            let mantissa_end = self.pos;
+            self.pos += 1;
+            if matches!(self.input.get(self.pos), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                self.pos = mantissa_end;
+            }
+        }
+        std::str::from_utf8(&self.input[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    /// An arc flag: a single `0` or `1`, which need not be followed by a separator.
+    fn flag(&mut self) -> Option<bool> {
+        let flag = match self.peek()? {
+            b'0' => false,
+            b'1' => true,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(flag)
+    }
+}
+
+/// How much a transform enlarges areas, as a single factor for lengths such as font sizes.
+fn scale_of(t: Transform) -> f32 {
+    (t.sx * t.sy - t.kx * t.ky).abs().sqrt()
+}
+
+fn skia_color(color: Color32) -> tiny_skia::Color {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    tiny_skia::Color::from_rgba8(r, g, b, a)
+}
+
+/// Find the `<svg>` element of a parsed `.svg` file.
+pub fn find_svg(root: NodeRef) -> Option<NodeRef> {
+    std::iter::once(root)
+        .chain(root.descendants())
+        .find(|node| matches!(node.node_type, NodeType::Element(HtmlTag::Svg)))
+}
@@ -0,0 +1,209 @@
+// table.rs
+use crate::dom::NodeRef;
+use crate::layout::{HtmlTag, Length, NodeType};
+use std::collections::HashSet;
+
+/// Browsers cap spans at these values so a bad attribute can't create millions of slots.
+const MAX_COLSPAN: usize = 1000;
+const MAX_ROWSPAN: usize = 65534;
+
+/// A `<td>` or `<th>` and the block of grid slots it covers.
+#[derive(Debug, Clone, Copy)]
+pub struct GridCell<'a> {
+    pub node: NodeRef<'a>,
+    pub row: usize,
+    pub column: usize,
+    pub row_span: usize,
+    pub column_span: usize,
+}
+
+impl GridCell<'_> {
+    /// The last row the cell covers.
+    pub fn last_row(&self) -> usize {
+        self.row + self.row_span - 1
+    }
+}
+
+/// A `<tr>` together with the row group (`<thead>`, `<tbody>`, `<tfoot>`) it belongs to.
+/// Rows placed directly in the table have no group.
+#[derive(Debug, Clone, Copy)]
+pub struct GridRow<'a> {
+    pub node: NodeRef<'a>,
+    pub group: Option<NodeRef<'a>>,
+}
+
+/// A table's cells assigned to rows and columns, following the HTML table model:
+/// cells fill the first free slot of their row, spans reserve slots in the rows below,
+/// and the header group comes first and the footer group last, wherever they are written.
+#[derive(Debug)]
+pub struct TableGrid<'a> {
+    pub caption: Option<NodeRef<'a>>,
+    pub rows: Vec<GridRow<'a>>,
+    /// Sorted by row, then column.
+    pub cells: Vec<GridCell<'a>>,
+    pub column_count: usize,
+    /// Widths set by `<col>` and `<colgroup>`, one entry per column.
+    pub column_widths: Vec<Option<Length>>,
+    /// The number of leading rows that come from the `<thead>`.
+    pub header_rows: usize,
+}
+
+impl<'a> TableGrid<'a> {
+    pub fn new(table: NodeRef<'a>) -> Self {
+        let mut grid = TableGrid {
+            caption: None,
+            rows: Vec::new(),
+            cells: Vec::new(),
+            column_count: 0,
+            column_widths: Vec::new(),
+            header_rows: 0,
+        };
+
+        // Sort the children into caption, column definitions and row groups.
+        // Consecutive direct <tr> children form one group of their own.
+        let mut head = None;
+        let mut foot = None;
+        let mut bodies: Vec<Vec<GridRow<'a>>> = Vec::new();
+        let mut loose_rows = Vec::new();
+        for child in table.children() {
+            if let NodeType::Element(HtmlTag::Tr) = child.node_type {
+                loose_rows.push(GridRow {
+                    node: child,
+                    group: None,
+                });
+                continue;
+            }
+            if !child.is_element() {
+                continue;
+            }
+            if !loose_rows.is_empty() {
+                bodies.push(std::mem::take(&mut loose_rows));
+            }
+            match child.node_type {
+                NodeType::Element(HtmlTag::Caption) if grid.caption.is_none() => {
+                    grid.caption = Some(child);
+                }
+                NodeType::Element(HtmlTag::Colgroup | HtmlTag::Col) => {
+                    grid.add_columns(child);
+                }
+                NodeType::Element(HtmlTag::Thead) if head.is_none() => head = Some(child),
+                NodeType::Element(HtmlTag::Tfoot) if foot.is_none() => foot = Some(child),
+                NodeType::Element(HtmlTag::Thead | HtmlTag::Tbody | HtmlTag::Tfoot) => {
+                    bodies.push(group_rows(child));
+                }
+                _ => {}
+            }
+        }
+        if !loose_rows.is_empty() {
+            bodies.push(loose_rows);
+        }
+
+        if let Some(head) = head {
+            grid.add_group(group_rows(head));
+            grid.header_rows = grid.rows.len();
+        }
+        for body in bodies {
+            grid.add_group(body);
+        }
+        if let Some(foot) = foot {
+            grid.add_group(group_rows(foot));
+        }
+
+        grid.column_count = grid.column_count.max(grid.column_widths.len());
+        grid.column_widths.resize(grid.column_count, None);
+        grid
+    }
+
+    /// Record the widths a `<colgroup>` or `<col>` gives to the next columns.
+    fn add_columns(&mut self, node: NodeRef) {
+        let cols: Vec<NodeRef> = node
+            .children()
+            .filter(|child| matches!(child.node_type, NodeType::Element(HtmlTag::Col)))
+            .collect();
+        // A colgroup with <col> children is described by them; otherwise by its own span
+        if matches!(node.node_type, NodeType::Element(HtmlTag::Colgroup)) && !cols.is_empty() {
+            let group_width = node.dimension("width");
+            for col in cols {
+                let width = col.dimension("width").or_else(|| group_width.clone());
+                for _ in 0..span_of(col) {
+                    self.column_widths.push(width.clone());
+                }
+            }
+        } else {
+            let width = node.dimension("width");
+            for _ in 0..span_of(node) {
+                self.column_widths.push(width.clone());
+            }
+        }
+    }
+
+    /// Place the cells of one row group. Row spans stop at the end of the group.
+    fn add_group(&mut self, rows: Vec<GridRow<'a>>) {
+        let first_row = self.rows.len();
+        let group_end = first_row + rows.len();
+        let first_cell = self.cells.len();
+        let mut taken = HashSet::new();
+
+        for (index, row) in rows.iter().enumerate() {
+            let row_index = first_row + index;
+            let mut column = 0;
+            let cells = row.node.children().filter(|cell| {
+                matches!(cell.node_type, NodeType::Element(HtmlTag::Td | HtmlTag::Th))
+            });
+            for cell in cells {
+                while taken.contains(&(row_index, column)) {
+                    column += 1;
+                }
+                let column_span = span_attribute(cell, "colspan").clamp(1, MAX_COLSPAN);
+                // rowspan="0" means "to the end of the group"
+                let row_span = match span_attribute(cell, "rowspan").min(MAX_ROWSPAN) {
+                    0 => group_end - row_index,
+                    span => span.min(group_end - row_index),
+                };
+                for r in row_index..row_index + row_span {
+                    for c in column..column + column_span {
+                        taken.insert((r, c));
+                    }
+                }
+                self.cells.push(GridCell {
+                    node: cell,
+                    row: row_index,
+                    column,
+                    row_span,
+                    column_span,
+                });
+                column += column_span;
+            }
+        }
+
+        self.column_count = self.cells[first_cell..]
+            .iter()
+            .map(|cell| cell.column + cell.column_span)
+            .fold(self.column_count, usize::max);
+        self.rows.extend(rows);
+    }
+}
+
+/// The rows of a `<thead>`, `<tbody>` or `<tfoot>`.
+fn group_rows(group: NodeRef) -> Vec<GridRow> {
+    group
+        .children()
+        .filter(|child| matches!(child.node_type, NodeType::Element(HtmlTag::Tr)))
+        .map(|node| GridRow {
+            node,
+            group: Some(group),
+        })
+        .collect()
+}
+
+/// A `colspan`/`rowspan` value; missing or invalid values count as 1.
+fn span_attribute(node: NodeRef, name: &str) -> usize {
+    node.get_attribute(name)
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(1)
+}
+
+/// The number of columns a `<col>` or `<colgroup>` stands for.
+fn span_of(node: NodeRef) -> usize {
+    span_attribute(node, "span").clamp(1, MAX_COLSPAN)
+}